@@ -1,24 +1,23 @@
+use keyvalue_client::config::Profile;
 use keyvalue_client::{BatchOperation, Client, Error};
 use serde_json::json;
-use std::env;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    // Get token from environment or generate
-    let token = env::var("KV_TOKEN").ok();
+    // Get token and base URL from the environment, or generate a token
+    let profile = Profile::from_env()?;
+    let has_token = profile.token.is_some();
+    let mut client = profile.apply(Client::builder()).build()?;
 
-    let client = if let Some(token) = token {
-        println!("=== Using Existing Token ===");
-        println!("Token: {}\n", token);
-        Client::new(token)
+    if has_token {
+        println!("=== Using Existing Token ===\n");
     } else {
         println!("=== Generating Token ===");
-        let client_temp = Client::new_without_token();
-        let resp = client_temp.generate(None).await?;
+        let resp = client.generate(None).await?;
         println!("Generated token: {}", resp.token);
         println!("Save this! Set KV_TOKEN environment variable to reuse.\n");
-        Client::new(resp.token)
-    };
+        client.set_token(resp.token);
+    }
 
     // Create batch operations
     println!("=== Batch Operations ===");
@@ -88,7 +87,7 @@ async fn main() -> Result<(), Error> {
 
     // Retrieve one of the stored values
     println!("\n=== Retrieving Data ===");
-    let mut client1 = Client::new(token1);
+    let client1 = Client::new(token1.clone());
     let retrieve_resp = client1.retrieve().await?;
     println!("Retrieved from {}: {}",
         token1,
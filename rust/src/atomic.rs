@@ -0,0 +1,260 @@
+//! All-or-nothing batches across tokens
+//!
+//! The batch endpoint applies each operation on its own: one failing store
+//! leaves the others written. [`Client::batch_with_mode`] with
+//! [`BatchMode::Atomic`] emulates a transaction on top of it. Before sending
+//! the batch it reads the current value of every token the batch writes;
+//! if any operation then fails, it stores those values back, or deletes
+//! tokens that had none, and fails with [`Error::BatchRolledBack`].
+//!
+//! This is compensation rather than isolation: other clients can see the
+//! batch's writes until they are undone, undoing adds events to each token's
+//! history and bumps its version, and a write made by someone else in
+//! between is overwritten by the rollback. Restored values keep their
+//! remaining TTL.
+//!
+//! Snapshots hold each value exactly as stored, with encrypted fields still
+//! sealed and checksum or compression envelopes intact, and rollback writes
+//! them back unchanged, whichever token they belong to.
+//!
+//! ```no_run
+//! use keyvalue_client::{BatchMode, BatchOperation, Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new_without_token();
+//! let store = |token: &str, data| BatchOperation {
+//!     action: "store".to_string(),
+//!     token: token.to_string(),
+//!     data: Some(data),
+//!     ttl: None,
+//!     patch: None,
+//!     version: None,
+//! };
+//! let operations = vec![
+//!     store("word-word-word-word-order", serde_json::json!({"id": 42, "status": "paid"})),
+//!     store("word-word-word-word-stock", serde_json::json!({"sku-7": 11})),
+//! ];
+//! match client.batch_with_mode(operations, BatchMode::Atomic).await {
+//!     Err(Error::BatchRolledBack { message, .. }) => eprintln!("nothing written: {}", message),
+//!     result => println!("{} written", result?.summary.succeeded),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::degraded::is_outage;
+use crate::multi::DEFAULT_CONCURRENCY;
+use crate::{BatchOperation, BatchResponse, Client, Endpoint, Error, RetrieveResponse, Ttl};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+
+/// How a batch handles operations that fail
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BatchMode {
+    /// Operations apply independently; failures are reported per result
+    #[default]
+    Independent,
+    /// A failing operation undoes the ones that applied
+    Atomic,
+}
+
+/// Value of a token before the batch, as stored, to restore on rollback
+struct Snapshot {
+    token: String,
+    value: Option<RetrieveResponse>,
+}
+
+impl Client {
+    /// Execute batch operations, undoing them all if one fails in [`BatchMode::Atomic`]
+    pub async fn batch_with_mode(
+        &self,
+        operations: Vec<BatchOperation>,
+        mode: BatchMode,
+    ) -> Result<BatchResponse, Error> {
+        if mode == BatchMode::Independent {
+            return self.batch(operations).await;
+        }
+        let snapshots = self.snapshot(&operations).await?;
+        let resp = match self.batch(operations).await {
+            Ok(resp) => resp,
+            // The batch may have been applied before the connection dropped
+            Err(e) if is_outage(&e) => {
+                self.rollback_batch(&snapshots, |_| true).await;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        let Some(failed) = resp.results.iter().find(|result| !result.success) else {
+            return Ok(resp);
+        };
+
+        let message = failed.error.clone().unwrap_or_else(|| format!("{} failed", failed.action));
+        let applied: Vec<&str> = resp
+            .results
+            .iter()
+            .zip(&resp.operations)
+            .filter(|(result, operation)| result.success && is_write(operation))
+            .map(|(_, operation)| operation.token.as_str())
+            .collect();
+        let not_undone = self.rollback_batch(&snapshots, |token| applied.contains(&token)).await;
+        Err(Error::BatchRolledBack {
+            message,
+            not_undone: resp
+                .operations
+                .iter()
+                .enumerate()
+                .filter(|(_, operation)| not_undone.contains(&operation.token.as_str()))
+                .map(|(i, _)| i)
+                .collect(),
+        })
+    }
+
+    /// Current stored value of every token the operations write, once per token
+    async fn snapshot(&self, operations: &[BatchOperation]) -> Result<Vec<Snapshot>, Error> {
+        let mut tokens: Vec<&str> = vec![];
+        for operation in operations.iter().filter(|operation| is_write(operation)) {
+            if !tokens.contains(&operation.token.as_str()) {
+                tokens.push(&operation.token);
+            }
+        }
+        stream::iter(tokens)
+            .map(|token| async move {
+                Ok::<_, Error>(Snapshot {
+                    token: token.to_string(),
+                    value: self.retrieve_stored(token).await?,
+                })
+            })
+            .buffered(DEFAULT_CONCURRENCY)
+            .try_collect()
+            .await
+    }
+
+    /// Value stored for `token` without decoding it, or `None` if nothing is stored
+    async fn retrieve_stored(&self, token: &str) -> Result<Option<RetrieveResponse>, Error> {
+        let request = self.auth.apply(self.http_client.get(self.url(Endpoint::Retrieve)), token);
+        match self.execute(Endpoint::Retrieve, request).await {
+            Ok(resp) => Ok(Some(resp)),
+            Err(Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Restore the snapshots of tokens matching `undo`, returning the tokens that could not be restored
+    async fn rollback_batch<'a>(&self, snapshots: &'a [Snapshot], undo: impl Fn(&str) -> bool) -> Vec<&'a str> {
+        let snapshots: Vec<&Snapshot> = snapshots.iter().filter(|snapshot| undo(&snapshot.token)).collect();
+        if snapshots.is_empty() {
+            return vec![];
+        }
+        let restores = snapshots
+            .iter()
+            .map(|snapshot| match &snapshot.value {
+                Some(value) => BatchOperation {
+                    action: "store".to_string(),
+                    token: snapshot.token.clone(),
+                    data: Some(value.data.clone()),
+                    ttl: value.expires_at.map(|at| Ttl::until(at).seconds()),
+                    patch: None,
+                    version: None,
+                },
+                None => BatchOperation {
+                    action: "delete".to_string(),
+                    token: snapshot.token.clone(),
+                    data: None,
+                    ttl: None,
+                    patch: None,
+                    version: None,
+                },
+            })
+            .collect();
+        // Without a token of its own the client sends the snapshots as they are, encoding none of them
+        let mut restorer = self.clone();
+        restorer.token = None;
+        match restorer.batch(restores).await {
+            Ok(resp) => {
+                let not_undone: Vec<&str> = snapshots
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !resp.results.get(*i).is_some_and(|result| result.success))
+                    .map(|(_, snapshot)| snapshot.token.as_str())
+                    .collect();
+                if !not_undone.is_empty() {
+                    tracing::warn!(tokens = not_undone.len(), "failed to roll back part of an atomic batch");
+                }
+                not_undone
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to roll back an atomic batch");
+                snapshots.iter().map(|snapshot| snapshot.token.as_str()).collect()
+            }
+        }
+    }
+}
+
+fn is_write(operation: &BatchOperation) -> bool {
+    matches!(operation.action.as_str(), "store" | "patch" | "delete")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use serde_json::{json, Value};
+
+    const OWN: &str = "word-word-word-word-own";
+    const OTHER: &str = "word-word-word-word-other";
+
+    fn store(token: &str, data: Value) -> BatchOperation {
+        BatchOperation {
+            action: "store".to_string(),
+            token: token.to_string(),
+            data: Some(data),
+            ttl: None,
+            patch: None,
+            version: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn failure_restores_or_deletes_every_written_token() {
+        let server = MockServer::start().await;
+        server.put(OWN, json!({"count": 1}));
+        let client = server.client(OWN);
+
+        let operations = vec![
+            store(OWN, json!({"count": 2})),
+            store(OTHER, json!({"count": 3})),
+            store("word-word-word-word-fail", json!({"fail": true})),
+        ];
+        let err = client.batch_with_mode(operations, BatchMode::Atomic).await.unwrap_err();
+        assert!(matches!(err, Error::BatchRolledBack { ref not_undone, .. } if not_undone.is_empty()));
+        assert_eq!(server.stored(OWN), Some(json!({"count": 1})));
+        assert_eq!(server.stored(OTHER), None);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn rollback_restores_values_as_stored() {
+        use crate::encryption::{FieldEncryption, FieldKey};
+
+        let server = MockServer::start().await;
+        let encrypted = |token| {
+            let encryption = FieldEncryption::new(&FieldKey::from_bytes([7; 32]), ["secret"]);
+            server.client(token).with_field_encryption(encryption)
+        };
+        encrypted(OWN).store(&json!({"secret": "own"}), None).await.unwrap();
+        encrypted(OTHER).store(&json!({"secret": "other"}), None).await.unwrap();
+        let (own, other) = (server.stored(OWN).unwrap(), server.stored(OTHER).unwrap());
+        assert!(!other.to_string().contains("\"other\""));
+
+        let operations = vec![
+            store(OWN, json!({"secret": "changed"})),
+            store(OTHER, json!({"secret": "changed"})),
+            store("word-word-word-word-fail", json!({"fail": true})),
+        ];
+        let client = encrypted(OWN);
+        let err = client.batch_with_mode(operations, BatchMode::Atomic).await.unwrap_err();
+        assert!(matches!(err, Error::BatchRolledBack { .. }));
+        assert_eq!(server.stored(OWN), Some(own));
+        assert_eq!(server.stored(OTHER), Some(other));
+        assert_eq!(encrypted(OTHER).retrieve().await.unwrap().data, json!({"secret": "other"}));
+    }
+}
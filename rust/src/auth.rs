@@ -0,0 +1,60 @@
+//! How the token is sent
+//!
+//! The hosted API reads the token from `X-KV-Token`. Deployments behind a
+//! gateway that normalizes authentication can send it another way instead:
+//!
+//! ```
+//! use keyvalue_client::{AuthScheme, Client};
+//!
+//! # fn main() -> Result<(), keyvalue_client::Error> {
+//! let client = Client::builder()
+//!     .base_url("https://kv.internal")
+//!     .token("word-word-word-word-word")
+//!     .auth_scheme(AuthScheme::Bearer)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Error;
+use reqwest::header::{HeaderName, AUTHORIZATION};
+use reqwest::RequestBuilder;
+
+const KV_TOKEN_HEADER: &str = "X-KV-Token";
+
+/// Header carrying the token on single-document requests
+///
+/// Batch operations carry their tokens in the request body and are not
+/// affected.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AuthScheme {
+    /// `X-KV-Token: <token>`
+    #[default]
+    KvToken,
+    /// `Authorization: Bearer <token>`
+    Bearer,
+    /// `<name>: <prefix><token>`
+    Header { name: HeaderName, prefix: String },
+}
+
+impl AuthScheme {
+    /// Custom header, with `prefix` prepended to the token (may be empty)
+    pub fn header(name: &str, prefix: impl Into<String>) -> Result<Self, Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| Error::Validation(format!("Invalid header name {:?}: {}", name, e)))?;
+        Ok(AuthScheme::Header {
+            name,
+            prefix: prefix.into(),
+        })
+    }
+
+    pub(crate) fn apply(&self, request: RequestBuilder, token: &str) -> RequestBuilder {
+        match self {
+            AuthScheme::KvToken => request.header(KV_TOKEN_HEADER, token),
+            AuthScheme::Bearer => request.header(AUTHORIZATION, format!("Bearer {}", token)),
+            AuthScheme::Header { name, prefix } => {
+                request.header(name, format!("{}{}", prefix, token))
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Migrating existing time series into history
+//!
+//! The API records history at write time and has no timestamped insert, so
+//! [`Client::backfill`] replays points as sequential stores, oldest first, at
+//! a fixed pace. The original timestamp is kept inside each object payload
+//! under [`BackfillOptions::timestamp_field`]. Rate limit responses are
+//! retried after the server's `Retry-After` delay, unless the client's
+//! [retry policy](crate::policy) already retried them.
+//!
+//! ```no_run
+//! use chrono::{Duration as ChronoDuration, Utc};
+//! use keyvalue_client::{BackfillOptions, Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let start = Utc::now() - ChronoDuration::days(1);
+//! let readings = (0..24).map(|hour| {
+//!     let at = start + ChronoDuration::hours(hour);
+//!     (at, serde_json::json!({"temperature": 20.0 + hour as f64 / 10.0}))
+//! });
+//!
+//! let options = BackfillOptions {
+//!     interval: Duration::from_millis(250),
+//!     ..Default::default()
+//! };
+//! let report = client.backfill(readings, &options).await?;
+//! println!("Wrote {} points, now at version {:?}", report.written, report.last_version);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error, RetryPolicy};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Retries of a rate limited store, on clients whose own policy does not retry
+const RATE_LIMIT_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    initial_backoff: Duration::from_millis(500),
+    max_backoff: Duration::from_secs(60),
+};
+
+/// Pacing and timestamp handling for [`Client::backfill`]
+#[derive(Debug, Clone)]
+pub struct BackfillOptions {
+    /// Pause between consecutive stores
+    pub interval: Duration,
+    /// Field of object payloads that receives the original timestamp, if any
+    pub timestamp_field: Option<String>,
+    /// TTL applied to every store
+    pub ttl: Option<i32>,
+}
+
+impl Default for BackfillOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timestamp_field: Some("timestamp".to_string()),
+            ttl: None,
+        }
+    }
+}
+
+/// Outcome of a backfill, or of its points stored before an error
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillReport {
+    /// Points stored
+    pub written: usize,
+    /// Version after the last store
+    pub last_version: Option<i32>,
+    /// Timestamp of the last point stored
+    pub last_at: Option<DateTime<Utc>>,
+}
+
+impl Client {
+    /// Store historical points in order, one every `options.interval`
+    ///
+    /// Points must arrive oldest first. Any error, including
+    /// [`Error::Validation`] for an out-of-order point, which is not written,
+    /// stops the backfill with [`Error::BackfillStopped`]. It carries the
+    /// report of the points stored before, so a rerun can skip everything up
+    /// to its `last_at`.
+    pub async fn backfill(
+        &self,
+        events: impl IntoIterator<Item = (DateTime<Utc>, Value)>,
+        options: &BackfillOptions,
+    ) -> Result<BackfillReport, Error> {
+        let mut report = BackfillReport::default();
+        match self.backfill_into(events, options, &mut report).await {
+            Ok(()) => Ok(report),
+            Err(e) => Err(Error::BackfillStopped {
+                report,
+                source: Box::new(e),
+            }),
+        }
+    }
+
+    async fn backfill_into(
+        &self,
+        events: impl IntoIterator<Item = (DateTime<Utc>, Value)>,
+        options: &BackfillOptions,
+        report: &mut BackfillReport,
+    ) -> Result<(), Error> {
+        for (at, mut data) in events {
+            if report.last_at.is_some_and(|last| at < last) {
+                return Err(Error::Validation(format!(
+                    "Backfill points must be in order: {} is before {}",
+                    at.to_rfc3339(),
+                    report.last_at.map(|last| last.to_rfc3339()).unwrap_or_default()
+                )));
+            }
+            if let (Some(field), Value::Object(object)) = (&options.timestamp_field, &mut data) {
+                object.insert(field.clone(), Value::String(at.to_rfc3339()));
+            }
+            if report.written > 0 {
+                tokio::time::sleep(options.interval).await;
+            }
+
+            let mut attempt = 0;
+            let resp = loop {
+                attempt += 1;
+                let result = self.store(&data, options.ttl).await;
+                match result.as_ref().err().and_then(|e| self.rate_limit_delay(&RATE_LIMIT_RETRY, attempt, e)) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => break result?,
+                }
+            };
+
+            report.written += 1;
+            report.last_version = Some(resp.version);
+            report.last_at = Some(at);
+        }
+        Ok(())
+    }
+}
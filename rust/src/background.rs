@@ -0,0 +1,221 @@
+//! Background sync task with graceful shutdown
+//!
+//! [`Client::sync`] collects the loops a long-running process usually needs —
+//! watchers, flushes of the offline write queue and writer lease renewals —
+//! and runs them on one task. [`SyncHandle::shutdown`] stops the loops, sends
+//! pending writes and releases the writer lease. Errors inside the loops are
+//! logged with `tracing` and the loops carry on.
+//!
+//! ```no_run
+//! use keyvalue_client::{AvailabilityPolicy, Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word")
+//!     .with_writer_id("worker-1")
+//!     .with_availability_policy(AvailabilityPolicy::default());
+//!
+//! let sync = client
+//!     .sync()
+//!     .watch(Duration::from_secs(10), |resp| println!("Version {}", resp.version))
+//!     .flush_queued_every(Duration::from_secs(30))
+//!     .renew_writer(Duration::from_secs(60))
+//!     .spawn();
+//!
+//! tokio::signal::ctrl_c().await?;
+//! sync.shutdown().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BufferedWriter, Client, Error, RetrieveResponse};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+type ChangeHandler = Box<dyn FnMut(RetrieveResponse) + Send>;
+
+struct Watcher {
+    client: Client,
+    interval: Duration,
+    on_change: ChangeHandler,
+}
+
+/// Loops to run on a [`SyncHandle`], created by [`Client::sync`]
+pub struct SyncBuilder {
+    client: Client,
+    watchers: Vec<Watcher>,
+    flush_every: Option<Duration>,
+    lease: Option<Duration>,
+    writers: Vec<BufferedWriter>,
+}
+
+impl SyncBuilder {
+    /// Call `on_change` with the document each time it changes, polling every `interval`
+    pub fn watch(
+        self,
+        interval: Duration,
+        on_change: impl FnMut(RetrieveResponse) + Send + 'static,
+    ) -> Self {
+        let client = self.client.clone();
+        self.watch_client(client, interval, on_change)
+    }
+
+    /// Like [`SyncBuilder::watch`], for another client such as one with a different token
+    pub fn watch_client(
+        mut self,
+        client: Client,
+        interval: Duration,
+        on_change: impl FnMut(RetrieveResponse) + Send + 'static,
+    ) -> Self {
+        self.watchers.push(Watcher {
+            client,
+            interval,
+            on_change: Box::new(on_change),
+        });
+        self
+    }
+
+    /// Send writes queued offline every `interval`
+    pub fn flush_queued_every(mut self, interval: Duration) -> Self {
+        self.flush_every = Some(interval);
+        self
+    }
+
+    /// Keep writer-ship claimed for `duration`, renewing at half of it
+    pub fn renew_writer(mut self, duration: Duration) -> Self {
+        self.lease = Some(duration);
+        self
+    }
+
+    /// Flush `writer` on shutdown
+    pub fn buffered_writer(mut self, writer: BufferedWriter) -> Self {
+        self.writers.push(writer);
+        self
+    }
+
+    /// Start the loops on a background task
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn spawn(self) -> SyncHandle {
+        let (stop, stopped) = watch::channel(false);
+        let task = tokio::spawn(run(self, stopped));
+        SyncHandle { stop, task }
+    }
+}
+
+/// Running background sync task
+///
+/// Dropping the handle shuts the task down like [`SyncHandle::shutdown`],
+/// without waiting for it.
+pub struct SyncHandle {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<Result<(), Error>>,
+}
+
+impl SyncHandle {
+    /// Stop the loops, send pending writes and release the writer lease
+    ///
+    /// Returns the first error from the final flushes or the release.
+    pub async fn shutdown(self) -> Result<(), Error> {
+        let _ = self.stop.send(true);
+        self.task.await.unwrap_or_else(|e| {
+            Err(Error::Validation(format!("Sync task failed: {}", e)))
+        })
+    }
+}
+
+impl Client {
+    /// Configure a background task running watchers, queue flushes and lease renewals
+    pub fn sync(&self) -> SyncBuilder {
+        SyncBuilder {
+            client: self.clone(),
+            watchers: vec![],
+            flush_every: None,
+            lease: None,
+            writers: vec![],
+        }
+    }
+}
+
+enum Event {
+    Changed(usize, Result<RetrieveResponse, Error>),
+    Flush,
+    Renew,
+}
+
+async fn run(builder: SyncBuilder, mut stopped: watch::Receiver<bool>) -> Result<(), Error> {
+    let SyncBuilder {
+        client,
+        watchers,
+        flush_every,
+        lease,
+        writers,
+    } = builder;
+    let (clients, mut handlers): (Vec<_>, Vec<_>) = watchers
+        .into_iter()
+        .map(|watcher| ((watcher.client, watcher.interval), watcher.on_change))
+        .unzip();
+
+    let mut streams: Vec<BoxStream<'_, Event>> = clients
+        .iter()
+        .enumerate()
+        .map(|(i, (client, interval))| {
+            client.watch(*interval).map(move |change| Event::Changed(i, change)).boxed()
+        })
+        .collect();
+    if let Some(interval) = flush_every {
+        streams.push(ticks(interval, || Event::Flush));
+    }
+    if let Some(duration) = lease {
+        if let Err(e) = client.claim_writer(duration).await {
+            tracing::warn!(error = %e, "failed to claim writer lease");
+        }
+        streams.push(ticks((duration / 2).max(Duration::from_millis(1)), || Event::Renew));
+    }
+    let mut events = stream::select_all(streams);
+
+    loop {
+        let event = tokio::select! {
+            _ = stopped.changed() => break,
+            Some(event) = events.next() => event,
+        };
+        match event {
+            Event::Changed(i, Ok(resp)) => (handlers[i])(resp),
+            Event::Changed(_, Err(e)) => tracing::warn!(error = %e, "watch failed"),
+            Event::Flush => {
+                if let Err(e) = client.flush_queued().await {
+                    tracing::warn!(error = %e, "failed to flush queued writes");
+                }
+            }
+            Event::Renew => {
+                if let Err(e) = client.claim_writer(lease.unwrap_or_default()).await {
+                    tracing::warn!(error = %e, "failed to renew writer lease");
+                }
+            }
+        }
+    }
+    drop(events);
+
+    let mut result = Ok(());
+    for writer in &writers {
+        result = result.and(writer.flush().await);
+    }
+    result = result.and(client.flush_queued().await.map(drop));
+    if lease.is_some() {
+        result = result.and(client.release_writer().await);
+    }
+    result
+}
+
+/// Stream yielding `event()` every `interval`, starting after one interval
+fn ticks(interval: Duration, event: fn() -> Event) -> BoxStream<'static, Event> {
+    let start = tokio::time::Instant::now() + interval;
+    let ticker = tokio::time::interval_at(start, interval);
+    stream::unfold(ticker, move |mut ticker| async move {
+        ticker.tick().await;
+        Some((event(), ticker))
+    })
+    .boxed()
+}
@@ -0,0 +1,225 @@
+//! Retrying only the failed operations of a batch
+//!
+//! Batch operations succeed or fail one by one. [`BatchResponse::failed_operations`]
+//! picks out the failures, and [`Client::retry_failed`] sends them again with
+//! the backoff of a [`RetryPolicy`] until they succeed or the attempts run
+//! out. Results keep the order of the original operations, so
+//! `results[i]` always answers `operations[i]`.
+//!
+//! Every failure is retried, including ones that will fail again, such as a
+//! version conflict. If a retry request fails as a whole, retrying stops
+//! there: the response merges what earlier retries achieved, and the
+//! operations left count as failed.
+//!
+//! ```no_run
+//! use keyvalue_client::{BatchOperation, Client, Error, RetryPolicy};
+//!
+//! # async fn run(operations: Vec<BatchOperation>) -> Result<(), Error> {
+//! let client = Client::new_without_token();
+//! let resp = client.batch(operations).await?;
+//! let resp = client.retry_failed(&resp, &RetryPolicy::default()).await?;
+//! for operation in resp.failed_operations() {
+//!     eprintln!("{} still failing", operation.action);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BatchOperation, BatchResponse, BatchResult, BatchSummary, Client, Error, RetryPolicy};
+
+impl BatchResponse {
+    /// The operations that failed, in request order
+    pub fn failed_operations(&self) -> Vec<&BatchOperation> {
+        self.failed_indices().map(|i| &self.operations[i]).collect()
+    }
+
+    /// Indices of failed operations; operations without a result count as failed
+    fn failed_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.operations.len()).filter(|i| !self.results.get(*i).is_some_and(|result| result.success))
+    }
+}
+
+impl Client {
+    /// Re-execute the failed operations of `response` with backoff, merging the results in order
+    pub async fn retry_failed(&self, response: &BatchResponse, policy: &RetryPolicy) -> Result<BatchResponse, Error> {
+        let mut results: Vec<Option<BatchResult>> = response.results.iter().map(|result| Some(result.clone())).collect();
+        results.resize_with(response.operations.len(), || None);
+        let mut failed: Vec<usize> = response.failed_indices().collect();
+        let mut meta = response.meta.clone();
+
+        for attempt in 1..policy.max_attempts {
+            if failed.is_empty() {
+                break;
+            }
+            tokio::time::sleep(policy.delay(attempt)).await;
+            let operations = failed.iter().map(|i| response.operations[*i].clone()).collect();
+            let retried = match self.batch(operations).await {
+                Ok(retried) => retried,
+                Err(e) => {
+                    tracing::warn!(error = %e, failed = failed.len(), "stopped retrying failed batch operations");
+                    break;
+                }
+            };
+            meta = retried.meta;
+
+            let mut still_failed = vec![];
+            for (n, i) in failed.into_iter().enumerate() {
+                match retried.results.get(n) {
+                    Some(result) => {
+                        if !result.success {
+                            still_failed.push(i);
+                        }
+                        results[i] = Some(result.clone());
+                    }
+                    None => still_failed.push(i),
+                }
+            }
+            failed = still_failed;
+        }
+
+        let results: Vec<BatchResult> = results
+            .into_iter()
+            .zip(&response.operations)
+            .map(|(result, operation)| {
+                result.unwrap_or_else(|| BatchResult {
+                    success: false,
+                    token: operation.token.clone(),
+                    action: operation.action.clone(),
+                    data: None,
+                    version: None,
+                    error: Some("No result returned".to_string()),
+                })
+            })
+            .collect();
+        Ok(BatchResponse {
+            success: results.iter().all(|result| result.success),
+            summary: summarize(&results),
+            results,
+            operations: response.operations.clone(),
+            meta,
+        })
+    }
+}
+
+fn summarize(results: &[BatchResult]) -> BatchSummary {
+    let succeeded = results.iter().filter(|result| result.success).count();
+    BatchSummary::from_counts(results.len(), succeeded)
+}
+
+impl BatchSummary {
+    /// Summary of `total` operations of which `succeeded` succeeded
+    pub(crate) fn from_counts(total: usize, succeeded: usize) -> Self {
+        let (total, succeeded) = (total as i32, succeeded as i32);
+        Self {
+            total,
+            succeeded,
+            failed: total - succeeded,
+            success_rate: format!("{:.0}%", f64::from(succeeded) * 100.0 / f64::from(total.max(1))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::ResponseMeta;
+    use serde_json::json;
+    use std::time::Duration;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    };
+
+    fn store(token: &str, fail: bool) -> BatchOperation {
+        BatchOperation {
+            action: "store".to_string(),
+            token: token.to_string(),
+            data: Some(json!({"fail": fail})),
+            ttl: None,
+            patch: None,
+            version: None,
+        }
+    }
+
+    /// Response to `operations` in which only the first succeeded
+    fn first_succeeded(operations: Vec<BatchOperation>) -> BatchResponse {
+        let results: Vec<BatchResult> = operations
+            .iter()
+            .enumerate()
+            .map(|(i, operation)| BatchResult {
+                success: i == 0,
+                token: operation.token.clone(),
+                action: operation.action.clone(),
+                data: None,
+                version: None,
+                error: (i > 0).then(|| "Unavailable".to_string()),
+            })
+            .collect();
+        BatchResponse {
+            success: true,
+            summary: summarize(&results),
+            results,
+            operations,
+            meta: ResponseMeta::default(),
+        }
+    }
+
+    #[test]
+    fn operations_without_results_count_as_failed() {
+        let mut resp = first_succeeded(vec![store("a", false), store("b", false), store("c", false)]);
+        resp.results.truncate(2);
+        let failed: Vec<&str> = resp.failed_operations().iter().map(|operation| operation.token.as_str()).collect();
+        assert_eq!(failed, ["b", "c"]);
+    }
+
+    #[test]
+    fn summary_rounds_the_success_rate() {
+        let summary = BatchSummary::from_counts(3, 2);
+        assert_eq!((summary.total, summary.succeeded, summary.failed), (3, 2, 1));
+        assert_eq!(summary.success_rate, "67%");
+        assert_eq!(BatchSummary::from_counts(0, 0).success_rate, "0%");
+    }
+
+    #[tokio::test]
+    async fn retries_merge_into_the_original_order() {
+        let server = MockServer::start().await;
+        let client = server.client("word-word-word-word-word");
+        let resp = first_succeeded(vec![store("a", false), store("b", false), store("c", true)]);
+
+        let merged = client.retry_failed(&resp, &POLICY).await.unwrap();
+        let outcomes: Vec<(&str, bool)> =
+            merged.results.iter().map(|result| (result.token.as_str(), result.success)).collect();
+        assert_eq!(outcomes, [("a", true), ("b", true), ("c", false)]);
+        assert!(!merged.success);
+        assert_eq!(merged.summary.succeeded, 2);
+        assert!(server.stored("b").is_some());
+    }
+
+    #[tokio::test]
+    async fn success_reflects_the_merged_results() {
+        let server = MockServer::start().await;
+        let client = server.client("word-word-word-word-word");
+        let mut resp = first_succeeded(vec![store("a", false), store("b", false)]);
+        resp.success = false;
+
+        let merged = client.retry_failed(&resp, &POLICY).await.unwrap();
+        assert!(merged.success);
+        assert!(merged.failed_operations().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_retry_request_keeps_earlier_results() {
+        let server = MockServer::start().await;
+        let client = server.client("word-word-word-word-word");
+        server.drop_batches_from(2);
+        let resp = first_succeeded(vec![store("a", false), store("b", false), store("c", true)]);
+
+        let merged = client.retry_failed(&resp, &POLICY).await.unwrap();
+        let outcomes: Vec<bool> = merged.results.iter().map(|result| result.success).collect();
+        assert_eq!(outcomes, [true, true, false]);
+        assert_eq!(merged.failed_operations().len(), 1);
+    }
+}
@@ -0,0 +1,480 @@
+//! `kv` command-line client (feature `cli`)
+//!
+//! The token is read from `--token`, then `KV_TOKEN`, then the profile named
+//! by `--profile` or `KV_PROFILE`, then the default profile of the config
+//! file (`$KV_CONFIG`, or `~/.config/keyvalue/config`). The base URL comes
+//! from `--url`, `KV_URL` or the same profile. TTLs accept `90s`, `2h`, `7d` and so on.
+//!
+//! Profiles in that file are managed with `kv profile`, through the
+//! library's [`Profiles`].
+
+use chrono::Utc;
+use futures_util::StreamExt;
+use keyvalue_client::config::{Profile, DEFAULT_PROFILE};
+use keyvalue_client::profiles::Profiles;
+use keyvalue_client::redact::truncate;
+use keyvalue_client::{
+    get_path, BatchOperation, ClassifiedType, Client, Error, HistoryOptions, PatchOperations, Ttl,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::process::ExitCode;
+use std::time::Duration;
+
+mod scaffold;
+
+const USAGE: &str = "\
+Usage: kv [--token TOKEN] [--url URL] [--profile NAME] [--pretty] <command> [args]
+
+Commands:
+  generate [--turnstile TOKEN]      Generate a new token
+  store [JSON|-] [--ttl TTL]        Store JSON (from stdin if omitted or -)
+  get [PATH]                        Retrieve data, or the value at a dot path
+  delete                            Delete data
+  patch [--version N] [--set PATH=JSON]... [--remove PATH]... [--ttl TTL]
+                                    Apply a partial update (current version if omitted)
+  history [--limit N] [--before SEQ] [--since TIME] [--type TYPE]
+                                    Query time-series history
+  batch [JSON|-]                    Run a JSON array of batch operations
+  watch [--interval SECS]           Print the data each time it changes
+  status                            Summarize the data, expiry and recent activity
+  scaffold <TEMPLATE> [DIR]         Create a starter project (dashboard, reporter, config)
+  profile list                      List saved profiles
+  profile create NAME [--timeout T] Save --token and --url as profile NAME
+  profile delete NAME               Delete profile NAME
+
+TTLs are seconds or durations such as 90s, 15m, 2h, 7d or 1h30m.
+Environment: KV_TOKEN, KV_URL, KV_PROFILE, KV_CONFIG";
+
+/// Invalid command line
+struct Usage(String);
+
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    pretty: bool,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, Usage> {
+        let mut parsed = Args {
+            positional: vec![],
+            options: vec![],
+            pretty: false,
+        };
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            if arg == "--pretty" {
+                parsed.pretty = true;
+            } else if arg == "-h" || arg == "--help" {
+                return Err(Usage(String::new()));
+            } else if let Some(name) = arg.strip_prefix("--") {
+                let (name, value) = match name.split_once('=') {
+                    Some((name, value)) => (name.to_string(), value.to_string()),
+                    None => {
+                        let value = args
+                            .next()
+                            .ok_or_else(|| Usage(format!("--{} needs a value", name)))?;
+                        (name.to_string(), value)
+                    }
+                };
+                parsed.options.push((name, value));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Last value given for an option
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Every value given for a repeatable option
+    fn options<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.options.iter().filter(move |(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn parsed<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, Usage> {
+        self.option(name)
+            .map(|value| {
+                value.parse().map_err(|_| Usage(format!("Invalid value for --{}: {}", name, value)))
+            })
+            .transpose()
+    }
+
+    fn ttl(&self) -> Result<Option<Ttl>, Usage> {
+        self.option("ttl")
+            .map(|value| Ttl::parse(value).map_err(|e| Usage(e.to_string())))
+            .transpose()
+    }
+
+    fn check_options(&self, allowed: &[&str]) -> Result<(), Usage> {
+        match self.options.iter().find(|(name, _)| !allowed.contains(&name.as_str())) {
+            Some((name, _)) => Err(Usage(format!("Unknown option --{}", name))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) if !args.positional.is_empty() => args,
+        Ok(_) => return usage_error(""),
+        Err(Usage(message)) => return usage_error(&message),
+    };
+
+    match run(&args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(Usage(message))) => usage_error(&message),
+        Err(Failure::Client(e)) => {
+            eprintln!("kv: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    if !message.is_empty() {
+        eprintln!("kv: {}\n", message);
+    }
+    eprintln!("{}", USAGE);
+    ExitCode::from(2)
+}
+
+enum Failure {
+    Usage(Usage),
+    Client(Error),
+}
+
+impl From<Usage> for Failure {
+    fn from(usage: Usage) -> Self {
+        Failure::Usage(usage)
+    }
+}
+
+impl<E: Into<Error>> From<E> for Failure {
+    fn from(e: E) -> Self {
+        Failure::Client(e.into())
+    }
+}
+
+async fn run(args: &Args) -> Result<(), Failure> {
+    if args.positional[0] == "profile" {
+        return manage_profiles(args);
+    }
+    let profile = selected_profile(args)?;
+    let token = args
+        .option("token")
+        .map(str::to_string)
+        .or_else(|| std::env::var("KV_TOKEN").ok())
+        .or(profile.token);
+    let url = args
+        .option("url")
+        .map(str::to_string)
+        .or_else(|| std::env::var("KV_URL").ok())
+        .or(profile.base_url);
+
+    let mut builder = Client::builder();
+    if let Some(token) = token {
+        builder = builder.token(token);
+    }
+    if let Some(url) = url {
+        builder = builder.base_url(url.trim_end_matches('/'));
+    }
+    if let Some(timeout) = profile.timeout {
+        builder = builder.timeout(timeout);
+    }
+    let client = builder.build()?;
+
+    let command = args.positional[0].as_str();
+    let operands = &args.positional[1..];
+    let global = ["token", "url", "profile"];
+    let allow = |extra: &[&str]| args.check_options(&[&global[..], extra].concat());
+
+    match command {
+        "generate" => {
+            allow(&["turnstile"])?;
+            print(args, &client.generate(args.option("turnstile")).await?)?;
+        }
+        "store" => {
+            allow(&["ttl"])?;
+            let data = json_operand(operands)?;
+            let ttl = args.ttl()?.map(Ttl::seconds);
+            print(args, &client.store(&data, ttl).await?)?;
+        }
+        "get" => {
+            allow(&[])?;
+            let resp = client.retrieve().await?;
+            match operands.first() {
+                Some(path) => match get_path(&resp.data, path) {
+                    Some(value) => print(args, value)?,
+                    None => {
+                        return Err(Error::NotFound {
+                            message: format!("No value at {}", path),
+                        }
+                        .into())
+                    }
+                },
+                None => print(args, &resp)?,
+            }
+        }
+        "delete" => {
+            allow(&[])?;
+            print(args, &client.delete().await?)?;
+        }
+        "patch" => {
+            allow(&["version", "set", "remove", "ttl"])?;
+            let mut set = HashMap::new();
+            for assignment in args.options("set") {
+                let (path, value) = assignment
+                    .split_once('=')
+                    .ok_or_else(|| Usage(format!("--set expects PATH=JSON, got {}", assignment)))?;
+                let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+                set.insert(path.to_string(), value);
+            }
+            let remove: Vec<String> = args.options("remove").map(str::to_string).collect();
+            if set.is_empty() && remove.is_empty() {
+                return Err(Usage("patch needs at least one --set or --remove".to_string()).into());
+            }
+            let patch = PatchOperations {
+                set: (!set.is_empty()).then_some(set),
+                remove: (!remove.is_empty()).then_some(remove),
+            };
+            let version = match args.parsed("version")? {
+                Some(version) => version,
+                None => client.retrieve().await?.version,
+            };
+            let ttl = args.ttl()?.map(Ttl::seconds);
+            print(args, &client.patch(version, &patch, ttl).await?)?;
+        }
+        "history" => {
+            allow(&["limit", "before", "since", "type"])?;
+            let options = HistoryOptions {
+                limit: args.parsed("limit")?,
+                before: args.parsed("before")?,
+                since: args.option("since").map(str::to_string),
+                type_filter: args.option("type").map(ClassifiedType::from),
+                ..Default::default()
+            };
+            print(args, &client.history(&options).await?)?;
+        }
+        "batch" => {
+            allow(&[])?;
+            let operations: Vec<BatchOperation> = serde_json::from_value(json_operand(operands)?)?;
+            print(args, &client.batch(operations).await?)?;
+        }
+        "status" => {
+            allow(&[])?;
+            print_status(&client).await?;
+        }
+        "watch" => {
+            allow(&["interval"])?;
+            let seconds: f64 = args.parsed("interval")?.unwrap_or(5.0);
+            let interval = Duration::try_from_secs_f64(seconds)
+                .ok()
+                .filter(|interval| !interval.is_zero())
+                .ok_or_else(|| Usage(format!("--interval must be a positive number of seconds, got {}", seconds)))?;
+            let mut changes = pin!(client.watch(interval));
+            while let Some(change) = changes.next().await {
+                match change {
+                    Ok(resp) => print(args, &resp)?,
+                    Err(e) => eprintln!("kv: {}", e),
+                }
+            }
+        }
+        "scaffold" => {
+            allow(&[])?;
+            let names: Vec<_> = scaffold::TEMPLATES.iter().map(|t| t.name).collect();
+            let template = match operands.first() {
+                Some(name) => scaffold::find(name).ok_or_else(|| {
+                    Usage(format!("Unknown template {}; expected one of {}", name, names.join(", ")))
+                })?,
+                None => {
+                    let list: Vec<_> = scaffold::TEMPLATES
+                        .iter()
+                        .map(|t| format!("  {:<12}{}", t.name, t.description))
+                        .collect();
+                    return Err(Usage(format!("scaffold needs a template:\n{}", list.join("\n"))).into());
+                }
+            };
+            let dir = PathBuf::from(operands.get(1).map_or(template.name, String::as_str));
+            for path in scaffold::generate(template, &dir)? {
+                println!("created {}", path.display());
+            }
+            println!("\nNext: cd {} && KV_TOKEN=... cargo run", dir.display());
+        }
+        other => return Err(Usage(format!("Unknown command {}", other)).into()),
+    }
+    Ok(())
+}
+
+/// Profile named by `--profile` or `KV_PROFILE`, or the default profile, from the config file
+fn selected_profile(args: &Args) -> Result<Profile, Failure> {
+    let name = args
+        .option("profile")
+        .map(str::to_string)
+        .or_else(|| std::env::var("KV_PROFILE").ok())
+        .filter(|name| !name.is_empty());
+    let Some(name) = name else {
+        // The default profile is optional: without a readable config file, flags and env vars suffice
+        let profiles = Profiles::load().ok();
+        let profile = profiles.as_ref().and_then(|profiles| profiles.get(DEFAULT_PROFILE));
+        return Ok(profile.cloned().unwrap_or_default());
+    };
+    let profiles = Profiles::load()?;
+    match profiles.get(&name) {
+        Some(profile) => Ok(profile.clone()),
+        None => {
+            let path = profiles.path().display();
+            Err(Usage(format!("No profile named {} in {}", name, path)).into())
+        }
+    }
+}
+
+/// `kv profile list`, `create NAME` and `delete NAME`
+fn manage_profiles(args: &Args) -> Result<(), Failure> {
+    let mut profiles = Profiles::load()?;
+    let operands: Vec<&str> = args.positional[1..].iter().map(String::as_str).collect();
+    match operands[..] {
+        ["list"] | [] => {
+            args.check_options(&[])?;
+            for name in profiles.list() {
+                let url = profiles.get(name).and_then(|profile| profile.base_url.as_deref());
+                println!("{:<16}{}", name, url.unwrap_or("(default URL)"));
+            }
+        }
+        ["create", name] => {
+            args.check_options(&["token", "url", "timeout"])?;
+            if profiles.get(name).is_some() {
+                return Err(Usage(format!("Profile {} already exists; delete it first", name)).into());
+            }
+            let timeout = args
+                .option("timeout")
+                .map(|value| Ttl::parse(value).map_err(|e| Usage(e.to_string())))
+                .transpose()?;
+            let profile = Profile {
+                token: args.option("token").map(str::to_string),
+                base_url: args.option("url").map(|url| url.trim_end_matches('/').to_string()),
+                timeout: timeout.map(Ttl::as_duration),
+            };
+            profiles.create(name, profile)?;
+            println!("created profile {} in {}", name, profiles.path().display());
+        }
+        ["delete", name] => {
+            args.check_options(&[])?;
+            if !profiles.delete(name)? {
+                return Err(Usage(format!("No profile named {}", name)).into());
+            }
+            println!("deleted profile {}", name);
+        }
+        _ => return Err(Usage("profile expects list, create NAME or delete NAME".to_string()).into()),
+    }
+    Ok(())
+}
+
+/// History events used to estimate write frequency
+const STATUS_HISTORY: i32 = 50;
+/// History events listed by `kv status`
+const STATUS_RECENT: usize = 5;
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+
+/// One-screen summary of the token's data and recent activity
+async fn print_status(client: &Client) -> Result<(), Error> {
+    let now = Utc::now();
+    match client.retrieve().await {
+        Ok(resp) => {
+            println!("Version:     {}", resp.version);
+            let updated = resp.updated_at.format(TIME_FORMAT);
+            println!("Updated:     {} ({} ago)", updated, span(now - resp.updated_at));
+            println!("Size:        {} bytes", serde_json::to_vec(&resp.data)?.len());
+            match resp.expires_at {
+                Some(at) => {
+                    println!("Expires:     {} (in {})", at.format(TIME_FORMAT), span(at - now))
+                }
+                None => println!("Expires:     never"),
+            }
+            if let Some(rate_limit) = resp.meta.rate_limit {
+                if let (Some(remaining), Some(limit)) = (rate_limit.remaining, rate_limit.limit) {
+                    println!("Rate limit:  {}/{} requests left", remaining, limit);
+                }
+            }
+        }
+        Err(Error::NotFound { .. }) => println!("No data stored"),
+        Err(e) => return Err(e),
+    }
+
+    let options = HistoryOptions {
+        limit: Some(STATUS_HISTORY),
+        ..Default::default()
+    };
+    let mut events = client.history(&options).await?.events;
+    events.sort_by_key(|event| std::cmp::Reverse(event.created_at));
+    if let (Some(newest), Some(oldest)) = (events.first(), events.last()) {
+        let hours = (newest.created_at - oldest.created_at).num_seconds() as f64 / 3600.0;
+        if events.len() > 1 && hours > 0.0 {
+            let rate = (events.len() - 1) as f64 / hours;
+            println!("Writes:      {:.1}/hour over the last {} events", rate, events.len());
+        }
+    }
+
+    println!();
+    if events.is_empty() {
+        println!("No history events");
+        return Ok(());
+    }
+    println!("Recent events:");
+    for event in events.iter().take(STATUS_RECENT) {
+        let summary = match (&event.numeric_value, &event.text_value) {
+            (Some(number), _) => number.to_string(),
+            (None, Some(text)) => text.clone(),
+            (None, None) => event.payload.to_string(),
+        };
+        println!(
+            "  #{:<6} {}  {:<8} {}",
+            event.seq,
+            event.created_at.format(TIME_FORMAT),
+            event.classified_type.as_ref().map_or("-", ClassifiedType::as_str),
+            truncate(&summary, 60)
+        );
+    }
+    Ok(())
+}
+
+/// Human-readable length of a time span, `0s` if negative
+fn span(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds().clamp(0, i32::MAX.into()) as i32;
+    match Ttl::from_secs(seconds) {
+        Ok(ttl) => ttl.to_string(),
+        Err(_) => "0s".to_string(),
+    }
+}
+
+/// JSON from the first operand, or stdin if it is missing or `-`
+fn json_operand(operands: &[String]) -> Result<Value, Failure> {
+    let text = match operands.first().map(String::as_str) {
+        None | Some("-") => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            text
+        }
+        Some(text) => text.to_string(),
+    };
+    serde_json::from_str(&text).map_err(|e| Usage(format!("Invalid JSON: {}", e)).into())
+}
+
+fn print(args: &Args, value: &impl Serialize) -> Result<(), Error> {
+    let text = if args.pretty {
+        serde_json::to_string_pretty(value)?
+    } else {
+        serde_json::to_string(value)?
+    };
+    println!("{}", text);
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! `kv scaffold`: starter projects wired to the SDK
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Project template: its files as (path, contents with `{{...}}` placeholders)
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    files: &'static [(&'static str, &'static str)],
+}
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "dashboard",
+        description: "axum web page showing the live document",
+        files: &[
+            ("Cargo.toml", include_str!("templates/dashboard/Cargo.toml.tmpl")),
+            ("README.md", include_str!("templates/dashboard/README.md")),
+            ("src/main.rs", include_str!("templates/dashboard/main.rs")),
+        ],
+    },
+    Template {
+        name: "reporter",
+        description: "IoT sensor loop with buffered, rate-limit friendly writes",
+        files: &[
+            ("Cargo.toml", include_str!("templates/reporter/Cargo.toml.tmpl")),
+            ("README.md", include_str!("templates/reporter/README.md")),
+            ("src/main.rs", include_str!("templates/reporter/main.rs")),
+        ],
+    },
+    Template {
+        name: "config",
+        description: "typed remote configuration reloaded on change",
+        files: &[
+            ("Cargo.toml", include_str!("templates/config/Cargo.toml.tmpl")),
+            ("README.md", include_str!("templates/config/README.md")),
+            ("src/main.rs", include_str!("templates/config/main.rs")),
+        ],
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|template| template.name == name)
+}
+
+/// Write `template` into a new project at `dir`, returning the files created
+///
+/// The package is named after the directory, which must not exist yet or be empty.
+pub fn generate(template: &Template, dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let name = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a valid package name", dir.display()),
+            )
+        })?;
+    if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists and is not empty", dir.display()),
+        ));
+    }
+
+    let mut created = vec![];
+    for (path, contents) in template.files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = contents
+            .replace("{{name}}", name)
+            .replace("{{sdk_version}}", env!("CARGO_PKG_VERSION"));
+        fs::write(&path, contents)?;
+        created.push(path);
+    }
+    Ok(created)
+}
@@ -0,0 +1,83 @@
+//! Remote configuration service backed by a Key-Value token
+//!
+//! Loads typed settings at startup, writing the defaults if nothing is stored
+//! yet, then applies changes as they are stored from anywhere else (for
+//! example `kv patch --set log_level=debug`).
+
+use futures_util::StreamExt;
+use keyvalue_client::{Client, Error};
+use serde::{Deserialize, Serialize};
+use std::pin::pin;
+use std::time::Duration;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(30);
+const STARTUP_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct AppConfig {
+    log_level: String,
+    max_connections: u32,
+    feature_flags: Vec<String>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            log_level: "info".to_string(),
+            max_connections: 100,
+            feature_flags: vec![],
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let token = std::env::var("KV_TOKEN").map_err(|_| Error::MissingToken)?;
+    let client = Client::new(token);
+
+    let mut config = load(&client).await?;
+    println!("Loaded {:?}", config);
+
+    let mut changes = pin!(client.watch(WATCH_INTERVAL));
+    while let Some(change) = changes.next().await {
+        let resp = match change {
+            Ok(resp) => resp,
+            Err(e) => {
+                eprintln!("watch: {}", e);
+                continue;
+            }
+        };
+        match serde_json::from_value::<AppConfig>(resp.data) {
+            Ok(updated) if updated != config => {
+                println!("Version {}: {:?}", resp.version, updated);
+                config = updated;
+            }
+            Ok(_) => {}
+            // Keep running with the last good config
+            Err(e) => eprintln!("Ignoring invalid config version {}: {}", resp.version, e),
+        }
+    }
+    Ok(())
+}
+
+/// Current config, storing the defaults on first run and retrying transient failures
+async fn load(client: &Client) -> Result<AppConfig, Error> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match client.retrieve_as::<AppConfig>().await {
+            Ok(resp) => return Ok(resp.data),
+            Err(Error::NotFound { .. }) => {
+                let defaults = AppConfig::default();
+                client.store_as(&defaults, None).await?;
+                return Ok(defaults);
+            }
+            Err(e) if e.is_retryable() && attempt < STARTUP_ATTEMPTS => {
+                let delay = Duration::from_secs(1 << attempt);
+                eprintln!("{}; retrying in {}s", e, delay.as_secs());
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
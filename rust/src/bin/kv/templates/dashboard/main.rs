@@ -0,0 +1,72 @@
+//! Live dashboard for a Key-Value token
+//!
+//! A background task watches the document and keeps the latest version in
+//! memory, so page loads never wait on the API and keep working through a
+//! short outage.
+
+use axum::extract::State;
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Json, Router};
+use futures_util::StreamExt;
+use keyvalue_client::Client;
+use serde_json::Value;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Default)]
+struct Latest(Arc<RwLock<Value>>);
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let token = std::env::var("KV_TOKEN").map_err(|_| "Set KV_TOKEN to the token to display")?;
+    let client = Client::new(token);
+    let latest = Latest::default();
+
+    let cache = latest.clone();
+    tokio::spawn(async move {
+        // Polls with If-None-Match, so unchanged data costs no payload
+        let mut changes = pin!(client.watch(POLL_INTERVAL));
+        while let Some(change) = changes.next().await {
+            match change {
+                Ok(resp) => *cache.0.write().await = resp.data,
+                // Keep serving the last value; the next poll retries
+                Err(e) => eprintln!("watch: {}", e),
+            }
+        }
+    });
+
+    let app = Router::new()
+        .route("/", get(page))
+        .route("/api/data", get(data))
+        .with_state(latest);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    println!("Dashboard on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn data(State(latest): State<Latest>) -> Json<Value> {
+    Json(latest.0.read().await.clone())
+}
+
+async fn page() -> Html<&'static str> {
+    Html(
+        r#"<!doctype html>
+<title>{{name}}</title>
+<pre id="data">Loading...</pre>
+<script>
+async function refresh() {
+  const resp = await fetch("/api/data");
+  document.getElementById("data").textContent = JSON.stringify(await resp.json(), null, 2);
+}
+refresh();
+setInterval(refresh, 5000);
+</script>"#,
+    )
+}
@@ -0,0 +1,49 @@
+//! IoT reporter pushing sensor readings to a Key-Value token
+//!
+//! The sensor is sampled at 10 Hz, but a buffered writer only sends the
+//! latest readings every ten seconds, staying well inside the rate limit.
+//! Ctrl-C flushes what is left before exiting.
+
+use keyvalue_client::{BufferedWriterOptions, Client, Error};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let token = std::env::var("KV_TOKEN").map_err(|_| Error::MissingToken)?;
+    let client = Client::new(token);
+    let writer = client.buffered_writer(BufferedWriterOptions {
+        interval: REPORT_INTERVAL,
+        ..Default::default()
+    });
+
+    let started = Instant::now();
+    let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let (temperature, humidity) = read_sensor(started.elapsed());
+                writer.set("sensor.temperature", temperature.into()).await?;
+                writer.set("sensor.humidity", humidity.into()).await?;
+                writer.set("sensor.reported_at", unix_time().into()).await?;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    writer.flush().await?;
+    println!("Flushed final readings");
+    Ok(())
+}
+
+/// Simulated readings; replace with your hardware driver
+fn read_sensor(elapsed: Duration) -> (f64, f64) {
+    let t = elapsed.as_secs_f64();
+    (21.0 + (t / 60.0).sin() * 2.0, 45.0 + (t / 90.0).cos() * 5.0)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,239 @@
+//! Coalescing writes from high-frequency producers
+//!
+//! A [`BufferedWriter`] accepts field updates as fast as they come, keeps only
+//! the latest value per field, and sends them as one patch every interval or
+//! once enough distinct fields are waiting. A sensor loop pushing at 10 Hz
+//! then costs one request per interval instead of ten per second.
+//!
+//! Updates pass through a bounded queue, so `set` waits when the background
+//! task falls behind. Failed flushes that may succeed later, such as rate
+//! limits, keep their fields for the next interval; newer values replace them.
+//! Other failures drop the fields. [`BufferedWriter::flush`] returns the
+//! error, while failures of the background flushes are logged with
+//! `tracing`.
+//!
+//! ```no_run
+//! use keyvalue_client::{BufferedWriterOptions, Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let writer = client.buffered_writer(BufferedWriterOptions {
+//!     interval: Duration::from_secs(5),
+//!     ..Default::default()
+//! });
+//!
+//! for reading in 0..50 {
+//!     writer.set("sensor.temperature", (20.0 + reading as f64 / 10.0).into()).await?;
+//!     tokio::time::sleep(Duration::from_millis(100)).await;
+//! }
+//! writer.flush().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error, PatchOperations, RetryPolicy};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// Retries of a flush after version conflicts with other writers
+const CONFLICT_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    initial_backoff: Duration::from_millis(20),
+    max_backoff: Duration::from_secs(1),
+};
+
+/// Pacing and limits for a [`BufferedWriter`]
+#[derive(Debug, Clone)]
+pub struct BufferedWriterOptions {
+    /// Time between flushes
+    pub interval: Duration,
+    /// Distinct fields waiting that trigger an early flush
+    pub max_pending: usize,
+    /// Updates queued for the background task before `set` waits
+    pub capacity: usize,
+    /// TTL applied on every flush
+    pub ttl: Option<i32>,
+}
+
+impl Default for BufferedWriterOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            max_pending: 100,
+            capacity: 1024,
+            ttl: None,
+        }
+    }
+}
+
+enum Command {
+    Set(String, Value),
+    Flush(oneshot::Sender<Result<(), Error>>),
+}
+
+/// Handle for sending field updates to a background coalescing task
+///
+/// Clones share the same task, which flushes what is left and stops after
+/// the last handle is dropped.
+#[derive(Clone)]
+pub struct BufferedWriter {
+    commands: mpsc::Sender<Command>,
+}
+
+impl BufferedWriter {
+    /// Set the field at dot-separated `path`, replacing any unsent value
+    pub async fn set(&self, path: &str, value: Value) -> Result<(), Error> {
+        if path.is_empty() || path.split('.').any(str::is_empty) {
+            return Err(Error::Validation(format!("Invalid field path '{}'", path)));
+        }
+        self.send(Command::Set(path.to_string(), value)).await
+    }
+
+    /// Send everything pending now and wait for the result
+    pub async fn flush(&self) -> Result<(), Error> {
+        let (reply, result) = oneshot::channel();
+        self.send(Command::Flush(reply)).await?;
+        result.await.unwrap_or_else(|_| Err(stopped()))
+    }
+
+    async fn send(&self, command: Command) -> Result<(), Error> {
+        self.commands.send(command).await.map_err(|_| stopped())
+    }
+}
+
+impl Client {
+    /// Start a [`BufferedWriter`] that patches coalesced updates into the document
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn buffered_writer(&self, options: BufferedWriterOptions) -> BufferedWriter {
+        let (commands, incoming) = mpsc::channel(options.capacity.max(1));
+        tokio::spawn(run(self.clone(), options, incoming));
+        BufferedWriter { commands }
+    }
+}
+
+async fn run(client: Client, options: BufferedWriterOptions, mut incoming: mpsc::Receiver<Command>) {
+    let mut ticker = tokio::time::interval(options.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut pending = HashMap::new();
+    loop {
+        tokio::select! {
+            next = incoming.recv() => match next {
+                Some(Command::Set(path, value)) => {
+                    pending.insert(path, value);
+                    if pending.len() >= options.max_pending {
+                        flush_logged(&client, &options, &mut pending).await;
+                    }
+                }
+                Some(Command::Flush(reply)) => {
+                    let _ = reply.send(flush(&client, &options, &mut pending).await);
+                }
+                None => {
+                    let fields = pending.len();
+                    if let Err(e) = flush(&client, &options, &mut pending).await {
+                        tracing::warn!(error = %e, fields, "buffered writer stopped, updates dropped");
+                    }
+                    return;
+                }
+            },
+            _ = ticker.tick() => {
+                flush_logged(&client, &options, &mut pending).await;
+            }
+        }
+    }
+}
+
+/// Flush on the task's own schedule, logging failures since no caller sees them
+async fn flush_logged(
+    client: &Client,
+    options: &BufferedWriterOptions,
+    pending: &mut HashMap<String, Value>,
+) {
+    let fields = pending.len();
+    if let Err(e) = flush(client, options, pending).await {
+        match pending.is_empty() {
+            true => tracing::warn!(error = %e, fields, "buffered writer flush failed, updates dropped"),
+            false => tracing::warn!(error = %e, fields, "buffered writer flush failed, will retry"),
+        }
+    }
+}
+
+/// Patch the pending fields in, keeping them only if the failure may pass
+async fn flush(
+    client: &Client,
+    options: &BufferedWriterOptions,
+    pending: &mut HashMap<String, Value>,
+) -> Result<(), Error> {
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let result = write(client, options, pending).await;
+    match &result {
+        Err(e) if e.is_retryable() => {}
+        _ => pending.clear(),
+    }
+    result
+}
+
+async fn write(
+    client: &Client,
+    options: &BufferedWriterOptions,
+    pending: &HashMap<String, Value>,
+) -> Result<(), Error> {
+    let patch = PatchOperations {
+        set: Some(pending.clone()),
+        remove: None,
+    };
+    let mut version = client.last_version();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let current = match version {
+            Some(version) => version,
+            None => match client.retrieve_existing().await? {
+                Some(current) => current.version,
+                None => {
+                    client.store(&nest(pending), options.ttl).await?;
+                    return Ok(());
+                }
+            },
+        };
+        match client.patch(current, &patch, options.ttl).await {
+            Ok(_) => return Ok(()),
+            Err(Error::Conflict { current_version, .. }) if attempt < CONFLICT_RETRY.max_attempts => {
+                version = current_version;
+            }
+            Err(Error::NotFound { .. }) if attempt < CONFLICT_RETRY.max_attempts => version = None,
+            Err(e) => return Err(e),
+        }
+        tokio::time::sleep(CONFLICT_RETRY.delay(attempt)).await;
+    }
+}
+
+/// Document holding each dot-separated path, for the first write
+fn nest(fields: &HashMap<String, Value>) -> Value {
+    let mut root = Map::new();
+    for (path, value) in fields {
+        let mut segments: Vec<&str> = path.split('.').collect();
+        let last = segments.pop().unwrap_or_default();
+        let mut object = &mut root;
+        for segment in segments {
+            let entry = object
+                .entry(segment)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(Map::new());
+            }
+            object = entry.as_object_mut().expect("just made an object");
+        }
+        object.insert(last.to_string(), value.clone());
+    }
+    Value::Object(root)
+}
+
+fn stopped() -> Error {
+    Error::Validation("Buffered writer stopped".to_string())
+}
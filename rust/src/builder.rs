@@ -0,0 +1,301 @@
+//! Client construction and transport settings
+//!
+//! ```
+//! use keyvalue_client::{Client, Proxy};
+//!
+//! # fn main() -> Result<(), keyvalue_client::Error> {
+//! // Route all traffic through Tor
+//! let client = Client::builder()
+//!     .token("word-word-word-word-word")
+//!     .proxy(Proxy::all("socks5h://127.0.0.1:9050")?)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::endpoint::Endpoints;
+use crate::secret::Token;
+use crate::{
+    mask_token, AuthScheme, BuildError, Client, ClientPolicy, Endpoint, Error, Strictness, TlsConfig,
+    DEFAULT_BASE_URL, DEFAULT_TIMEOUT,
+};
+use reqwest::Client as HttpClient;
+#[cfg(feature = "secrecy")]
+use secrecy::SecretString;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Proxy server for HTTP, HTTPS or SOCKS5 traffic
+///
+/// Supported URL schemes are `http`, `https`, `socks5` and `socks5h` (DNS
+/// resolved by the proxy, as needed for Tor).
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    inner: reqwest::Proxy,
+}
+
+impl Proxy {
+    /// Proxy all requests
+    pub fn all(url: &str) -> Result<Self, Error> {
+        Self::parse(url, |url| reqwest::Proxy::all(url))
+    }
+
+    /// Proxy only plain `http://` requests
+    pub fn http(url: &str) -> Result<Self, Error> {
+        Self::parse(url, |url| reqwest::Proxy::http(url))
+    }
+
+    /// Proxy only `https://` requests
+    pub fn https(url: &str) -> Result<Self, Error> {
+        Self::parse(url, |url| reqwest::Proxy::https(url))
+    }
+
+    /// Authenticate to the proxy with a username and password
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Self {
+        self.inner = self.inner.basic_auth(username, password);
+        self
+    }
+
+    fn parse(
+        url: &str,
+        build: impl FnOnce(&str) -> reqwest::Result<reqwest::Proxy>,
+    ) -> Result<Self, Error> {
+        let scheme = url.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase());
+        if !matches!(scheme.as_deref(), Some("http" | "https" | "socks5" | "socks5h")) {
+            return Err(Error::Validation(format!("Unsupported proxy URL: {}", url)));
+        }
+        let inner = build(url)
+            .map_err(|e| Error::Validation(format!("Invalid proxy URL {}: {}", url, e)))?;
+        Ok(Self { inner })
+    }
+}
+
+/// Builder for [`Client`]
+#[derive(Clone)]
+pub struct ClientBuilder {
+    base_url: String,
+    endpoints: Endpoints,
+    token: Option<Token>,
+    auth: AuthScheme,
+    timeout: Duration,
+    proxies: Vec<Proxy>,
+    no_proxy: bool,
+    tls: Option<TlsConfig>,
+    http_client: Option<HttpClient>,
+    policy: ClientPolicy,
+}
+
+impl fmt::Debug for ClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientBuilder")
+            .field("base_url", &self.base_url)
+            .field("endpoints", &self.endpoints)
+            .field("token", &self.token.as_deref().map(mask_token))
+            .field("auth", &self.auth)
+            .field("timeout", &self.timeout)
+            .field("proxies", &self.proxies)
+            .field("no_proxy", &self.no_proxy)
+            .field("tls", &self.tls)
+            .field("http_client", &self.http_client)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            endpoints: Endpoints::default(),
+            token: None,
+            auth: AuthScheme::default(),
+            timeout: DEFAULT_TIMEOUT,
+            proxies: Vec::new(),
+            no_proxy: false,
+            tls: None,
+            http_client: None,
+            policy: ClientPolicy::default(),
+        }
+    }
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the base URL
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = url.into();
+        self
+    }
+
+    /// Mount every endpoint under a path prefix, e.g. `/kv` for `<base>/kv/api/store`
+    pub fn path_prefix(mut self, prefix: &str) -> Self {
+        self.endpoints.set_prefix(prefix);
+        self
+    }
+
+    /// Send requests for one endpoint to a custom path, still under the prefix
+    pub fn endpoint_path(mut self, endpoint: Endpoint, path: &str) -> Self {
+        self.endpoints.set_path(endpoint, path);
+        self
+    }
+
+    /// Set the default token
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into().into());
+        self
+    }
+
+    /// Set the default token from a [`SecretString`], zeroized when no longer used
+    #[cfg(feature = "secrecy")]
+    pub fn secret_token(mut self, token: SecretString) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Send the token in another header, e.g. `Authorization: Bearer`
+    pub fn auth_scheme(mut self, auth: AuthScheme) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Set the request timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Fall back to `urls`, in order, when the base URL is unreachable
+    ///
+    /// See [`Client::with_failover`]; replaces the failover URLs of a policy set so far.
+    pub fn failover(mut self, urls: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.policy.failover = urls.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Apply retries, rate limits, caching, compression and failover from `policy`
+    ///
+    /// A timeout in the policy replaces the one set so far.
+    pub fn policy(mut self, policy: ClientPolicy) -> Self {
+        if let Some(timeout) = policy.timeout {
+            self.timeout = timeout;
+        }
+        self.policy = policy;
+        self
+    }
+
+    /// Add a proxy; proxies are tried in the order added
+    ///
+    /// Configuring any proxy disables detection of `HTTP_PROXY`-style
+    /// environment variables.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Connect directly, ignoring configured and environment proxies
+    pub fn no_proxy(mut self) -> Self {
+        self.no_proxy = true;
+        self
+    }
+
+    /// Use custom TLS roots, client certificates or pins
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Send requests through an existing `reqwest::Client`
+    ///
+    /// The shared client keeps its own pooling, proxy, TLS and timeout
+    /// settings; [`timeout`](Self::timeout), [`proxy`](Self::proxy),
+    /// [`no_proxy`](Self::no_proxy) and [`tls`](Self::tls) are ignored.
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Build the client
+    ///
+    /// Fails if the TLS settings are rejected or the TLS backend cannot be
+    /// initialized on this platform.
+    pub fn build(self) -> Result<Client, BuildError> {
+        let http_client = self.http_client()?;
+
+        let client = Client {
+            base_url: self.base_url.into(),
+            endpoints: Arc::new(self.endpoints),
+            token: self.token,
+            auth: Arc::new(self.auth),
+            http_client,
+            middleware: Arc::new([]),
+            contract: None,
+            captcha: None,
+            schema_fingerprints: false,
+            writer_id: None,
+            journal: None,
+            store_dedup: false,
+            last_store: Arc::default(),
+            redactor: Arc::default(),
+            stats: Arc::default(),
+            versions: Arc::default(),
+            auto_version: false,
+            degradation: None,
+            notices: Arc::default(),
+            notice_handler: None,
+            tier: Arc::default(),
+            size_check: true,
+            profiler: None,
+            strictness: Strictness::default(),
+            unknown_fields: Arc::default(),
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "encryption")]
+            field_encryption: None,
+            retry: None,
+            rate_limiter: None,
+            failover: None,
+            coalesce: false,
+            flights: Arc::default(),
+            circuit: None,
+            idempotency: None,
+            checksum: None,
+        };
+        Ok(self.policy.into_client(client))
+    }
+}
+
+impl ClientBuilder {
+    fn http_client(&self) -> Result<HttpClient, BuildError> {
+        if let Some(http_client) = &self.http_client {
+            return Ok(http_client.clone());
+        }
+
+        let mut http = HttpClient::builder().timeout(self.timeout);
+        #[cfg(feature = "rustls")]
+        {
+            http = http.use_rustls_tls();
+        }
+        if self.no_proxy {
+            http = http.no_proxy();
+        } else {
+            for proxy in &self.proxies {
+                http = http.proxy(proxy.inner.clone());
+            }
+        }
+        if let Some(tls) = &self.tls {
+            http = tls.apply(http)?;
+        }
+        Ok(http.build()?)
+    }
+}
+
+impl Client {
+    /// Start building a client with custom transport settings
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+}
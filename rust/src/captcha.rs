@@ -0,0 +1,111 @@
+//! Automatic Turnstile tokens for `generate`
+//!
+//! When the API refuses to generate a token without a Turnstile challenge
+//! response, a client with a [`CaptchaProvider`] asks it for one and retries
+//! once. Providers can be async closures, or a [`CaptchaCommand`] that runs an
+//! external program (e.g. a headless browser script) and reads the token from
+//! its standard output.
+//!
+//! ```no_run
+//! use keyvalue_client::{CaptchaCommand, Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new_without_token()
+//!     .with_captcha_provider(CaptchaCommand::new("./solve-turnstile.sh").arg("key-value.co"));
+//! let resp = client.generate(None).await?;
+//! println!("{}", resp.token);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error};
+use reqwest::StatusCode;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Future returned by [`CaptchaProvider::turnstile_token`]
+pub type CaptchaFuture<'a> = Pin<Box<dyn Future<Output = Result<String, Error>> + Send + 'a>>;
+
+/// Source of Turnstile challenge responses
+pub trait CaptchaProvider: Send + Sync {
+    /// Solve a challenge and return the response token
+    fn turnstile_token(&self) -> CaptchaFuture<'_>;
+}
+
+impl<F, Fut> CaptchaProvider for F
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, Error>> + Send + 'static,
+{
+    fn turnstile_token(&self) -> CaptchaFuture<'_> {
+        Box::pin(self())
+    }
+}
+
+/// Provider that runs a program and uses its trimmed standard output as the token
+#[derive(Debug, Clone)]
+pub struct CaptchaCommand {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CaptchaCommand {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Add an argument
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl CaptchaProvider for CaptchaCommand {
+    fn turnstile_token(&self) -> CaptchaFuture<'_> {
+        Box::pin(async move {
+            let output = tokio::process::Command::new(&self.program)
+                .args(&self.args)
+                .stdin(std::process::Stdio::null())
+                .output()
+                .await?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let mut message = format!("{} exited with {}", self.program, output.status);
+                if !stderr.trim().is_empty() {
+                    message = format!("{}: {}", message, stderr.trim());
+                }
+                return Err(Error::Captcha(message));
+            }
+            let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if token.is_empty() {
+                return Err(Error::Captcha(format!("{} printed no token", self.program)));
+            }
+            Ok(token)
+        })
+    }
+}
+
+/// Whether a `generate` error means a Turnstile token is needed
+pub(crate) fn is_required(error: &Error) -> bool {
+    let (Error::Unauthorized { status, message } | Error::Api { status, message }) = error else {
+        return false;
+    };
+    let message = message.to_ascii_lowercase();
+    matches!(
+        *status,
+        StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) && (message.contains("turnstile") || message.contains("captcha"))
+}
+
+impl Client {
+    /// Solve Turnstile challenges with `provider` when `generate` requires one
+    pub fn with_captcha_provider(mut self, provider: impl CaptchaProvider + 'static) -> Self {
+        self.captcha = Some(Arc::new(provider));
+        self
+    }
+}
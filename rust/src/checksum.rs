@@ -0,0 +1,126 @@
+//! Content hashes stored with the data and checked on every read
+//!
+//! With [`Client::with_checksums`], `store` wraps each value in an envelope
+//! holding a hash of its canonical JSON (keys sorted):
+//!
+//! ```json
+//! {"_checksum": {"alg": "sha256", "hash": "9f86d0..."}, "value": {...}}
+//! ```
+//!
+//! `retrieve`, `history` and everything built on them verify and unwrap such
+//! envelopes, whether or not checksums are enabled on the reading client, and
+//! fail with [`Error::IntegrityMismatch`] when the value no longer matches
+//! its hash. An object with a `_checksum` field but anything other than
+//! `value` beside it, or, on a client with checksums enabled, a value with no
+//! envelope at all, fails with [`Error::ChecksumEnvelope`]. Patches would
+//! change the envelope rather than the value inside it, so a client with
+//! checksums enabled rejects them; replace checksummed documents with `store`
+//! instead. BLAKE3 needs the `blake3` feature.
+//!
+//! ```no_run
+//! use keyvalue_client::checksum::ChecksumAlgorithm;
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word").with_checksums(ChecksumAlgorithm::Sha256);
+//! client
+//!     .store(&serde_json::json!({"firmware": "2.1.0", "sha256": "ab12..."}), None)
+//!     .await?;
+//!
+//! match client.retrieve().await {
+//!     Err(Error::IntegrityMismatch { .. }) => eprintln!("manifest corrupted, not installing"),
+//!     result => println!("{}", result?.data["firmware"]),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dedup::canonical_json;
+use crate::{hex, Client, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+
+pub const CHECKSUM_FIELD: &str = "_checksum";
+const VALUE_FIELD: &str = "value";
+
+/// Hash function named in the envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    /// Lowercase hex hash of the canonical JSON of `value`
+    pub fn hash(self, value: &Value) -> String {
+        let canonical = canonical_json(value);
+        let digest: Vec<u8> = match self {
+            ChecksumAlgorithm::Sha256 => Sha256::digest(canonical.as_bytes()).to_vec(),
+            #[cfg(feature = "blake3")]
+            ChecksumAlgorithm::Blake3 => blake3::hash(canonical.as_bytes()).as_bytes().to_vec(),
+        };
+        hex(&digest)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checksum {
+    alg: String,
+    hash: String,
+}
+
+impl Client {
+    /// Store values inside an envelope with their `algorithm` hash
+    pub fn with_checksums(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum = Some(algorithm);
+        self
+    }
+
+    /// `data` in a checksum envelope, if enabled
+    pub(crate) fn checksum_payload<'a>(&self, data: &'a Value) -> Cow<'a, Value> {
+        let Some(algorithm) = self.checksum else {
+            return Cow::Borrowed(data);
+        };
+        Cow::Owned(serde_json::json!({
+            CHECKSUM_FIELD: {"alg": algorithm, "hash": algorithm.hash(data)},
+            VALUE_FIELD: data,
+        }))
+    }
+
+    /// Replace a checksum envelope in `data` with the value inside it, after verifying the hash
+    pub(crate) fn verify_checksum(&self, data: &mut Value) -> Result<(), Error> {
+        let Some(envelope) = data.as_object().filter(|obj| obj.contains_key(CHECKSUM_FIELD)) else {
+            return match self.checksum {
+                Some(_) => Err(Error::ChecksumEnvelope("Stored value has no checksum".to_string())),
+                None => Ok(()),
+            };
+        };
+        if let Some(field) = envelope.keys().find(|key| *key != CHECKSUM_FIELD && *key != VALUE_FIELD) {
+            return Err(Error::ChecksumEnvelope(format!(
+                "Unexpected field {:?} beside the checksum, as left by a patch",
+                field
+            )));
+        }
+        if !envelope.contains_key(VALUE_FIELD) {
+            return Err(Error::ChecksumEnvelope("Checksum without a value".to_string()));
+        }
+        let checksum = Checksum::deserialize(&envelope[CHECKSUM_FIELD])
+            .map_err(|e| Error::ChecksumEnvelope(format!("Malformed checksum: {}", e)))?;
+        let algorithm = ChecksumAlgorithm::deserialize(Value::String(checksum.alg.clone()))
+            .map_err(|_| Error::Validation(format!("Unsupported checksum algorithm {}", checksum.alg)))?;
+        let value = data[VALUE_FIELD].take();
+        let found = algorithm.hash(&value);
+        if found != checksum.hash {
+            return Err(Error::IntegrityMismatch {
+                expected: checksum.hash,
+                found,
+            });
+        }
+        *data = value;
+        Ok(())
+    }
+}
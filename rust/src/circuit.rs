@@ -0,0 +1,270 @@
+//! Failing fast while the API is down
+//!
+//! With [`Client::with_circuit_breaker`], a client that sees
+//! `failure_threshold` outages in a row (connection failures, timeouts and
+//! `5xx` responses, after retries and failover) opens its circuit: for the
+//! next `open_for`, requests fail immediately with [`Error::CircuitOpen`]
+//! instead of reaching the server. After that one request is let through as
+//! a probe. If it succeeds the circuit closes again; if it fails the circuit
+//! stays open for another `open_for`.
+//!
+//! Any response from the server other than a `5xx`, including `404` and
+//! `409`, counts as a success. Clones share the circuit. With an
+//! [`AvailabilityPolicy`](crate::AvailabilityPolicy), reads are served from
+//! the cache and writes queued while the circuit is open.
+//!
+//! ```no_run
+//! use keyvalue_client::circuit::CircuitBreakerPolicy;
+//! use keyvalue_client::{Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word").with_circuit_breaker(CircuitBreakerPolicy {
+//!     failure_threshold: 3,
+//!     open_for: Duration::from_secs(60),
+//! });
+//!
+//! match client.retrieve().await {
+//!     Err(Error::CircuitOpen { retry_in }) => println!("API down, next probe in {:?}", retry_in),
+//!     result => println!("{:?}", result?.data),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::degraded::is_outage;
+use crate::policy::duration;
+use crate::{Client, Error};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// When to open the circuit and how long to keep it open
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CircuitBreakerPolicy {
+    /// Consecutive failed requests that open the circuit
+    pub failure_threshold: u32,
+    /// Time to fail fast before letting a probe request through
+    #[serde(with = "duration")]
+    pub open_for: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_for: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Whether requests are currently let through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    /// Failing fast until the next probe
+    Open,
+    /// A probe request is in flight
+    HalfOpen,
+}
+
+#[derive(Debug)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { since: Instant },
+}
+
+/// Circuit shared by a client and its clones
+pub(crate) struct CircuitBreaker {
+    policy: CircuitBreakerPolicy,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(policy: CircuitBreakerPolicy) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Let a request through, or fail it while the circuit is open
+    fn admit(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now < until => Err(Error::CircuitOpen { retry_in: until - now }),
+            // A probe that never reported back, e.g. because it was cancelled, is replaced
+            State::HalfOpen { since } if now < since + self.policy.open_for => Err(Error::CircuitOpen {
+                retry_in: since + self.policy.open_for - now,
+            }),
+            _ => {
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+        }
+    }
+
+    /// Count the outcome of an admitted request
+    fn record<T>(&self, result: &Result<T, Error>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let failed = matches!(result, Err(e) if is_outage(e));
+        *state = match (&*state, failed) {
+            (_, false) => State::Closed { failures: 0 },
+            (State::Closed { failures }, true) if failures + 1 < self.policy.failure_threshold => {
+                State::Closed { failures: failures + 1 }
+            }
+            // Requests admitted before the circuit opened do not extend it
+            (State::Open { until }, true) => State::Open { until: *until },
+            (_, true) => {
+                if let Err(e) = result {
+                    tracing::warn!(error = %e, "opening circuit after repeated failures");
+                }
+                State::Open {
+                    until: Instant::now() + self.policy.open_for,
+                }
+            }
+        };
+    }
+
+    fn state(&self) -> CircuitState {
+        match *self.state.lock().unwrap_or_else(|e| e.into_inner()) {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+impl Client {
+    /// Fail requests fast with [`Error::CircuitOpen`] during outages
+    pub fn with_circuit_breaker(mut self, policy: CircuitBreakerPolicy) -> Self {
+        self.circuit = Some(Arc::new(CircuitBreaker::new(policy)));
+        self
+    }
+
+    /// State of the circuit breaker; always closed without one
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit.as_ref().map_or(CircuitState::Closed, |circuit| circuit.state())
+    }
+
+    /// Run `send` unless the circuit is open, counting its outcome
+    pub(crate) async fn guarded<T>(&self, send: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        let Some(circuit) = &self.circuit else {
+            return send.await;
+        };
+        circuit.admit()?;
+        let result = send.await;
+        circuit.record(&result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    fn breaker(failure_threshold: u32, open_for: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerPolicy {
+            failure_threshold,
+            open_for,
+        })
+    }
+
+    fn outage() -> Result<(), Error> {
+        Err(Error::Server {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "Unavailable".to_string(),
+        })
+    }
+
+    fn not_found() -> Result<(), Error> {
+        Err(Error::NotFound {
+            message: "Not found".to_string(),
+        })
+    }
+
+    #[test]
+    fn opens_after_consecutive_outages_only() {
+        let circuit = breaker(3, Duration::from_secs(60));
+        for result in [outage(), outage(), not_found(), outage(), outage()] {
+            circuit.admit().unwrap();
+            circuit.record(&result);
+        }
+        assert_eq!(circuit.state(), CircuitState::Closed);
+
+        circuit.record(&outage());
+        assert_eq!(circuit.state(), CircuitState::Open);
+        assert!(matches!(circuit.admit(), Err(Error::CircuitOpen { retry_in }) if retry_in <= Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn late_failures_do_not_extend_an_open_circuit() {
+        let circuit = breaker(1, Duration::from_millis(50));
+        circuit.record(&outage());
+        let State::Open { until } = *circuit.state.lock().unwrap() else {
+            panic!("circuit should be open");
+        };
+        circuit.record(&outage());
+        assert!(matches!(*circuit.state.lock().unwrap(), State::Open { until: later } if later == until));
+    }
+
+    #[test]
+    fn one_probe_closes_or_reopens_the_circuit() {
+        let circuit = breaker(1, Duration::from_millis(20));
+        circuit.record(&outage());
+        std::thread::sleep(Duration::from_millis(30));
+
+        circuit.admit().unwrap();
+        assert_eq!(circuit.state(), CircuitState::HalfOpen);
+        assert!(matches!(circuit.admit(), Err(Error::CircuitOpen { .. })));
+        circuit.record(&outage());
+        assert_eq!(circuit.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(30));
+        circuit.admit().unwrap();
+        circuit.record(&Ok(()));
+        assert_eq!(circuit.state(), CircuitState::Closed);
+        circuit.admit().unwrap();
+    }
+
+    #[test]
+    fn abandoned_probes_are_replaced() {
+        let circuit = breaker(1, Duration::from_millis(20));
+        circuit.record(&outage());
+        std::thread::sleep(Duration::from_millis(30));
+        circuit.admit().unwrap();
+        std::thread::sleep(Duration::from_millis(30));
+        circuit.admit().unwrap();
+        assert_eq!(circuit.state(), CircuitState::HalfOpen);
+    }
+
+    #[tokio::test]
+    async fn open_circuits_skip_the_request() {
+        let client = Client::new("word-word-word-word-word").with_circuit_breaker(CircuitBreakerPolicy {
+            failure_threshold: 1,
+            open_for: Duration::from_secs(60),
+        });
+        assert_eq!(client.circuit_state(), CircuitState::Closed);
+        assert!(client.guarded(async { outage() }).await.is_err());
+
+        let clone = client.clone();
+        let mut sent = false;
+        let result = clone.guarded(async { sent = true; Ok(()) }).await;
+        assert!(matches!(result, Err(Error::CircuitOpen { .. })));
+        assert!(!sent);
+        assert_eq!(client.circuit_state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn policies_read_durations_from_config() {
+        let policy: CircuitBreakerPolicy = serde_json::from_value(serde_json::json!({"open_for": "2m"})).unwrap();
+        assert_eq!(policy, CircuitBreakerPolicy { open_for: Duration::from_secs(120), ..Default::default() });
+        assert!(serde_json::from_value::<CircuitBreakerPolicy>(serde_json::json!({"threshold": 1})).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! Clean series from the API's classification of history events
+//!
+//! The API classifies each history payload, filling `classified_type`,
+//! `numeric_value` or `text_value` and a `confidence` between 0 and 1. The
+//! helpers here pick out the events worth charting or alerting on, and turn
+//! them into typed series sorted oldest first, ready for
+//! [`timeseries`](crate::timeseries), [`threshold`](crate::threshold) or
+//! [`forecast`](crate::forecast).
+//!
+//! ```
+//! use keyvalue_client::{ClassifiedType, HistoryEvent, HistoryResponse};
+//!
+//! let history: HistoryResponse = serde_json::from_value(serde_json::json!({
+//!     "success": true,
+//!     "events": [
+//!         {"seq": 3, "created_at": "2025-01-01T00:02:00Z", "expires_at": null, "classified_type": "numeric",
+//!          "numeric_value": 21.5, "text_value": null, "confidence": 0.95, "payload": 21.5},
+//!         {"seq": 2, "created_at": "2025-01-01T00:01:00Z", "expires_at": null, "classified_type": "numeric",
+//!          "numeric_value": 80.0, "text_value": null, "confidence": 0.4, "payload": "80?"},
+//!         {"seq": 1, "created_at": "2025-01-01T00:00:00Z", "expires_at": null, "classified_type": "text",
+//!          "numeric_value": null, "text_value": "door open", "confidence": 0.9, "payload": "door open"}
+//!     ],
+//!     "pagination": {"limit": 50, "before": null, "since": null, "has_more": false}
+//! }))?;
+//!
+//! let readings: Vec<_> = history
+//!     .events_with_confidence(0.8)
+//!     .filter_map(HistoryEvent::numeric_sample)
+//!     .collect();
+//! assert_eq!(readings.len(), 1);
+//! assert_eq!(history.numeric_series()[0].value, 80.0);
+//! assert_eq!(history.text_series()[0].value, "door open");
+//! assert_eq!(history.events_of_type(&ClassifiedType::Numeric).count(), 2);
+//! # Ok::<(), serde_json::Error>(())
+//! ```
+
+use crate::timeseries::{samples_from_events, Sample};
+use crate::{ClassifiedType, HistoryEvent, HistoryResponse};
+use chrono::{DateTime, Utc};
+
+/// Text value observed at a point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextSample {
+    pub at: DateTime<Utc>,
+    pub value: String,
+}
+
+impl HistoryEvent {
+    /// Whether the classification confidence is at least `min`; false if none was given
+    pub fn is_confident(&self, min: f64) -> bool {
+        self.confidence.is_some_and(|confidence| confidence >= min)
+    }
+
+    /// The event's `numeric_value` as a sample
+    pub fn numeric_sample(&self) -> Option<Sample> {
+        self.numeric_value.map(|value| Sample {
+            at: self.created_at,
+            value,
+        })
+    }
+
+    /// The event's `text_value` as a sample
+    pub fn text_sample(&self) -> Option<TextSample> {
+        self.text_value.as_ref().map(|value| TextSample {
+            at: self.created_at,
+            value: value.clone(),
+        })
+    }
+}
+
+impl HistoryResponse {
+    /// Events classified with a confidence of at least `min`, in page order
+    pub fn events_with_confidence(&self, min: f64) -> impl Iterator<Item = &HistoryEvent> + '_ {
+        self.events.iter().filter(move |event| event.is_confident(min))
+    }
+
+    /// Events classified as `typ`, in page order
+    pub fn events_of_type<'a>(&'a self, typ: &'a ClassifiedType) -> impl Iterator<Item = &'a HistoryEvent> + 'a {
+        self.events
+            .iter()
+            .filter(move |event| event.classified_type.as_ref() == Some(typ))
+    }
+
+    /// Numeric values of the page, oldest first
+    pub fn numeric_series(&self) -> Vec<Sample> {
+        samples_from_events(&self.events)
+    }
+
+    /// Text values of the page, oldest first
+    pub fn text_series(&self) -> Vec<TextSample> {
+        let mut samples: Vec<_> = self.events.iter().filter_map(HistoryEvent::text_sample).collect();
+        samples.sort_by_key(|sample| sample.at);
+        samples
+    }
+}
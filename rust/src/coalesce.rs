@@ -0,0 +1,191 @@
+//! Sharing one in-flight retrieve between concurrent callers
+//!
+//! With [`Client::with_retrieve_coalescing`], a `retrieve` issued while
+//! another one for the same token is already waiting on the API joins it
+//! instead of sending a second request, and both get the same response.
+//! Web handlers that all read the same config blob then cost one request
+//! per burst instead of one per handler. Clones share in-flight requests;
+//! what they share is the value as stored, which each caller then decrypts
+//! and unwraps with its own settings.
+//!
+//! A store, patch or delete through the client ends the sharing: retrieves
+//! started after such a write completes send a fresh request, so a task
+//! always reads its own writes. A `404` is shared like a response; if the
+//! shared request fails otherwise, each waiting caller sends its own request
+//! and sees its own error.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word").with_retrieve_coalescing(true);
+//!
+//! let (a, b) = tokio::join!(client.retrieve(), client.retrieve());
+//! assert_eq!(a?.version, b?.version);
+//! assert_eq!(client.stats().total().requests, 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error, RetrieveResponse};
+use std::collections::HashMap;
+use std::future::Future;
+use tokio::sync::watch;
+
+/// State of a shared retrieve, as seen by callers waiting on it
+#[derive(Clone)]
+pub(crate) enum Flight {
+    Pending,
+    Landed(RetrieveResponse),
+    /// Nothing is stored
+    Missing(String),
+    /// The request failed otherwise; waiters send their own
+    Failed,
+}
+
+/// Retrieves in flight, by base URL and token
+pub(crate) type Flights = HashMap<String, watch::Receiver<Flight>>;
+
+impl Client {
+    /// Share concurrent retrieves for the same token in one request
+    pub fn with_retrieve_coalescing(mut self, enabled: bool) -> Self {
+        self.coalesce = enabled;
+        self
+    }
+
+    /// Run `fetch`, or wait for the same retrieve already in flight
+    pub(crate) async fn coalesced<F>(
+        &self,
+        token: &str,
+        fetch: impl FnOnce() -> F,
+    ) -> Result<RetrieveResponse, Error>
+    where
+        F: Future<Output = Result<RetrieveResponse, Error>>,
+    {
+        if !self.coalesce {
+            return fetch().await;
+        }
+        let key = self.flight_key(token);
+        let joined = {
+            let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+            match flights.get(&key) {
+                Some(flight) => Err(flight.clone()),
+                None => {
+                    let (landed, flight) = watch::channel(Flight::Pending);
+                    flights.insert(key.clone(), flight);
+                    Ok(landed)
+                }
+            }
+        };
+
+        let landed = match joined {
+            Ok(landed) => landed,
+            Err(mut flight) => {
+                let outcome = flight
+                    .wait_for(|flight| !matches!(flight, Flight::Pending))
+                    .await
+                    .map(|flight| flight.clone());
+                return match outcome {
+                    Ok(Flight::Landed(resp)) => Ok(resp),
+                    Ok(Flight::Missing(message)) => Err(Error::NotFound { message }),
+                    // Failed, or the leading caller was cancelled
+                    _ => fetch().await,
+                };
+            }
+        };
+
+        let guard = FlightGuard {
+            client: self,
+            key,
+            flight: landed.subscribe(),
+        };
+        let result = fetch().await;
+        let _ = landed.send(match &result {
+            Ok(resp) => Flight::Landed(resp.clone()),
+            Err(Error::NotFound { message }) => Flight::Missing(message.clone()),
+            Err(_) => Flight::Failed,
+        });
+        drop(guard);
+        result
+    }
+
+    /// Stop sharing the retrieve in flight for the current token, after a write
+    pub(crate) fn forget_flight(&self) {
+        if let Some(token) = self.token.as_deref() {
+            let key = self.flight_key(token);
+            self.flights.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+        }
+    }
+
+    fn flight_key(&self, token: &str) -> String {
+        format!("{} {}", self.base_url, token)
+    }
+}
+
+/// Removes a flight from the map when its leading call ends, however it ends
+struct FlightGuard<'a> {
+    client: &'a Client,
+    key: String,
+    flight: watch::Receiver<Flight>,
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        let mut flights = self.client.flights.lock().unwrap_or_else(|e| e.into_inner());
+        // A write may have let a newer flight take this one's place
+        if flights.get(&self.key).is_some_and(|flight| flight.same_channel(&self.flight)) {
+            flights.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::MockServer;
+    use serde_json::json;
+
+    const TOKEN: &str = "word-word-word-word-word";
+
+    #[tokio::test]
+    async fn concurrent_retrieves_share_one_request() {
+        let server = MockServer::start().await;
+        server.put(TOKEN, json!({"n": 1}));
+        let client = server.client(TOKEN).with_retrieve_coalescing(true);
+
+        let (a, b) = tokio::join!(client.retrieve(), client.retrieve());
+        assert_eq!(a.unwrap().data, b.unwrap().data);
+        assert_eq!(server.requests("/api/retrieve"), 1);
+    }
+
+    #[tokio::test]
+    async fn missing_documents_are_shared_and_writes_end_sharing() {
+        let server = MockServer::start().await;
+        let client = server.client(TOKEN).with_retrieve_coalescing(true);
+
+        let (a, b) = tokio::join!(client.retrieve_opt(), client.retrieve_opt());
+        assert!(a.unwrap().is_none() && b.unwrap().is_none());
+        assert_eq!(server.requests("/api/retrieve"), 1);
+
+        client.store(&json!({"n": 2}), None).await.unwrap();
+        assert_eq!(client.retrieve().await.unwrap().data, json!({"n": 2}));
+        assert_eq!(server.requests("/api/retrieve"), 2);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn each_caller_decodes_with_its_own_settings() {
+        use crate::encryption::{FieldEncryption, FieldKey};
+
+        let server = MockServer::start().await;
+        let encryption = FieldEncryption::new(&FieldKey::from_bytes([7; 32]), ["secret"]);
+        let decrypting = server.client(TOKEN).with_retrieve_coalescing(true).with_field_encryption(encryption);
+        decrypting.store(&json!({"secret": "s"}), None).await.unwrap();
+        let mut raw = decrypting.clone();
+        raw.field_encryption = None;
+
+        let (plain, sealed) = tokio::join!(decrypting.retrieve(), raw.retrieve());
+        assert_eq!(server.requests("/api/retrieve"), 1);
+        assert_eq!(plain.unwrap().data, json!({"secret": "s"}));
+        assert_eq!(sealed.unwrap().data, server.stored(TOKEN).unwrap());
+    }
+}
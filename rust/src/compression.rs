@@ -0,0 +1,234 @@
+//! Transparent gzip or zstd compression of stored values
+//!
+//! With [`Client::with_compression`], `store` compresses values whose JSON is
+//! larger than a threshold and sends them as an envelope naming the codec,
+//! with the compressed bytes in standard base64:
+//!
+//! ```json
+//! {"@encoding": "zstd", "@data": "KLUv/WBkAP0MAFIiK..."}
+//! ```
+//!
+//! A value is only wrapped if the envelope comes out smaller. `retrieve`,
+//! `history` and everything built on them unwrap envelopes automatically,
+//! whether or not compression is enabled on the reading client, so readers
+//! and writers can be migrated separately. An object with `@encoding` or
+//! `@data` and any other field is a damaged envelope and fails with
+//! [`Error::Compression`]. Patches would apply to the envelope as stored, not
+//! to the value inside it, so a client with compression enabled rejects them;
+//! replace compressed values with `store` instead.
+//!
+//! ```no_run
+//! use keyvalue_client::compression::Compression;
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word")
+//!     .with_compression(Compression::zstd().threshold(512));
+//!
+//! let readings: Vec<f64> = (0..5_000).map(|i| i as f64 * 0.25).collect();
+//! client.store(&serde_json::json!({"readings": readings}), None).await?;
+//!
+//! let back = client.retrieve().await?;
+//! assert_eq!(back.data["readings"][1], 0.25);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{Read, Write};
+
+pub const ENCODING_FIELD: &str = "@encoding";
+pub const DATA_FIELD: &str = "@data";
+
+/// Values smaller than this many bytes of JSON are stored as is by default
+pub const DEFAULT_THRESHOLD: usize = 1024;
+
+/// Largest decompressed value accepted, to bound memory on corrupt or hostile input
+const MAX_DECOMPRESSED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Compression algorithm named in the envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    /// Name stored in the envelope
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gzip" => Some(Codec::Gzip),
+            "zstd" => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// When and how `store` compresses values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Compression {
+    pub codec: Codec,
+    /// Smallest value to compress, in bytes of JSON
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+    /// Codec-specific level; `None` for the codec's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<i32>,
+}
+
+fn default_threshold() -> usize {
+    DEFAULT_THRESHOLD
+}
+
+impl Compression {
+    pub fn gzip() -> Self {
+        Self {
+            codec: Codec::Gzip,
+            threshold: DEFAULT_THRESHOLD,
+            level: None,
+        }
+    }
+
+    /// Usually smaller and faster than gzip
+    pub fn zstd() -> Self {
+        Self {
+            codec: Codec::Zstd,
+            threshold: DEFAULT_THRESHOLD,
+            level: None,
+        }
+    }
+
+    /// Only compress values of at least `bytes` bytes of JSON
+    pub fn threshold(mut self, bytes: usize) -> Self {
+        self.threshold = bytes;
+        self
+    }
+
+    /// gzip 0-9 or zstd 1-22; higher is smaller but slower
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = Some(level);
+        self
+    }
+}
+
+/// `data` compressed into an envelope, regardless of size
+pub fn compress(data: &Value, compression: &Compression) -> Result<Value, Error> {
+    let json = serde_json::to_vec(data)?;
+    let bytes = match compression.codec {
+        Codec::Gzip => {
+            let level = compression.level.map_or(flate2::Compression::default(), |level| {
+                flate2::Compression::new(level.clamp(0, 9) as u32)
+            });
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+            encoder.write_all(&json)?;
+            encoder.finish()?
+        }
+        Codec::Zstd => zstd::encode_all(json.as_slice(), compression.level.unwrap_or(0))?,
+    };
+    Ok(serde_json::json!({
+        ENCODING_FIELD: compression.codec.name(),
+        DATA_FIELD: STANDARD.encode(bytes),
+    }))
+}
+
+/// Value inside an envelope, or `None` if `data` is not one
+///
+/// Fails on an object that has only part of an envelope or fields beside it.
+pub fn decompress(data: &Value) -> Result<Option<Value>, Error> {
+    let Some(envelope) = data
+        .as_object()
+        .filter(|obj| obj.contains_key(ENCODING_FIELD) || obj.contains_key(DATA_FIELD))
+    else {
+        return Ok(None);
+    };
+    let (Some(encoding), Some(encoded), 2) = (
+        envelope.get(ENCODING_FIELD).and_then(Value::as_str),
+        envelope.get(DATA_FIELD).and_then(Value::as_str),
+        envelope.len(),
+    ) else {
+        let fields: Vec<&str> = envelope.keys().map(String::as_str).collect();
+        return Err(Error::Compression(format!(
+            "Damaged envelope with fields {}, as left by a patch",
+            fields.join(", ")
+        )));
+    };
+    let codec = Codec::from_name(encoding)
+        .ok_or_else(|| Error::Compression(format!("Unsupported encoding {:?}", encoding)))?;
+    let bytes = STANDARD
+        .decode(encoded)
+        .map_err(|e| Error::Compression(format!("Invalid base64: {}", e)))?;
+
+    let mut json = vec![];
+    let read = match codec {
+        Codec::Gzip => flate2::read::GzDecoder::new(bytes.as_slice())
+            .take(MAX_DECOMPRESSED_BYTES + 1)
+            .read_to_end(&mut json),
+        Codec::Zstd => zstd::Decoder::new(bytes.as_slice())
+            .and_then(|decoder| decoder.take(MAX_DECOMPRESSED_BYTES + 1).read_to_end(&mut json)),
+    };
+    read.map_err(|e| Error::Compression(format!("Corrupt {} data: {}", codec, e)))?;
+    if json.len() as u64 > MAX_DECOMPRESSED_BYTES {
+        return Err(Error::Compression(format!(
+            "Decompressed value exceeds {} bytes",
+            MAX_DECOMPRESSED_BYTES
+        )));
+    }
+    Ok(Some(serde_json::from_slice(&json)?))
+}
+
+impl Client {
+    /// Compress values stored with `store` per `compression`
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub(crate) fn compresses(&self) -> bool {
+        self.compression.is_some()
+    }
+
+    /// `data` as it should be sent: compressed if enabled, large enough and worth it
+    pub(crate) fn compress_payload<'a>(&self, data: &'a Value) -> Result<Cow<'a, Value>, Error> {
+        let Some(compression) = &self.compression else {
+            return Ok(Cow::Borrowed(data));
+        };
+        let plain = serde_json::to_string(data)?.len();
+        if plain < compression.threshold {
+            return Ok(Cow::Borrowed(data));
+        }
+        let envelope = compress(data, compression)?;
+        if envelope.to_string().len() >= plain {
+            return Ok(Cow::Borrowed(data));
+        }
+        Ok(Cow::Owned(envelope))
+    }
+
+    /// Replace a compressed envelope in `data` with the value inside it
+    pub(crate) fn decompress_payload(&self, data: &mut Value) -> Result<(), Error> {
+        if let Some(value) = decompress(data)? {
+            *data = value;
+        }
+        Ok(())
+    }
+}
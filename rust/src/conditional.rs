@@ -0,0 +1,146 @@
+//! Write-if-unchanged stores
+//!
+//! The store endpoint has no version check, so [`Client::store_if_version`]
+//! replaces the document with a versioned patch instead: it sets every field
+//! that differs from the current document and removes fields that are gone.
+//! The server rejects the patch if another write landed first, so the store
+//! is as safe as [`Client::patch`].
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let current = client.retrieve().await?;
+//!
+//! let mut config = current.data.clone();
+//! config["mode"] = "eco".into();
+//! match client.store_if_version(&config, current.version, None).await {
+//!     Ok(resp) => println!("Stored version {}", resp.version),
+//!     Err(Error::Conflict { current_version, .. }) => {
+//!         println!("Someone else wrote version {:?} first", current_version)
+//!     }
+//!     Err(e) => return Err(e),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error, PatchOperations, PatchResponse, RetrieveResponse};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+impl Client {
+    /// Replace the document with `data` only if it is still at `expected_version`
+    ///
+    /// Both the stored document and `data` must be JSON objects, and top-level
+    /// field names may not contain `.`; nested objects with such names are
+    /// replaced whole. Fails with [`Error::Conflict`] carrying the
+    /// current version when the document has moved on, or with
+    /// `current_version: None` when there is no document.
+    pub async fn store_if_version(
+        &self,
+        data: &Value,
+        expected_version: i32,
+        ttl: Option<i32>,
+    ) -> Result<PatchResponse, Error> {
+        self.check_payload_size(data)?;
+        let current = self.retrieve_expecting(expected_version).await?;
+        let patch = replacement_patch(&current.data, data)?;
+        self.patch(expected_version, &patch, ttl).await
+    }
+
+    /// Current document, failing with [`Error::Conflict`] unless it is at `expected_version`
+    pub(crate) async fn retrieve_expecting(&self, expected_version: i32) -> Result<RetrieveResponse, Error> {
+        let Some(current) = self.retrieve_existing().await? else {
+            return Err(Error::Conflict {
+                current_version: None,
+                message: "No document stored".to_string(),
+            });
+        };
+        if current.version != expected_version {
+            return Err(Error::Conflict {
+                current_version: Some(current.version),
+                message: format!(
+                    "Expected version {}, found {}",
+                    expected_version, current.version
+                ),
+            });
+        }
+        Ok(current)
+    }
+}
+
+/// Patch turning the stored document `old` into exactly `new`
+pub(crate) fn replacement_patch(old: &Value, new: &Value) -> Result<PatchOperations, Error> {
+    let Value::Object(new) = new else {
+        return Err(Error::Validation(
+            "store_if_version requires a JSON object".to_string(),
+        ));
+    };
+    let Value::Object(old) = old else {
+        return Err(Error::Validation(
+            "store_if_version requires the stored document to be a JSON object".to_string(),
+        ));
+    };
+
+    let mut set = HashMap::new();
+    let mut remove = vec![];
+    collect_replacement(old, new, "", &mut set, &mut remove)?;
+    if set.is_empty() && remove.is_empty() {
+        // Nothing changed; rewrite the top-level fields so the store still bumps the version
+        set.extend(new.iter().map(|(key, value)| (key.clone(), value.clone())));
+    }
+    Ok(PatchOperations {
+        set: Some(set),
+        remove: (!remove.is_empty()).then_some(remove),
+    })
+}
+
+/// Operations turning `old` into exactly `new`, recursing into shared objects
+fn collect_replacement(
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+    prefix: &str,
+    set: &mut HashMap<String, Value>,
+    remove: &mut Vec<String>,
+) -> Result<(), Error> {
+    let path = |key: &str| {
+        if key.contains('.') {
+            return Err(Error::Validation(format!("Field '{}' cannot contain '.'", key)));
+        }
+        Ok(if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        })
+    };
+
+    for key in old.keys().filter(|key| !new.contains_key(*key)) {
+        remove.push(path(key)?);
+    }
+    for (key, new_value) in new {
+        match (old.get(key), new_value) {
+            (Some(old_value), _) if old_value == new_value => {}
+            (Some(Value::Object(old_nested)), Value::Object(new_nested)) if !has_dotted_key(old_nested) && !has_dotted_key(new_nested) => {
+                collect_replacement(old_nested, new_nested, &path(key)?, set, remove)?;
+            }
+            _ => {
+                set.insert(path(key)?, new_value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether any key in `map`, at any depth, contains `.` and so cannot be a patch path
+fn has_dotted_key(map: &Map<String, Value>) -> bool {
+    map.iter().any(|(key, value)| {
+        key.contains('.') || value.as_object().is_some_and(has_dotted_key)
+    })
+}
+
+/// Whether a patch can express every top-level field of `data`
+pub(crate) fn patchable(data: &Value) -> bool {
+    data.as_object().is_some_and(|map| !map.keys().any(|key| key.contains('.')))
+}
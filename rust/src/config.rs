@@ -0,0 +1,386 @@
+//! Client settings from the environment or a config file
+//!
+//! [`Client::from_env`] reads `KV_TOKEN`, `KV_BASE_URL` (or `KV_URL`, as the
+//! `kv` CLI uses) and `KV_TIMEOUT`. [`Client::from_config_file`] reads an
+//! INI-style file with named profiles, picking the one named by `KV_PROFILE`
+//! or `default`:
+//!
+//! ```text
+//! # Lines before any section belong to the default profile
+//! token = "word-word-word-word-word"
+//!
+//! [profile staging]
+//! token = "other-other-other-other-other"
+//! base_url = "https://staging.key-value.co"
+//! timeout = 10s
+//! ```
+//!
+//! [`Client::from_url`] takes everything in one connection string, handy when
+//! a deployment injects a single secret:
+//! `kv://word-word-word-word-word@kv.example.com:8443/base?timeout=10s`. Use
+//! `kv+http://` for servers without TLS. `KV_BASE_URL` and `KV_URL` accept
+//! the same form.
+//!
+//! The `kv` CLI reads the file at [`default_path`], which
+//! [`Profiles`](crate::profiles::Profiles) also edits. Timeouts take the same
+//! syntax as [`Ttl`], e.g. `500`, `30s` or `2m`. For other combinations, such
+//! as env vars overriding a profile, merge [`Profile`]s and apply them to a
+//! builder:
+//!
+//! ```no_run
+//! use keyvalue_client::config::Profile;
+//! use keyvalue_client::Client;
+//!
+//! # fn main() -> Result<(), keyvalue_client::BuildError> {
+//! let profile = Profile::load("kv.conf", "staging")?.or(Profile::from_env()?);
+//! let client = profile.apply(Client::builder()).build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{mask_token, BuildError, Client, ClientBuilder, Ttl};
+use reqwest::Url;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Profile used when `KV_PROFILE` is unset
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// `$KV_CONFIG`, or `keyvalue/config` in the user's config directory
+pub fn default_path() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    if let Some(path) = var("KV_CONFIG") {
+        return Some(path.into());
+    }
+    let config_dir = var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("keyvalue").join("config"))
+}
+
+/// Client settings from one source; unset fields keep the builder's defaults
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub token: Option<String>,
+    pub base_url: Option<String>,
+    pub timeout: Option<Duration>,
+}
+
+impl fmt::Debug for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profile")
+            .field("token", &self.token.as_deref().map(mask_token))
+            .field("base_url", &self.base_url)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl Profile {
+    /// Settings from `KV_TOKEN`, `KV_BASE_URL` or `KV_URL`, and `KV_TIMEOUT`
+    ///
+    /// A `kv://` URL in `KV_BASE_URL` or `KV_URL` is expanded as by
+    /// [`Profile::from_url`], with the other variables taking precedence.
+    pub fn from_env() -> Result<Self, BuildError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+        let mut profile = Self {
+            token: var("KV_TOKEN"),
+            base_url: var("KV_BASE_URL").or_else(|| var("KV_URL")),
+            timeout: var("KV_TIMEOUT")
+                .map(|value| parse_timeout("KV_TIMEOUT", &value))
+                .transpose()?,
+        };
+        if let Some(url) = profile.base_url.take() {
+            profile = match is_connection_url(&url) {
+                true => profile.or(Self::from_url(&url)?),
+                false => Self {
+                    base_url: Some(url),
+                    ..profile
+                },
+            };
+        }
+        Ok(profile)
+    }
+
+    /// Settings from a `kv://TOKEN@HOST[:PORT][/PATH][?timeout=DURATION]` connection URL
+    ///
+    /// `kv://` connects over HTTPS and `kv+http://` over plain HTTP.
+    ///
+    /// ```
+    /// use keyvalue_client::config::Profile;
+    /// use std::time::Duration;
+    ///
+    /// # fn main() -> Result<(), keyvalue_client::BuildError> {
+    /// let profile = Profile::from_url("kv://word-word-word-word-word@kv.example.com?timeout=5s")?;
+    /// assert_eq!(profile.token.as_deref(), Some("word-word-word-word-word"));
+    /// assert_eq!(profile.base_url.as_deref(), Some("https://kv.example.com"));
+    /// assert_eq!(profile.timeout, Some(Duration::from_secs(5)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_url(url: &str) -> Result<Self, BuildError> {
+        // Never echo the URL itself: it carries the token
+        let invalid = |reason: String| BuildError::Config(format!("connection URL {}", reason));
+        let parsed = Url::parse(url.trim()).map_err(|e| invalid(format!("is malformed: {}", e)))?;
+        let scheme = match parsed.scheme() {
+            "kv" => "https",
+            "kv+http" => "http",
+            other => return Err(invalid(format!("has scheme {:?}, expected kv://", other))),
+        };
+        if parsed.password().is_some() {
+            return Err(invalid("has a password; put only the token before '@'".to_string()));
+        }
+        let host = parsed
+            .host_str()
+            .filter(|host| !host.is_empty())
+            .ok_or_else(|| invalid("has no host".to_string()))?;
+
+        let mut base_url = format!("{}://{}", scheme, host);
+        if let Some(port) = parsed.port() {
+            base_url.push_str(&format!(":{}", port));
+        }
+        base_url.push_str(parsed.path().trim_end_matches('/'));
+        let mut profile = Self {
+            token: Some(parsed.username())
+                .filter(|token| !token.is_empty())
+                .map(str::to_string),
+            base_url: Some(base_url),
+            timeout: None,
+        };
+        for (key, value) in parsed.query_pairs() {
+            match key.as_ref() {
+                "timeout" => profile.timeout = Some(parse_timeout("timeout", &value)?),
+                other => return Err(invalid(format!("has unknown option {:?}", other))),
+            }
+        }
+        Ok(profile)
+    }
+
+    /// Settings of profile `name` in the config file at `path`
+    ///
+    /// Fails if the file cannot be read or has no such profile.
+    pub fn load(path: impl AsRef<Path>, name: &str) -> Result<Self, BuildError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| BuildError::Config(format!("{}: {}", path.display(), e)))?;
+        Self::parse(&text, name)?.ok_or_else(|| {
+            BuildError::Config(format!("{}: no profile named {:?}", path.display(), name))
+        })
+    }
+
+    /// Settings of profile `name` in config file `text`, if it has that profile
+    pub fn parse(text: &str, name: &str) -> Result<Option<Self>, BuildError> {
+        let mut found = None;
+        for (number, (section, line)) in sections(text).enumerate() {
+            let line = line.trim();
+            if !is_entry(line) || section != name {
+                continue;
+            }
+
+            let profile = found.get_or_insert_with(Self::default);
+            let invalid = |reason: String| {
+                BuildError::Config(format!("line {}: {}", number + 1, reason))
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected `key = value`, found {:?}", line)))?;
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "token" => profile.token = Some(value),
+                "base_url" | "url" => profile.base_url = Some(value),
+                "timeout" => profile.timeout = Some(parse_timeout("timeout", &value)?),
+                // Keys for other tools
+                _ => {}
+            }
+        }
+        Ok(found)
+    }
+
+    /// Fill fields unset here from `fallback`
+    pub fn or(self, fallback: Profile) -> Self {
+        Self {
+            token: self.token.or(fallback.token),
+            base_url: self.base_url.or(fallback.base_url),
+            timeout: self.timeout.or(fallback.timeout),
+        }
+    }
+
+    /// Apply the fields that are set to `builder`
+    pub fn apply(self, mut builder: ClientBuilder) -> ClientBuilder {
+        if let Some(token) = self.token {
+            builder = builder.token(token);
+        }
+        if let Some(url) = self.base_url {
+            builder = builder.base_url(url.trim_end_matches('/'));
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        builder
+    }
+}
+
+impl Client {
+    /// Create a client from a `kv://TOKEN@HOST` connection URL
+    ///
+    /// See [`Profile::from_url`] for the format.
+    pub fn from_url(url: &str) -> Result<Self, BuildError> {
+        Profile::from_url(url)?.apply(Client::builder()).build()
+    }
+
+    /// Create a client from `KV_TOKEN`, `KV_BASE_URL` and `KV_TIMEOUT`
+    ///
+    /// Unset variables keep the defaults, so the client may have no token.
+    pub fn from_env() -> Result<Self, BuildError> {
+        Profile::from_env()?.apply(Client::builder()).build()
+    }
+
+    /// Create a client from the `KV_PROFILE` profile of a config file, or `default`
+    pub fn from_config_file(path: impl AsRef<Path>) -> Result<Self, BuildError> {
+        let name = std::env::var("KV_PROFILE").unwrap_or_else(|_| DEFAULT_PROFILE.to_string());
+        Self::from_config_profile(path, &name)
+    }
+
+    /// Create a client from profile `name` of a config file
+    pub fn from_config_profile(path: impl AsRef<Path>, name: &str) -> Result<Self, BuildError> {
+        Profile::load(path, name)?.apply(Client::builder()).build()
+    }
+}
+
+/// Each line of config file `text` with the profile it belongs to
+///
+/// Lines before any section belong to the default profile, and a section
+/// header to the profile it opens.
+pub(crate) fn sections(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut section = DEFAULT_PROFILE;
+    text.lines().map(move |line| {
+        if let Some(header) = line.trim().strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let header = header.trim();
+            section = header.strip_prefix("profile ").unwrap_or(header).trim();
+        }
+        (section, line)
+    })
+}
+
+/// Whether trimmed config file `line` sets a key, rather than being blank, a comment or a header
+pub(crate) fn is_entry(line: &str) -> bool {
+    !(line.is_empty() || line.starts_with('#') || line.starts_with(';') || line.starts_with('['))
+}
+
+fn is_connection_url(url: &str) -> bool {
+    let url = url.trim_start();
+    url.starts_with("kv://") || url.starts_with("kv+http://")
+}
+
+fn parse_timeout(key: &str, value: &str) -> Result<Duration, BuildError> {
+    Ttl::parse(value)
+        .map(Ttl::as_duration)
+        .map_err(|_| BuildError::Config(format!("invalid {} {:?}", key, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+# Lines before any section belong to the default profile
+token = "word-word-word-word-word"
+timeout = 30s
+
+[profile staging]
+; comment
+token = other-other-other-other-other
+url = "https://staging.test/"
+editor = vim
+
+[ production ]
+base_url = https://kv.test
+"#;
+
+    #[test]
+    fn parse_reads_the_named_section_only() {
+        let default = Profile::parse(CONFIG, DEFAULT_PROFILE).unwrap().unwrap();
+        assert_eq!(default.token.as_deref(), Some("word-word-word-word-word"));
+        assert_eq!(default.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(default.base_url, None);
+
+        let staging = Profile::parse(CONFIG, "staging").unwrap().unwrap();
+        assert_eq!(staging.token.as_deref(), Some("other-other-other-other-other"));
+        assert_eq!(staging.base_url.as_deref(), Some("https://staging.test/"));
+
+        let production = Profile::parse(CONFIG, "production").unwrap().unwrap();
+        assert_eq!(production.base_url.as_deref(), Some("https://kv.test"));
+        assert_eq!(Profile::parse(CONFIG, "missing").unwrap(), None);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = Profile::parse("[profile a]\ntoken\n", "a").unwrap_err();
+        assert!(matches!(error, BuildError::Config(message) if message.starts_with("line 2:")));
+        assert!(matches!(
+            Profile::parse("timeout = soon", DEFAULT_PROFILE),
+            Err(BuildError::Config(message)) if message == "invalid timeout \"soon\""
+        ));
+        // Broken lines in other profiles do not matter
+        assert!(Profile::parse("[profile a]\ntoken\n", "b").unwrap().is_none());
+    }
+
+    #[test]
+    fn sections_label_every_line_with_its_profile() {
+        let labels: Vec<_> = sections("a = 1\n[profile x]\nb = 2\n[y]\n").map(|(section, _)| section).collect();
+        assert_eq!(labels, [DEFAULT_PROFILE, "x", "x", "y"]);
+        assert!(is_entry("token = x"));
+        for line in ["", "# note", "; note", "[profile x]"] {
+            assert!(!is_entry(line), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn connection_urls_carry_token_host_path_and_timeout() {
+        let profile = Profile::from_url("kv+http://word-word-word-word-word@localhost:8080/kv/?timeout=2m").unwrap();
+        assert_eq!(
+            profile,
+            Profile {
+                token: Some("word-word-word-word-word".to_string()),
+                base_url: Some("http://localhost:8080/kv".to_string()),
+                timeout: Some(Duration::from_secs(120)),
+            }
+        );
+        assert_eq!(Profile::from_url("kv://kv.test").unwrap().token, None);
+    }
+
+    #[test]
+    fn invalid_connection_urls_do_not_echo_the_token() {
+        for url in [
+            "https://word-word-word-word-word@kv.test",
+            "kv://word-word-word-word-word:secret@kv.test",
+            "kv://word-word-word-word-word@kv.test?retries=3",
+            "kv://word-word-word-word-word@kv.test?timeout=soon",
+            "not a url word-word-word-word-word",
+        ] {
+            let Err(BuildError::Config(message)) = Profile::from_url(url) else {
+                panic!("{} should be rejected", url);
+            };
+            assert!(!message.contains("word-word"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn or_prefers_own_fields() {
+        let own = Profile {
+            token: Some("own-own-own-own-own".to_string()),
+            ..Default::default()
+        };
+        let fallback = Profile {
+            token: Some("word-word-word-word-word".to_string()),
+            base_url: Some("https://kv.test".to_string()),
+            timeout: None,
+        };
+        let merged = own.or(fallback);
+        assert_eq!(merged.token.as_deref(), Some("own-own-own-own-own"));
+        assert_eq!(merged.base_url.as_deref(), Some("https://kv.test"));
+        assert_eq!(format!("{:?}", merged).matches("own-****").count(), 1);
+        assert!(!format!("{:?}", merged).contains("own-own-own"));
+    }
+}
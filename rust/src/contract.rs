@@ -0,0 +1,268 @@
+//! Data contracts checked on retrieve
+//!
+//! A client with a contract rejects retrieved data that does not satisfy it
+//! with [`Error::ContractViolation`], listing every problem found, so bad
+//! writer data fails at the boundary instead of deep in consumer logic.
+//! Typed data stored with a schema envelope is checked without the envelope.
+//!
+//! ```no_run
+//! use keyvalue_client::contract::{Schema, Typed};
+//! use keyvalue_client::{Client, Error};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Reading {
+//!     temperature: f64,
+//! }
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let schema = Schema::new(serde_json::json!({
+//!     "type": "object",
+//!     "required": ["temperature"],
+//!     "properties": { "temperature": { "type": "number", "minimum": -50, "maximum": 150 } }
+//! }));
+//! let client = Client::new("word-word-word-word-word").with_contract(schema);
+//!
+//! match client.retrieve().await {
+//!     Err(Error::ContractViolation { violations }) => {
+//!         for v in violations {
+//!             eprintln!("{}: {}", v.path, v.message);
+//!         }
+//!     }
+//!     other => println!("{:?}", other?.data),
+//! }
+//!
+//! // Or check against a Rust type
+//! let client = Client::new("word-word-word-word-word").with_contract(Typed::<Reading>::new());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::typed::envelope_value;
+use crate::{Client, Error};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Single way in which data breaks a contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Location in the data, e.g. `readings[2].value`, or `.` for the root
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.path, self.message)
+    }
+}
+
+/// Rule that retrieved data must satisfy
+pub trait Contract: Send + Sync {
+    /// Every violation found in `data`; empty when it conforms
+    fn check(&self, data: &Value) -> Vec<Violation>;
+}
+
+impl<F> Contract for F
+where
+    F: Fn(&Value) -> Vec<Violation> + Send + Sync,
+{
+    fn check(&self, data: &Value) -> Vec<Violation> {
+        self(data)
+    }
+}
+
+/// Contract satisfied by data that deserializes into `T`
+pub struct Typed<T> {
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Typed<T> {
+    pub fn new() -> Self {
+        Self { _type: PhantomData }
+    }
+}
+
+impl<T> Default for Typed<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: DeserializeOwned> Contract for Typed<T> {
+    fn check(&self, data: &Value) -> Vec<Violation> {
+        match serde_path_to_error::deserialize::<_, T>(data) {
+            Ok(_) => vec![],
+            Err(e) => vec![Violation {
+                path: e.path().to_string(),
+                message: e.into_inner().to_string(),
+            }],
+        }
+    }
+}
+
+/// Contract described by a subset of JSON Schema
+///
+/// Supports `type`, `enum`, `const`, `properties`, `required`,
+/// `additionalProperties`, `items`, `minimum`, `maximum`, `minLength`,
+/// `maxLength`, `minItems` and `maxItems`. Other keywords are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    schema: Value,
+}
+
+impl Schema {
+    pub fn new(schema: Value) -> Self {
+        Self { schema }
+    }
+}
+
+impl Contract for Schema {
+    fn check(&self, data: &Value) -> Vec<Violation> {
+        let mut violations = vec![];
+        check_schema(&self.schema, data, "", &mut violations);
+        violations
+    }
+}
+
+fn check_schema(schema: &Value, data: &Value, path: &str, out: &mut Vec<Violation>) {
+    let mut fail = |message: String| {
+        out.push(Violation {
+            path: if path.is_empty() { ".".to_string() } else { path.to_string() },
+            message,
+        })
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|t| has_type(data, t)) {
+            fail(format!("expected {}, found {}", types.join(" or "), type_name(data)));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(data) {
+            fail(format!("{} is not one of the allowed values", data));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != data {
+            fail(format!("expected {}, found {}", expected, data));
+        }
+    }
+
+    let bound = |name: &str| schema.get(name).and_then(Value::as_f64);
+    if let Some(n) = data.as_f64() {
+        if let Some(min) = bound("minimum").filter(|min| n < *min) {
+            fail(format!("{} is less than the minimum {}", n, min));
+        }
+        if let Some(max) = bound("maximum").filter(|max| n > *max) {
+            fail(format!("{} is greater than the maximum {}", n, max));
+        }
+    }
+    let mut check_len = |len: usize, what: &str, min_key: &str, max_key: &str| {
+        if let Some(min) = bound(min_key).filter(|min| (len as f64) < *min) {
+            fail(format!("{} {} is shorter than the minimum {}", what, len, min));
+        }
+        if let Some(max) = bound(max_key).filter(|max| (len as f64) > *max) {
+            fail(format!("{} {} is longer than the maximum {}", what, len, max));
+        }
+    };
+    match data {
+        Value::String(s) => check_len(s.chars().count(), "length", "minLength", "maxLength"),
+        Value::Array(items) => check_len(items.len(), "item count", "minItems", "maxItems"),
+        _ => {}
+    }
+
+    match data {
+        Value::Object(obj) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for name in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+                if let Some(name) = name.as_str().filter(|name| !obj.contains_key(*name)) {
+                    out.push(Violation {
+                        path: join(path, name),
+                        message: "required field is missing".to_string(),
+                    });
+                }
+            }
+            for (name, value) in obj {
+                let field_path = join(path, name);
+                match properties.and_then(|props| props.get(name)) {
+                    Some(field_schema) => check_schema(field_schema, value, &field_path, out),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => out.push(Violation {
+                            path: field_path,
+                            message: "unexpected field".to_string(),
+                        }),
+                        Some(extra @ Value::Object(_)) => {
+                            check_schema(extra, value, &field_path, out)
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_schema(item_schema, item, &format!("{}[{}]", path, i), out);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+fn has_type(data: &Value, name: &str) -> bool {
+    match name {
+        "integer" => data.is_i64() || data.is_u64(),
+        "number" => data.is_number(),
+        other => type_name(data) == other,
+    }
+}
+
+fn type_name(data: &Value) -> &'static str {
+    match data {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+impl Client {
+    /// Check retrieved data against a contract
+    pub fn with_contract(mut self, contract: impl Contract + 'static) -> Self {
+        self.contract = Some(Arc::new(contract));
+        self
+    }
+
+    pub(crate) fn check_contract(&self, data: &Value) -> Result<(), Error> {
+        let Some(contract) = &self.contract else {
+            return Ok(());
+        };
+        let violations = contract.check(envelope_value(data));
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ContractViolation { violations })
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Numeric history as a polars `DataFrame` or an ndarray matrix
+//!
+//! [`HistoryResponse::to_polars`] and [`HistoryResponse::to_ndarray`] take
+//! the events of a page that carry a `numeric_value`, oldest first, for
+//! analysis with the usual data science tooling. Both crates are re-exported
+//! so their versions match this crate's.
+//!
+//! ```no_run
+//! use keyvalue_client::polars::prelude::*;
+//! use keyvalue_client::{Client, HistoryOptions};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::new("word-word-word-word-word");
+//! let telemetry = client.history(&HistoryOptions::default()).await?.to_polars()?;
+//! println!("{}\nmean {:?}", telemetry, telemetry.column("value")?.f64()?.mean());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{HistoryEvent, HistoryResponse};
+use ndarray::Array2;
+use polars::prelude::*;
+
+impl HistoryResponse {
+    /// Numeric events as columns `seq`, `created_at` (UTC, without time zone), `value` and `confidence`
+    pub fn to_polars(&self) -> PolarsResult<DataFrame> {
+        let events = numeric_events(&self.events);
+        let seq: Vec<i32> = events.iter().map(|(event, _)| event.seq).collect();
+        let created_at: Vec<i64> = events.iter().map(|(event, _)| event.created_at.timestamp_millis()).collect();
+        let value: Vec<f64> = events.iter().map(|(_, value)| *value).collect();
+        let confidence: Vec<Option<f64>> = events.iter().map(|(event, _)| event.confidence).collect();
+
+        let created_at = Series::new("created_at".into(), created_at)
+            .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?;
+        DataFrame::new(vec![
+            Column::new("seq".into(), seq),
+            created_at.into(),
+            Column::new("value".into(), value),
+            Column::new("confidence".into(), confidence),
+        ])
+    }
+
+    /// Numeric events as rows of Unix time in seconds and value
+    pub fn to_ndarray(&self) -> Array2<f64> {
+        let events = numeric_events(&self.events);
+        let mut array = Array2::zeros((events.len(), 2));
+        for (mut row, (event, value)) in array.rows_mut().into_iter().zip(&events) {
+            row[0] = event.created_at.timestamp_millis() as f64 / 1000.0;
+            row[1] = *value;
+        }
+        array
+    }
+}
+
+/// Events with a numeric value, oldest first
+fn numeric_events(events: &[HistoryEvent]) -> Vec<(&HistoryEvent, f64)> {
+    let mut numeric: Vec<_> = events
+        .iter()
+        .filter_map(|event| event.numeric_value.map(|value| (event, value)))
+        .collect();
+    numeric.sort_by_key(|(event, _)| event.created_at);
+    numeric
+}
@@ -0,0 +1,101 @@
+//! Store deduplication by content hash
+//!
+//! With dedup enabled, `store` remembers the canonical hash of the last payload
+//! it stored and skips the request entirely when asked to store the same value
+//! again, returning the previous response. The hash is tracked client-side only:
+//! `patch` and `delete` reset it, but writes from other clients go unnoticed.
+//! Stores with a TTL are always sent, since they refresh the expiry.
+
+use crate::{hex, Client, StoreResponse};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// SHA-256 of the canonical JSON encoding of `value`, as lowercase hex
+///
+/// Object keys are sorted so logically equal documents hash identically
+/// regardless of field order.
+pub fn content_hash(value: &Value) -> String {
+    hex(&Sha256::digest(canonical_json(value).as_bytes()))
+}
+
+/// Hash and response of the last deduplicated store
+pub(crate) struct LastStore {
+    hash: String,
+    response: StoreResponse,
+}
+
+impl Client {
+    /// Skip stores whose payload matches the last stored payload
+    pub fn with_store_dedup(mut self, enabled: bool) -> Self {
+        self.store_dedup = enabled;
+        self
+    }
+
+    /// Return the previous response if `data` matches the last stored payload
+    pub(crate) fn dedup_lookup(&self, data: &Value, ttl: Option<i32>) -> Option<StoreResponse> {
+        if !self.store_dedup || ttl.is_some() {
+            return None;
+        }
+        let hash = content_hash(data);
+        let last = self.last_store.lock().unwrap_or_else(|e| e.into_inner());
+        last.as_ref()
+            .filter(|last| last.hash == hash)
+            .map(|last| last.response.clone())
+    }
+
+    /// Remember a successful store for future dedup checks
+    pub(crate) fn dedup_record(&self, data: &Value, ttl: Option<i32>, response: &StoreResponse) {
+        if !self.store_dedup {
+            return;
+        }
+        let last = ttl.is_none().then(|| LastStore {
+            hash: content_hash(data),
+            response: response.clone(),
+        });
+        *self.last_store.lock().unwrap_or_else(|e| e.into_inner()) = last;
+    }
+
+    /// Forget the last stored payload after the document changed by other means
+    pub(crate) fn dedup_reset(&self) {
+        if self.store_dedup {
+            *self.last_store.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+    }
+}
+
+/// JSON encoding of `value` with object keys sorted at every level
+pub(crate) fn canonical_json(value: &Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(value, &mut canonical);
+    canonical
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
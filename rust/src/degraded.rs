@@ -0,0 +1,272 @@
+//! Graceful degradation during API outages
+//!
+//! With an [`AvailabilityPolicy`] set, [`Client::retrieve_available`] answers
+//! from the last successfully retrieved value when the API is unreachable,
+//! tagged [`Availability::Degraded`] with its age. [`Client::store_available`]
+//! and [`Client::patch_available`] queue writes in memory instead, and the
+//! queue is sent in order before the next write once the API is back, or
+//! explicitly with [`Client::flush_queued`].
+//!
+//! Connection failures, timeouts and `5xx` responses count as an outage; any
+//! other error is returned as usual. A degraded read does not reflect writes
+//! still waiting in the queue.
+//!
+//! ```no_run
+//! use keyvalue_client::{Availability, AvailabilityPolicy, Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word")
+//!     .with_availability_policy(AvailabilityPolicy::default());
+//!
+//! match client.retrieve_available().await? {
+//!     Availability::Live(resp) => println!("{}", resp.data),
+//!     Availability::Degraded { value, age } => {
+//!         println!("{} (offline, {}s old)", value.data, age.as_secs())
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::journal::JournalOp;
+use crate::{Client, Error, PatchOperations, PatchResponse, RetrieveResponse, StoreResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How stale cached reads may be and how many writes may wait offline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AvailabilityPolicy {
+    /// Oldest cached value served during an outage; `None` serves any age
+    #[serde(with = "crate::policy::duration::option", skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<Duration>,
+    /// Writes held while the API is unreachable before writes fail again
+    pub max_queued: usize,
+}
+
+impl Default for AvailabilityPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            max_queued: 1000,
+        }
+    }
+}
+
+/// Value read under an [`AvailabilityPolicy`]
+#[derive(Debug, Clone)]
+pub enum Availability<T> {
+    /// Fresh from the API
+    Live(T),
+    /// Last value retrieved before the API became unreachable
+    Degraded { value: T, age: Duration },
+}
+
+impl<T> Availability<T> {
+    pub fn value(&self) -> &T {
+        match self {
+            Availability::Live(value) | Availability::Degraded { value, .. } => value,
+        }
+    }
+
+    pub fn into_value(self) -> T {
+        match self {
+            Availability::Live(value) | Availability::Degraded { value, .. } => value,
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        matches!(self, Availability::Degraded { .. })
+    }
+}
+
+/// Write made under an [`AvailabilityPolicy`]
+#[derive(Debug)]
+pub enum WriteOutcome<T> {
+    /// Accepted by the API
+    Sent(T),
+    /// Held until the API is reachable; `pending` writes are now queued
+    Queued { pending: usize },
+}
+
+/// Cached reads and queued writes, shared by clones of a client
+pub(crate) struct Degradation {
+    policy: AvailabilityPolicy,
+    cache: Mutex<HashMap<String, (Instant, RetrieveResponse)>>,
+    queue: Mutex<VecDeque<(String, JournalOp)>>,
+    /// Held while flushing so concurrent flushes never send a write twice
+    flushing: tokio::sync::Mutex<()>,
+}
+
+impl Client {
+    /// Serve cached reads and queue writes while the API is unreachable
+    pub fn with_availability_policy(mut self, policy: AvailabilityPolicy) -> Self {
+        self.degradation = Some(Arc::new(Degradation {
+            policy,
+            cache: Mutex::default(),
+            queue: Mutex::default(),
+            flushing: tokio::sync::Mutex::default(),
+        }));
+        self
+    }
+
+    /// Retrieve data, falling back to the last retrieved value during an outage
+    pub async fn retrieve_available(&self) -> Result<Availability<RetrieveResponse>, Error> {
+        let Some(degradation) = &self.degradation else {
+            return self.retrieve().await.map(Availability::Live);
+        };
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+
+        match self.retrieve().await {
+            Ok(resp) => {
+                let mut cache = degradation.cache.lock().unwrap_or_else(|e| e.into_inner());
+                cache.insert(token.to_string(), (Instant::now(), resp.clone()));
+                Ok(Availability::Live(resp))
+            }
+            Err(e) if is_outage(&e) => {
+                let cache = degradation.cache.lock().unwrap_or_else(|e| e.into_inner());
+                let age = |stored_at: &Instant| stored_at.elapsed();
+                match cache.get(token) {
+                    Some((stored_at, resp))
+                        if degradation.policy.max_age.is_none_or(|max| age(stored_at) <= max) =>
+                    {
+                        Ok(Availability::Degraded {
+                            value: resp.clone(),
+                            age: age(stored_at),
+                        })
+                    }
+                    _ => Err(e),
+                }
+            }
+            Err(Error::NotFound { message }) => {
+                let mut cache = degradation.cache.lock().unwrap_or_else(|e| e.into_inner());
+                cache.remove(token);
+                Err(Error::NotFound { message })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Store data, queueing it while the API is unreachable
+    ///
+    /// Queued writes are flushed first; if the API rejects one of them, its
+    /// error is returned and `data` is not sent.
+    pub async fn store_available(
+        &self,
+        data: &Value,
+        ttl: Option<i32>,
+    ) -> Result<WriteOutcome<StoreResponse>, Error> {
+        let op = JournalOp::Store {
+            data: data.clone(),
+            ttl,
+        };
+        self.write_available(op, self.store(data, ttl)).await
+    }
+
+    /// Patch data, queueing the patch while the API is unreachable
+    ///
+    /// A queued patch is sent with `version` as given, so it fails with
+    /// [`Error::Conflict`] on flush if the document changed in the meantime.
+    pub async fn patch_available(
+        &self,
+        version: i32,
+        patch: &PatchOperations,
+        ttl: Option<i32>,
+    ) -> Result<WriteOutcome<PatchResponse>, Error> {
+        let op = JournalOp::Patch {
+            version,
+            patch: patch.clone(),
+            ttl,
+        };
+        self.write_available(op, self.patch(version, patch, ttl)).await
+    }
+
+    /// Writes waiting for the API to become reachable
+    pub fn queued_writes(&self) -> usize {
+        self.degradation.as_ref().map_or(0, |degradation| {
+            degradation.queue.lock().unwrap_or_else(|e| e.into_inner()).len()
+        })
+    }
+
+    /// Send queued writes in order, returning how many were sent
+    ///
+    /// Stops at the first outage, leaving the rest queued. A write the API
+    /// rejects is dropped from the queue and its error returned.
+    pub async fn flush_queued(&self) -> Result<usize, Error> {
+        let Some(degradation) = &self.degradation else {
+            return Ok(0);
+        };
+        let _flushing = degradation.flushing.lock().await;
+        let mut sent = 0;
+        loop {
+            let next = degradation.queue.lock().unwrap_or_else(|e| e.into_inner()).front().cloned();
+            let Some((token, op)) = next else {
+                return Ok(sent);
+            };
+
+            let mut client = self.clone();
+            client.set_token(token);
+            let result = match &op {
+                JournalOp::Store { data, ttl } => client.store(data, *ttl).await.map(drop),
+                JournalOp::Patch {
+                    version,
+                    patch,
+                    ttl,
+                } => client.patch(*version, patch, *ttl).await.map(drop),
+            };
+            match result {
+                Err(e) if is_outage(&e) => return Err(e),
+                result => {
+                    degradation.queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+                    result?;
+                    sent += 1;
+                }
+            }
+        }
+    }
+
+    /// Flush the queue, then run `send`, queueing `op` instead on an outage
+    async fn write_available<T>(
+        &self,
+        op: JournalOp,
+        send: impl Future<Output = Result<T, Error>>,
+    ) -> Result<WriteOutcome<T>, Error> {
+        let Some(degradation) = &self.degradation else {
+            return send.await.map(WriteOutcome::Sent);
+        };
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+
+        // Earlier writes go first so the document ends up in write order
+        let result = match self.flush_queued().await {
+            Ok(_) => send.await,
+            Err(e) if is_outage(&e) => Err(e),
+            Err(e) => return Err(e),
+        };
+        match result {
+            Err(e) if is_outage(&e) => {
+                let mut queue = degradation.queue.lock().unwrap_or_else(|e| e.into_inner());
+                if queue.len() >= degradation.policy.max_queued {
+                    return Err(e);
+                }
+                queue.push_back((token.to_string(), op));
+                Ok(WriteOutcome::Queued {
+                    pending: queue.len(),
+                })
+            }
+            result => result.map(WriteOutcome::Sent),
+        }
+    }
+}
+
+/// Whether `error` means the API could not be reached or failed on its side
+pub(crate) fn is_outage(error: &Error) -> bool {
+    match error {
+        Error::Request(e) => e.is_connect() || e.is_timeout(),
+        Error::Server { .. } | Error::CircuitOpen { .. } => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,214 @@
+//! Structured differences between JSON documents
+//!
+//! [`diff`] walks two values and lists every leaf that was added, removed or
+//! changed, addressed by JSON pointer (RFC 6901) so paths stay unambiguous
+//! for keys containing `.` or `/` and can be fed back to [`get_path`].
+//! Arrays are compared index by index. [`Client::diff_with_history`] compares
+//! a past history event with the current value, for audit logs and change
+//! review.
+//!
+//! ```
+//! use keyvalue_client::diff::{diff, Change};
+//! use serde_json::json;
+//!
+//! let old = json!({"mode": "eco", "targets": [20, 21]});
+//! let new = json!({"mode": "boost", "targets": [20], "fan": true});
+//! let changes = diff(&old, &new);
+//!
+//! assert_eq!(changes.len(), 3);
+//! assert_eq!(changes.changes[0], Change::Added { path: "/fan".to_string(), value: json!(true) });
+//! assert_eq!(changes.to_string(), "+ /fan: true\n~ /mode: \"eco\" -> \"boost\"\n- /targets/1: 21\n");
+//! ```
+//!
+//! [`get_path`]: crate::get_path
+
+use crate::{Client, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// One difference between two documents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Change {
+    Added { path: String, value: Value },
+    Removed { path: String, value: Value },
+    Changed { path: String, old: Value, new: Value },
+}
+
+impl Change {
+    /// JSON pointer to the value that differs; empty for the whole document
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. } | Change::Removed { path, .. } | Change::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {}: {}", path, value),
+            Change::Removed { path, value } => write!(f, "- {}: {}", path, value),
+            Change::Changed { path, old, new } => write!(f, "~ {}: {} -> {}", path, old, new),
+        }
+    }
+}
+
+/// Changes turning one document into another, in path order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JsonDiff {
+    pub changes: Vec<Change>,
+}
+
+impl JsonDiff {
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Whether the documents are equal
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn added(&self) -> impl Iterator<Item = &Change> + '_ {
+        self.changes.iter().filter(|change| matches!(change, Change::Added { .. }))
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = &Change> + '_ {
+        self.changes.iter().filter(|change| matches!(change, Change::Removed { .. }))
+    }
+
+    pub fn changed(&self) -> impl Iterator<Item = &Change> + '_ {
+        self.changes.iter().filter(|change| matches!(change, Change::Changed { .. }))
+    }
+
+    /// Paths of all changes
+    pub fn paths(&self) -> impl Iterator<Item = &str> + '_ {
+        self.changes.iter().map(Change::path)
+    }
+}
+
+/// One change per line, `+` added, `-` removed, `~` changed
+impl fmt::Display for JsonDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Leaf-level changes turning `old` into `new`
+pub fn diff(old: &Value, new: &Value) -> JsonDiff {
+    let mut changes = vec![];
+    walk(old, new, &mut String::new(), &mut changes);
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    JsonDiff { changes }
+}
+
+fn walk(old: &Value, new: &Value, path: &mut String, changes: &mut Vec<Change>) {
+    match (old, new) {
+        _ if old == new => {}
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                with_segment(path, key, |path| match new.get(key) {
+                    Some(new_value) => walk(old_value, new_value, path, changes),
+                    None => changes.push(Change::Removed {
+                        path: path.clone(),
+                        value: old_value.clone(),
+                    }),
+                });
+            }
+            for (key, new_value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                with_segment(path, key, |path| {
+                    changes.push(Change::Added {
+                        path: path.clone(),
+                        value: new_value.clone(),
+                    })
+                });
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for i in 0..old.len().max(new.len()) {
+                with_segment(path, &i.to_string(), |path| match (old.get(i), new.get(i)) {
+                    (Some(old_value), Some(new_value)) => walk(old_value, new_value, path, changes),
+                    (Some(old_value), None) => changes.push(Change::Removed {
+                        path: path.clone(),
+                        value: old_value.clone(),
+                    }),
+                    (None, Some(new_value)) => changes.push(Change::Added {
+                        path: path.clone(),
+                        value: new_value.clone(),
+                    }),
+                    (None, None) => {}
+                });
+            }
+        }
+        _ => changes.push(Change::Changed {
+            path: path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+/// Run `f` with `segment` appended to `path` as an escaped pointer segment
+fn with_segment(path: &mut String, segment: &str, f: impl FnOnce(&mut String)) {
+    let len = path.len();
+    path.push('/');
+    path.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    f(path);
+    path.truncate(len);
+}
+
+impl Client {
+    /// Changes from history event `seq` to the current value
+    ///
+    /// A value that is no longer stored counts as `null`. Fails with
+    /// [`Error::NotFound`] if event `seq` is not retained.
+    pub async fn diff_with_history(&self, seq: i32) -> Result<JsonDiff, Error> {
+        let event = self.history_event(seq).await?;
+        let current = self.retrieve_existing().await?.map_or(Value::Null, |resp| resp.data);
+        Ok(diff(&event.data, &current))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_path;
+    use serde_json::json;
+
+    #[test]
+    fn paths_escape_keys_and_resolve_with_get_path() {
+        let old = json!({"a/b": {"c~d": 1, "e.f": 2}});
+        let new = json!({"a/b": {"c~d": 3, "e.f": 2}});
+        let changes = diff(&old, &new);
+        assert_eq!(changes.paths().collect::<Vec<_>>(), ["/a~1b/c~0d"]);
+        assert_eq!(get_path(&new, changes.changes[0].path()), Some(&json!(3)));
+    }
+
+    #[test]
+    fn nested_values_and_arrays_diff_by_leaf() {
+        let old = json!({"user": {"name": "Ada", "tags": ["x", "y"]}, "gone": null});
+        let new = json!({"user": {"name": "Grace", "tags": ["x", "y", "z"]}});
+        let changes = diff(&old, &new);
+        assert_eq!(changes.to_string(), "- /gone: null\n~ /user/name: \"Ada\" -> \"Grace\"\n+ /user/tags/2: \"z\"\n");
+        assert_eq!((changes.added().count(), changes.removed().count(), changes.changed().count()), (1, 1, 1));
+    }
+
+    #[test]
+    fn type_changes_replace_the_whole_value() {
+        let changes = diff(&json!({"a": {"b": 1}}), &json!({"a": [1]}));
+        assert_eq!(changes.changes, [Change::Changed { path: "/a".to_string(), old: json!({"b": 1}), new: json!([1]) }]);
+        assert_eq!(diff(&json!(1), &json!("1")).changes[0].path(), "");
+        assert!(diff(&json!({"a": [1, {"b": 2}]}), &json!({"a": [1, {"b": 2}]})).is_empty());
+    }
+
+    #[test]
+    fn changes_serialize_with_an_op_tag() {
+        let change = Change::Removed { path: "/a".to_string(), value: json!(1) };
+        assert_eq!(serde_json::to_value(&change).unwrap(), json!({"op": "removed", "path": "/a", "value": 1}));
+    }
+}
@@ -0,0 +1,138 @@
+//! Leader election between redundant workers
+//!
+//! Several instances of a cron job or consumer run for redundancy, but only
+//! one should act at a time. Each calls [`Election::campaign`] with its own
+//! node id on a token dedicated to the election. Campaigning claims the
+//! [writer lease](crate::lease) for `lease_ttl` and renews it every third of
+//! that; while another node's lease is live, it keeps trying instead. A node
+//! takes the lead on its second claim in a row, as the first on a missing
+//! document is an unversioned store that a rival could overwrite, and counts
+//! itself leader until a third of the TTL before its lease runs out, so a
+//! leader that cannot renew, say during an outage, steps down before another
+//! node can take over.
+//!
+//! ```no_run
+//! use keyvalue_client::election::{Election, LeadershipEvent};
+//! use keyvalue_client::{Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let mut election = Election::campaign(client, "worker-1", Duration::from_secs(30));
+//!
+//! while let Some(event) = election.next_event().await {
+//!     match event {
+//!         LeadershipEvent::Elected => println!("leading: starting jobs"),
+//!         LeadershipEvent::Lost => println!("no longer leading: stopping jobs"),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Change in this node's leadership
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeadershipEvent {
+    Elected,
+    Lost,
+}
+
+/// Campaign for leadership running on a background task
+///
+/// Dropping the election resigns like [`Election::resign`], without waiting.
+pub struct Election {
+    leader: watch::Receiver<bool>,
+    stop: watch::Sender<bool>,
+    task: JoinHandle<Result<(), Error>>,
+}
+
+impl Election {
+    /// Start campaigning as `node_id` for the lease on `client`'s token
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn campaign(client: Client, node_id: impl Into<String>, lease_ttl: Duration) -> Self {
+        let client = client.with_writer_id(node_id);
+        let (leader_tx, leader) = watch::channel(false);
+        let (stop, stopped) = watch::channel(false);
+        let task = tokio::spawn(campaign(client, lease_ttl, leader_tx, stopped));
+        Self { leader, stop, task }
+    }
+
+    /// Whether this node currently leads
+    pub fn is_leader(&self) -> bool {
+        *self.leader.borrow()
+    }
+
+    /// Wait for this node to gain or lose leadership
+    ///
+    /// Returns `None` once the campaign has ended. Changes that undo each
+    /// other before this is called are not reported.
+    pub async fn next_event(&mut self) -> Option<LeadershipEvent> {
+        self.leader.changed().await.ok()?;
+        Some(match *self.leader.borrow_and_update() {
+            true => LeadershipEvent::Elected,
+            false => LeadershipEvent::Lost,
+        })
+    }
+
+    /// Receiver of whether this node leads, for other tasks to wait on
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.leader.clone()
+    }
+
+    /// Stop campaigning and release the lease if this node holds it
+    pub async fn resign(self) -> Result<(), Error> {
+        let _ = self.stop.send(true);
+        self.task.await.unwrap_or_else(|e| {
+            Err(Error::Validation(format!("Election task failed: {}", e)))
+        })
+    }
+}
+
+async fn campaign(
+    client: Client,
+    lease_ttl: Duration,
+    leader: watch::Sender<bool>,
+    mut stopped: watch::Receiver<bool>,
+) -> Result<(), Error> {
+    let renew_every = (lease_ttl / 3).max(Duration::from_millis(1));
+    let margin = chrono::Duration::from_std(renew_every).unwrap_or(chrono::Duration::MAX);
+    // Expiry, less the margin, of this node's last claim
+    let mut claimed_until: Option<DateTime<Utc>> = None;
+    let mut leads = false;
+    loop {
+        match client.claim_writer(lease_ttl).await {
+            Ok(lease) => {
+                leads = claimed_until.is_some_and(|until| until > Utc::now());
+                claimed_until = Some(lease.expires_at - margin);
+            }
+            Err(Error::WriterLeaseHeld { holder, .. }) => {
+                tracing::debug!(%holder, "another node leads");
+                claimed_until = None;
+                leads = false;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to claim leadership");
+                leads = leads && claimed_until.is_some_and(|until| until > Utc::now());
+            }
+        }
+        leader.send_if_modified(|current| std::mem::replace(current, leads) != leads);
+
+        tokio::select! {
+            _ = stopped.changed() => break,
+            _ = tokio::time::sleep(renew_every) => {}
+        }
+    }
+
+    leader.send_replace(false);
+    if claimed_until.is_some() {
+        return client.release_writer().await;
+    }
+    Ok(())
+}
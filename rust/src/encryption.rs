@@ -0,0 +1,540 @@
+//! Client-side encryption of selected fields
+//!
+//! With [`Client::with_field_encryption`], `store` and `patch` encrypt the
+//! fields at the configured dot-separated paths, such as `user.email`, with
+//! AES-256-GCM and leave the rest of the document readable, so the server can
+//! still classify and chart it. An encrypted field holds an envelope with the
+//! nonce and ciphertext in standard base64:
+//!
+//! ```json
+//! {"user": {"name": "Ada", "email": {"@encrypted": "3q2+7w..."}}}
+//! ```
+//!
+//! The field's path is bound to its ciphertext, so moving an encrypted value
+//! to another field makes it fail to decrypt. `retrieve`, `history` and
+//! everything built on them decrypt the configured paths and leave plain
+//! values there as they are, so existing documents can be migrated by
+//! storing them again. Patches may set an encrypted field or one of its
+//! ancestors, but not a path inside it. Batch operations on the client's own
+//! token are encrypted the same way; other tokens' are sent as given.
+//!
+//! To rotate keys, encrypt with a [`Keyring`]: envelopes name the key they
+//! were sealed with (`"@key": "2025-06"`), new writes use the current key and
+//! older keys stay in the ring to read what they sealed. Envelopes without a
+//! key id are tried against every key. [`Client::reencrypt`] moves a stored
+//! document to the current key; values in history keep their old keys.
+//!
+//! ```no_run
+//! use keyvalue_client::encryption::{FieldEncryption, FieldKey};
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run(key: [u8; 32]) -> Result<(), Error> {
+//! let encryption = FieldEncryption::new(&FieldKey::from_bytes(key), ["user.email", "user.phone"]);
+//! let client = Client::new("word-word-word-word-word").with_field_encryption(encryption);
+//!
+//! client
+//!     .store(&serde_json::json!({"user": {"name": "Ada", "email": "ada@example.com"}}), None)
+//!     .await?;
+//! assert_eq!(client.retrieve().await?.data["user"]["email"], "ada@example.com");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::path::get_path;
+use crate::{Client, Error, PatchOperations, Ttl};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use zeroize::Zeroize;
+
+pub const ENCRYPTED_FIELD: &str = "@encrypted";
+pub const KEY_ID_FIELD: &str = "@key";
+
+const NONCE_LEN: usize = 12;
+
+/// 256-bit field encryption key, zeroized on drop
+#[derive(Clone)]
+pub struct FieldKey([u8; 32]);
+
+impl FieldKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Fresh random key, e.g. to keep in a secrets manager
+    pub fn generate() -> Self {
+        let mut bytes = [0; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Drop for FieldKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for FieldKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FieldKey(****)")
+    }
+}
+
+/// Keys by id: the current one seals new values, all of them open old ones
+#[derive(Clone)]
+pub struct Keyring {
+    /// Current key first
+    keys: Vec<(String, Aes256Gcm)>,
+}
+
+impl Keyring {
+    /// Ring whose current key is `key`, named `id`
+    pub fn new(id: impl Into<String>, key: &FieldKey) -> Self {
+        Self {
+            keys: vec![(id.into(), cipher(key))],
+        }
+    }
+
+    /// Also open values sealed with `key`, named `id`
+    pub fn with_old_key(mut self, id: impl Into<String>, key: &FieldKey) -> Self {
+        self.keys.push((id.into(), cipher(key)));
+        self
+    }
+
+    /// Seal new values with `key`, named `id`, keeping the previous keys for reading
+    pub fn rotate(mut self, id: impl Into<String>, key: &FieldKey) -> Self {
+        self.keys.insert(0, (id.into(), cipher(key)));
+        self
+    }
+
+    /// Id of the key new values are sealed with
+    pub fn current_id(&self) -> &str {
+        &self.keys[0].0
+    }
+
+    fn current(&self) -> &Aes256Gcm {
+        &self.keys[0].1
+    }
+
+    /// Keys that may have sealed an envelope naming `id`, or all of them if it names none
+    fn candidates<'a>(&'a self, id: Option<&'a str>) -> impl Iterator<Item = &'a Aes256Gcm> + 'a {
+        self.keys
+            .iter()
+            .filter(move |(key_id, _)| id.is_none_or(|id| id == key_id))
+            .map(|(_, cipher)| cipher)
+    }
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.keys.iter().map(|(id, _)| id)).finish()
+    }
+}
+
+/// Which fields to encrypt, and the keys to encrypt them with
+#[derive(Clone)]
+pub struct FieldEncryption {
+    keyring: Keyring,
+    paths: Vec<String>,
+}
+
+impl fmt::Debug for FieldEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldEncryption")
+            .field("keyring", &self.keyring)
+            .field("paths", &self.paths)
+            .finish()
+    }
+}
+
+impl FieldEncryption {
+    /// Encrypt the fields at `paths` with `key`, without naming it in envelopes
+    pub fn new(key: &FieldKey, paths: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::with_keyring(Keyring::new("", key), paths)
+    }
+
+    /// Encrypt the fields at `paths` with the current key of `keyring`
+    pub fn with_keyring(keyring: Keyring, paths: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            keyring,
+            paths: paths.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Copy of `data` with each configured field present in it encrypted
+    pub fn encrypt(&self, data: &Value) -> Result<Value, Error> {
+        let mut data = data.clone();
+        for path in &self.paths {
+            self.seal_at(&mut data, path, path)?;
+        }
+        Ok(data)
+    }
+
+    /// Decrypt the configured fields of `data` in place
+    pub fn decrypt(&self, data: &mut Value) -> Result<(), Error> {
+        for path in &self.paths {
+            let Some(field) = field_mut(data, path) else {
+                continue;
+            };
+            let Some(sealed) = sealed(field) else {
+                continue;
+            };
+            *field = self
+                .open(&sealed, path)
+                .ok_or_else(|| Error::Encryption(format!("Cannot decrypt `{}`: wrong key or altered value", path)))?;
+        }
+        Ok(())
+    }
+
+    /// Configured fields of stored `data` that are plain or sealed with an old key
+    fn stale_fields(&self, data: &Value) -> Vec<&str> {
+        let current = self.keyring.current_id();
+        self.paths
+            .iter()
+            .filter(|path| {
+                get_path(data, path).is_some_and(|field| match sealed(field) {
+                    Some(sealed) => sealed.key_id.unwrap_or_default() != current,
+                    None => true,
+                })
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// `patch` with the configured fields it sets encrypted
+    fn encrypt_patch<'a>(&self, patch: &'a PatchOperations) -> Result<Cow<'a, PatchOperations>, Error> {
+        let Some(set) = &patch.set else {
+            return Ok(Cow::Borrowed(patch));
+        };
+        let mut encrypted = set.clone();
+        for (target, value) in encrypted.iter_mut() {
+            for path in &self.paths {
+                if target == path {
+                    if sealed(value).is_none() {
+                        *value = self.seal(value, path)?;
+                    }
+                } else if let Some(inner) = path.strip_prefix(target.as_str()).and_then(|p| p.strip_prefix('.')) {
+                    self.seal_at(value, inner, path)?;
+                } else if target.starts_with(path.as_str()) && target[path.len()..].starts_with('.') {
+                    return Err(Error::Validation(format!(
+                        "Cannot patch `{}` inside encrypted field `{}`; set `{}` as a whole",
+                        target, path, path
+                    )));
+                }
+            }
+        }
+        Ok(Cow::Owned(PatchOperations {
+            set: Some(encrypted),
+            remove: patch.remove.clone(),
+        }))
+    }
+
+    /// Encrypt the field at `relative` within `data`, bound to its full `path`
+    fn seal_at(&self, data: &mut Value, relative: &str, path: &str) -> Result<(), Error> {
+        if let Some(field) = field_mut(data, relative) {
+            if sealed(field).is_none() {
+                *field = self.seal(field, path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn seal(&self, value: &Value, path: &str) -> Result<Value, Error> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let plain = serde_json::to_vec(value)?;
+        let payload = Payload {
+            msg: &plain,
+            aad: path.as_bytes(),
+        };
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.keyring
+                .current()
+                .encrypt(&nonce, payload)
+                .map_err(|_| Error::Encryption(format!("Cannot encrypt `{}`", path)))?,
+        );
+        let mut envelope = serde_json::json!({ ENCRYPTED_FIELD: BASE64.encode(sealed) });
+        let id = self.keyring.current_id();
+        if !id.is_empty() {
+            envelope[KEY_ID_FIELD] = id.into();
+        }
+        Ok(envelope)
+    }
+
+    fn open(&self, sealed: &Sealed<'_>, path: &str) -> Option<Value> {
+        let bytes = BASE64.decode(sealed.ciphertext).ok()?;
+        if bytes.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        self.keyring.candidates(sealed.key_id).find_map(|cipher| {
+            let payload = Payload {
+                msg: ciphertext,
+                aad: path.as_bytes(),
+            };
+            let plain = cipher.decrypt(Nonce::from_slice(nonce), payload).ok()?;
+            serde_json::from_slice(&plain).ok()
+        })
+    }
+}
+
+impl Client {
+    /// Encrypt selected fields on `store` and `patch`, decrypting them on reads
+    pub fn with_field_encryption(mut self, encryption: FieldEncryption) -> Self {
+        self.field_encryption = Some(Arc::new(encryption));
+        self
+    }
+
+    /// Rewrite the stored document so every configured field is sealed with the current key
+    ///
+    /// Returns whether anything was rewritten. Fields are replaced with a
+    /// versioned patch, so a concurrent write fails this call with
+    /// [`Error::Conflict`] instead of being lost; documents stored with
+    /// checksums or compression are stored again whole, keeping their TTL.
+    pub async fn reencrypt(&self) -> Result<bool, Error> {
+        let encryption = self
+            .field_encryption
+            .clone()
+            .ok_or_else(|| Error::Validation("Field encryption is not enabled".to_string()))?;
+        let mut raw = self.clone();
+        raw.field_encryption = None;
+        let Some(current) = raw.retrieve_existing().await? else {
+            return Ok(false);
+        };
+        let stale = encryption.stale_fields(&current.data);
+        if stale.is_empty() {
+            return Ok(false);
+        }
+
+        let mut data = current.data.clone();
+        encryption.decrypt(&mut data)?;
+        if self.wraps_payload() {
+            let ttl = current.expires_at.map(|at| Ttl::until(at).seconds());
+            self.store(&data, ttl).await?;
+        } else {
+            let set = stale
+                .into_iter()
+                .filter_map(|path| Some((path.to_string(), get_path(&data, path)?.clone())))
+                .collect();
+            let patch = PatchOperations {
+                set: Some(set),
+                remove: None,
+            };
+            self.patch(current.version, &patch, None).await?;
+        }
+        Ok(true)
+    }
+
+    /// `data` with the configured fields encrypted
+    pub(crate) fn encrypt_fields<'a>(&self, data: &'a Value) -> Result<Cow<'a, Value>, Error> {
+        match &self.field_encryption {
+            Some(encryption) => Ok(Cow::Owned(encryption.encrypt(data)?)),
+            None => Ok(Cow::Borrowed(data)),
+        }
+    }
+
+    /// `patch` with the configured fields it sets encrypted
+    pub(crate) fn encrypt_patch<'a>(&self, patch: &'a PatchOperations) -> Result<Cow<'a, PatchOperations>, Error> {
+        match &self.field_encryption {
+            Some(encryption) => encryption.encrypt_patch(patch),
+            None => Ok(Cow::Borrowed(patch)),
+        }
+    }
+
+    /// Decrypt the configured fields of `data` in place
+    pub(crate) fn decrypt_fields(&self, data: &mut Value) -> Result<(), Error> {
+        match &self.field_encryption {
+            Some(encryption) => encryption.decrypt(data),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The value at dot-separated `path` through nested objects
+fn field_mut<'a>(data: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.').try_fold(data, |value, segment| value.as_object_mut()?.get_mut(segment))
+}
+
+/// Contents of an encrypted field envelope
+struct Sealed<'a> {
+    ciphertext: &'a str,
+    key_id: Option<&'a str>,
+}
+
+fn sealed(value: &Value) -> Option<Sealed<'_>> {
+    let obj = value.as_object()?;
+    let key_id = match obj.get(KEY_ID_FIELD) {
+        Some(id) => Some(id.as_str()?),
+        None => None,
+    };
+    if obj.len() != 1 + usize::from(key_id.is_some()) {
+        return None;
+    }
+    Some(Sealed {
+        ciphertext: obj.get(ENCRYPTED_FIELD)?.as_str()?,
+        key_id,
+    })
+}
+
+fn cipher(key: &FieldKey) -> Aes256Gcm {
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(byte: u8) -> FieldKey {
+        FieldKey::from_bytes([byte; 32])
+    }
+
+    fn encryption(byte: u8) -> FieldEncryption {
+        FieldEncryption::new(&key(byte), ["user.email"])
+    }
+
+    #[test]
+    fn round_trip_encrypts_only_configured_fields() {
+        let plain = json!({"user": {"name": "Ada", "email": "ada@example.com"}});
+        let mut data = encryption(1).encrypt(&plain).unwrap();
+        assert_eq!(data["user"]["name"], "Ada");
+        assert!(data["user"]["email"][ENCRYPTED_FIELD].is_string());
+        assert!(!data.to_string().contains("ada@example.com"));
+
+        encryption(1).decrypt(&mut data).unwrap();
+        assert_eq!(data, plain);
+    }
+
+    #[test]
+    fn encrypt_leaves_sealed_and_missing_fields_alone() {
+        let sealed = encryption(1).encrypt(&json!({"user": {"email": "a@b.c"}})).unwrap();
+        assert_eq!(encryption(1).encrypt(&sealed).unwrap(), sealed);
+        let without = json!({"user": {"name": "Ada"}});
+        assert_eq!(encryption(1).encrypt(&without).unwrap(), without);
+    }
+
+    #[test]
+    fn decrypt_leaves_plain_values() {
+        let mut data = json!({"user": {"email": "ada@example.com"}});
+        encryption(1).decrypt(&mut data).unwrap();
+        assert_eq!(data["user"]["email"], "ada@example.com");
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let mut data = encryption(1).encrypt(&json!({"user": {"email": "a@b.c"}})).unwrap();
+        assert!(matches!(encryption(2).decrypt(&mut data), Err(Error::Encryption(_))));
+    }
+
+    #[test]
+    fn moved_field_fails() {
+        let both = FieldEncryption::new(&key(1), ["user.email", "user.phone"]);
+        let mut data = both.encrypt(&json!({"user": {"email": "a@b.c"}})).unwrap();
+        data["user"]["phone"] = data["user"]["email"].take();
+        assert!(matches!(both.decrypt(&mut data), Err(Error::Encryption(_))));
+    }
+
+    #[test]
+    fn tampered_ciphertext_fails() {
+        let mut data = encryption(1).encrypt(&json!({"user": {"email": "a@b.c"}})).unwrap();
+        let mut bytes = BASE64.decode(data["user"]["email"][ENCRYPTED_FIELD].as_str().unwrap()).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        data["user"]["email"][ENCRYPTED_FIELD] = BASE64.encode(&bytes).into();
+        assert!(matches!(encryption(1).decrypt(&mut data), Err(Error::Encryption(_))));
+
+        data["user"]["email"][ENCRYPTED_FIELD] = "AAAA".into();
+        assert!(matches!(encryption(1).decrypt(&mut data), Err(Error::Encryption(_))));
+    }
+
+    #[test]
+    fn keyring_reads_old_keys_and_seals_with_current() {
+        let old = FieldEncryption::with_keyring(Keyring::new("2024", &key(1)), ["secret"]);
+        let sealed = old.encrypt(&json!({"secret": 42})).unwrap();
+        assert_eq!(sealed["secret"][KEY_ID_FIELD], "2024");
+
+        let rotated = Keyring::new("2024", &key(1)).rotate("2025", &key(2));
+        let new = FieldEncryption::with_keyring(rotated, ["secret"]);
+        let mut data = sealed.clone();
+        new.decrypt(&mut data).unwrap();
+        assert_eq!(data["secret"], 42);
+        assert_eq!(new.encrypt(&data).unwrap()["secret"][KEY_ID_FIELD], "2025");
+
+        let forgotten = FieldEncryption::with_keyring(Keyring::new("2025", &key(2)), ["secret"]);
+        assert!(forgotten.decrypt(&mut sealed.clone()).is_err());
+    }
+
+    #[test]
+    fn unnamed_envelopes_try_every_key() {
+        let mut data = encryption(1).encrypt(&json!({"user": {"email": "a@b.c"}})).unwrap();
+        let ring = Keyring::new("2025", &key(2)).with_old_key("2024", &key(1));
+        FieldEncryption::with_keyring(ring, ["user.email"]).decrypt(&mut data).unwrap();
+        assert_eq!(data["user"]["email"], "a@b.c");
+    }
+
+    #[test]
+    fn stale_fields_are_plain_or_sealed_with_old_keys() {
+        let old = FieldEncryption::with_keyring(Keyring::new("2024", &key(1)), ["a", "b"]);
+        let stored = old.encrypt(&json!({"a": 1, "b": 2})).unwrap();
+        let current = FieldEncryption::with_keyring(Keyring::new("2025", &key(2)), ["a", "b", "c"]);
+        let mut stored = stored;
+        stored["b"] = current.encrypt(&json!({"b": 2})).unwrap()["b"].take();
+        stored["c"] = json!(3);
+        assert_eq!(current.stale_fields(&stored), ["a", "c"]);
+    }
+
+    #[test]
+    fn patches_seal_fields_and_ancestors_once() {
+        let set = |path: &str, value: Value| PatchOperations {
+            set: Some([(path.to_string(), value)].into_iter().collect()),
+            remove: None,
+        };
+        let encryption = encryption(1);
+
+        let field = encryption.encrypt_patch(&set("user.email", json!("a@b.c"))).unwrap().into_owned();
+        let sealed = &field.set.as_ref().unwrap()["user.email"];
+        assert!(sealed[ENCRYPTED_FIELD].is_string());
+        let again = encryption.encrypt_patch(&field).unwrap().into_owned();
+        assert_eq!(&again.set.as_ref().unwrap()["user.email"], sealed);
+
+        let ancestor = encryption
+            .encrypt_patch(&set("user", json!({"name": "Ada", "email": "a@b.c"})))
+            .unwrap()
+            .into_owned();
+        let user = &ancestor.set.as_ref().unwrap()["user"];
+        assert_eq!(user["name"], "Ada");
+        assert!(user["email"][ENCRYPTED_FIELD].is_string());
+
+        let inside = encryption.encrypt_patch(&set("user.email.domain", json!("b.c"))).map(Cow::into_owned);
+        assert!(matches!(inside, Err(Error::Validation(_))));
+        let sibling = encryption.encrypt_patch(&set("user.emails", json!([]))).unwrap().into_owned();
+        assert_eq!(sibling.set.as_ref().unwrap()["user.emails"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn versioned_and_lease_writes_seal_fields() {
+        use crate::mock::MockServer;
+
+        let server = MockServer::start().await;
+        let token = "word-word-word-word-word";
+        let client = server.client(token).with_field_encryption(encryption(1)).with_auto_version(true);
+        client.store(&json!({"user": {"email": "a@b.c"}}), None).await.unwrap();
+        client.store(&json!({"user": {"email": "d@e.f", "name": "Ada"}}), None).await.unwrap();
+        client.clone().with_writer_id("writer").claim_writer(std::time::Duration::from_secs(60)).await.unwrap();
+
+        let stored = server.stored(token).unwrap();
+        assert!(stored["user"]["email"][ENCRYPTED_FIELD].is_string());
+        assert!(!stored.to_string().contains("d@e.f"));
+        assert_eq!(client.retrieve().await.unwrap().data["user"]["email"], "d@e.f");
+    }
+}
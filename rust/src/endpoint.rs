@@ -0,0 +1,86 @@
+//! API endpoints and their URL paths
+//!
+//! Paths default to the hosted service's `/api/...` routes. Self-hosted
+//! instances behind a reverse proxy can mount them under a prefix or move
+//! individual endpoints with [`ClientBuilder::path_prefix`] and
+//! [`ClientBuilder::endpoint_path`].
+//!
+//! ```
+//! use keyvalue_client::{Client, Endpoint};
+//!
+//! # fn main() -> Result<(), keyvalue_client::Error> {
+//! // https://internal.example.com/kv/api/store, .../kv/v2/history
+//! let client = Client::builder()
+//!     .base_url("https://internal.example.com")
+//!     .path_prefix("/kv")
+//!     .endpoint_path(Endpoint::History, "/v2/history")
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ClientBuilder::path_prefix`]: crate::ClientBuilder::path_prefix
+//! [`ClientBuilder::endpoint_path`]: crate::ClientBuilder::endpoint_path
+
+use std::collections::BTreeMap;
+
+/// API endpoint a request is sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Endpoint {
+    Generate,
+    Store,
+    Retrieve,
+    Delete,
+    /// Partial updates; shares `/api/store` with [`Endpoint::Store`] by default
+    Patch,
+    History,
+    Batch,
+    /// Health check used by [`Client::ping`](crate::Client::ping)
+    Health,
+    /// Quota introspection used by [`Client::usage`](crate::Client::usage)
+    Usage,
+}
+
+impl Endpoint {
+    /// Path on the hosted service
+    pub fn default_path(self) -> &'static str {
+        match self {
+            Endpoint::Generate => "/api/generate",
+            Endpoint::Store | Endpoint::Patch => "/api/store",
+            Endpoint::Retrieve => "/api/retrieve",
+            Endpoint::Delete => "/api/delete",
+            Endpoint::History => "/api/history",
+            Endpoint::Batch => "/api/batch",
+            Endpoint::Health => "/api/health",
+            Endpoint::Usage => "/api/usage",
+        }
+    }
+}
+
+/// Path prefix and per-endpoint overrides
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Endpoints {
+    prefix: String,
+    paths: BTreeMap<Endpoint, String>,
+}
+
+impl Endpoints {
+    pub(crate) fn set_prefix(&mut self, prefix: &str) {
+        let prefix = prefix.trim_matches('/');
+        self.prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", prefix)
+        };
+    }
+
+    pub(crate) fn set_path(&mut self, endpoint: Endpoint, path: &str) {
+        self.paths.insert(endpoint, format!("/{}", path.trim_start_matches('/')));
+    }
+
+    /// Full path for an endpoint, including the prefix
+    pub(crate) fn path(&self, endpoint: Endpoint) -> String {
+        let path = self.paths.get(&endpoint).map_or(endpoint.default_path(), String::as_str);
+        format!("{}{}", self.prefix, path)
+    }
+}
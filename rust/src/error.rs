@@ -0,0 +1,280 @@
+//! Client error type
+
+use crate::contract::Violation;
+use crate::{BackfillReport, SchemaFingerprint};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Client errors
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("HTTP request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Not found: {message}")]
+    NotFound { message: String },
+
+    #[error("Unauthorized ({status}): {message}")]
+    Unauthorized { status: StatusCode, message: String },
+
+    #[error("Version conflict: {message}")]
+    Conflict {
+        /// Current server version, when reported
+        current_version: Option<i32>,
+        message: String,
+    },
+
+    #[error("Payload too large: {message}")]
+    PayloadTooLarge {
+        /// Payload size in bytes, when reported or estimated
+        size: Option<u64>,
+        /// Size limit in bytes, when reported
+        limit: Option<u64>,
+        message: String,
+    },
+
+    #[error("Rate limited: {message}")]
+    RateLimited {
+        /// Delay requested by the server's `Retry-After` header
+        retry_after: Option<Duration>,
+        message: String,
+    },
+
+    #[error("Server error ({status}): {message}")]
+    Server { status: StatusCode, message: String },
+
+    /// Any other non-success response
+    #[error("API error ({status}): {message}")]
+    Api { status: StatusCode, message: String },
+
+    /// Successful response whose body did not match the expected shape
+    #[error("Failed to decode response ({status}) at `{path}`: {source}")]
+    Decode {
+        status: StatusCode,
+        /// Path to the offending field, e.g. `events[3].created_at`
+        path: String,
+        /// Raw response body, redacted and truncated
+        body: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("TLS configuration error: {0}")]
+    Tls(String),
+
+    #[error("Failed to build client: {0}")]
+    Build(#[from] BuildError),
+
+    /// A batch sent in the background failed, or an operation in it did
+    #[error("Scheduled batch failed: {message}")]
+    BatchFailed {
+        /// HTTP status of the failed batch request, if any
+        status: Option<StatusCode>,
+        message: String,
+    },
+
+    /// An operation of an atomic batch failed and the batch was rolled back
+    #[error("Atomic batch rolled back: {message}")]
+    BatchRolledBack {
+        /// Error of the first failed operation
+        message: String,
+        /// Operations that applied but could not be undone, by index
+        not_undone: Vec<usize>,
+    },
+
+    /// A backfill failed after storing the points in `report`
+    #[error("Backfill stopped after {} points: {source}", report.written)]
+    BackfillStopped {
+        report: BackfillReport,
+        #[source]
+        source: Box<Error>,
+    },
+
+    /// The captcha provider could not produce a Turnstile token
+    #[error("Captcha provider failed: {0}")]
+    Captcha(String),
+
+    /// Stored data is not a valid protobuf envelope for the requested message
+    #[error("Protobuf payload error: {0}")]
+    Proto(String),
+
+    /// Token vault file could not be opened, read or decrypted
+    #[error("Token vault error: {0}")]
+    Vault(String),
+
+    /// Stored data is a compressed envelope that cannot be decoded
+    #[error("Compression error: {0}")]
+    Compression(String),
+
+    /// An encrypted field could not be encrypted or decrypted
+    #[error("Field encryption error: {0}")]
+    Encryption(String),
+
+    /// OS credential store could not be reached, or refused to read or write a token
+    #[error("Credential store error: {0}")]
+    TokenStore(String),
+
+    /// Local SQLite copy of a value could not be read or written
+    #[error("Local store error: {0}")]
+    LocalStore(String),
+
+    /// Stored data is unsigned, or its ed25519 signature does not verify
+    #[error("Invalid payload signature: {0}")]
+    InvalidSignature(String),
+
+    /// Requests fail fast after repeated outages; see [`circuit`](crate::circuit)
+    #[error("Circuit open after repeated failures, next attempt in {retry_in:?}")]
+    CircuitOpen {
+        /// Time until a probe request is let through
+        retry_in: Duration,
+    },
+
+    #[error("Token is required")]
+    MissingToken,
+
+    #[error("Validation error: {0}")]
+    Validation(String),
+
+    #[error("JSON serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Retrieved data does not match the checksum stored with it
+    #[error("Integrity mismatch: expected hash {expected}, found {found}")]
+    IntegrityMismatch { expected: String, found: String },
+
+    /// Retrieved data lacks the checksum envelope it should have, or has extra fields beside it
+    #[error("Checksum envelope missing or damaged: {0}")]
+    ChecksumEnvelope(String),
+
+    #[error("Schema mismatch: expected {expected}, found {found}")]
+    SchemaMismatch {
+        expected: SchemaFingerprint,
+        found: SchemaFingerprint,
+    },
+
+    /// Retrieved data broke the client's contract
+    #[error("Contract violation: {}", join_violations(violations))]
+    ContractViolation { violations: Vec<Violation> },
+
+    #[error("Writer lease held by {holder} until {expires_at}")]
+    WriterLeaseHeld {
+        holder: String,
+        expires_at: DateTime<Utc>,
+    },
+}
+
+/// Failure to set up the HTTP client in [`ClientBuilder::build`](crate::ClientBuilder::build)
+#[derive(Error, Debug)]
+pub enum BuildError {
+    /// TLS roots, client identity or backend could not be initialized
+    #[error("TLS initialization failed: {0}")]
+    Tls(String),
+
+    #[error("HTTP client initialization failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// Settings from the environment or a config file could not be used
+    #[error("Invalid client configuration: {0}")]
+    Config(String),
+}
+
+impl Error {
+    /// HTTP status of the API response that caused this error, if any
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Request(e) => e.status(),
+            Error::NotFound { .. } => Some(StatusCode::NOT_FOUND),
+            Error::Conflict { .. } => Some(StatusCode::CONFLICT),
+            Error::PayloadTooLarge { .. } => Some(StatusCode::PAYLOAD_TOO_LARGE),
+            Error::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            Error::Unauthorized { status, .. }
+            | Error::Server { status, .. }
+            | Error::Api { status, .. } => Some(*status),
+            Error::BatchFailed { status, .. } => *status,
+            Error::BackfillStopped { source, .. } => source.status(),
+            _ => None,
+        }
+    }
+
+    /// Whether the same request may succeed if retried
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Request(e) => e.is_timeout() || e.is_connect(),
+            Error::RateLimited { .. } | Error::Server { .. } => true,
+            Error::Api { status, .. } => *status == StatusCode::REQUEST_TIMEOUT,
+            _ => false,
+        }
+    }
+
+    /// Build the semantic error for a non-success response
+    pub(crate) fn from_response(status: StatusCode, headers: &HeaderMap, body: &str) -> Self {
+        let body: Value = serde_json::from_str(body).unwrap_or_default();
+        let field = |names: &[&str]| names.iter().find_map(|name| body.get(*name));
+        let message = field(&["error"])
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("HTTP {}", status));
+
+        match status {
+            StatusCode::NOT_FOUND => Error::NotFound { message },
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Error::Unauthorized { status, message }
+            }
+            StatusCode::CONFLICT => Error::Conflict {
+                current_version: field(&["current_version", "currentVersion"])
+                    .and_then(Value::as_i64)
+                    .and_then(|v| i32::try_from(v).ok()),
+                message,
+            },
+            StatusCode::PAYLOAD_TOO_LARGE => Error::PayloadTooLarge {
+                size: field(&["size"]).and_then(Value::as_u64),
+                limit: field(&["limit", "max_size", "maxSize"]).and_then(Value::as_u64),
+                message,
+            },
+            StatusCode::TOO_MANY_REQUESTS => Error::RateLimited {
+                retry_after: parse_retry_after(headers),
+                message,
+            },
+            s if s.is_server_error() => Error::Server { status, message },
+            _ => Error::Api { status, message },
+        }
+    }
+
+    /// Message from the server for API errors
+    pub(crate) fn message_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Error::NotFound { message }
+            | Error::Unauthorized { message, .. }
+            | Error::Conflict { message, .. }
+            | Error::PayloadTooLarge { message, .. }
+            | Error::RateLimited { message, .. }
+            | Error::Server { message, .. }
+            | Error::Api { message, .. } => Some(message),
+            _ => None,
+        }
+    }
+}
+
+fn join_violations(violations: &[Violation]) -> String {
+    let shown: Vec<_> = violations.iter().take(3).map(ToString::to_string).collect();
+    match violations.len() - shown.len() {
+        0 => shown.join("; "),
+        more => format!("{}; and {} more", shown.join("; "), more),
+    }
+}
+
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
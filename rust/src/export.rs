@@ -0,0 +1,188 @@
+//! History exporters for CSV and InfluxDB line protocol
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, CsvExporter, Error, HistoryOptions};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let mut exporter = CsvExporter::new(std::io::stdout());
+//! client.export_history(&HistoryOptions::default(), &mut exporter).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{ClassifiedType, Client, Error, HistoryEvent, HistoryOptions};
+use futures_util::TryStreamExt;
+use std::io::{self, Write};
+use std::pin::pin;
+
+/// Sink for history events
+pub trait HistoryExporter {
+    /// Write a single event
+    fn write_event(&mut self, event: &HistoryEvent) -> io::Result<()>;
+
+    /// Called once after the last event
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes events as CSV with a header row
+pub struct CsvExporter<W: Write> {
+    writer: W,
+    wrote_header: bool,
+}
+
+impl<W: Write> CsvExporter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            wrote_header: false,
+        }
+    }
+
+    /// Consume the exporter, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.wrote_header {
+            writeln!(
+                self.writer,
+                "seq,created_at,expires_at,classified_type,numeric_value,text_value,confidence,payload"
+            )?;
+            self.wrote_header = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> HistoryExporter for CsvExporter<W> {
+    fn write_event(&mut self, event: &HistoryEvent) -> io::Result<()> {
+        self.write_header()?;
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{}",
+            event.seq,
+            event.created_at.to_rfc3339(),
+            event.expires_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            csv_field(event.classified_type.as_ref().map_or("", ClassifiedType::as_str)),
+            event.numeric_value.map(|v| v.to_string()).unwrap_or_default(),
+            csv_field(event.text_value.as_deref().unwrap_or("")),
+            event.confidence.map(|v| v.to_string()).unwrap_or_default(),
+            csv_field(&event.payload.to_string()),
+        )
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.writer.flush()
+    }
+}
+
+/// Writes events as InfluxDB line protocol
+///
+/// Each event becomes one point in `measurement`, tagged with `type` when
+/// classified, with `value`, `text`, `confidence` and `seq` fields and a
+/// nanosecond timestamp from `created_at`.
+pub struct InfluxExporter<W: Write> {
+    writer: W,
+    measurement: String,
+}
+
+impl<W: Write> InfluxExporter<W> {
+    pub fn new(writer: W, measurement: impl Into<String>) -> Self {
+        Self {
+            writer,
+            measurement: measurement.into(),
+        }
+    }
+
+    /// Consume the exporter, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> HistoryExporter for InfluxExporter<W> {
+    fn write_event(&mut self, event: &HistoryEvent) -> io::Result<()> {
+        let mut line = influx_escape(&self.measurement, &[',', ' ']);
+        if let Some(typ) = &event.classified_type {
+            line.push_str(",type=");
+            line.push_str(&influx_escape(typ.as_str(), &[',', '=', ' ']));
+        }
+
+        let mut fields = vec![];
+        if let Some(value) = event.numeric_value {
+            fields.push(format!("value={}", value));
+        }
+        if let Some(text) = &event.text_value {
+            fields.push(format!("text=\"{}\"", influx_escape(text, &['"'])));
+        }
+        if let Some(confidence) = event.confidence {
+            fields.push(format!("confidence={}", confidence));
+        }
+        fields.push(format!("seq={}i", event.seq));
+
+        line.push(' ');
+        line.push_str(&fields.join(","));
+        if let Some(ns) = event.created_at.timestamp_nanos_opt() {
+            line.push(' ');
+            line.push_str(&ns.to_string());
+        }
+        writeln!(self.writer, "{}", line)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Client {
+    /// Stream all history matching `options` into an exporter, following pagination
+    ///
+    /// Events are written in the order the API returns them (newest first).
+    /// Returns the number of events written.
+    pub async fn export_history(
+        &self,
+        options: &HistoryOptions,
+        exporter: &mut impl HistoryExporter,
+    ) -> Result<usize, Error> {
+        let mut pages = pin!(self.history_pages(options));
+        let mut count = 0;
+
+        while let Some(page) = pages.try_next().await? {
+            for event in &page.events {
+                exporter.write_event(event)?;
+            }
+            count += page.events.len();
+        }
+
+        exporter.finish()?;
+        Ok(count)
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn influx_escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        if c == '\n' {
+            escaped.push_str("\\n");
+            continue;
+        }
+        escaped.push(c);
+    }
+    escaped
+}
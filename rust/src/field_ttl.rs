@@ -0,0 +1,263 @@
+//! Per-field expiry within a single document
+//!
+//! The API only expires whole documents. [`Client::set_field`] records when
+//! each field was written, and optionally when it expires, under
+//! [`FIELD_STAMPS_FIELD`] keyed by dot-notation path. Reads through
+//! [`Client::get_fresh`] ignore stale or expired fields, and
+//! [`Client::sweep_expired_fields`] removes expired fields with a versioned
+//! patch. Fields written by plain `store` or `patch` carry no stamp and are
+//! never fresh.
+//!
+//! `set_field` is a versioned patch retried on version conflicts. A missing
+//! document is created empty first and only patched once read back
+//! unchanged, but the API has no create-if-absent, so two first writes at
+//! once can still lose one; store the document up front where that matters.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let ttl = Duration::from_secs(300);
+//! client.set_field("sensors.door", &serde_json::json!("open"), Some(ttl)).await?;
+//!
+//! let door: Option<String> = client.get_fresh("sensors.door", Duration::from_secs(60)).await?;
+//! let removed = client.sweep_expired_fields().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::path::get_path;
+use crate::{Client, Error, PatchOperations, PatchResponse, RetryPolicy};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// Document field holding per-field write and expiry times
+pub const FIELD_STAMPS_FIELD: &str = "_fields";
+
+/// Retries of `set_field` after version conflicts
+const CONFLICT_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 10,
+    initial_backoff: Duration::from_millis(20),
+    max_backoff: Duration::from_secs(1),
+};
+
+/// Write and expiry time of one field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldStamp {
+    pub written_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl FieldStamp {
+    /// Whether the field has passed its expiry time
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Utc::now())
+    }
+
+    /// Whether the field is unexpired and was written within `max_age`
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        let age = (Utc::now() - self.written_at).to_std().unwrap_or_default();
+        !self.is_expired() && age <= max_age
+    }
+}
+
+/// Field stamps recorded in a stored document, keyed by path
+pub fn field_stamps(data: &Value) -> BTreeMap<String, FieldStamp> {
+    data.get(FIELD_STAMPS_FIELD)
+        .and_then(|stamps| BTreeMap::deserialize(stamps).ok())
+        .unwrap_or_default()
+}
+
+impl Client {
+    /// Write one field, stamping it with the current time and optional expiry
+    pub async fn set_field(
+        &self,
+        path: &str,
+        value: &Value,
+        ttl: Option<Duration>,
+    ) -> Result<PatchResponse, Error> {
+        let reserved = path.split('.').next() == Some(FIELD_STAMPS_FIELD);
+        if path.is_empty() || path.starts_with('/') || reserved {
+            return Err(Error::Validation(format!("Invalid field path: {:?}", path)));
+        }
+        let now = Utc::now();
+        let expires_at = ttl
+            .map(|ttl| {
+                chrono::Duration::from_std(ttl)
+                    .map(|ttl| now + ttl)
+                    .map_err(|_| Error::Validation("Field TTL out of range".to_string()))
+            })
+            .transpose()?;
+        let stamp = FieldStamp {
+            written_at: now,
+            expires_at,
+        };
+
+        let mut attempt = 0;
+        let mut created = None;
+        loop {
+            attempt += 1;
+            let Some(current) = self.retrieve_existing().await? else {
+                if attempt >= CONFLICT_RETRY.max_attempts {
+                    return Err(Error::Conflict {
+                        current_version: None,
+                        message: "Document deleted while it was being created".to_string(),
+                    });
+                }
+                // Create the document, then read it back and patch the field in
+                created = Some(self.store(&Value::Object(Map::new()), None).await?.version);
+                continue;
+            };
+            if created.take().is_some_and(|version| version != current.version) {
+                // Another writer got in between creating the document and reading it back
+                if attempt >= CONFLICT_RETRY.max_attempts {
+                    return Err(Error::Conflict {
+                        current_version: Some(current.version),
+                        message: "Document changed while it was being created".to_string(),
+                    });
+                }
+                tokio::time::sleep(CONFLICT_RETRY.delay(attempt)).await;
+                continue;
+            }
+            let mut stamps = field_stamps(&current.data);
+            stamps.insert(path.to_string(), stamp.clone());
+
+            let patch = PatchOperations {
+                set: Some(HashMap::from([
+                    (path.to_string(), value.clone()),
+                    (FIELD_STAMPS_FIELD.to_string(), serde_json::to_value(&stamps)?),
+                ])),
+                remove: None,
+            };
+            match self.patch(current.version, &patch, None).await {
+                Err(Error::Conflict { .. }) if attempt < CONFLICT_RETRY.max_attempts => {
+                    tokio::time::sleep(CONFLICT_RETRY.delay(attempt)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Value at `path` if it is unexpired and was written within `max_age`
+    pub async fn get_fresh<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        max_age: Duration,
+    ) -> Result<Option<T>, Error> {
+        let resp = self.retrieve().await?;
+        let fresh = field_stamps(&resp.data)
+            .get(path)
+            .is_some_and(|stamp| stamp.is_fresh(max_age));
+        if !fresh {
+            return Ok(None);
+        }
+        get_path(&resp.data, path)
+            .map(|value| T::deserialize(value).map_err(Error::from))
+            .transpose()
+    }
+
+    /// Remove expired fields and their stamps, returning the removed paths
+    ///
+    /// Fails with [`Error::Conflict`] if the document changes concurrently.
+    pub async fn sweep_expired_fields(&self) -> Result<Vec<String>, Error> {
+        let Some(current) = self.retrieve_existing().await? else {
+            return Ok(vec![]);
+        };
+        let (expired, live): (BTreeMap<_, _>, BTreeMap<_, _>) = field_stamps(&current.data)
+            .into_iter()
+            .partition(|(_, stamp)| stamp.is_expired());
+        if expired.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let removed: Vec<String> = expired.into_keys().collect();
+        let patch = PatchOperations {
+            set: Some(HashMap::from([(
+                FIELD_STAMPS_FIELD.to_string(),
+                serde_json::to_value(&live)?,
+            )])),
+            remove: Some(removed.clone()),
+        };
+        self.patch(current.version, &patch, None).await?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use serde_json::json;
+
+    const TOKEN: &str = "word-word-word-word-word";
+
+    #[test]
+    fn stamps_expire_and_age() {
+        let hour = chrono::Duration::hours(1);
+        let old = FieldStamp { written_at: Utc::now() - hour, expires_at: None };
+        assert!(!old.is_expired());
+        assert!(!old.is_fresh(Duration::from_secs(60)));
+        assert!(old.is_fresh(Duration::from_secs(7200)));
+
+        let expired = FieldStamp { written_at: Utc::now(), expires_at: Some(Utc::now() - hour) };
+        assert!(expired.is_expired() && !expired.is_fresh(Duration::MAX));
+    }
+
+    #[test]
+    fn unreadable_stamps_are_ignored() {
+        assert!(field_stamps(&json!({"_fields": "x"})).is_empty());
+        assert!(field_stamps(&json!([1])).is_empty());
+    }
+
+    #[tokio::test]
+    async fn set_field_creates_the_document_and_stamps_the_field() {
+        let server = MockServer::start().await;
+        let client = server.client(TOKEN);
+
+        client.set_field("sensors.door", &json!("open"), None).await.unwrap();
+        let door: Option<String> = client.get_fresh("sensors.door", Duration::from_secs(60)).await.unwrap();
+        assert_eq!(door.as_deref(), Some("open"));
+        let stored = server.stored(TOKEN).unwrap();
+        assert!(stored["_fields"]["sensors.door"]["written_at"].is_string());
+
+        assert!(matches!(client.set_field("_fields.x", &json!(1), None).await, Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn concurrent_writes_both_land() {
+        let server = MockServer::start().await;
+        server.put(TOKEN, json!({}));
+        let client = server.client(TOKEN);
+
+        let (one, two) = (json!(1), json!(2));
+        let (a, b) = tokio::join!(client.set_field("a", &one, None), client.set_field("b", &two, None));
+        a.unwrap();
+        b.unwrap();
+        let stored = server.stored(TOKEN).unwrap();
+        assert_eq!((&stored["a"], &stored["b"]), (&json!(1), &json!(2)));
+        assert_eq!(field_stamps(&stored).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn sweep_removes_expired_fields_and_their_stamps() {
+        let server = MockServer::start().await;
+        let expired = serde_json::to_value(FieldStamp {
+            written_at: Utc::now(),
+            expires_at: Some(Utc::now() - chrono::Duration::seconds(1)),
+        })
+        .unwrap();
+        server.put(TOKEN, json!({"gone": 1, "kept": 2, "_fields": {"gone": expired}}));
+        let client = server.client(TOKEN);
+
+        assert_eq!(client.sweep_expired_fields().await.unwrap(), ["gone"]);
+        assert_eq!(server.stored(TOKEN).unwrap(), json!({"kept": 2, "_fields": {}}));
+        assert!(client.sweep_expired_fields().await.unwrap().is_empty());
+    }
+}
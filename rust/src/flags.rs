@@ -0,0 +1,334 @@
+//! Feature flags served from a document
+//!
+//! A token holds one flag document, keyed by flag name. A flag is either
+//! `true`/`false` or a rule that turns it on for some contexts:
+//!
+//! ```json
+//! {
+//!   "new_ui": { "rollout": 25, "allow": ["user-7"], "when": { "plan": ["pro", "team"] } },
+//!   "dark_mode": true,
+//!   "legacy_export": { "enabled": false }
+//! }
+//! ```
+//!
+//! [`FeatureFlags`] loads the document once and answers
+//! [`is_enabled`](FeatureFlags::is_enabled) from memory, so checks cost no
+//! request; [`refresh`](FeatureFlags::refresh) or
+//! [`refresh_every`](FeatureFlags::refresh_every) picks up changes with
+//! conditional retrieves. A rule is evaluated in order: `enabled: false`
+//! turns the flag off, a context key in `deny` or `allow` turns it off or
+//! on, every attribute in `when` must have one of the listed values, and
+//! `rollout` turns it on for that percentage of context keys. Rollouts hash
+//! the flag name with the key, so a user stays in or out as the percentage
+//! grows, and different flags pick different users. Unknown flags, and
+//! flags that fail to parse, including rules with misspelt fields, are off.
+//!
+//! ```no_run
+//! use keyvalue_client::flags::{FeatureFlags, FlagContext};
+//! use keyvalue_client::{Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let flags = FeatureFlags::load(client).await?;
+//! flags.refresh_every(Duration::from_secs(30));
+//!
+//! let user = FlagContext::new("user-42").with_attribute("plan", "pro");
+//! if flags.is_enabled("new_ui", &user) {
+//!     println!("rendering the new UI");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+/// Rollout buckets per percent, so rollouts can be set to 0.01%
+const BUCKETS_PER_PERCENT: u32 = 100;
+
+/// One flag of the document: a bare bool, or a rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawFlag")]
+pub struct Flag {
+    pub enabled: bool,
+    /// Percentage of context keys the flag is on for, all of them if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<f64>,
+    /// Context keys the flag is always on for, unless disabled
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Context keys the flag is always off for
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    /// Attribute values a context must have, any of the listed ones per attribute
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub when: BTreeMap<String, Vec<String>>,
+}
+
+impl Flag {
+    /// Flag on for everyone
+    pub fn on() -> Self {
+        Self::from(true)
+    }
+
+    /// Flag on for `percent` of context keys
+    pub fn rollout(percent: f64) -> Self {
+        Self {
+            rollout: Some(percent),
+            ..Self::on()
+        }
+    }
+
+    /// Whether the flag named `name` is on for `context`
+    pub fn evaluate(&self, name: &str, context: &FlagContext) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if let Some(key) = &context.key {
+            if self.deny.contains(key) {
+                return false;
+            }
+            if self.allow.contains(key) {
+                return true;
+            }
+        }
+        let targeted = self.when.iter().all(|(attribute, values)| {
+            context.attributes.get(attribute).is_some_and(|value| values.contains(value))
+        });
+        if !targeted {
+            return false;
+        }
+        match (self.rollout, &context.key) {
+            (None, _) => true,
+            (Some(percent), _) if percent >= 100.0 => true,
+            (Some(percent), Some(key)) => f64::from(bucket(name, key)) < percent * f64::from(BUCKETS_PER_PERCENT),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+impl From<bool> for Flag {
+    fn from(enabled: bool) -> Self {
+        Self {
+            enabled,
+            rollout: None,
+            allow: vec![],
+            deny: vec![],
+            when: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawFlag {
+    Bool(bool),
+    Rule(RawRule),
+}
+
+/// Rule as written; unknown fields fail, so a misspelt `rollout` turns the flag off rather than on
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+    #[serde(default)]
+    rollout: Option<f64>,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    #[serde(default)]
+    when: BTreeMap<String, Vec<String>>,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl From<RawFlag> for Flag {
+    fn from(raw: RawFlag) -> Self {
+        match raw {
+            RawFlag::Bool(enabled) => Flag::from(enabled),
+            RawFlag::Rule(RawRule {
+                enabled,
+                rollout,
+                allow,
+                deny,
+                when,
+            }) => Flag {
+                enabled,
+                rollout,
+                allow,
+                deny,
+                when,
+            },
+        }
+    }
+}
+
+/// Who a flag is checked for: a stable key such as a user id, and attributes to target
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlagContext {
+    /// Key for `allow`, `deny` and rollouts; without one, partial rollouts are off
+    pub key: Option<String>,
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl FlagContext {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: Some(key.into()),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Context without a key, e.g. a signed-out visitor
+    pub fn anonymous() -> Self {
+        Self::default()
+    }
+
+    pub fn with_attribute(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// Flag document as last retrieved
+#[derive(Default)]
+struct Snapshot {
+    version: Option<i32>,
+    flags: BTreeMap<String, Flag>,
+}
+
+struct Inner {
+    client: Client,
+    snapshot: RwLock<Snapshot>,
+}
+
+/// Feature flags from one token's document, cached in memory
+///
+/// Clones share the cache.
+#[derive(Clone)]
+pub struct FeatureFlags {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for FeatureFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let snapshot = self.inner.snapshot.read().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("FeatureFlags")
+            .field("version", &snapshot.version)
+            .field("flags", &snapshot.flags.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl FeatureFlags {
+    /// Retrieve the flag document of `client`'s token; a missing document has no flags
+    pub async fn load(client: Client) -> Result<Self, Error> {
+        let flags = Self {
+            inner: Arc::new(Inner {
+                client,
+                snapshot: RwLock::default(),
+            }),
+        };
+        flags.refresh().await?;
+        Ok(flags)
+    }
+
+    /// Whether flag `name` is on for `context`, from the cached document
+    pub fn is_enabled(&self, name: &str, context: &FlagContext) -> bool {
+        self.flag(name).is_some_and(|flag| flag.evaluate(name, context))
+    }
+
+    /// Cached rule of flag `name`
+    pub fn flag(&self, name: &str) -> Option<Flag> {
+        let snapshot = self.inner.snapshot.read().unwrap_or_else(|e| e.into_inner());
+        snapshot.flags.get(name).cloned()
+    }
+
+    /// Names of the cached flags, in sorted order
+    pub fn names(&self) -> Vec<String> {
+        let snapshot = self.inner.snapshot.read().unwrap_or_else(|e| e.into_inner());
+        snapshot.flags.keys().cloned().collect()
+    }
+
+    /// Version of the cached document, `None` if there is none
+    pub fn version(&self) -> Option<i32> {
+        self.inner.snapshot.read().unwrap_or_else(|e| e.into_inner()).version
+    }
+
+    /// Fetch the document if it changed; returns whether the cache was updated
+    pub async fn refresh(&self) -> Result<bool, Error> {
+        let version = self.version();
+        let change = match version {
+            Some(version) => self.inner.client.retrieve_if_newer(version).await,
+            None => self.inner.client.retrieve().await.map(Some),
+        };
+        let snapshot = match change {
+            Ok(Some(resp)) => Snapshot {
+                version: Some(resp.version),
+                flags: parse_flags(resp.data),
+            },
+            Ok(None) => return Ok(false),
+            Err(Error::NotFound { .. }) if version.is_none() => return Ok(false),
+            Err(Error::NotFound { .. }) => Snapshot::default(),
+            Err(e) => return Err(e),
+        };
+        *self.inner.snapshot.write().unwrap_or_else(|e| e.into_inner()) = snapshot;
+        Ok(true)
+    }
+
+    /// Refresh every `interval` on a background task until every clone is dropped
+    ///
+    /// Failed refreshes are logged and keep the cached flags. Must be called
+    /// within a Tokio runtime.
+    pub fn refresh_every(&self, interval: Duration) {
+        let inner = Arc::downgrade(&self.inner);
+        tokio::spawn(refresh_loop(inner, interval));
+    }
+}
+
+async fn refresh_loop(inner: Weak<Inner>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        if let Err(e) = (FeatureFlags { inner }).refresh().await {
+            tracing::warn!(error = %e, "failed to refresh feature flags");
+        }
+    }
+}
+
+/// Flags of a document, skipping and logging the ones that do not parse
+fn parse_flags(data: Value) -> BTreeMap<String, Flag> {
+    let Value::Object(flags) = data else {
+        tracing::warn!("feature flag document is not an object");
+        return BTreeMap::new();
+    };
+    flags
+        .into_iter()
+        .filter_map(|(name, flag)| match serde_json::from_value(flag) {
+            Ok(flag) => Some((name, flag)),
+            Err(e) => {
+                tracing::warn!(flag = %name, error = %e, "invalid feature flag");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Rollout bucket of `key` for flag `name`, in `0..100 * BUCKETS_PER_PERCENT`
+fn bucket(name: &str, key: &str) -> u32 {
+    let hash = Sha256::new().chain_update(name).chain_update(":").chain_update(key).finalize();
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) % (100 * BUCKETS_PER_PERCENT)
+}
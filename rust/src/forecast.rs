@@ -0,0 +1,359 @@
+//! Forecasting over numeric history
+//!
+//! Two lightweight models, both returning predictions with confidence bands:
+//! a least-squares [`LinearTrend`] for steady drift (battery drain, disk
+//! usage), and [`HoltWinters`] exponential smoothing for level and trend that
+//! change over time, optionally with a repeating season.
+//!
+//! ```
+//! use chrono::{Duration, TimeZone, Utc};
+//! use keyvalue_client::forecast::LinearTrend;
+//! use keyvalue_client::timeseries::Sample;
+//!
+//! // Battery at 90% dropping 2% per hour
+//! let start = Utc.with_ymd_and_hms(2025, 1, 6, 0, 0, 0).unwrap();
+//! let samples: Vec<_> = (0..10)
+//!     .map(|h| Sample { at: start + Duration::hours(h), value: 90.0 - 2.0 * h as f64 })
+//!     .collect();
+//!
+//! let trend = LinearTrend::fit(&samples).unwrap();
+//! let empty_at = trend.time_to_reach(0.0).unwrap();
+//! assert_eq!(empty_at, start + Duration::hours(45));
+//!
+//! let friday = Utc.with_ymd_and_hms(2025, 1, 10, 0, 0, 0).unwrap();
+//! assert!(empty_at < friday);
+//! ```
+
+use crate::timeseries::{seconds_between, Sample};
+use crate::Error;
+use chrono::{DateTime, Duration, Utc};
+
+/// Predicted value with a confidence band
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForecastPoint {
+    pub at: DateTime<Utc>,
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Least-squares straight line through the samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearTrend {
+    origin: DateTime<Utc>,
+    /// Change in value per second
+    pub slope: f64,
+    /// Value at the first sample's timestamp
+    pub intercept: f64,
+    /// Standard deviation of the residuals
+    pub residual_std: f64,
+    n: usize,
+    mean_x: f64,
+    sxx: f64,
+}
+
+impl LinearTrend {
+    /// Fit a line to the samples
+    ///
+    /// Returns `None` for fewer than two samples or when all samples share one
+    /// timestamp.
+    pub fn fit(samples: &[Sample]) -> Option<Self> {
+        let origin = samples.iter().map(|s| s.at).min()?;
+        let n = samples.len();
+        let xs: Vec<f64> = samples.iter().map(|s| seconds_between(origin, s.at)).collect();
+        let mean_x = xs.iter().sum::<f64>() / n as f64;
+        let mean_y = samples.iter().map(|s| s.value).sum::<f64>() / n as f64;
+
+        let sxx: f64 = xs.iter().map(|x| (x - mean_x).powi(2)).sum();
+        if n < 2 || sxx == 0.0 {
+            return None;
+        }
+        let sxy: f64 = xs
+            .iter()
+            .zip(samples)
+            .map(|(x, s)| (x - mean_x) * (s.value - mean_y))
+            .sum();
+        let slope = sxy / sxx;
+        let intercept = mean_y - slope * mean_x;
+
+        let sse: f64 = xs
+            .iter()
+            .zip(samples)
+            .map(|(x, s)| (s.value - (intercept + slope * x)).powi(2))
+            .sum();
+        let residual_std = if n > 2 { (sse / (n - 2) as f64).sqrt() } else { 0.0 };
+
+        Some(Self {
+            origin,
+            slope,
+            intercept,
+            residual_std,
+            n,
+            mean_x,
+            sxx,
+        })
+    }
+
+    /// Predicted value at `at`, with a prediction interval at `confidence` (e.g. 0.95)
+    pub fn predict(&self, at: DateTime<Utc>, confidence: f64) -> ForecastPoint {
+        let x = seconds_between(self.origin, at);
+        let value = self.intercept + self.slope * x;
+        let spread = self.residual_std
+            * (1.0 + 1.0 / self.n as f64 + (x - self.mean_x).powi(2) / self.sxx).sqrt();
+        let margin = z_score(confidence) * spread;
+        ForecastPoint {
+            at,
+            value,
+            lower: value - margin,
+            upper: value + margin,
+        }
+    }
+
+    /// When the trend line reaches `threshold`
+    ///
+    /// Returns `None` for a flat trend, or one so nearly flat that the time
+    /// is out of range. The result may lie in the past.
+    pub fn time_to_reach(&self, threshold: f64) -> Option<DateTime<Utc>> {
+        if self.slope == 0.0 {
+            return None;
+        }
+        offset(self.origin, (threshold - self.intercept) / self.slope)
+    }
+}
+
+/// Repeating pattern for [`HoltWinters`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Seasonality {
+    /// Samples per season, e.g. 24 for hourly readings with a daily cycle
+    pub period: usize,
+    /// Smoothing factor for the seasonal component, in `0..=1`
+    pub gamma: f64,
+}
+
+/// Holt-Winters exponential smoothing with additive trend and season
+///
+/// Samples are assumed to be evenly spaced; forecast steps use the average
+/// interval between them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HoltWinters {
+    /// Smoothing factor for the level, in `0..=1`
+    pub alpha: f64,
+    /// Smoothing factor for the trend, in `0..=1`
+    pub beta: f64,
+    pub season: Option<Seasonality>,
+}
+
+impl Default for HoltWinters {
+    fn default() -> Self {
+        Self {
+            alpha: 0.5,
+            beta: 0.1,
+            season: None,
+        }
+    }
+}
+
+impl HoltWinters {
+    pub fn with_season(mut self, period: usize, gamma: f64) -> Self {
+        self.season = Some(Seasonality { period, gamma });
+        self
+    }
+
+    /// Forecast `steps` intervals past the last sample, with bands at `confidence`
+    pub fn forecast(
+        &self,
+        samples: &[Sample],
+        steps: usize,
+        confidence: f64,
+    ) -> Result<Vec<ForecastPoint>, Error> {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by_key(|sample| sample.at);
+        let values: Vec<f64> = sorted.iter().map(|s| s.value).collect();
+
+        let factors = [Some(self.alpha), Some(self.beta), self.season.map(|s| s.gamma)];
+        if factors.iter().flatten().any(|f| !(0.0..=1.0).contains(f)) {
+            return Err(Error::Validation(
+                "Smoothing factors must be between 0 and 1".to_string(),
+            ));
+        }
+        let period = self.season.map_or(1, |s| s.period);
+        if period == 0 {
+            return Err(Error::Validation("Season period must be at least 1".to_string()));
+        }
+        let required = (2 * period).max(2);
+        if values.len() < required {
+            return Err(Error::Validation(format!(
+                "Forecast needs at least {} samples, got {}",
+                required,
+                values.len()
+            )));
+        }
+
+        let gamma = self.season.map_or(0.0, |s| s.gamma);
+        let first = mean(&values[..period]);
+        let second = mean(&values[period..2 * period]);
+        let mut level = first;
+        let mut trend = (second - first) / period as f64;
+        let mut seasonal: Vec<f64> = if self.season.is_some() {
+            values[..period].iter().map(|v| v - first).collect()
+        } else {
+            vec![0.0]
+        };
+
+        let mut squared_errors = 0.0;
+        let mut fitted = 0usize;
+        for (t, &value) in values.iter().enumerate().skip(period) {
+            let s = seasonal[t % period];
+            let error = value - (level + trend + s);
+            squared_errors += error * error;
+            fitted += 1;
+
+            let previous = level;
+            level = self.alpha * (value - s) + (1.0 - self.alpha) * (level + trend);
+            trend = self.beta * (level - previous) + (1.0 - self.beta) * trend;
+            if self.season.is_some() {
+                seasonal[t % period] = gamma * (value - level) + (1.0 - gamma) * s;
+            }
+        }
+        let sigma = (squared_errors / fitted as f64).sqrt();
+
+        let last = sorted[sorted.len() - 1].at;
+        let span = seconds_between(sorted[0].at, last);
+        let step = span / (sorted.len() - 1) as f64;
+        let z = z_score(confidence);
+
+        (1..=steps)
+            .map(|h| {
+                let at = offset(last, h as f64 * step).ok_or_else(|| {
+                    Error::Validation(format!("Forecast step {} is past the supported date range", h))
+                })?;
+                let value = level + h as f64 * trend + seasonal[(values.len() + h - 1) % period];
+                let margin = z * sigma * (h as f64).sqrt();
+                Ok(ForecastPoint {
+                    at,
+                    value,
+                    lower: value - margin,
+                    upper: value + margin,
+                })
+            })
+            .collect()
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// `from` moved by `seconds`, or `None` if that is not a representable time
+fn offset(from: DateTime<Utc>, seconds: f64) -> Option<DateTime<Utc>> {
+    let millis = (seconds * 1000.0).round();
+    if !millis.is_finite() {
+        return None;
+    }
+    from.checked_add_signed(Duration::try_milliseconds(millis as i64)?)
+}
+
+/// Two-sided standard normal quantile for `confidence` (Abramowitz & Stegun 26.2.23)
+fn z_score(confidence: f64) -> f64 {
+    let p = ((1.0 - confidence.clamp(0.0, 0.999_999)) / 2.0).max(f64::MIN_POSITIVE);
+    let t = (-2.0 * p.ln()).sqrt();
+    let z = t - (2.515517 + 0.802853 * t + 0.010328 * t * t)
+        / (1.0 + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t);
+    z.max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    /// One sample per hour
+    fn hourly(values: impl IntoIterator<Item = f64>) -> Vec<Sample> {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(h, value)| Sample { at: start() + Duration::hours(h as i64), value })
+            .collect()
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn exact_line_has_no_spread() {
+        let trend = LinearTrend::fit(&hourly((0..5).map(|h| 10.0 + 3.0 * h as f64))).unwrap();
+        assert!(close(trend.slope * 3600.0, 3.0) && close(trend.intercept, 10.0));
+        assert!(close(trend.residual_std, 0.0));
+
+        let point = trend.predict(start() + Duration::hours(10), 0.95);
+        assert!(close(point.value, 40.0) && close(point.lower, point.upper));
+        assert_eq!(trend.time_to_reach(25.0), Some(start() + Duration::hours(5)));
+    }
+
+    #[test]
+    fn bands_widen_with_distance_and_confidence() {
+        let trend = LinearTrend::fit(&hourly([0.0, 2.0, 1.0, 3.0, 2.0, 4.0])).unwrap();
+        let width = |hours, confidence| {
+            let point = trend.predict(start() + Duration::hours(hours), confidence);
+            point.upper - point.lower
+        };
+        assert!(width(20, 0.95) > width(3, 0.95));
+        assert!(width(3, 0.99) > width(3, 0.8));
+    }
+
+    #[test]
+    fn fit_needs_two_distinct_timestamps() {
+        assert_eq!(LinearTrend::fit(&[]), None);
+        assert_eq!(LinearTrend::fit(&hourly([1.0])), None);
+        let same = [Sample { at: start(), value: 1.0 }, Sample { at: start(), value: 2.0 }];
+        assert_eq!(LinearTrend::fit(&same), None);
+        let flat = LinearTrend::fit(&hourly([5.0, 5.0])).unwrap();
+        assert_eq!(flat.time_to_reach(0.0), None);
+    }
+
+    #[test]
+    fn holt_winters_continues_a_line() {
+        let forecast = HoltWinters::default().forecast(&hourly((0..6).map(|h| 2.0 * h as f64)), 2, 0.95).unwrap();
+        assert_eq!(forecast[0].at, start() + Duration::hours(6));
+        assert!(close(forecast[0].value, 12.0) && close(forecast[1].value, 14.0));
+        assert!(close(forecast[1].upper, forecast[1].lower));
+    }
+
+    #[test]
+    fn holt_winters_repeats_the_season() {
+        let samples = hourly([0.0, 10.0, 0.0, 10.0, 0.0, 10.0]);
+        let forecast = HoltWinters::default().with_season(2, 0.3).forecast(&samples, 3, 0.95).unwrap();
+        let values: Vec<f64> = forecast.iter().map(|point| point.value).collect();
+        assert!(close(values[0], 0.0) && close(values[1], 10.0) && close(values[2], 0.0));
+    }
+
+    #[test]
+    fn holt_winters_validates_its_settings() {
+        let samples = hourly([1.0, 2.0, 3.0]);
+        let invalid = |model: HoltWinters| matches!(model.forecast(&samples, 1, 0.9), Err(Error::Validation(_)));
+        assert!(invalid(HoltWinters { alpha: 1.5, ..HoltWinters::default() }));
+        assert!(invalid(HoltWinters::default().with_season(0, 0.5)));
+        assert!(invalid(HoltWinters::default().with_season(2, 0.5)));
+        assert!(invalid(HoltWinters::default().with_season(1, -0.1)));
+    }
+
+    #[test]
+    fn z_scores_match_the_normal_table() {
+        assert!((z_score(0.95) - 1.96).abs() < 1e-3);
+        assert!((z_score(0.99) - 2.576).abs() < 1e-3);
+        assert_eq!(z_score(0.0), 0.0);
+        assert!(z_score(1.0).is_finite());
+    }
+
+    #[test]
+    fn offsets_out_of_range_are_none() {
+        assert_eq!(offset(start(), 1.5), Some(start() + Duration::milliseconds(1500)));
+        assert_eq!(offset(start(), f64::INFINITY), None);
+        assert_eq!(offset(start(), 1e20), None);
+    }
+}
@@ -0,0 +1,48 @@
+//! Server health checks
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let status = client.status().await?;
+//! println!("API {:?} in {:?}", status.version, status.latency);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Endpoint, Error, ResponseMeta};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Response from the health endpoint
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ApiStatus {
+    /// Status reported by the server, e.g. `ok`
+    #[serde(default)]
+    pub status: Option<String>,
+    /// API version, when reported
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Round-trip time of the health request
+    #[serde(skip)]
+    pub latency: Duration,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
+}
+
+impl Client {
+    /// Check that the server is reachable and healthy, returning the round-trip time
+    pub async fn ping(&self) -> Result<Duration, Error> {
+        Ok(self.status().await?.latency)
+    }
+
+    /// Query the health endpoint for server status and API version
+    pub async fn status(&self) -> Result<ApiStatus, Error> {
+        let request = self.http_client.get(self.url(Endpoint::Health));
+        let started = Instant::now();
+        let mut status: ApiStatus = self.execute(Endpoint::Health, request).await?;
+        status.latency = started.elapsed();
+        Ok(status)
+    }
+}
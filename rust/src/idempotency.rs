@@ -0,0 +1,87 @@
+//! Idempotency keys for writes
+//!
+//! With [`Client::with_idempotency_keys`] every store, patch and batch request
+//! carries an `Idempotency-Key` header with a fresh random key. The key is
+//! chosen once per call, so when the retry policy resends a request whose
+//! response was lost, the server recognizes the repeat and answers with the
+//! original result instead of applying the write twice.
+//! [`ResponseMeta::idempotent_replay`](crate::ResponseMeta::idempotent_replay)
+//! tells whether that happened.
+//!
+//! [`Client::with_idempotency_key`] sends a key of your choosing instead,
+//! for one logical write retried across calls or restarts. Every write made
+//! through that client carries the same key, so use it for that write only.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let order = serde_json::json!({"id": 42, "status": "paid"});
+//! let resp = client
+//!     .with_idempotency_key("order-42-paid")
+//!     .store(&order, None)
+//!     .await?;
+//! if resp.meta.idempotent_replay {
+//!     println!("already stored by an earlier attempt");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{hex, Client};
+use reqwest::RequestBuilder;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Header carrying the idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Where a client's idempotency keys come from
+#[derive(Debug, Clone)]
+pub(crate) enum Idempotency {
+    /// A new random key per write
+    Generated,
+    /// The same key on every write
+    Fixed(Arc<str>),
+}
+
+impl Client {
+    /// Send a fresh `Idempotency-Key` with every store, patch and batch
+    pub fn with_idempotency_keys(mut self, enabled: bool) -> Self {
+        self.idempotency = enabled.then_some(Idempotency::Generated);
+        self
+    }
+
+    /// Send `key` as the `Idempotency-Key` of every store, patch and batch
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency = Some(Idempotency::Fixed(Arc::from(key.into())));
+        self
+    }
+
+    /// Add the idempotency key header to a write request, if enabled
+    pub(crate) fn idempotent(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.idempotency {
+            None => request,
+            Some(Idempotency::Generated) => request.header(IDEMPOTENCY_KEY_HEADER, generate_key()),
+            Some(Idempotency::Fixed(key)) => request.header(IDEMPOTENCY_KEY_HEADER, &**key),
+        }
+    }
+}
+
+/// 128-bit random key in lowercase hex
+pub(crate) fn generate_key() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = Sha256::new();
+    hasher.update(RandomState::new().build_hasher().finish().to_le_bytes());
+    hasher.update(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.update(now.as_nanos().to_le_bytes());
+    }
+    hex(&hasher.finalize()[..16])
+}
@@ -0,0 +1,149 @@
+//! Multi-consumer message inbox
+//!
+//! An [`Inbox`] is a [`KvList`] of messages at `<path>.messages` plus, per
+//! consumer, the IDs it has acknowledged at `<path>.acks.<consumer>`. Any
+//! client may publish; each consumer sees a message as unread until it acks
+//! it, independently of other consumers. Old messages fall off the list once
+//! it reaches capacity, acknowledged or not.
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use keyvalue_client::{Client, Error};
+//! use std::pin::pin;
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let phone = client.inbox::<String>("alerts", "phone", 50)?;
+//!
+//! phone.publish(&"Door left open".to_string()).await?;
+//!
+//! let mut incoming = pin!(phone.watch_unread(Duration::from_secs(10)));
+//! while let Some(message) = incoming.next().await {
+//!     let message = message?;
+//!     println!("{}", message.value);
+//!     phone.ack(message.seq).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::list::MAX_WRITE_ATTEMPTS;
+use crate::path::get_path;
+use crate::{Client, Error, KvList, ListEntry, PatchOperations};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// Shared inbox read by a single named consumer
+pub struct Inbox<'a, T = Value> {
+    client: &'a Client,
+    messages: KvList<'a, T>,
+    acks_path: String,
+}
+
+impl<T: Serialize + DeserializeOwned> Inbox<'_, T> {
+    /// Publish a message, returning its ID
+    pub async fn publish(&self, message: &T) -> Result<u64, Error> {
+        Ok(self.messages.push(message).await?.seq)
+    }
+
+    /// Messages this consumer has not acknowledged, oldest first
+    pub async fn unread(&self) -> Result<Vec<ListEntry<T>>, Error> {
+        match self.client.retrieve_existing().await? {
+            Some(current) => self.unread_in(&current.data),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Acknowledge a message for this consumer
+    pub async fn ack(&self, id: u64) -> Result<(), Error> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let Some(current) = self.client.retrieve_existing().await? else {
+                return Ok(());
+            };
+            let mut acked = self.acked_in(&current.data);
+            if !acked.insert(id) {
+                return Ok(());
+            }
+            // Acks for messages that have fallen off the list are no longer needed
+            if let Some(oldest) = self.messages.entries_from(&current.data)?.first() {
+                acked.retain(|seq| *seq >= oldest.seq);
+            }
+
+            let patch = PatchOperations {
+                set: Some(HashMap::from([(
+                    self.acks_path.clone(),
+                    serde_json::to_value(&acked)?,
+                )])),
+                remove: None,
+            };
+            match self.client.patch(current.version, &patch, None).await {
+                Ok(_) => return Ok(()),
+                Err(Error::Conflict { .. }) if attempt < MAX_WRITE_ATTEMPTS => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Stream unread messages as they arrive, polling every `interval`
+    ///
+    /// Messages already unread when the stream starts are yielded first. Each
+    /// message is yielded once, even if it is not acknowledged.
+    pub fn watch_unread(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<ListEntry<T>, Error>> + '_ {
+        let mut last_seen = None;
+        self.client.watch(interval).flat_map(move |change| {
+            let items = match change.and_then(|resp| self.unread_in(&resp.data)) {
+                Ok(unread) => unread
+                    .into_iter()
+                    .filter(|message| last_seen.is_none_or(|seen| message.seq > seen))
+                    .collect::<Vec<_>>(),
+                Err(e) => return stream::iter(vec![Err(e)]),
+            };
+            if let Some(last) = items.last() {
+                last_seen = Some(last.seq);
+            }
+            stream::iter(items.into_iter().map(Ok).collect::<Vec<_>>())
+        })
+    }
+
+    fn unread_in(&self, data: &Value) -> Result<Vec<ListEntry<T>>, Error> {
+        let acked = self.acked_in(data);
+        let mut messages = self.messages.entries_from(data)?;
+        messages.retain(|message| !acked.contains(&message.seq));
+        Ok(messages)
+    }
+
+    fn acked_in(&self, data: &Value) -> BTreeSet<u64> {
+        get_path(data, &self.acks_path)
+            .and_then(|acks| BTreeSet::deserialize(acks).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Client {
+    /// Inbox at `path` read as `consumer`, keeping at most `capacity` messages
+    pub fn inbox<T: Serialize + DeserializeOwned>(
+        &self,
+        path: &str,
+        consumer: &str,
+        capacity: usize,
+    ) -> Result<Inbox<'_, T>, Error> {
+        if consumer.is_empty() || consumer.contains('.') {
+            return Err(Error::Validation(format!("Invalid inbox consumer: {:?}", consumer)));
+        }
+        Ok(Inbox {
+            client: self,
+            messages: self.list(format!("{}.messages", path), capacity),
+            acks_path: format!("{}.acks.{}", path, consumer),
+        })
+    }
+}
@@ -0,0 +1,394 @@
+//! Schema inference from stored data
+//!
+//! Tokens inherited from prototypes rarely come with documentation.
+//! [`Client::infer_schema`] looks at the stored value, and optionally recent
+//! history payloads, and describes what it saw as a JSON Schema or as Rust
+//! type definitions. Fields missing from some samples become optional, and
+//! fields seen with conflicting types fall back to `serde_json::Value`.
+//!
+//! ```
+//! use keyvalue_client::infer::infer;
+//! use serde_json::json;
+//!
+//! let samples = [
+//!     json!({"temperature": 21.5, "unit": "C"}),
+//!     json!({"temperature": 22, "unit": "C", "note": "door open"}),
+//! ];
+//! let inferred = infer(&samples);
+//! assert_eq!(inferred.json_schema()["required"], json!(["temperature", "unit"]));
+//! assert!(inferred.rust_types("Reading").contains("pub note: Option<String>,"));
+//! ```
+
+use crate::contract::Schema;
+use crate::typed::envelope_value;
+use crate::{Client, Error, HistoryOptions};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// Shape shared by a set of JSON values, built by [`infer`] or [`Client::infer_schema`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InferredSchema {
+    root: Shape,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Shape {
+    /// Values merged into this shape
+    seen: usize,
+    null: bool,
+    boolean: bool,
+    integer: bool,
+    number: bool,
+    string: bool,
+    array: Option<Box<Shape>>,
+    object: Option<Object>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Object {
+    /// Objects merged; a field seen fewer times is optional
+    seen: usize,
+    fields: BTreeMap<String, Shape>,
+}
+
+/// Infer the shape shared by `samples`
+pub fn infer<'a>(samples: impl IntoIterator<Item = &'a Value>) -> InferredSchema {
+    let mut root = Shape::default();
+    for sample in samples {
+        root.merge(envelope_value(sample));
+    }
+    InferredSchema { root }
+}
+
+impl InferredSchema {
+    /// Number of values the shape was inferred from
+    pub fn samples(&self) -> usize {
+        self.root.seen
+    }
+
+    /// JSON Schema describing every sample
+    pub fn json_schema(&self) -> Value {
+        let mut schema = self.root.json_schema();
+        if let Value::Object(obj) = &mut schema {
+            obj.insert(
+                "$schema".to_string(),
+                "https://json-schema.org/draft/2020-12/schema".into(),
+            );
+        }
+        schema
+    }
+
+    /// The JSON Schema as a [`Schema`] contract, for [`Client::with_contract`]
+    pub fn to_contract(&self) -> Schema {
+        Schema::new(self.json_schema())
+    }
+
+    /// Rust struct definitions with serde derives, the root named `name`
+    pub fn rust_types(&self, name: &str) -> String {
+        let mut types = RustTypes::default();
+        let root = types.type_of(&self.root, &pascal_case(name));
+        if self.root.object.is_none() || self.root.kinds() != 1 {
+            types.defs.push(format!("pub type {} = {};\n", pascal_case(name), root));
+        }
+        types.defs.join("\n")
+    }
+}
+
+impl Shape {
+    fn merge(&mut self, value: &Value) {
+        self.seen += 1;
+        match value {
+            Value::Null => self.null = true,
+            Value::Bool(_) => self.boolean = true,
+            Value::Number(n) if n.is_i64() || n.is_u64() => self.integer = true,
+            Value::Number(_) => self.number = true,
+            Value::String(_) => self.string = true,
+            Value::Array(items) => {
+                let shape = self.array.get_or_insert_with(Default::default);
+                for item in items {
+                    shape.merge(item);
+                }
+            }
+            Value::Object(obj) => {
+                let object = self.object.get_or_insert_with(Default::default);
+                object.seen += 1;
+                for (name, value) in obj {
+                    object.fields.entry(name.clone()).or_default().merge(value);
+                }
+            }
+        }
+    }
+
+    /// Distinct non-null kinds seen, counting integer and number as one
+    fn kinds(&self) -> usize {
+        [
+            self.boolean,
+            self.integer || self.number,
+            self.string,
+            self.array.is_some(),
+            self.object.is_some(),
+        ]
+        .into_iter()
+        .filter(|&kind| kind)
+        .count()
+    }
+
+    fn json_schema(&self) -> Value {
+        let mut types = vec![];
+        let mut schema = Map::new();
+        if self.boolean {
+            types.push("boolean");
+        }
+        if self.number {
+            types.push("number");
+        } else if self.integer {
+            types.push("integer");
+        }
+        if self.string {
+            types.push("string");
+        }
+        if let Some(items) = &self.array {
+            types.push("array");
+            if items.seen > 0 {
+                schema.insert("items".to_string(), items.json_schema());
+            }
+        }
+        if let Some(object) = &self.object {
+            types.push("object");
+            let properties: Map<String, Value> = object
+                .fields
+                .iter()
+                .map(|(name, field)| (name.clone(), field.json_schema()))
+                .collect();
+            let required: Vec<&String> = object
+                .fields
+                .iter()
+                .filter(|(_, field)| field.seen == object.seen)
+                .map(|(name, _)| name)
+                .collect();
+            schema.insert("properties".to_string(), properties.into());
+            if !required.is_empty() {
+                schema.insert("required".to_string(), json!(required));
+            }
+        }
+        if self.null {
+            types.push("null");
+        }
+
+        match types.as_slice() {
+            [] => {}
+            [single] => {
+                schema.insert("type".to_string(), (*single).into());
+            }
+            _ => {
+                schema.insert("type".to_string(), json!(types));
+            }
+        }
+        Value::Object(schema)
+    }
+}
+
+#[derive(Default)]
+struct RustTypes {
+    defs: Vec<String>,
+    names: BTreeSet<String>,
+}
+
+impl RustTypes {
+    /// Rust type for `shape`, defining a struct named after `hint` for objects
+    fn type_of(&mut self, shape: &Shape, hint: &str) -> String {
+        let inner = match shape.kinds() {
+            0 => "serde_json::Value".to_string(),
+            1 if shape.boolean => "bool".to_string(),
+            1 if shape.number => "f64".to_string(),
+            1 if shape.integer => "i64".to_string(),
+            1 if shape.string => "String".to_string(),
+            1 => match (&shape.array, &shape.object) {
+                (Some(items), _) => format!("Vec<{}>", self.type_of(items, &singular(hint))),
+                (_, Some(object)) => self.define(object, hint),
+                _ => unreachable!("one kind is set"),
+            },
+            _ => "serde_json::Value".to_string(),
+        };
+        if shape.null && shape.kinds() > 0 {
+            format!("Option<{}>", inner)
+        } else {
+            inner
+        }
+    }
+
+    fn define(&mut self, object: &Object, hint: &str) -> String {
+        let mut name = hint.to_string();
+        let mut n = 1;
+        while !self.names.insert(name.clone()) {
+            n += 1;
+            name = format!("{}{}", hint, n);
+        }
+
+        // Reserve the slot so the root comes first and nested types follow
+        let slot = self.defs.len();
+        self.defs.push(String::new());
+        let mut def = String::new();
+        let _ = writeln!(def, "#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]");
+        let _ = writeln!(def, "pub struct {} {{", name);
+        for (field, shape) in &object.fields {
+            let mut ty = self.type_of(shape, &pascal_case(field));
+            let optional = shape.seen < object.seen;
+            if optional && !ty.starts_with("Option<") {
+                ty = format!("Option<{}>", ty);
+            }
+            let ident = field_ident(field);
+            if ident.trim_start_matches("r#") != field {
+                let _ = writeln!(def, "    #[serde(rename = {:?})]", field);
+            }
+            if optional {
+                let _ = writeln!(def, "    #[serde(skip_serializing_if = \"Option::is_none\")]");
+            }
+            let _ = writeln!(def, "    pub {}: {},", ident, ty);
+        }
+        def.push_str("}\n");
+        self.defs[slot] = def;
+        name
+    }
+}
+
+impl Client {
+    /// Infer the schema of the stored value and up to `history` recent history payloads
+    ///
+    /// A missing token value yields an empty shape when no history is requested.
+    pub async fn infer_schema(&self, history: Option<i32>) -> Result<InferredSchema, Error> {
+        let mut samples = vec![];
+        if let Some(current) = self.retrieve_existing().await? {
+            samples.push(current.data);
+        }
+        if let Some(limit) = history.filter(|&limit| limit > 0) {
+            let options = HistoryOptions {
+                limit: Some(limit),
+                ..Default::default()
+            };
+            samples.extend(self.history(&options).await?.events.into_iter().map(|e| e.payload));
+        }
+        Ok(infer(&samples))
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in text.chars() {
+        if !c.is_alphanumeric() {
+            words.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+            prev_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && prev_lower {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        current.extend(c.to_lowercase());
+    }
+    words.extend((!current.is_empty()).then_some(current));
+    words
+}
+
+fn pascal_case(text: &str) -> String {
+    let name: String = words(text)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|c| c.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        })
+        .collect();
+    match name.chars().next() {
+        None => "Field".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("Field{}", name),
+        Some(_) => name,
+    }
+}
+
+fn field_ident(field: &str) -> String {
+    let ident = words(field).join("_");
+    match ident.chars().next() {
+        None => "field".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("field_{}", ident),
+        Some(_) if matches!(ident.as_str(), "self" | "super" | "crate" | "Self") => {
+            format!("{}_", ident)
+        }
+        Some(_) if KEYWORDS.contains(&ident.as_str()) => format!("r#{}", ident),
+        Some(_) => ident,
+    }
+}
+
+/// Struct name for the items of an array field, e.g. `Readings` to `Reading`
+fn singular(name: &str) -> String {
+    match name.strip_suffix('s') {
+        Some(stem) if !stem.is_empty() && !stem.ends_with('s') => stem.to_string(),
+        _ => format!("{}Item", name),
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "gen", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_split_on_case_and_punctuation() {
+        assert_eq!(words("userID-last_seen2x"), ["user", "id", "last", "seen2x"]);
+        assert_eq!(pascal_case("sensor_readings"), "SensorReadings");
+        assert_eq!(pascal_case("2fa"), "Field2fa");
+        assert_eq!(pascal_case("--"), "Field");
+        assert_eq!(field_ident("lastSeen"), "last_seen");
+        assert_eq!(field_ident("type"), "r#type");
+        assert_eq!(field_ident("self"), "self_");
+        assert_eq!(field_ident("3d"), "field_3d");
+        assert_eq!(singular("Readings"), "Reading");
+        assert_eq!(singular("Address"), "AddressItem");
+    }
+
+    #[test]
+    fn schema_merges_kinds_and_marks_optional_fields() {
+        let samples = [json!({"n": 1, "tag": "a"}), json!({"n": 1.5, "tag": null}), json!({"n": 2})];
+        let schema = infer(&samples).json_schema();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["required"], json!(["n"]));
+        assert_eq!(schema["properties"]["n"]["type"], "number");
+        assert_eq!(schema["properties"]["tag"]["type"], json!(["string", "null"]));
+        assert_eq!(infer(&samples).samples(), 3);
+
+        let list = infer(&[json!([1, 2]), json!([])]).json_schema();
+        assert_eq!(list["items"]["type"], "integer");
+        assert!(infer(&[json!([])]).json_schema().get("items").is_none());
+    }
+
+    #[test]
+    fn rust_types_define_nested_structs_root_first() {
+        let samples = [
+            json!({"type": "door", "readings": [{"at": "x", "value": 1}], "meta": {"ok": true}}),
+            json!({"type": "window", "readings": [], "meta": {"ok": false}, "lastSeen": "y"}),
+        ];
+        let types = infer(&samples).rust_types("sensor");
+        let structs: Vec<&str> = types.lines().filter(|line| line.starts_with("pub struct")).collect();
+        assert_eq!(structs, ["pub struct Sensor {", "pub struct Meta {", "pub struct Reading {"]);
+        assert!(types.contains("    #[serde(rename = \"lastSeen\")]\n    #[serde(skip_serializing_if = \"Option::is_none\")]\n    pub last_seen: Option<String>,"));
+        assert!(types.contains("    pub r#type: String,"));
+        assert!(types.contains("    pub readings: Vec<Reading>,"));
+    }
+
+    #[test]
+    fn conflicting_and_non_object_roots_become_aliases() {
+        assert_eq!(infer(&[json!(1), json!("a")]).rust_types("x"), "pub type X = serde_json::Value;\n");
+        assert_eq!(infer(&[json!([true]), json!(null)]).rust_types("flags"), "pub type Flags = Option<Vec<bool>>;\n");
+        let twice = infer(&[json!({"a": {"b": 1}, "c": {"a": {"d": 2}}})]).rust_types("root");
+        assert!(twice.contains("pub struct A {") && twice.contains("pub struct A2 {"));
+    }
+}
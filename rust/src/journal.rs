@@ -0,0 +1,383 @@
+//! Local write-ahead journal for `store` and `patch`
+//!
+//! With a journal attached, every write is appended to the journal file and
+//! fsynced before it is sent, then marked acknowledged once the API accepts it
+//! (or aborted if the API rejects it). Fields configured for encryption are
+//! journaled encrypted. After a crash, call [`Client::recover_journal`] at
+//! startup to replay writes that were never acknowledged.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error, Journal};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word")
+//!     .with_journal(Journal::open("kv.journal")?);
+//! let report = client.recover_journal().await?;
+//! println!("Replayed {} writes", report.replayed);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error, PatchOperations};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Journaled write
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalOp {
+    Store {
+        data: Value,
+        ttl: Option<i32>,
+    },
+    Patch {
+        version: i32,
+        patch: PatchOperations,
+        ttl: Option<i32>,
+    },
+}
+
+/// Write that was recorded but never acknowledged or aborted
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub op: JournalOp,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Outcome of [`Client::recover_journal`]
+#[derive(Debug, Default)]
+pub struct RecoveryReport {
+    /// Writes replayed and acknowledged
+    pub replayed: usize,
+    /// Writes the API rejected on replay, by journal ID
+    pub rejected: Vec<(u64, Error)>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum Record {
+    Intent {
+        id: u64,
+        at: DateTime<Utc>,
+        op: JournalOp,
+    },
+    Ack {
+        id: u64,
+    },
+    Abort {
+        id: u64,
+    },
+}
+
+/// Append-only journal file of pending writes
+pub struct Journal {
+    path: PathBuf,
+    state: Mutex<JournalState>,
+}
+
+struct JournalState {
+    file: File,
+    next_id: u64,
+}
+
+impl Journal {
+    /// Open or create a journal file
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let next_id = read_records(&path)?
+            .iter()
+            .map(|record| match record {
+                Record::Intent { id, .. } | Record::Ack { id } | Record::Abort { id } => *id + 1,
+            })
+            .max()
+            .unwrap_or(1);
+
+        Ok(Self {
+            path,
+            state: Mutex::new(JournalState { file, next_id }),
+        })
+    }
+
+    /// Path of the journal file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes that were recorded but never completed, oldest first
+    pub fn pending(&self) -> io::Result<Vec<JournalEntry>> {
+        let _state = self.lock();
+        let mut pending = BTreeMap::new();
+        for record in read_records(&self.path)? {
+            match record {
+                Record::Intent { id, at, op } => {
+                    pending.insert(id, JournalEntry { id, op, recorded_at: at });
+                }
+                Record::Ack { id } | Record::Abort { id } => {
+                    pending.remove(&id);
+                }
+            }
+        }
+        Ok(pending.into_values().collect())
+    }
+
+    /// Rewrite the journal keeping only pending writes
+    pub fn compact(&self) -> io::Result<()> {
+        let pending = self.pending()?;
+        let mut state = self.lock();
+
+        let tmp_path = self.path.with_extension("compact");
+        let mut tmp = File::create(&tmp_path)?;
+        for entry in pending {
+            write_record(
+                &mut tmp,
+                &Record::Intent {
+                    id: entry.id,
+                    at: entry.recorded_at,
+                    op: entry.op,
+                },
+            )?;
+        }
+        tmp.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        state.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    pub(crate) fn record_intent(&self, op: JournalOp) -> io::Result<u64> {
+        let mut state = self.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        write_record(&mut state.file, &Record::Intent { id, at: Utc::now(), op })?;
+        state.file.sync_data()?;
+        Ok(id)
+    }
+
+    pub(crate) fn record_ack(&self, id: u64) -> io::Result<()> {
+        write_record(&mut self.lock().file, &Record::Ack { id })
+    }
+
+    pub(crate) fn record_abort(&self, id: u64) -> io::Result<()> {
+        write_record(&mut self.lock().file, &Record::Abort { id })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JournalState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Client {
+    /// Journal `store` and `patch` calls to a local write-ahead journal
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(Arc::new(journal));
+        self
+    }
+
+    /// Replay journaled writes that were never acknowledged, oldest first
+    ///
+    /// Stops at the first transport failure or retryable API error so writes are
+    /// never applied out of order.
+    pub async fn recover_journal(&self) -> Result<RecoveryReport, Error> {
+        let Some(journal) = &self.journal else {
+            return Ok(RecoveryReport::default());
+        };
+
+        let mut report = RecoveryReport::default();
+        for entry in journal.pending()? {
+            let result = match &entry.op {
+                JournalOp::Store { data, ttl } => self.store_raw(data, *ttl).await.map(|_| ()),
+                JournalOp::Patch {
+                    version,
+                    patch,
+                    ttl,
+                } => self.patch_raw(*version, patch, *ttl).await.map(|_| ()),
+            };
+
+            match result {
+                Ok(()) => {
+                    journal.record_ack(entry.id)?;
+                    report.replayed += 1;
+                }
+                Err(e) if is_rejection(&e) => {
+                    journal.record_abort(entry.id)?;
+                    report.rejected.push((entry.id, e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Record a write intent, returning its journal ID when journaling is enabled
+    pub(crate) fn journal_intent(
+        &self,
+        op: impl FnOnce() -> JournalOp,
+    ) -> Result<Option<u64>, Error> {
+        match &self.journal {
+            Some(journal) => Ok(Some(journal.record_intent(op())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Mark a journaled write as acknowledged or aborted based on its result
+    ///
+    /// Transport failures and retryable API errors leave the write pending for
+    /// recovery. A failure to record the outcome is logged rather than
+    /// returned, since the write itself already succeeded or failed; recovery
+    /// then replays it.
+    pub(crate) fn journal_complete<T>(&self, id: Option<u64>, result: &Result<T, Error>) {
+        let (Some(journal), Some(id)) = (&self.journal, id) else {
+            return;
+        };
+        let recorded = match result {
+            Ok(_) => journal.record_ack(id),
+            Err(e) if is_rejection(e) => journal.record_abort(id),
+            Err(_) => Ok(()),
+        };
+        if let Err(e) = recorded {
+            tracing::warn!(error = %e, id, "failed to record journaled write outcome");
+        }
+    }
+}
+
+/// Whether the API or the client definitively rejected a write, so replaying it is pointless
+fn is_rejection(error: &Error) -> bool {
+    matches!(error, Error::Validation(_)) || (error.status().is_some() && !error.is_retryable())
+}
+
+fn read_records(path: &Path) -> io::Result<Vec<Record>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    let mut records = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        // A torn final line from a crash mid-append is ignored
+        if let Ok(record) = serde_json::from_str(&line) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+fn write_record(file: &mut File, record: &Record) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use serde_json::json;
+
+    const TOKEN: &str = "word-word-word-word-word";
+
+    /// Path in the temp directory, removed when dropped
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("kv-journal-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn store(n: i64) -> JournalOp {
+        JournalOp::Store { data: json!({"n": n}), ttl: None }
+    }
+
+    #[test]
+    fn pending_skips_completed_writes_across_reopening() {
+        let path = TempPath::new("pending");
+        let journal = Journal::open(&path.0).unwrap();
+        let ids: Vec<u64> = (0..3).map(|n| journal.record_intent(store(n)).unwrap()).collect();
+        journal.record_ack(ids[0]).unwrap();
+        journal.record_abort(ids[2]).unwrap();
+
+        let journal = Journal::open(&path.0).unwrap();
+        let pending: Vec<u64> = journal.pending().unwrap().iter().map(|entry| entry.id).collect();
+        assert_eq!(pending, [ids[1]]);
+        assert!(journal.record_intent(store(3)).unwrap() > ids[2]);
+
+        journal.compact().unwrap();
+        assert_eq!(journal.pending().unwrap().len(), 2);
+        assert_eq!(std::fs::read_to_string(&path.0).unwrap().lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn recovery_replays_pending_writes_in_order() {
+        let path = TempPath::new("recover");
+        let journal = Journal::open(&path.0).unwrap();
+        journal.record_intent(store(1)).unwrap();
+        journal.record_intent(JournalOp::Patch {
+            version: 1,
+            patch: PatchOperations::from_merge_patch(&json!({"m": 2})).unwrap(),
+            ttl: None,
+        })
+        .unwrap();
+
+        let server = MockServer::start().await;
+        let client = server.client(TOKEN).with_journal(journal);
+        let report = client.recover_journal().await.unwrap();
+        assert_eq!(report.replayed, 2);
+        assert!(report.rejected.is_empty());
+        assert_eq!(server.stored(TOKEN), Some(json!({"n": 1, "m": 2})));
+        assert_eq!(client.recover_journal().await.unwrap().replayed, 0);
+    }
+
+    #[tokio::test]
+    async fn writes_the_client_refuses_are_not_left_pending() {
+        let path = TempPath::new("refused");
+        let server = MockServer::start().await;
+        server.put(TOKEN, json!({"n": 1}));
+        let client = server.client(TOKEN).with_checksums(crate::checksum::ChecksumAlgorithm::Sha256);
+        let client = client.with_journal(Journal::open(&path.0).unwrap());
+
+        let patch = PatchOperations::from_merge_patch(&json!({"n": 2})).unwrap();
+        assert!(matches!(client.patch(1, &patch, None).await, Err(Error::Validation(_))));
+        assert!(client.journal.as_ref().unwrap().pending().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn encrypted_writes_are_journaled_and_replayed_sealed() {
+        use crate::encryption::{FieldEncryption, FieldKey};
+
+        let path = TempPath::new("sealed");
+        let encryption = || FieldEncryption::new(&FieldKey::from_bytes([7; 32]), ["secret"]);
+        let offline = Client::builder().base_url("http://127.0.0.1:9").token(TOKEN).build().unwrap();
+        let offline = offline.with_field_encryption(encryption()).with_journal(Journal::open(&path.0).unwrap());
+        assert!(offline.store(&json!({"secret": "hunter2"}), None).await.is_err());
+        assert!(!std::fs::read_to_string(&path.0).unwrap().contains("hunter2"));
+        let JournalOp::Store { data: sealed, .. } = offline.journal.as_ref().unwrap().pending().unwrap().remove(0).op else {
+            unreachable!()
+        };
+
+        let server = MockServer::start().await;
+        let client = server.client(TOKEN).with_field_encryption(encryption());
+        let client = client.with_journal(Journal::open(&path.0).unwrap());
+        assert_eq!(client.recover_journal().await.unwrap().replayed, 1);
+        assert_eq!(server.stored(TOKEN), Some(sealed));
+        assert_eq!(client.retrieve().await.unwrap().data, json!({"secret": "hunter2"}));
+    }
+}
@@ -0,0 +1,141 @@
+//! Advisory single-writer guard
+//!
+//! A client configured with [`Client::with_writer_id`] can claim writer-ship of a
+//! token by recording a lease in the stored document under [`WRITER_LEASE_FIELD`].
+//! While a lease held by another writer is live, guarded clients refuse to
+//! `store` or `patch` and return [`Error::WriterLeaseHeld`]. Clients without a
+//! writer ID are not guarded, so this only coordinates cooperating processes.
+
+use crate::{Client, Error, PatchOperations, RetrieveResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Document field holding the writer lease
+pub const WRITER_LEASE_FIELD: &str = "_writer";
+
+/// Writer-ship claim recorded in the document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WriterLease {
+    pub holder: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl WriterLease {
+    /// Read the lease from a stored document
+    pub fn from_data(data: &Value) -> Option<Self> {
+        data.get(WRITER_LEASE_FIELD)
+            .and_then(|lease| Self::deserialize(lease).ok())
+    }
+
+    /// Whether the lease has not yet expired
+    pub fn is_live(&self) -> bool {
+        self.expires_at > Utc::now()
+    }
+}
+
+impl Client {
+    /// Enable the single-writer guard, identifying this client as `id`
+    pub fn with_writer_id(mut self, id: impl Into<String>) -> Self {
+        self.writer_id = Some(Arc::from(id.into()));
+        self
+    }
+
+    /// Claim (or renew) writer-ship for `duration`
+    pub async fn claim_writer(&self, duration: Duration) -> Result<WriterLease, Error> {
+        let writer_id = self.require_writer_id()?;
+        let lease = WriterLease {
+            holder: writer_id.to_string(),
+            expires_at: Utc::now()
+                + chrono::Duration::from_std(duration)
+                    .map_err(|_| Error::Validation("Lease duration out of range".to_string()))?,
+        };
+
+        match self.retrieve_existing().await? {
+            None => {
+                let data = serde_json::json!({ WRITER_LEASE_FIELD: lease });
+                let sealed = self.encrypt_fields(&data)?;
+                self.store_raw(&sealed, None).await?;
+            }
+            Some(current) => {
+                self.ensure_not_leased(&current.data)?;
+                if !current.data.is_object() {
+                    return Err(Error::Validation(
+                        "Writer lease requires an object document".to_string(),
+                    ));
+                }
+                let patch = PatchOperations {
+                    set: Some(HashMap::from([(
+                        WRITER_LEASE_FIELD.to_string(),
+                        serde_json::to_value(&lease)?,
+                    )])),
+                    remove: None,
+                };
+                let sealed = self.encrypt_patch(&patch)?;
+                self.patch_raw(current.version, &sealed, None).await?;
+            }
+        }
+
+        Ok(lease)
+    }
+
+    /// Release writer-ship if this client holds it
+    pub async fn release_writer(&self) -> Result<(), Error> {
+        let writer_id = self.require_writer_id()?;
+        let Some(current) = self.retrieve_existing().await? else {
+            return Ok(());
+        };
+
+        let held = WriterLease::from_data(&current.data)
+            .is_some_and(|lease| lease.holder == writer_id);
+        if held {
+            let patch = PatchOperations {
+                set: None,
+                remove: Some(vec![WRITER_LEASE_FIELD.to_string()]),
+            };
+            self.patch_raw(current.version, &patch, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Check the guard before a write, returning this client's own live lease if any
+    pub(crate) async fn check_writer_guard(&self) -> Result<Option<WriterLease>, Error> {
+        if self.writer_id.is_none() {
+            return Ok(None);
+        }
+        let Some(current) = self.retrieve_existing().await? else {
+            return Ok(None);
+        };
+        self.ensure_not_leased(&current.data)?;
+        Ok(WriterLease::from_data(&current.data).filter(WriterLease::is_live))
+    }
+
+    fn ensure_not_leased(&self, data: &Value) -> Result<(), Error> {
+        match WriterLease::from_data(data) {
+            Some(lease) if lease.is_live() && self.writer_id.as_deref() != Some(lease.holder.as_str()) => {
+                Err(Error::WriterLeaseHeld {
+                    holder: lease.holder,
+                    expires_at: lease.expires_at,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn require_writer_id(&self) -> Result<&str, Error> {
+        self.writer_id
+            .as_deref()
+            .ok_or_else(|| Error::Validation("Writer ID is required".to_string()))
+    }
+
+    pub(crate) async fn retrieve_existing(&self) -> Result<Option<RetrieveResponse>, Error> {
+        match self.retrieve_raw().await {
+            Ok(resp) => Ok(Some(resp)),
+            Err(Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
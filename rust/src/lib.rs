@@ -24,137 +24,478 @@
 //! ```
 
 use chrono::{DateTime, Utc};
-use reqwest::{Client as HttpClient, StatusCode};
+use futures_util::stream::{self, Stream};
+use reqwest::{Client as HttpClient, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use thiserror::Error;
 
-const DEFAULT_BASE_URL: &str = "https://key-value.co";
-const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
-
-/// Client errors
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error("HTTP request failed: {0}")]
-    Request(#[from] reqwest::Error),
+pub mod atomic;
+pub mod auth;
+pub mod backfill;
+pub mod background;
+pub mod batch_retry;
+pub mod buffered;
+pub mod builder;
+pub mod captcha;
+pub mod checksum;
+pub mod circuit;
+pub mod classified;
+mod coalesce;
+pub mod conditional;
+pub mod config;
+pub mod contract;
+pub mod dedup;
+pub mod degraded;
+pub mod diff;
+pub mod election;
+pub mod endpoint;
+mod error;
+pub mod export;
+pub mod field_ttl;
+pub mod flags;
+pub mod forecast;
+pub mod health;
+pub mod idempotency;
+pub mod inbox;
+pub mod infer;
+pub mod journal;
+pub mod lease;
+pub mod list;
+pub mod merge_patch;
+pub mod meta;
+pub mod metadata;
+pub mod middleware;
+pub mod multi;
+pub mod notices;
+pub mod path;
+pub mod policy;
+pub mod profiler;
+pub mod profiles;
+pub mod queue;
+pub mod reconcile;
+pub mod redact;
+pub mod reload;
+pub mod replicated;
+pub mod sampling;
+pub mod scheduler;
+pub mod scope;
+mod secret;
+pub mod sharded;
+pub mod signing;
+pub mod sink;
+pub mod size;
+pub mod stats;
+pub mod strict;
+pub mod tail;
+pub mod threshold;
+pub mod time_travel;
+pub mod timeseries;
+pub mod tls;
+pub mod ttl;
+pub mod typed;
+pub mod usage;
+pub mod versioned;
+pub mod versions;
+pub mod watch;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "dataframe")]
+pub mod dataframe;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "sqlite")]
+pub mod local;
+#[cfg(feature = "prost")]
+pub mod proto;
+#[cfg(feature = "qr")]
+pub mod qr;
+#[cfg(feature = "tower")]
+pub mod service;
+#[cfg(feature = "ed25519")]
+pub mod signed;
+#[cfg(feature = "keyring")]
+pub mod token_store;
+#[cfg(feature = "units")]
+pub mod units;
+#[cfg(feature = "test-vectors")]
+pub mod vectors;
+#[cfg(feature = "vault")]
+pub mod vault;
+#[cfg(feature = "wordlist")]
+pub mod wordlist;
+#[cfg(test)]
+mod mock;
 
-    #[error("API error ({status}): {message}")]
-    Api { status: StatusCode, message: String },
+pub use atomic::BatchMode;
+pub use auth::AuthScheme;
+pub use backfill::{BackfillOptions, BackfillReport};
+pub use background::{SyncBuilder, SyncHandle};
+pub use buffered::{BufferedWriter, BufferedWriterOptions};
+pub use builder::{ClientBuilder, Proxy};
+pub use captcha::{CaptchaCommand, CaptchaProvider};
+pub use circuit::{CircuitBreakerPolicy, CircuitState};
+pub use dedup::content_hash;
+pub use degraded::{Availability, AvailabilityPolicy, WriteOutcome};
+pub use diff::JsonDiff;
+#[cfg(feature = "ed25519")]
+pub use ed25519_dalek;
+pub use election::{Election, LeadershipEvent};
+pub use endpoint::Endpoint;
+pub use error::{BuildError, Error};
+pub use export::{CsvExporter, HistoryExporter, InfluxExporter};
+pub use field_ttl::{FieldStamp, FIELD_STAMPS_FIELD};
+pub use flags::{FeatureFlags, Flag, FlagContext};
+pub use health::ApiStatus;
+pub use inbox::Inbox;
+pub use infer::InferredSchema;
+pub use journal::{Journal, JournalEntry, JournalOp, RecoveryReport};
+pub use lease::{WriterLease, WRITER_LEASE_FIELD};
+pub use list::{KvList, ListEntry};
+pub use merge_patch::{apply_merge_patch, merge_patch_from_diff};
+pub use meta::{RateLimit, ResponseMeta};
+pub use metadata::Metadata;
+pub use middleware::{DefaultHeaders, Middleware};
+#[cfg(feature = "dataframe")]
+pub use ndarray;
+pub use notices::{ServerNotice, ServerNotices};
+pub use path::get_path;
+pub use policy::{ClientPolicy, RateLimitPolicy, RetryPolicy};
+#[cfg(feature = "dataframe")]
+pub use polars;
+pub use profiler::{ProfileReport, Profiler};
+pub use queue::{Claimed, JobQueue};
+pub use redact::{mask_token, RedactionRule, Redactor};
+pub use reload::{ConfigWatcher, ConfigWatcherBuilder};
+pub use sampling::Sampling;
+pub use scheduler::{Scheduled, Scheduler};
+pub use scope::Scope;
+#[cfg(feature = "secrecy")]
+pub use secrecy;
+pub use signing::RequestSigner;
+pub use stats::{EndpointStats, TrafficStats};
+pub use strict::{Strictness, UnknownFields};
+pub use time_travel::ValueAt;
+#[cfg(feature = "rustls")]
+pub use tls::Pin;
+pub use tls::TlsConfig;
+pub use ttl::Ttl;
+pub use typed::{
+    EventDecodeFailure, SchemaFingerprint, TypedHistoryEvent, TypedHistoryResponse,
+    TypedRetrieveResponse,
+};
+pub use usage::Usage;
+pub use versioned::VersionedValue;
 
-    #[error("Token is required")]
-    MissingToken,
+use circuit::CircuitBreaker;
+use coalesce::Flights;
+use contract::Contract;
+use degraded::Degradation;
+use dedup::LastStore;
+use endpoint::Endpoints;
+use idempotency::Idempotency;
+use meta::WithMeta;
+use notices::NoticeHandler;
+use policy::{Failover, RateLimiter};
+use profiler::Sample;
+use redact::truncate;
+use secret::Token;
+use size::Tier;
 
-    #[error("Validation error: {0}")]
-    Validation(String),
-
-    #[error("JSON serialization error: {0}")]
-    Serialization(#[from] serde_json::Error),
-}
+const DEFAULT_BASE_URL: &str = "https://key-value.co";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_ERROR_BODY_LEN: usize = 1024;
 
 /// Key-Value API client
+///
+/// `Client` is `Send + Sync` and cheap to clone: configuration and the HTTP
+/// connection pool sit behind `Arc`s, so clones can be handed to request
+/// handlers or tasks freely. Clones share the connection pool, journal and
+/// store dedup cache; changing the token or base URL on a clone gives it its
+/// own dedup cache.
+///
+/// ```
+/// fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+/// assert_shareable::<keyvalue_client::Client>();
+/// ```
+#[derive(Clone)]
 pub struct Client {
-    base_url: String,
-    token: Option<String>,
+    base_url: Arc<str>,
+    endpoints: Arc<Endpoints>,
+    token: Option<Token>,
+    auth: Arc<AuthScheme>,
     http_client: HttpClient,
+    middleware: Arc<[Arc<dyn Middleware>]>,
+    contract: Option<Arc<dyn Contract>>,
+    captcha: Option<Arc<dyn CaptchaProvider>>,
+    schema_fingerprints: bool,
+    writer_id: Option<Arc<str>>,
+    journal: Option<Arc<Journal>>,
+    store_dedup: bool,
+    last_store: Arc<Mutex<Option<LastStore>>>,
+    redactor: Arc<Redactor>,
+    stats: Arc<Mutex<TrafficStats>>,
+    versions: Arc<Mutex<HashMap<String, i32>>>,
+    auto_version: bool,
+    degradation: Option<Arc<Degradation>>,
+    notices: Arc<Mutex<ServerNotices>>,
+    notice_handler: Option<NoticeHandler>,
+    tier: Arc<Mutex<Option<Tier>>>,
+    size_check: bool,
+    profiler: Option<Profiler>,
+    strictness: Strictness,
+    unknown_fields: Arc<Mutex<UnknownFields>>,
+    #[cfg(feature = "compression")]
+    compression: Option<compression::Compression>,
+    #[cfg(feature = "encryption")]
+    field_encryption: Option<Arc<encryption::FieldEncryption>>,
+    retry: Option<RetryPolicy>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    failover: Option<Arc<Failover>>,
+    coalesce: bool,
+    flights: Arc<Mutex<Flights>>,
+    circuit: Option<Arc<CircuitBreaker>>,
+    idempotency: Option<Idempotency>,
+    checksum: Option<checksum::ChecksumAlgorithm>,
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("base_url", &self.base_url)
+            .field("token", &self.token.as_deref().map(mask_token))
+            .field("auth", &self.auth)
+            .field("writer_id", &self.writer_id)
+            .field("schema_fingerprints", &self.schema_fingerprints)
+            .field("store_dedup", &self.store_dedup)
+            .field("auto_version", &self.auto_version)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Client {
     /// Create a new client with a token
+    ///
+    /// Panics if the HTTP client cannot be initialized; use
+    /// [`Client::builder`] to handle that as a [`BuildError`].
     pub fn new(token: impl Into<String>) -> Self {
-        Self {
-            base_url: DEFAULT_BASE_URL.to_string(),
-            token: Some(token.into()),
-            http_client: HttpClient::builder()
-                .timeout(DEFAULT_TIMEOUT)
-                .build()
-                .expect("Failed to build HTTP client"),
-        }
+        ClientBuilder::new().token(token).build().expect("Failed to build HTTP client")
     }
 
     /// Create a client without a default token
+    ///
+    /// Panics like [`Client::new`].
     pub fn new_without_token() -> Self {
-        Self {
-            base_url: DEFAULT_BASE_URL.to_string(),
-            token: None,
-            http_client: HttpClient::builder()
-                .timeout(DEFAULT_TIMEOUT)
-                .build()
-                .expect("Failed to build HTTP client"),
-        }
+        ClientBuilder::new().build().expect("Failed to build HTTP client")
     }
 
     /// Set the base URL
     pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
-        self.base_url = url.into();
+        self.base_url = Arc::from(url.into());
+        self.last_store = Arc::default();
+        self.versions = Arc::default();
         self
     }
 
     /// Set the default token
     pub fn set_token(&mut self, token: impl Into<String>) {
-        self.token = Some(token.into());
+        self.token = Some(token.into().into());
+        self.last_store = Arc::default();
     }
 
     /// Generate a new 5-word memorable token
+    ///
+    /// Without a `turnstile_token`, a challenge demanded by the API is solved
+    /// with the [captcha provider](Client::with_captcha_provider), if any.
     pub async fn generate(&self, turnstile_token: Option<&str>) -> Result<GenerateResponse, Error> {
+        let result = self.generate_raw(turnstile_token).await;
+        match (&result, &self.captcha) {
+            (Err(e), Some(provider)) if turnstile_token.is_none() && captcha::is_required(e) => {
+                let token = provider.turnstile_token().await?;
+                self.generate_raw(Some(&token)).await
+            }
+            _ => result,
+        }
+    }
+
+    async fn generate_raw(
+        &self,
+        turnstile_token: Option<&str>,
+    ) -> Result<GenerateResponse, Error> {
         let mut payload = HashMap::new();
         if let Some(token) = turnstile_token {
             payload.insert("turnstileToken", token);
         }
 
-        let resp = self.http_client
-            .post(format!("{}/api/generate", self.base_url))
-            .json(&payload)
-            .send()
-            .await?;
+        let request = self.http_client
+            .post(self.url(Endpoint::Generate))
+            .json(&payload);
 
-        self.handle_response(resp).await
+        self.execute(Endpoint::Generate, request).await
     }
 
     /// Store JSON data
     pub async fn store(&self, data: &Value, ttl: Option<i32>) -> Result<StoreResponse, Error> {
-        let token = self.token.as_ref().ok_or(Error::MissingToken)?;
+        if let Some(previous) = self.dedup_lookup(data, ttl) {
+            return Ok(previous);
+        }
+        self.store_versioned(data, None, ttl).await
+    }
+
+    /// Store `data` like [`store`](Self::store), only if the document is still at `version`
+    ///
+    /// Without a version, stores unconditionally unless auto-versioning applies.
+    pub(crate) async fn store_versioned(
+        &self,
+        data: &Value,
+        version: Option<i32>,
+        ttl: Option<i32>,
+    ) -> Result<StoreResponse, Error> {
+        let original = data;
+
+        let data = match self.check_writer_guard().await? {
+            Some(lease) if data.is_object() && data.get(WRITER_LEASE_FIELD).is_none() => {
+                let mut data = data.clone();
+                data[WRITER_LEASE_FIELD] = serde_json::to_value(lease)?;
+                Cow::Owned(data)
+            }
+            _ => Cow::Borrowed(data),
+        };
+
+        // Encrypted before journaling so sealed fields never reach the disk in plain text
+        let sealed = self.encrypt_fields(&data)?;
+        let intent = self.journal_intent(|| JournalOp::Store {
+            data: sealed.clone().into_owned(),
+            ttl,
+        })?;
+        let result = match version.or_else(|| self.auto_version_for(&data)) {
+            // Diffed against the decrypted document, so given the plain value
+            Some(version) => self.store_checked(&data, version, ttl).await,
+            None => self.store_raw(&sealed, ttl).await,
+        };
+        self.journal_complete(intent, &result);
+        match &result {
+            Ok(resp) => self.dedup_record(original, ttl, resp),
+            Err(_) => self.dedup_reset(),
+        }
+        result
+    }
+
+    /// Store `data`, whose fields are already encrypted
+    async fn store_raw(&self, data: &Value, ttl: Option<i32>) -> Result<StoreResponse, Error> {
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+        let data = &self.wrap_payload(data)?;
+        self.check_payload_size(data)?;
 
         let mut payload = serde_json::json!({"data": data});
         if let Some(ttl_value) = ttl {
             payload["ttl"] = serde_json::json!(ttl_value);
         }
 
-        let resp = self.http_client
-            .post(format!("{}/api/store", self.base_url))
-            .header("X-KV-Token", token)
-            .json(&payload)
-            .send()
-            .await?;
+        self.log_request_body("/api/store", &payload);
+        let request = self.idempotent(self.http_client.post(self.url(Endpoint::Store)));
+        let request = self.auth.apply(request, token).json(&payload);
 
-        self.handle_response(resp).await
+        let result = self.execute(Endpoint::Store, request).await;
+        self.forget_flight();
+        self.track_version(&result, |resp: &StoreResponse| resp.version);
+        if let Ok(resp) = &result {
+            self.learn_tier(&resp.tier);
+        }
+        result
     }
 
     /// Retrieve data
     pub async fn retrieve(&self) -> Result<RetrieveResponse, Error> {
-        let token = self.token.as_ref().ok_or(Error::MissingToken)?;
+        let resp = self.retrieve_raw().await?;
+        self.check_contract(&resp.data)?;
+        Ok(resp)
+    }
 
-        let resp = self.http_client
-            .get(format!("{}/api/retrieve", self.base_url))
-            .header("X-KV-Token", token)
-            .send()
-            .await?;
+    /// Retrieve data, or `None` if nothing is stored
+    pub async fn retrieve_opt(&self) -> Result<Option<RetrieveResponse>, Error> {
+        match self.retrieve().await {
+            Ok(resp) => Ok(Some(resp)),
+            Err(Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
 
-        self.handle_response(resp).await
+    async fn retrieve_raw(&self) -> Result<RetrieveResponse, Error> {
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+        // Shared as stored; each caller decodes with its own settings
+        let mut resp = self.coalesced(token, || self.send_retrieve(token)).await?;
+        self.decode_payload(&mut resp.data)?;
+        Ok(resp)
+    }
+
+    async fn send_retrieve(&self, token: &str) -> Result<RetrieveResponse, Error> {
+        let request = self.auth.apply(self.http_client.get(self.url(Endpoint::Retrieve)), token);
+
+        let result = self.execute(Endpoint::Retrieve, request).await;
+        self.track_version(&result, |resp: &RetrieveResponse| resp.version);
+        result
+    }
+
+    /// Retrieve data only if its version differs from `last_version`
+    ///
+    /// Sends `If-None-Match` so the server can answer `304 Not Modified`
+    /// without a body. Returns `None` when the data is unchanged; a document
+    /// that was deleted and rewritten counts as changed even if its version
+    /// restarted lower.
+    pub async fn retrieve_if_newer(
+        &self,
+        last_version: i32,
+    ) -> Result<Option<RetrieveResponse>, Error> {
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+
+        let request = self.http_client
+            .get(self.url(Endpoint::Retrieve))
+            .header(reqwest::header::IF_NONE_MATCH, format!("\"{}\"", last_version));
+        let request = self.auth.apply(request, token);
+
+        let result = self.execute::<RetrieveResponse>(Endpoint::Retrieve, request).await;
+        self.track_version(&result, |resp| resp.version);
+        match result {
+            Ok(resp) if resp.version == last_version => Ok(None),
+            Ok(mut resp) => {
+                self.decode_payload(&mut resp.data)?;
+                self.check_contract(&resp.data)?;
+                Ok(Some(resp))
+            }
+            Err(Error::Api { status, .. }) if status == reqwest::StatusCode::NOT_MODIFIED => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
     }
 
     /// Delete data
     pub async fn delete(&self) -> Result<DeleteResponse, Error> {
-        let token = self.token.as_ref().ok_or(Error::MissingToken)?;
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+        self.dedup_reset();
 
-        let resp = self.http_client
-            .delete(format!("{}/api/delete", self.base_url))
-            .header("X-KV-Token", token)
-            .send()
-            .await?;
+        let request = self.auth.apply(self.http_client.delete(self.url(Endpoint::Delete)), token);
 
-        self.handle_response(resp).await
+        let result = self.execute(Endpoint::Delete, request).await;
+        self.forget_flight();
+        if result.is_ok() {
+            self.set_version(Some(token), None);
+        }
+        result
+    }
+
+    /// Delete data, or return `None` if nothing was stored
+    pub async fn delete_opt(&self) -> Result<Option<DeleteResponse>, Error> {
+        match self.delete().await {
+            Ok(resp) => Ok(Some(resp)),
+            Err(Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
     /// Apply atomic partial updates
@@ -164,7 +505,29 @@ impl Client {
         patch: &PatchOperations,
         ttl: Option<i32>,
     ) -> Result<PatchResponse, Error> {
-        let token = self.token.as_ref().ok_or(Error::MissingToken)?;
+        self.check_writer_guard().await?;
+        self.dedup_reset();
+
+        let sealed = self.encrypt_patch(patch)?;
+        let intent = self.journal_intent(|| JournalOp::Patch {
+            version,
+            patch: sealed.clone().into_owned(),
+            ttl,
+        })?;
+        let result = self.patch_raw(version, &sealed, ttl).await;
+        self.journal_complete(intent, &result);
+        result
+    }
+
+    /// Apply `patch`, whose values are already encrypted
+    async fn patch_raw(
+        &self,
+        version: i32,
+        patch: &PatchOperations,
+        ttl: Option<i32>,
+    ) -> Result<PatchResponse, Error> {
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+        self.ensure_patchable()?;
 
         let mut payload = serde_json::json!({
             "version": version,
@@ -174,21 +537,25 @@ impl Client {
             payload["ttl"] = serde_json::json!(ttl_value);
         }
 
-        let resp = self.http_client
-            .patch(format!("{}/api/store", self.base_url))
-            .header("X-KV-Token", token)
-            .json(&payload)
-            .send()
-            .await?;
+        self.log_request_body("/api/store", &payload);
+        let request = self.idempotent(self.http_client.patch(self.url(Endpoint::Patch)));
+        let request = self.auth.apply(request, token).json(&payload);
 
-        self.handle_response(resp).await
+        let mut result = self.execute(Endpoint::Patch, request).await;
+        self.forget_flight();
+        self.track_version(&result, |resp: &PatchResponse| resp.version);
+        if let Ok(resp) = &mut result {
+            self.learn_tier(&resp.tier);
+            self.decrypt_fields(&mut resp.data)?;
+        }
+        result
     }
 
     /// Query time-series history
     pub async fn history(&self, options: &HistoryOptions) -> Result<HistoryResponse, Error> {
-        let token = self.token.as_ref().ok_or(Error::MissingToken)?;
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
 
-        let mut url = format!("{}/api/history", self.base_url);
+        let mut url = self.url(Endpoint::History);
         let mut query = vec![];
 
         if let Some(limit) = options.limit {
@@ -205,20 +572,46 @@ impl Client {
         }
 
         if !query.is_empty() {
-            url.push_str("?");
+            url.push('?');
             url.push_str(&query.join("&"));
         }
 
-        let resp = self.http_client
-            .get(&url)
-            .header("X-KV-Token", token)
-            .send()
-            .await?;
+        let request = self.auth.apply(self.http_client.get(&url), token);
+
+        let mut resp: HistoryResponse = self.execute(Endpoint::History, request).await?;
+        for event in &mut resp.events {
+            self.decode_payload(&mut event.payload)?;
+        }
+        Ok(resp)
+    }
 
-        self.handle_response(resp).await
+    /// Stream history pages matching `options`, following pagination until exhausted
+    pub fn history_pages(
+        &self,
+        options: &HistoryOptions,
+    ) -> impl Stream<Item = Result<HistoryResponse, Error>> + '_ {
+        stream::try_unfold(Some(options.clone()), move |state| async move {
+            let Some(mut options) = state else {
+                return Ok(None);
+            };
+            let page = self.history(&options).await?;
+            let next = match page.events.last() {
+                Some(last) if page.pagination.has_more => {
+                    options.before = Some(last.seq);
+                    Some(options)
+                }
+                _ => None,
+            };
+            Ok(Some((page, next)))
+        })
     }
 
     /// Execute batch operations
+    ///
+    /// Stores and patches of this client's own token are encoded like
+    /// [`store`](Self::store) and [`patch`](Self::patch): encrypted fields
+    /// are sealed and checksum or compression envelopes applied, and values
+    /// it retrieves are decoded.
     pub async fn batch(&self, operations: Vec<BatchOperation>) -> Result<BatchResponse, Error> {
         if operations.is_empty() {
             return Err(Error::Validation("At least one operation required".to_string()));
@@ -227,46 +620,300 @@ impl Client {
             return Err(Error::Validation("Maximum 100 operations per batch".to_string()));
         }
 
-        let payload = serde_json::json!({"operations": operations});
+        let encoded = operations
+            .iter()
+            .map(|operation| self.encode_operation(operation))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let payload = serde_json::json!({"operations": encoded});
+
+        self.log_request_body("/api/batch", &payload);
+        let request = self.idempotent(self.http_client.post(self.url(Endpoint::Batch)))
+            .json(&payload);
 
-        let resp = self.http_client
-            .post(format!("{}/api/batch", self.base_url))
-            .json(&payload)
-            .send()
-            .await?;
+        let mut resp: BatchResponse = self.execute(Endpoint::Batch, request).await?;
+        for result in resp.results.iter_mut().filter(|result| result.success) {
+            match result.action.as_str() {
+                "delete" => self.set_version(Some(&result.token), None),
+                action => {
+                    if let (Some(data), true) = (&mut result.data, action == "retrieve" && self.owns(&result.token)) {
+                        self.decode_payload(data)?;
+                    }
+                    if let Some(version) = result.version {
+                        self.set_version(Some(&result.token), Some(version));
+                    }
+                }
+            }
+        }
+        resp.operations = operations;
+        Ok(resp)
+    }
 
-        self.handle_response(resp).await
+    /// `data` as stored: fields encrypted, then checksum and compression envelopes added as enabled
+    pub(crate) fn encode_payload(&self, data: &Value) -> Result<Value, Error> {
+        let sealed = self.encrypt_fields(data)?;
+        self.wrap_payload(&sealed)
     }
 
-    async fn handle_response<T: for<'de> Deserialize<'de>>(&self, resp: reqwest::Response) -> Result<T, Error> {
+    /// `sealed`, its fields already encrypted, in the checksum and compression envelopes enabled
+    fn wrap_payload(&self, sealed: &Value) -> Result<Value, Error> {
+        let data = self.checksum_payload(sealed);
+        Ok(self.compress_payload(&data)?.into_owned())
+    }
+
+    /// `operation` as sent: writes to this client's own token are encoded like `store` and `patch`
+    fn encode_operation<'a>(&self, operation: &'a BatchOperation) -> Result<Cow<'a, BatchOperation>, Error> {
+        if !self.owns(&operation.token) {
+            return Ok(Cow::Borrowed(operation));
+        }
+        match (operation.action.as_str(), &operation.data, &operation.patch) {
+            ("store", Some(data), _) => Ok(Cow::Owned(BatchOperation {
+                data: Some(self.encode_payload(data)?),
+                ..operation.clone()
+            })),
+            ("patch", _, Some(patch)) => {
+                self.ensure_patchable()?;
+                Ok(Cow::Owned(BatchOperation {
+                    patch: Some(self.encrypt_patch(patch)?.into_owned()),
+                    ..operation.clone()
+                }))
+            }
+            _ => Ok(Cow::Borrowed(operation)),
+        }
+    }
+
+    /// Whether `token` is this client's current token
+    fn owns(&self, token: &str) -> bool {
+        self.token.as_deref() == Some(token)
+    }
+
+    /// Whether stores wrap the value in a checksum or compression envelope
+    pub(crate) fn wraps_payload(&self) -> bool {
+        self.checksum.is_some() || self.compresses()
+    }
+
+    /// Fail if patches would corrupt the envelopes this client stores
+    pub(crate) fn ensure_patchable(&self) -> Result<(), Error> {
+        if self.wraps_payload() {
+            return Err(Error::Validation(
+                "Patches cannot update checksummed or compressed values; store the whole value instead"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Turn a stored payload back into the value written: decompressed, verified, then decrypted
+    fn decode_payload(&self, data: &mut Value) -> Result<(), Error> {
+        self.decompress_payload(data)?;
+        self.verify_checksum(data)?;
+        self.decrypt_fields(data)
+    }
+
+    fn url(&self, endpoint: Endpoint) -> String {
+        format!("{}{}", self.base_url, self.endpoints.path(endpoint))
+    }
+
+    /// Send a request through the middleware stack and decode the response
+    async fn execute<T: for<'de> Deserialize<'de> + WithMeta>(
+        &self,
+        endpoint: Endpoint,
+        request: RequestBuilder,
+    ) -> Result<T, Error> {
+        self.guarded(self.dispatch(endpoint, request)).await
+    }
+
+    /// Send a request, retrying and failing over per the client's policy
+    async fn dispatch<T: for<'de> Deserialize<'de> + WithMeta>(
+        &self,
+        endpoint: Endpoint,
+        request: RequestBuilder,
+    ) -> Result<T, Error> {
+        let mut request = request.build()?;
+        let start = self.active_base();
+        let (mut base, mut tried, mut attempt) = (start, 1, 1);
+        loop {
+            // Each attempt starts from the request as built, so middleware sees its final URL and time
+            let again = match (&self.retry, &self.failover) {
+                (None, None) => None,
+                _ => request.try_clone(),
+            };
+            self.route(&mut request, base)?;
+            for middleware in self.middleware.iter() {
+                middleware.on_request(&mut request)?;
+            }
+            let result = self.send(endpoint, request).await;
+            let error = match &result {
+                Err(e) if degraded::is_outage(e) => e,
+                Err(e) => {
+                    self.settle_base(base);
+                    e
+                }
+                Ok(_) => {
+                    self.settle_base(base);
+                    return result;
+                }
+            };
+            let Some(again) = again else {
+                return result;
+            };
+            request = again;
+
+            if degraded::is_outage(error) {
+                if let Some(next) = self.next_base(start, tried) {
+                    tracing::warn!(error = %error, "failing over to the next base URL");
+                    (base, tried) = (next, tried + 1);
+                    continue;
+                }
+            }
+            let Some(delay) = self.retry_delay(attempt, error) else {
+                return result;
+            };
+            tokio::time::sleep(delay).await;
+            (base, tried, attempt) = (start, 1, attempt + 1);
+        }
+    }
+
+    /// Send one attempt of a request and decode the response
+    async fn send<T: for<'de> Deserialize<'de> + WithMeta>(
+        &self,
+        endpoint: Endpoint,
+        request: reqwest::Request,
+    ) -> Result<T, Error> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
+        let bytes_sent = request.body().and_then(|body| body.as_bytes()).map_or(0, <[u8]>::len);
+        self.record_stats(|stats| stats.record_request(endpoint, bytes_sent));
+        let sample = self
+            .profiler
+            .as_ref()
+            .and_then(|profiler| profiler.sample(endpoint, bytes_sent));
+
+        let resp = self.http_client.execute(request).await?;
+        for middleware in self.middleware.iter() {
+            middleware.on_response(&resp);
+        }
+        self.observe_notice(endpoint, resp.headers());
+
+        self.handle_response(endpoint, resp, sample).await
+    }
+
+    async fn handle_response<T: for<'de> Deserialize<'de> + WithMeta>(
+        &self,
+        endpoint: Endpoint,
+        resp: reqwest::Response,
+        sample: Option<Sample>,
+    ) -> Result<T, Error> {
         let status = resp.status();
+        let headers = resp.headers().clone();
+        let meta = ResponseMeta::from_headers(&headers);
+
+        let body = resp.text().await?;
+        self.record_stats(|stats| stats.record_response(endpoint, body.len()));
+        if let (Some(profiler), Some(sample)) = (&self.profiler, sample) {
+            profiler.record(sample, body.len());
+        }
+        self.log_response_body(status, &body);
 
         if status.is_success() {
-            Ok(resp.json().await?)
-        } else {
-            let error_body: ErrorResponse = resp.json().await.unwrap_or_else(|_| ErrorResponse {
-                error: format!("HTTP {}", status),
-            });
-            Err(Error::Api {
+            let decode_error = |path: String, source: serde_json::Error| Error::Decode {
                 status,
-                message: error_body.error,
-            })
+                path,
+                body: truncate(
+                    &self.mask_own_token(&self.redactor.redact_str(&body)),
+                    MAX_ERROR_BODY_LEN,
+                ),
+                source,
+            };
+            let mut unknown = vec![];
+            let deserializer = &mut serde_json::Deserializer::from_str(&body);
+            let mut value: T = if self.strictness == Strictness::Lenient {
+                serde_path_to_error::deserialize(deserializer)
+            } else {
+                let mut skipped = |path: serde_ignored::Path<'_>| {
+                    unknown.push((strict::field_path(&path, true), strict::field_path(&path, false)));
+                };
+                serde_path_to_error::deserialize(serde_ignored::Deserializer::new(deserializer, &mut skipped))
+            }
+            .map_err(|e| decode_error(e.path().to_string(), e.into_inner()))?;
+
+            if !unknown.is_empty() {
+                let first = unknown[0].0.clone();
+                let mut paths: Vec<String> = unknown.into_iter().map(|(_, path)| path).collect();
+                paths.sort();
+                paths.dedup();
+                self.record_unknown_fields(endpoint, paths);
+                if self.strictness == Strictness::Deny {
+                    let source = serde::de::Error::custom(format_args!("unknown field `{}`", first));
+                    return Err(decode_error(first, source));
+                }
+            }
+            value.set_meta(meta);
+            Ok(value)
+        } else {
+            let mut error = Error::from_response(status, &headers, &body);
+            if let Some(message) = error.message_mut() {
+                *message = self.mask_own_token(&self.redactor.redact_str(message));
+            }
+            Err(error)
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct ErrorResponse {
-    error: String,
+#[cfg(not(feature = "compression"))]
+impl Client {
+    fn compresses(&self) -> bool {
+        false
+    }
+
+    fn compress_payload<'a>(&self, data: &'a Value) -> Result<Cow<'a, Value>, Error> {
+        Ok(Cow::Borrowed(data))
+    }
+
+    fn decompress_payload(&self, _data: &mut Value) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[cfg(not(feature = "encryption"))]
+impl Client {
+    fn encrypt_fields<'a>(&self, data: &'a Value) -> Result<Cow<'a, Value>, Error> {
+        Ok(Cow::Borrowed(data))
+    }
+
+    fn encrypt_patch<'a>(&self, patch: &'a PatchOperations) -> Result<Cow<'a, PatchOperations>, Error> {
+        Ok(Cow::Borrowed(patch))
+    }
+
+    fn decrypt_fields(&self, _data: &mut Value) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Lowercase hex encoding of `bytes`
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct GenerateResponse {
     pub success: bool,
     pub token: String,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl fmt::Debug for GenerateResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GenerateResponse")
+            .field("success", &self.success)
+            .field("token", &mask_token(&self.token))
+            .field("meta", &self.meta)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct StoreResponse {
     pub success: bool,
     pub message: String,
@@ -275,24 +922,30 @@ pub struct StoreResponse {
     pub version: i32,
     pub updated_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RetrieveResponse {
     pub success: bool,
     pub data: Value,
     pub version: i32,
     pub updated_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DeleteResponse {
     pub success: bool,
     pub message: String,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PatchOperations {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub set: Option<HashMap<String, Value>>,
@@ -309,14 +962,18 @@ pub struct PatchResponse {
     pub data: Value,
     pub size: i32,
     pub tier: String,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct HistoryOptions {
     pub limit: Option<i32>,
     pub before: Option<i32>,
     pub since: Option<String>,
-    pub type_filter: Option<String>,
+    pub type_filter: Option<ClassifiedType>,
+    /// Client-side sampling, applied by [`Client::history_sampled`]
+    pub sampling: Option<Sampling>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -324,21 +981,84 @@ pub struct HistoryEvent {
     pub seq: i32,
     pub created_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
-    pub classified_type: Option<String>,
+    pub classified_type: Option<ClassifiedType>,
     pub numeric_value: Option<f64>,
     pub text_value: Option<String>,
     pub confidence: Option<f64>,
     pub payload: Value,
 }
 
+/// Kind of value the API classified a history event's payload as
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ClassifiedType {
+    Numeric,
+    Text,
+    Boolean,
+    Json,
+    /// Any other type, as named by the server
+    Unknown(String),
+}
+
+impl ClassifiedType {
+    /// Name used by the API
+    pub fn as_str(&self) -> &str {
+        match self {
+            ClassifiedType::Numeric => "numeric",
+            ClassifiedType::Text => "text",
+            ClassifiedType::Boolean => "boolean",
+            ClassifiedType::Json => "json",
+            ClassifiedType::Unknown(name) => name,
+        }
+    }
+}
+
+impl From<&str> for ClassifiedType {
+    fn from(name: &str) -> Self {
+        match name {
+            "numeric" => ClassifiedType::Numeric,
+            "text" => ClassifiedType::Text,
+            "boolean" => ClassifiedType::Boolean,
+            "json" => ClassifiedType::Json,
+            other => ClassifiedType::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl From<String> for ClassifiedType {
+    fn from(name: String) -> Self {
+        match ClassifiedType::from(name.as_str()) {
+            ClassifiedType::Unknown(_) => ClassifiedType::Unknown(name),
+            known => known,
+        }
+    }
+}
+
+impl From<ClassifiedType> for String {
+    fn from(typ: ClassifiedType) -> Self {
+        match typ {
+            ClassifiedType::Unknown(name) => name,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ClassifiedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct HistoryResponse {
     pub success: bool,
     pub events: Vec<HistoryEvent>,
     pub pagination: HistoryPagination,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HistoryPagination {
     pub limit: i32,
     pub before: Option<i32>,
@@ -346,7 +1066,7 @@ pub struct HistoryPagination {
     pub has_more: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BatchOperation {
     pub action: String,
     pub token: String,
@@ -360,7 +1080,20 @@ pub struct BatchOperation {
     pub version: Option<i32>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl fmt::Debug for BatchOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchOperation")
+            .field("action", &self.action)
+            .field("token", &mask_token(&self.token))
+            .field("data", &self.data)
+            .field("ttl", &self.ttl)
+            .field("patch", &self.patch)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct BatchResult {
     pub success: bool,
     pub token: String,
@@ -370,11 +1103,66 @@ pub struct BatchResult {
     pub error: Option<String>,
 }
 
+impl fmt::Debug for BatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchResult")
+            .field("success", &self.success)
+            .field("token", &mask_token(&self.token))
+            .field("action", &self.action)
+            .field("data", &self.data)
+            .field("version", &self.version)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+/// Cause of a failed batch operation, parsed from [`BatchResult::error`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchErrorKind {
+    /// The token has no data
+    NotFound,
+    /// The expected version did not match
+    Conflict,
+    /// The value exceeds the tier's size limit
+    TooLarge,
+    /// The operation was rejected by rate limiting
+    RateLimited,
+    /// Any other failure; see the error message
+    Other,
+}
+
+impl BatchResult {
+    /// Cause of the failure, or `None` if the operation succeeded
+    pub fn error_kind(&self) -> Option<BatchErrorKind> {
+        if self.success {
+            return None;
+        }
+        let message = self.error.as_deref().unwrap_or_default().to_ascii_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|needle| message.contains(needle));
+        Some(if has(&["not found", "does not exist", "no data"]) {
+            BatchErrorKind::NotFound
+        } else if has(&["conflict", "version mismatch"]) {
+            BatchErrorKind::Conflict
+        } else if has(&["too large", "exceeds", "size limit"]) {
+            BatchErrorKind::TooLarge
+        } else if has(&["rate limit", "too many requests"]) {
+            BatchErrorKind::RateLimited
+        } else {
+            BatchErrorKind::Other
+        })
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct BatchResponse {
     pub success: bool,
     pub results: Vec<BatchResult>,
     pub summary: BatchSummary,
+    /// Operations as sent, in request order
+    #[serde(skip)]
+    pub operations: Vec<BatchOperation>,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -0,0 +1,244 @@
+//! Capped, ordered lists inside a document
+//!
+//! A [`KvList`] keeps its entries at a dot-notation path in the stored value
+//! as `{"next_seq": 3, "items": [{"seq": 2, "at": "...", "value": ...}]}`,
+//! oldest first. Each push reads the list, appends, drops the oldest entries
+//! beyond the capacity and writes it back with a versioned patch, retrying on
+//! version conflicts. A missing document is created empty first and only
+//! patched once read back unchanged, but the API has no create-if-absent, so
+//! two first pushes at once can still lose one; store the document up front
+//! where that matters.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let logs = client.list::<String>("logs", 100);
+//!
+//! logs.push(&"service started".to_string()).await?;
+//! for entry in logs.recent(10).await? {
+//!     println!("{} {}", entry.at, entry.value);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::path::get_path;
+use crate::{Client, Error, PatchOperations};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Attempts before a read-modify-write gives up on version conflicts
+pub(crate) const MAX_WRITE_ATTEMPTS: usize = 5;
+
+/// Entry in a [`KvList`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListEntry<T> {
+    /// Position in the list, increasing with every push and never reused
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub value: T,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Stored<T> {
+    next_seq: u64,
+    items: Vec<ListEntry<T>>,
+}
+
+impl<T> Default for Stored<T> {
+    fn default() -> Self {
+        Self {
+            next_seq: 0,
+            items: Vec::new(),
+        }
+    }
+}
+
+/// Ring buffer of at most `capacity` entries stored at a path in the document
+pub struct KvList<'a, T = Value> {
+    client: &'a Client,
+    path: String,
+    capacity: usize,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> KvList<'_, T> {
+    /// Path of the list within the document
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Append a value, trimming the oldest entries beyond capacity
+    pub async fn push(&self, value: &T) -> Result<ListEntry<T>, Error> {
+        let value = serde_json::to_value(value)?;
+        let mut attempt = 0;
+        let mut created = None;
+        loop {
+            attempt += 1;
+            let Some(current) = self.client.retrieve_existing().await? else {
+                if attempt >= MAX_WRITE_ATTEMPTS {
+                    return Err(Error::Conflict {
+                        current_version: None,
+                        message: "List document deleted while it was being created".to_string(),
+                    });
+                }
+                // Create the document, then read it back and patch it like any other
+                created = Some(self.client.store(&Value::Object(Map::new()), None).await?.version);
+                continue;
+            };
+            if created.take().is_some_and(|version| version != current.version) {
+                // Another writer got in between creating the document and reading it back
+                if attempt >= MAX_WRITE_ATTEMPTS {
+                    return Err(Error::Conflict {
+                        current_version: Some(current.version),
+                        message: "List document changed while it was being created".to_string(),
+                    });
+                }
+                continue;
+            }
+            let version = current.version;
+            let mut list = self.read::<Value>(&current.data)?;
+
+            let entry = ListEntry {
+                seq: list.next_seq,
+                at: Utc::now(),
+                value: value.clone(),
+            };
+            list.next_seq += 1;
+            list.items.push(entry.clone());
+            let excess = list.items.len().saturating_sub(self.capacity);
+            list.items.drain(..excess);
+
+            let patch = PatchOperations {
+                set: Some(HashMap::from([(self.path.clone(), serde_json::to_value(&list)?)])),
+                remove: None,
+            };
+            match self.client.patch(version, &patch, None).await {
+                Ok(_) => {
+                    return Ok(ListEntry {
+                        seq: entry.seq,
+                        at: entry.at,
+                        value: serde_json::from_value(entry.value)?,
+                    })
+                }
+                Err(Error::Conflict { .. }) if attempt < MAX_WRITE_ATTEMPTS => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Up to `n` most recent entries, oldest first
+    pub async fn recent(&self, n: usize) -> Result<Vec<ListEntry<T>>, Error> {
+        let mut entries = self.entries().await?;
+        let skip = entries.len().saturating_sub(n);
+        entries.drain(..skip);
+        Ok(entries)
+    }
+
+    /// All entries, oldest first
+    pub async fn entries(&self) -> Result<Vec<ListEntry<T>>, Error> {
+        let Some(current) = self.client.retrieve_existing().await? else {
+            return Ok(vec![]);
+        };
+        self.entries_from(&current.data)
+    }
+
+    /// Entries of this list within an already retrieved document
+    pub fn entries_from(&self, data: &Value) -> Result<Vec<ListEntry<T>>, Error> {
+        Ok(self.read::<T>(data)?.items)
+    }
+
+    /// Remove every entry, keeping the sequence counter
+    pub async fn clear(&self) -> Result<(), Error> {
+        let Some(current) = self.client.retrieve_existing().await? else {
+            return Ok(());
+        };
+        let mut list = self.read::<Value>(&current.data)?;
+        if list.items.is_empty() {
+            return Ok(());
+        }
+        list.items.clear();
+        let patch = PatchOperations {
+            set: Some(HashMap::from([(self.path.clone(), serde_json::to_value(&list)?)])),
+            remove: None,
+        };
+        self.client.patch(current.version, &patch, None).await?;
+        Ok(())
+    }
+
+    fn read<V: DeserializeOwned>(&self, data: &Value) -> Result<Stored<V>, Error> {
+        match get_path(data, &self.path) {
+            None | Some(Value::Null) => Ok(Stored::default()),
+            Some(list) => Stored::deserialize(list).map_err(|e| {
+                Error::Validation(format!("Value at {:?} is not a list: {}", self.path, e))
+            }),
+        }
+    }
+}
+
+impl Client {
+    /// List of at most `capacity` entries stored at `path` within the document
+    pub fn list<T: Serialize + DeserializeOwned>(
+        &self,
+        path: impl Into<String>,
+        capacity: usize,
+    ) -> KvList<'_, T> {
+        KvList {
+            client: self,
+            path: path.into(),
+            capacity: capacity.max(1),
+            _type: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::MockServer;
+    use serde_json::json;
+
+    const TOKEN: &str = "word-word-word-word-word";
+
+    #[tokio::test]
+    async fn first_push_creates_the_document() {
+        let server = MockServer::start().await;
+        let client = server.client(TOKEN);
+        let logs = client.list::<String>("logs", 10);
+
+        let entry = logs.push(&"started".to_string()).await.unwrap();
+        assert_eq!((entry.seq, entry.value.as_str()), (0, "started"));
+        assert_eq!(server.requests("/api/retrieve"), 2);
+        assert_eq!(server.stored(TOKEN).unwrap()["logs"]["next_seq"], 1);
+    }
+
+    #[tokio::test]
+    async fn pushes_keep_the_newest_entries_within_capacity() {
+        let server = MockServer::start().await;
+        server.put(TOKEN, json!({"name": "kept"}));
+        let client = server.client(TOKEN);
+        let list = client.list::<u32>("events.recent", 2);
+
+        for n in 0..3 {
+            list.push(&n).await.unwrap();
+        }
+        let entries = list.entries().await.unwrap();
+        let values: Vec<(u64, u32)> = entries.iter().map(|entry| (entry.seq, entry.value)).collect();
+        assert_eq!(values, [(1, 1), (2, 2)]);
+        assert_eq!(list.recent(1).await.unwrap()[0].value, 2);
+        assert_eq!(server.stored(TOKEN).unwrap()["name"], "kept");
+
+        list.clear().await.unwrap();
+        assert!(list.entries().await.unwrap().is_empty());
+        assert_eq!(list.push(&9).await.unwrap().seq, 3);
+    }
+}
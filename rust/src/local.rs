@@ -0,0 +1,368 @@
+//! Offline-first local copy of a value, synced with the API
+//!
+//! A [`LocalStore`] keeps a client's value in a SQLite database. Reads come
+//! from the local copy without a request, writes go to the local copy first
+//! and are stored on the server by [`LocalStore::sync`], typically from the
+//! task [`LocalStore::spawn_sync`] starts, which also syncs right after each
+//! local write. Without a connection the app keeps working on the local copy
+//! and pending writes go out once the server is reachable again.
+//!
+//! A sync pushes a pending local write as a versioned store, so it only lands
+//! if the server still holds the version the write was based on. Otherwise it
+//! settles the conflict per the [`ConflictResolution`]: by default the side
+//! written last wins, or a callback merges both sides. Without pending writes it pulls newer server
+//! values. One database can hold the values of many tokens.
+//!
+//! ```no_run
+//! use keyvalue_client::local::LocalStore;
+//! use keyvalue_client::{Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let store = LocalStore::open("settings.db", Client::new("word-word-word-word-word"))?;
+//! let sync = store.spawn_sync(Duration::from_secs(60));
+//!
+//! store.store(&serde_json::json!({"theme": "dark"}), None)?;
+//! println!("{}", store.retrieve().await?.data["theme"]);
+//!
+//! sync.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{hex, Client, Error, RetrieveResponse};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+
+/// `revision` counts local writes; writes up to `synced_revision` are on the server
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS documents (
+    key TEXT PRIMARY KEY,
+    data TEXT NOT NULL,
+    ttl INTEGER,
+    version INTEGER,
+    updated_at TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    synced_revision INTEGER NOT NULL
+)";
+
+/// Local copy of a value
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalValue {
+    pub data: Value,
+    /// Server version the copy is based on; `None` if it never came from the server
+    pub version: Option<i32>,
+    /// When the server, or the local store for a pending write, last wrote the value
+    pub updated_at: DateTime<Utc>,
+    /// Whether a local write has not been stored on the server yet
+    pub pending: bool,
+}
+
+/// A pending local write whose server value changed since it was based on it
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub local: LocalValue,
+    /// Value on the server; `None` if it was deleted
+    pub remote: Option<RetrieveResponse>,
+}
+
+/// How [`LocalStore::sync`] settles conflicts
+#[derive(Clone, Default)]
+pub enum ConflictResolution {
+    /// Keep the side with the later `updated_at`; a local write beats a deletion
+    #[default]
+    LastWriteWins,
+    /// Store the value the callback returns
+    Custom(Arc<dyn Fn(&Conflict) -> Value + Send + Sync>),
+}
+
+impl ConflictResolution {
+    pub fn custom(resolve: impl Fn(&Conflict) -> Value + Send + Sync + 'static) -> Self {
+        ConflictResolution::Custom(Arc::new(resolve))
+    }
+}
+
+impl fmt::Debug for ConflictResolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictResolution::LastWriteWins => f.write_str("LastWriteWins"),
+            ConflictResolution::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// What a [`LocalStore::sync`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    /// Both sides already agreed
+    InSync,
+    /// A pending local write was stored at `version`
+    Pushed { version: i32 },
+    /// The local copy was replaced with the server value at `version`
+    Pulled { version: i32 },
+    /// A conflict was settled in favor of a local or merged value, stored at `version`
+    Resolved { version: i32 },
+    /// The value was deleted on the server and the local copy removed
+    Removed,
+}
+
+/// Local SQLite copy of a client's value
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    client: Client,
+    /// Row key, a hash of the base URL and token
+    key: String,
+    db: Arc<Mutex<Connection>>,
+    resolution: ConflictResolution,
+    written: Arc<Notify>,
+}
+
+/// Local row with its write counters
+struct Row {
+    value: LocalValue,
+    ttl: Option<i32>,
+    revision: i64,
+}
+
+impl LocalStore {
+    /// Keep `client`'s value in the database at `path`, creating it if needed
+    pub fn open(path: impl AsRef<Path>, client: Client) -> Result<Self, Error> {
+        let token = client.token.as_deref().ok_or(Error::MissingToken)?;
+        let key = hex(&Sha256::digest(format!("{} {}", client.base_url, token)));
+        let db = Connection::open(path).map_err(db_error)?;
+        db.execute(SCHEMA, []).map_err(db_error)?;
+        Ok(Self {
+            client,
+            key,
+            db: Arc::new(Mutex::new(db)),
+            resolution: ConflictResolution::default(),
+            written: Arc::default(),
+        })
+    }
+
+    pub fn with_conflict_resolution(mut self, resolution: ConflictResolution) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Local copy, without contacting the server
+    pub fn get(&self) -> Result<Option<LocalValue>, Error> {
+        Ok(self.row()?.map(|row| row.value))
+    }
+
+    /// Local copy, fetched from the server only if there is none yet
+    pub async fn retrieve(&self) -> Result<LocalValue, Error> {
+        if let Some(value) = self.get()? {
+            return Ok(value);
+        }
+        let resp = self.client.retrieve().await?;
+        self.save(0, &resp.data, resp.version, resp.updated_at)?;
+        self.get()?.ok_or_else(|| Error::LocalStore("Value vanished after saving".to_string()))
+    }
+
+    /// Write `data` locally, to be stored on the server by the next sync
+    pub fn store(&self, data: &Value, ttl: Option<i32>) -> Result<LocalValue, Error> {
+        let db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+        db.execute(
+            "INSERT INTO documents (key, data, ttl, version, updated_at, revision, synced_revision)
+             VALUES (?1, ?2, ?3, NULL, ?4, 1, 0)
+             ON CONFLICT(key) DO UPDATE SET data = ?2, ttl = ?3, updated_at = ?4, revision = revision + 1",
+            params![self.key, data.to_string(), ttl, Utc::now().to_rfc3339()],
+        )
+        .map_err(db_error)?;
+        drop(db);
+        self.written.notify_one();
+        self.get()?.ok_or_else(|| Error::LocalStore("Value vanished after saving".to_string()))
+    }
+
+    /// Whether a local write has not been stored on the server yet
+    pub fn has_pending(&self) -> Result<bool, Error> {
+        Ok(self.get()?.is_some_and(|value| value.pending))
+    }
+
+    /// Push a pending local write or pull a newer server value
+    pub async fn sync(&self) -> Result<SyncOutcome, Error> {
+        let local = self.row()?;
+        let remote = self.client.retrieve_existing().await?;
+        let Some(row) = local else {
+            return match remote {
+                Some(remote) => {
+                    self.save(0, &remote.data, remote.version, remote.updated_at)?;
+                    Ok(SyncOutcome::Pulled { version: remote.version })
+                }
+                None => Ok(SyncOutcome::InSync),
+            };
+        };
+
+        if !row.value.pending {
+            return match remote {
+                Some(remote) if Some(remote.version) == row.value.version => Ok(SyncOutcome::InSync),
+                Some(remote) => {
+                    self.save(row.revision, &remote.data, remote.version, remote.updated_at)?;
+                    Ok(SyncOutcome::Pulled { version: remote.version })
+                }
+                None => {
+                    self.remove(row.revision)?;
+                    Ok(SyncOutcome::Removed)
+                }
+            };
+        }
+
+        let mut remote = remote;
+        if remote.as_ref().map(|remote| remote.version) == row.value.version {
+            match self.push(&row, &row.value.data, row.value.version).await {
+                Ok(version) => return Ok(SyncOutcome::Pushed { version }),
+                // Another write landed since the retrieve
+                Err(Error::Conflict { .. }) => remote = self.client.retrieve_existing().await?,
+                Err(e) => return Err(e),
+            }
+        }
+        let conflict = Conflict {
+            local: row.value.clone(),
+            remote,
+        };
+        let resolved = match (&self.resolution, &conflict.remote) {
+            (ConflictResolution::LastWriteWins, Some(remote)) if remote.updated_at > row.value.updated_at => {
+                self.save(row.revision, &remote.data, remote.version, remote.updated_at)?;
+                return Ok(SyncOutcome::Pulled { version: remote.version });
+            }
+            (ConflictResolution::LastWriteWins, _) => row.value.data.clone(),
+            (ConflictResolution::Custom(resolve), _) => resolve(&conflict),
+        };
+        let based_on = conflict.remote.as_ref().map(|remote| remote.version);
+        let version = self.push(&row, &resolved, based_on).await?;
+        Ok(SyncOutcome::Resolved { version })
+    }
+
+    /// Sync every `interval` and after each local write on a background task
+    ///
+    /// Failed syncs are logged and retried on the next tick. Must be called
+    /// within a Tokio runtime.
+    pub fn spawn_sync(&self, interval: Duration) -> LocalSyncHandle {
+        let store = self.clone();
+        let (stop, mut stopped) = watch::channel(false);
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = stopped.changed() => return,
+                    _ = ticker.tick() => {}
+                    _ = store.written.notified() => {}
+                }
+                if let Err(e) = store.sync().await {
+                    tracing::warn!(error = %e, "local store sync failed");
+                }
+            }
+        });
+        LocalSyncHandle { stop, task }
+    }
+
+    /// Store `data` for the local write in `row` if the server is still at `version`, and record the new version
+    ///
+    /// With no `version`, the server had no value and `data` is stored as is.
+    async fn push(&self, row: &Row, data: &Value, version: Option<i32>) -> Result<i32, Error> {
+        let resp = self.client.store_versioned(data, version, row.ttl).await?;
+        self.save(row.revision, data, resp.version, resp.updated_at)?;
+        Ok(resp.version)
+    }
+
+    /// Record server `version` of `data` as covering local writes up to `revision`
+    ///
+    /// The local data is only replaced if no write came in after `revision`.
+    fn save(&self, revision: i64, data: &Value, version: i32, updated_at: DateTime<Utc>) -> Result<(), Error> {
+        let db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+        db.execute(
+            "INSERT INTO documents (key, data, ttl, version, updated_at, revision, synced_revision)
+             VALUES (?1, ?2, NULL, ?3, ?4, ?5, ?5)
+             ON CONFLICT(key) DO UPDATE SET
+                 version = ?3,
+                 synced_revision = ?5,
+                 data = CASE WHEN revision = ?5 THEN ?2 ELSE data END,
+                 updated_at = CASE WHEN revision = ?5 THEN ?4 ELSE updated_at END",
+            params![self.key, data.to_string(), version, updated_at.to_rfc3339(), revision],
+        )
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Drop the local copy unless it was written after `revision`
+    fn remove(&self, revision: i64) -> Result<(), Error> {
+        let db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+        db.execute(
+            "DELETE FROM documents WHERE key = ?1 AND revision = ?2",
+            params![self.key, revision],
+        )
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn row(&self) -> Result<Option<Row>, Error> {
+        let db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+        let row = db
+            .query_row(
+                "SELECT data, ttl, version, updated_at, revision, synced_revision FROM documents WHERE key = ?1",
+                params![self.key],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, i64>(4)?,
+                        row.get::<_, i64>(5)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(db_error)?;
+        let Some((data, ttl, version, updated_at, revision, synced_revision)) = row else {
+            return Ok(None);
+        };
+        let updated_at = DateTime::parse_from_rfc3339(&updated_at)
+            .map_err(|e| Error::LocalStore(format!("Invalid timestamp {:?}: {}", updated_at, e)))?
+            .with_timezone(&Utc);
+        Ok(Some(Row {
+            value: LocalValue {
+                data: serde_json::from_str(&data)?,
+                version,
+                updated_at,
+                pending: revision > synced_revision,
+            },
+            ttl,
+            revision,
+        }))
+    }
+}
+
+/// Running [`LocalStore::spawn_sync`] task
+///
+/// Dropping the handle stops the task like [`LocalSyncHandle::shutdown`],
+/// without waiting for it.
+pub struct LocalSyncHandle {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl LocalSyncHandle {
+    /// Stop after the sync in progress, if any
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        let _ = self.task.await;
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> Error {
+    Error::LocalStore(e.to_string())
+}
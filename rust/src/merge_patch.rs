@@ -0,0 +1,295 @@
+//! JSON Merge Patch (RFC 7386)
+//!
+//! Merge patches are translated into the API's `set`/`remove` model using
+//! dot-notation paths: `null` members become removals, nested objects are
+//! merged field by field, and any other value is set as-is.
+//!
+//! How a nested object applies depends on the stored member it patches: it
+//! is merged into an object, replaces anything else, and an empty one leaves
+//! an object unchanged. [`PatchOperations::from_merge_patch_onto`] consults
+//! the current document to get this right. Without it,
+//! [`PatchOperations::from_merge_patch`] assumes every nested object patches
+//! an object, relying on the server to create missing intermediate objects,
+//! and rejects empty ones, which would mean either "no change" or "set `{}`".
+
+use crate::{Client, Error, PatchOperations, PatchResponse};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Compute the minimal merge patch that turns `old` into `new`
+///
+/// Merge patches cannot express setting a member to `null`; such members in
+/// `new` produce a removal instead.
+pub fn merge_patch_from_diff(old: &Value, new: &Value) -> Value {
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return new.clone();
+    };
+
+    let mut patch = Map::new();
+    for key in old.keys() {
+        if !new.contains_key(key) {
+            patch.insert(key.clone(), Value::Null);
+        }
+    }
+    for (key, new_value) in new {
+        match old.get(key) {
+            Some(old_value) if old_value == new_value => {}
+            Some(old_value) if old_value.is_object() && new_value.is_object() => {
+                patch.insert(key.clone(), merge_patch_from_diff(old_value, new_value));
+            }
+            _ => {
+                patch.insert(key.clone(), new_value.clone());
+            }
+        }
+    }
+    Value::Object(patch)
+}
+
+/// Apply a merge patch to `target` in place
+pub fn apply_merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            apply_merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+impl PatchOperations {
+    /// Translate a merge patch into `set`/`remove` operations
+    ///
+    /// Fails on empty nested objects; see the [module docs](crate::merge_patch).
+    pub fn from_merge_patch(patch: &Value) -> Result<Self, Error> {
+        Self::collect(patch, None)
+    }
+
+    /// Translate a merge patch into the operations that apply it to `current`
+    ///
+    /// The operations turn `current` into what [`apply_merge_patch`] makes of it.
+    pub fn from_merge_patch_onto(patch: &Value, current: &Value) -> Result<Self, Error> {
+        Self::collect(patch, Some(current.as_object().unwrap_or(&Map::new())))
+    }
+
+    fn collect(patch: &Value, current: Option<&Map<String, Value>>) -> Result<Self, Error> {
+        let Value::Object(patch) = patch else {
+            return Err(Error::Validation("Merge patch must be a JSON object".to_string()));
+        };
+
+        let mut set = HashMap::new();
+        let mut remove = vec![];
+        collect_operations(patch, current, "", &mut set, &mut remove)?;
+
+        Ok(Self {
+            set: (!set.is_empty()).then_some(set),
+            remove: (!remove.is_empty()).then_some(remove),
+        })
+    }
+}
+
+impl Client {
+    /// Apply an RFC 7386 merge patch with optimistic concurrency
+    ///
+    /// The patch is translated without the stored document, as by
+    /// [`PatchOperations::from_merge_patch`].
+    pub async fn merge_patch(
+        &self,
+        version: i32,
+        patch: &Value,
+        ttl: Option<i32>,
+    ) -> Result<PatchResponse, Error> {
+        let operations = PatchOperations::from_merge_patch(patch)?;
+        self.patch(version, &operations, ttl).await
+    }
+}
+
+/// Operations for `patch`, consulting the members of `current` if known
+fn collect_operations(
+    patch: &Map<String, Value>,
+    current: Option<&Map<String, Value>>,
+    prefix: &str,
+    set: &mut HashMap<String, Value>,
+    remove: &mut Vec<String>,
+) -> Result<(), Error> {
+    for (key, value) in patch {
+        if key.contains('.') {
+            return Err(Error::Validation(format!(
+                "Merge patch key '{}' cannot contain '.'",
+                key
+            )));
+        }
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        match value {
+            Value::Null => remove.push(path),
+            Value::Object(nested) => match current.map(|current| current.get(key)) {
+                None if nested.is_empty() => {
+                    return Err(Error::Validation(format!(
+                        "Merge patch member '{}' is an empty object, which needs the current document to apply",
+                        path
+                    )));
+                }
+                None => collect_operations(nested, None, &path, set, remove)?,
+                Some(Some(Value::Object(member))) => {
+                    collect_operations(nested, Some(member), &path, set, remove)?
+                }
+                // Nothing to merge into, so the member becomes the patch less its removals
+                Some(_) => {
+                    let mut replacement = Value::Null;
+                    apply_merge_patch(&mut replacement, value);
+                    set.insert(path, replacement);
+                }
+            },
+            _ => {
+                set.insert(path, value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn operations(patch: Value) -> PatchOperations {
+        PatchOperations::from_merge_patch(&patch).unwrap()
+    }
+
+    #[test]
+    fn nested_objects_become_dotted_paths() {
+        let ops = operations(json!({"a": {"b": 1, "c": null}, "d": [1, 2]}));
+        let set = ops.set.unwrap();
+        assert_eq!(set.len(), 2);
+        assert_eq!(set["a.b"], 1);
+        assert_eq!(set["d"], json!([1, 2]));
+        assert_eq!(ops.remove.unwrap(), ["a.c"]);
+    }
+
+    #[test]
+    fn empty_nested_objects_need_the_current_document() {
+        for patch in [json!({"cfg": {}}), json!({"a": {"b": {}}})] {
+            assert!(matches!(PatchOperations::from_merge_patch(&patch), Err(Error::Validation(_))));
+        }
+        let ops = PatchOperations::from_merge_patch_onto(&json!({"cfg": {}, "n": {}}), &json!({"cfg": {"x": 1}})).unwrap();
+        assert!(ops.remove.is_none());
+        assert_eq!(ops.set.unwrap(), HashMap::from([("n".to_string(), json!({}))]));
+    }
+
+    /// `target` after the operations, applied the way the API does
+    fn apply_operations(mut target: Value, ops: &PatchOperations) -> Value {
+        for (path, value) in ops.set.iter().flatten() {
+            let mut member = &mut target;
+            for segment in path.split('.') {
+                if !member.is_object() {
+                    *member = json!({});
+                }
+                member = member.as_object_mut().unwrap().entry(segment).or_insert(Value::Null);
+            }
+            *member = value.clone();
+        }
+        for path in ops.remove.iter().flatten() {
+            let (parent, key) = path.rsplit_once('.').unwrap_or(("", path));
+            let mut member = Some(&mut target);
+            for segment in parent.split('.').filter(|segment| !segment.is_empty()) {
+                member = member.and_then(|member| member.get_mut(segment));
+            }
+            if let Some(Value::Object(map)) = member {
+                map.remove(key);
+            }
+        }
+        target
+    }
+
+    #[test]
+    fn operations_onto_the_current_document_agree_with_apply() {
+        let cases = [
+            (json!({"a": {"b": "c"}}), json!({"a": {"b": "d", "c": null}})),
+            (json!({"a": {"b": 1}}), json!({"a": {}})),
+            (json!({"a": 5}), json!({"a": {}})),
+            (json!({"a": 5}), json!({"a": {"b": {"c": 1, "d": null}}})),
+            (json!({"a": [1]}), json!({"a": {"b": 2}})),
+            (json!({}), json!({"a": {"bb": {"ccc": null}}})),
+            (json!({"e": null}), json!({"a": 1, "e": {}})),
+            (json!([1, 2]), json!({"a": "b", "c": null})),
+        ];
+        for (target, patch) in cases {
+            let ops = PatchOperations::from_merge_patch_onto(&patch, &target).unwrap();
+            let mut expected = target.clone();
+            apply_merge_patch(&mut expected, &patch);
+            // Like the merge patch, the operations start a non-object document afresh
+            let start = if target.is_object() { target.clone() } else { json!({}) };
+            assert_eq!(apply_operations(start, &ops), expected, "{} patched with {}", target, patch);
+        }
+    }
+
+    #[test]
+    fn empty_patch_has_no_operations() {
+        let ops = operations(json!({}));
+        assert!(ops.set.is_none() && ops.remove.is_none());
+    }
+
+    #[test]
+    fn rejects_non_objects_and_dotted_keys() {
+        assert!(matches!(PatchOperations::from_merge_patch(&json!([1])), Err(Error::Validation(_))));
+        assert!(matches!(PatchOperations::from_merge_patch(&json!("x")), Err(Error::Validation(_))));
+        assert!(matches!(
+            PatchOperations::from_merge_patch(&json!({"a": {"b.c": 1}})),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn apply_follows_rfc_7386() {
+        // Examples from RFC 7386, appendix A
+        let cases = [
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "b"}), json!({"b": "c"}), json!({"a": "b", "b": "c"})),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (json!({"a": {"b": "c"}}), json!({"a": {"b": "d", "c": null}}), json!({"a": {"b": "d"}})),
+            (json!({"a": [{"b": "c"}]}), json!({"a": [1]}), json!({"a": [1]})),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"e": null}), json!({"a": 1}), json!({"e": null, "a": 1})),
+            (json!([1, 2]), json!({"a": "b", "c": null}), json!({"a": "b"})),
+            (json!({}), json!({"a": {"bb": {"ccc": null}}}), json!({"a": {"bb": {}}})),
+        ];
+        for (target, patch, expected) in cases {
+            let mut result = target.clone();
+            apply_merge_patch(&mut result, &patch);
+            assert_eq!(result, expected, "{} patched with {}", target, patch);
+        }
+    }
+
+    #[test]
+    fn diff_produces_a_patch_that_applies() {
+        let old = json!({"a": 1, "b": {"c": 2, "d": 3}, "e": 5, "f": "same"});
+        let new = json!({"a": 1, "b": {"c": 4}, "e": {}, "g": [1]});
+        let patch = merge_patch_from_diff(&old, &new);
+        assert_eq!(patch, json!({"b": {"c": 4, "d": null}, "e": {}, "f": null, "g": [1]}));
+
+        let mut applied = old.clone();
+        apply_merge_patch(&mut applied, &patch);
+        assert_eq!(applied, new);
+        let ops = PatchOperations::from_merge_patch_onto(&patch, &old).unwrap();
+        assert_eq!(apply_operations(old.clone(), &ops), new);
+        assert_eq!(merge_patch_from_diff(&new, &new), json!({}));
+    }
+}
@@ -0,0 +1,86 @@
+//! Response metadata from HTTP headers
+
+use reqwest::header::HeaderMap;
+
+/// Headers checked, in order, for a request ID
+const REQUEST_ID_HEADERS: &[&str] = &["x-request-id", "x-correlation-id", "x-vercel-id", "cf-ray"];
+
+/// Headers checked for a replayed idempotent response
+const IDEMPOTENT_REPLAY_HEADERS: &[&str] = &["idempotent-replayed", "x-idempotent-replayed"];
+
+/// Metadata attached to every API response
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// Request ID to quote in support requests
+    pub request_id: Option<String>,
+    /// Rate limit state reported by the server
+    pub rate_limit: Option<RateLimit>,
+    /// Whether the server answered a repeated idempotency key with the original result
+    pub idempotent_replay: bool,
+}
+
+/// `X-RateLimit-*` header values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests allowed in the current window
+    pub limit: Option<u64>,
+    /// Requests remaining in the current window
+    pub remaining: Option<u64>,
+    /// Window reset, as sent by the server (unix timestamp or seconds until reset)
+    pub reset: Option<u64>,
+}
+
+impl ResponseMeta {
+    /// Extract metadata from response headers
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let number = |name: &str| header(name).and_then(|v| v.trim().parse().ok());
+
+        let rate_limit = RateLimit {
+            limit: number("x-ratelimit-limit"),
+            remaining: number("x-ratelimit-remaining"),
+            reset: number("x-ratelimit-reset"),
+        };
+
+        Self {
+            request_id: REQUEST_ID_HEADERS
+                .iter()
+                .find_map(|name| header(name))
+                .map(str::to_string),
+            rate_limit: (rate_limit != RateLimit::default()).then_some(rate_limit),
+            idempotent_replay: IDEMPOTENT_REPLAY_HEADERS
+                .iter()
+                .any(|name| header(name).is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))),
+        }
+    }
+}
+
+/// Response types that carry [`ResponseMeta`]
+pub(crate) trait WithMeta {
+    fn set_meta(&mut self, meta: ResponseMeta);
+}
+
+macro_rules! impl_with_meta {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl WithMeta for $ty {
+                fn set_meta(&mut self, meta: ResponseMeta) {
+                    self.meta = meta;
+                }
+            }
+        )*
+    };
+}
+
+impl_with_meta!(
+    crate::GenerateResponse,
+    crate::StoreResponse,
+    crate::RetrieveResponse,
+    crate::DeleteResponse,
+    crate::PatchResponse,
+    crate::HistoryResponse,
+    crate::BatchResponse,
+    crate::ApiStatus,
+    crate::Usage,
+    crate::Metadata,
+);
@@ -0,0 +1,63 @@
+//! Presence and metadata checks without the payload
+//!
+//! [`Client::metadata`] asks the retrieve endpoint for just the version,
+//! timestamps and size via its `fields` parameter. Servers that ignore the
+//! parameter still send the payload, which is skipped while decoding.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! if client.exists().await? {
+//!     let metadata = client.metadata().await?;
+//!     println!("Version {} updated {}", metadata.version, metadata.updated_at);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Endpoint, Error, ResponseMeta};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Fields requested from the retrieve endpoint
+const METADATA_FIELDS: &str = "version,updated_at,expires_at,size";
+
+/// Version, timestamps and size of the stored document
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Metadata {
+    pub version: i32,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Payload size in bytes, if the server reports it
+    #[serde(default)]
+    pub size: Option<i32>,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
+}
+
+impl Client {
+    /// Whether any data is stored for the current token
+    pub async fn exists(&self) -> Result<bool, Error> {
+        match self.metadata().await {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Version, timestamps and size of the stored document
+    pub async fn metadata(&self) -> Result<Metadata, Error> {
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+
+        let request = self.http_client
+            .get(self.url(Endpoint::Retrieve))
+            .query(&[("fields", METADATA_FIELDS)]);
+        let request = self.auth.apply(request, token);
+
+        let result = self.execute(Endpoint::Retrieve, request).await;
+        self.track_version(&result, |metadata: &Metadata| metadata.version);
+        result
+    }
+}
@@ -0,0 +1,99 @@
+//! Request and response hooks
+//!
+//! Middleware runs on every API call, in the order it was added: `on_request`
+//! sees the fully built request just before it is sent, and `on_response` sees
+//! the response before its body is read. Retries and failovers send a fresh
+//! copy of the request, routed to its base URL, through `on_request` again.
+//!
+//! ```
+//! use keyvalue_client::{Client, Error, Middleware};
+//!
+//! struct Signer;
+//!
+//! impl Middleware for Signer {
+//!     fn on_request(&self, request: &mut reqwest::Request) -> Result<(), Error> {
+//!         let signature = format!("{} {}", request.method(), request.url().path());
+//!         request.headers_mut().insert("X-Signature", signature.parse().unwrap());
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let client = Client::new("word-word-word-word-word")
+//!     .with_header("X-Tenant", "acme")
+//!     .unwrap()
+//!     .with_middleware(Signer);
+//! ```
+
+use crate::{Client, Error};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Request, Response};
+use std::sync::Arc;
+
+/// Hook around every request the client sends
+pub trait Middleware: Send + Sync {
+    /// Inspect or modify an outgoing request; an error aborts the call
+    fn on_request(&self, request: &mut Request) -> Result<(), Error> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// Observe a response before its body is read
+    fn on_response(&self, response: &Response) {
+        let _ = response;
+    }
+}
+
+/// Middleware that adds fixed headers to every request
+///
+/// Headers already set on a request are left untouched.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultHeaders {
+    headers: HeaderMap,
+}
+
+impl DefaultHeaders {
+    pub fn new(headers: HeaderMap) -> Self {
+        Self { headers }
+    }
+
+    /// Add a header, validating its name and value
+    pub fn insert(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| Error::Validation(format!("Invalid header name {:?}: {}", name, e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| Error::Validation(format!("Invalid value for header {}: {}", name, e)))?;
+        self.headers.append(name, value);
+        Ok(())
+    }
+}
+
+impl Middleware for DefaultHeaders {
+    fn on_request(&self, request: &mut Request) -> Result<(), Error> {
+        let headers = request.headers_mut();
+        for name in self.headers.keys() {
+            if !headers.contains_key(name) {
+                for value in self.headers.get_all(name) {
+                    headers.append(name, value.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Add a middleware hook, run after those already added
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        let mut stack = self.middleware.to_vec();
+        stack.push(Arc::new(middleware));
+        self.middleware = stack.into();
+        self
+    }
+
+    /// Send an extra header with every request
+    pub fn with_header(self, name: &str, value: &str) -> Result<Self, Error> {
+        let mut headers = DefaultHeaders::default();
+        headers.insert(name, value)?;
+        Ok(self.with_middleware(headers))
+    }
+}
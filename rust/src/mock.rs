@@ -0,0 +1,241 @@
+//! In-memory API server for unit tests
+//!
+//! Serves store, patch, retrieve, delete and batch over plain HTTP on a
+//! local port, keeping each token's document and version. Batch stores of
+//! `{"fail": true}` are rejected so tests can make one operation fail, and
+//! whole batch requests can be made to fail by dropping the connection.
+
+use crate::Client;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const NOW: &str = "2026-01-01T00:00:00Z";
+
+/// Runs until the test's runtime shuts down
+#[derive(Clone)]
+pub(crate) struct MockServer {
+    pub(crate) url: String,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    docs: HashMap<String, (Value, i32)>,
+    /// Requests received, by path
+    requests: HashMap<String, usize>,
+    /// Batch request, counting from 1, from which on the connection is dropped
+    drop_batches_from: Option<usize>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    token: String,
+    body: Value,
+}
+
+impl MockServer {
+    pub(crate) async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Self {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            state: Arc::default(),
+        };
+        let state = server.state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, state.clone()));
+            }
+        });
+        server
+    }
+
+    /// Client of this server using `token`
+    pub(crate) fn client(&self, token: &str) -> Client {
+        Client::builder().base_url(&self.url).token(token).build().unwrap()
+    }
+
+    /// Document stored for `token`, exactly as the client sent it
+    pub(crate) fn stored(&self, token: &str) -> Option<Value> {
+        self.state.lock().unwrap().docs.get(token).map(|(data, _)| data.clone())
+    }
+
+    pub(crate) fn put(&self, token: &str, data: Value) {
+        let mut state = self.state.lock().unwrap();
+        let version = state.docs.get(token).map_or(1, |(_, version)| version + 1);
+        state.docs.insert(token.to_string(), (data, version));
+    }
+
+    /// Number of requests received for `path`
+    pub(crate) fn requests(&self, path: &str) -> usize {
+        self.state.lock().unwrap().requests.get(path).copied().unwrap_or(0)
+    }
+
+    /// Close the connection instead of answering the `n`th batch request and those after it
+    pub(crate) fn drop_batches_from(&self, n: usize) {
+        self.state.lock().unwrap().drop_batches_from = Some(n);
+    }
+}
+
+async fn serve(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    while let Some(request) = read_request(&mut stream).await {
+        let answer = {
+            let mut state = state.lock().unwrap();
+            let count = state.requests.entry(request.path.clone()).or_default();
+            *count += 1;
+            let count = *count;
+            match state.drop_batches_from {
+                Some(n) if request.path == "/api/batch" && count >= n => None,
+                _ => Some(respond(&mut state.docs, &request)),
+            }
+        };
+        let Some((status, body)) = answer else {
+            return;
+        };
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        if stream.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 8192];
+    loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&buf[..end]).to_string();
+            let header = |name: &str| {
+                head.lines().find_map(|line| {
+                    let (key, value) = line.split_once(':')?;
+                    key.eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+                })
+            };
+            let length: usize = header("content-length").map_or(0, |length| length.parse().unwrap());
+            if buf.len() >= end + 4 + length {
+                let mut words = head.split_whitespace();
+                let method = words.next()?.to_string();
+                let path = words.next()?.split('?').next()?.to_string();
+                let body = serde_json::from_slice(&buf[end + 4..end + 4 + length]).unwrap_or(Value::Null);
+                let token = header("x-kv-token").unwrap_or_default();
+                return Some(Request { method, path, token, body });
+            }
+        }
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+}
+
+fn respond(docs: &mut HashMap<String, (Value, i32)>, request: &Request) -> (u16, Value) {
+    let body = &request.body;
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/api/store") => {
+            let version = store(docs, &request.token, body["data"].clone());
+            (200, json!({
+                "success": true, "message": "Stored", "size": body["data"].to_string().len(),
+                "tier": "free", "version": version, "updated_at": NOW, "expires_at": null,
+            }))
+        }
+        ("PATCH", "/api/store") => match docs.get_mut(&request.token) {
+            None => not_found(),
+            Some((_, version)) if body["version"].as_i64() != Some(i64::from(*version)) => {
+                (409, json!({"error": "Version conflict", "current_version": *version}))
+            }
+            Some((data, version)) => {
+                apply_patch(data, &body["patch"]);
+                *version += 1;
+                (200, json!({
+                    "success": true, "version": *version, "updated_at": NOW, "expires_at": null,
+                    "data": data, "size": data.to_string().len(), "tier": "free",
+                }))
+            }
+        },
+        ("GET", "/api/retrieve") => match docs.get(&request.token) {
+            Some((data, version)) => (200, json!({
+                "success": true, "data": data, "version": version, "updated_at": NOW, "expires_at": null,
+            })),
+            None => not_found(),
+        },
+        ("DELETE", "/api/delete") => match docs.remove(&request.token) {
+            Some(_) => (200, json!({"success": true, "message": "Deleted"})),
+            None => not_found(),
+        },
+        ("POST", "/api/batch") => {
+            let operations = body["operations"].as_array().cloned().unwrap_or_default();
+            let results: Vec<Value> = operations.iter().map(|operation| batch_operation(docs, operation)).collect();
+            let succeeded = results.iter().filter(|result| result["success"] == true).count();
+            (200, json!({
+                "success": succeeded == results.len(),
+                "results": results,
+                "summary": {
+                    "total": operations.len(), "succeeded": succeeded,
+                    "failed": operations.len() - succeeded, "successRate": "",
+                },
+            }))
+        }
+        _ => (404, json!({"error": "Unknown endpoint"})),
+    }
+}
+
+fn batch_operation(docs: &mut HashMap<String, (Value, i32)>, operation: &Value) -> Value {
+    let token = operation["token"].as_str().unwrap_or_default();
+    let action = operation["action"].as_str().unwrap_or_default();
+    let (data, version) = match action {
+        "store" if operation["data"]["fail"] != true => {
+            (Value::Null, Some(store(docs, token, operation["data"].clone())))
+        }
+        "delete" if docs.remove(token).is_some() => (Value::Null, None),
+        "retrieve" => match docs.get(token) {
+            Some((data, version)) => (data.clone(), Some(*version)),
+            None => return json!({"success": false, "token": token, "action": action, "error": "Not found"}),
+        },
+        _ => return json!({"success": false, "token": token, "action": action, "error": "Rejected"}),
+    };
+    json!({"success": true, "token": token, "action": action, "data": data, "version": version, "error": null})
+}
+
+fn store(docs: &mut HashMap<String, (Value, i32)>, token: &str, data: Value) -> i32 {
+    let version = docs.get(token).map_or(1, |(_, version)| version + 1);
+    docs.insert(token.to_string(), (data, version));
+    version
+}
+
+/// Apply dotted-path `set` and `remove` operations as the API does
+fn apply_patch(data: &mut Value, patch: &Value) {
+    for (path, value) in patch["set"].as_object().into_iter().flatten() {
+        let mut target = &mut *data;
+        for segment in path.split('.') {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            target = target.as_object_mut().unwrap().entry(segment).or_insert(Value::Null);
+        }
+        *target = value.clone();
+    }
+    for path in patch["remove"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+        let (parent, key) = path.rsplit_once('.').unwrap_or(("", path));
+        let mut target = Some(&mut *data);
+        for segment in parent.split('.').filter(|segment| !segment.is_empty()) {
+            target = target.and_then(|target| target.get_mut(segment));
+        }
+        if let Some(Value::Object(map)) = target {
+            map.remove(key);
+        }
+    }
+}
+
+fn not_found() -> (u16, Value) {
+    (404, json!({"error": "Not found"}))
+}
@@ -0,0 +1,157 @@
+//! Running one operation across many tokens
+//!
+//! The batch endpoint takes at most 100 operations. For fleet-wide reads and
+//! config pushes beyond that, the helpers here run the same operation on a
+//! set of clients, one per token, at most `concurrency` at a time, and
+//! collect each token's result in input order so one failing token does not
+//! hide the others.
+//!
+//! ```no_run
+//! use keyvalue_client::{multi, Client};
+//!
+//! # async fn run() {
+//! let clients: Vec<Client> = ["word-word-word-word-one", "word-word-word-word-two"]
+//!     .into_iter()
+//!     .map(Client::new)
+//!     .collect();
+//!
+//! let config = serde_json::json!({"sample_rate": 10});
+//! let pushed = multi::store_all(&clients, &config, None, multi::DEFAULT_CONCURRENCY).await;
+//! for (i, e) in pushed.failures() {
+//!     eprintln!("{:?} failed: {}", clients[i], e);
+//! }
+//! println!("{} of {} updated", pushed.succeeded(), pushed.len());
+//! # }
+//! ```
+//!
+//! [`Client::batch_concurrent`] does the same for more operations than one
+//! batch takes: it splits them into batches of 100 and sends those
+//! concurrently.
+
+use crate::scheduler::MAX_BATCH;
+use crate::{BatchOperation, BatchResponse, BatchSummary, Client, Error, RetrieveResponse, StoreResponse};
+use futures_util::stream::{self, StreamExt};
+use serde_json::Value;
+use std::future::Future;
+
+/// Requests in flight at once when no other limit is given
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Per-client results of a fan-out, in the order the clients were given
+#[derive(Debug)]
+pub struct FanOut<T> {
+    pub results: Vec<Result<T, Error>>,
+}
+
+impl<T> FanOut<T> {
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Number of clients the operation succeeded for
+    pub fn succeeded(&self) -> usize {
+        self.results.iter().filter(|result| result.is_ok()).count()
+    }
+
+    /// Whether the operation succeeded for every client
+    pub fn all_succeeded(&self) -> bool {
+        self.results.iter().all(Result::is_ok)
+    }
+
+    /// Index of each failed client with its error
+    pub fn failures(&self) -> impl Iterator<Item = (usize, &Error)> + '_ {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, result)| result.as_ref().err().map(|e| (i, e)))
+    }
+
+    /// All values, or the first error by client order
+    pub fn into_result(self) -> Result<Vec<T>, Error> {
+        self.results.into_iter().collect()
+    }
+}
+
+/// Run `op` on every client, at most `concurrency` at a time
+pub async fn run_all<'a, T, F, Fut>(clients: &'a [Client], concurrency: usize, op: F) -> FanOut<T>
+where
+    F: Fn(&'a Client) -> Fut,
+    Fut: Future<Output = Result<T, Error>> + 'a,
+{
+    let results = stream::iter(clients)
+        .map(op)
+        .buffered(concurrency.max(1))
+        .collect()
+        .await;
+    FanOut { results }
+}
+
+/// Retrieve each client's value
+pub async fn retrieve_all(clients: &[Client], concurrency: usize) -> FanOut<RetrieveResponse> {
+    run_all(clients, concurrency, Client::retrieve).await
+}
+
+/// Store the same value under each client's token
+pub async fn store_all(
+    clients: &[Client],
+    data: &Value,
+    ttl: Option<i32>,
+    concurrency: usize,
+) -> FanOut<StoreResponse> {
+    run_all(clients, concurrency, |client| client.store(data, ttl)).await
+}
+
+/// Outcome of [`Client::batch_concurrent`]
+#[derive(Debug)]
+pub struct ChunkedBatch {
+    /// Operations as given
+    pub operations: Vec<BatchOperation>,
+    /// Response or error of each batch of 100, in order
+    pub chunks: FanOut<BatchResponse>,
+    /// Totals over all operations; those of failed batches count as failed
+    pub summary: BatchSummary,
+}
+
+impl ChunkedBatch {
+    /// The operations that failed, alone or with their batch, in order
+    pub fn failed_operations(&self) -> Vec<&BatchOperation> {
+        self.operations
+            .chunks(MAX_BATCH)
+            .zip(&self.chunks.results)
+            .flat_map(|(operations, chunk)| match chunk {
+                Ok(resp) => resp.failed_operations(),
+                Err(_) => operations.iter().collect(),
+            })
+            .collect()
+    }
+}
+
+impl Client {
+    /// Send any number of operations as batches of 100, at most `concurrency` at a time
+    ///
+    /// A failing batch does not stop the others. Batches run in parallel, so
+    /// operations on the same token in different batches may apply in any order.
+    pub async fn batch_concurrent(&self, operations: Vec<BatchOperation>, concurrency: usize) -> ChunkedBatch {
+        let results = stream::iter(operations.chunks(MAX_BATCH))
+            .map(|chunk| self.batch(chunk.to_vec()))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+        let chunks = FanOut { results };
+        let succeeded = chunks
+            .results
+            .iter()
+            .flatten()
+            .map(|resp| resp.results.iter().filter(|result| result.success).count())
+            .sum();
+        ChunkedBatch {
+            summary: BatchSummary::from_counts(operations.len(), succeeded),
+            operations,
+            chunks,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Deprecation and sunset notices sent by the API
+//!
+//! Responses carrying a `Deprecation` header ([RFC 9745]), a `Sunset` header
+//! ([RFC 8594]) or a `Link` with `rel="deprecation"` or `rel="sunset"` are
+//! recorded per endpoint and reported once on first sight, or again if the
+//! notice changes. By default the report is a `tracing` warning; replace it
+//! with [`Client::on_server_notice`]. [`Client::server_notices`] lists every
+//! notice seen so far.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word").on_server_notice(|notice| {
+//!     eprintln!("{:?} is deprecated, removal {:?}", notice.endpoint, notice.sunset)
+//! });
+//! client.retrieve().await?;
+//! for notice in client.server_notices().iter() {
+//!     println!("{:?}: {:?}", notice.endpoint, notice.link);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [RFC 9745]: https://www.rfc-editor.org/rfc/rfc9745
+//! [RFC 8594]: https://www.rfc-editor.org/rfc/rfc8594
+
+use crate::{Client, Endpoint};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, LINK};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Deprecation state of an endpoint as reported by the API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerNotice {
+    pub endpoint: Endpoint,
+    /// Whether the endpoint is marked deprecated
+    pub deprecated: bool,
+    /// When the deprecation took or takes effect, if given
+    pub deprecated_at: Option<DateTime<Utc>>,
+    /// When the endpoint is expected to stop working
+    pub sunset: Option<DateTime<Utc>>,
+    /// Documentation linked from the notice
+    pub link: Option<String>,
+}
+
+impl ServerNotice {
+    /// Notice carried by response headers, if any
+    pub fn from_headers(endpoint: Endpoint, headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+        let deprecation = header("deprecation");
+        let sunset = header("sunset").and_then(parse_http_date);
+        let link = headers
+            .get_all(LINK)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .find_map(notice_link);
+        if deprecation.is_none() && sunset.is_none() && link.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            endpoint,
+            deprecated: deprecation.is_some_and(|v| !v.eq_ignore_ascii_case("false")),
+            deprecated_at: deprecation.and_then(|v| match v.strip_prefix('@') {
+                // RFC 9745 structured date: `@<unix seconds>`
+                Some(seconds) => DateTime::from_timestamp(seconds.parse().ok()?, 0),
+                // Earlier drafts used an HTTP date
+                None => parse_http_date(v),
+            }),
+            sunset,
+            link,
+        })
+    }
+}
+
+/// Notices seen by a client and its clones, one per endpoint
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerNotices {
+    endpoints: BTreeMap<Endpoint, ServerNotice>,
+}
+
+impl ServerNotices {
+    /// Latest notice for an endpoint
+    pub fn get(&self, endpoint: Endpoint) -> Option<&ServerNotice> {
+        self.endpoints.get(&endpoint)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ServerNotice> + '_ {
+        self.endpoints.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+}
+
+pub(crate) type NoticeHandler = Arc<dyn Fn(&ServerNotice) + Send + Sync>;
+
+impl Client {
+    /// Report new server notices to `handler` instead of logging a warning
+    pub fn on_server_notice(
+        mut self,
+        handler: impl Fn(&ServerNotice) + Send + Sync + 'static,
+    ) -> Self {
+        self.notice_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Deprecation and sunset notices seen so far
+    pub fn server_notices(&self) -> ServerNotices {
+        self.notices.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Record the notice in a response's headers, reporting it if it is new
+    pub(crate) fn observe_notice(&self, endpoint: Endpoint, headers: &HeaderMap) {
+        let Some(notice) = ServerNotice::from_headers(endpoint, headers) else {
+            return;
+        };
+        {
+            let mut notices = self.notices.lock().unwrap_or_else(|e| e.into_inner());
+            if notices.endpoints.get(&endpoint) == Some(&notice) {
+                return;
+            }
+            notices.endpoints.insert(endpoint, notice.clone());
+        }
+
+        match &self.notice_handler {
+            Some(handler) => handler(&notice),
+            None => tracing::warn!(
+                endpoint = ?notice.endpoint,
+                deprecated = notice.deprecated,
+                sunset = ?notice.sunset,
+                link = notice.link.as_deref(),
+                "API endpoint deprecation notice"
+            ),
+        }
+    }
+}
+
+fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// URL of a `Link` entry with a deprecation or sunset relation
+fn notice_link(entry: &str) -> Option<String> {
+    let (target, params) = entry.trim().split_once(';')?;
+    let is_notice = params.split(';').any(|param| {
+        matches!(
+            param.trim().split_once('='),
+            Some((name, rel)) if name.trim().eq_ignore_ascii_case("rel")
+                && rel.trim().trim_matches('"').split_whitespace().any(|rel| {
+                    rel.eq_ignore_ascii_case("deprecation") || rel.eq_ignore_ascii_case("sunset")
+                })
+        )
+    });
+    is_notice.then(|| target.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+}
@@ -0,0 +1,38 @@
+//! Sub-value lookup by dot-notation path or JSON pointer
+//!
+//! Paths starting with `/` are JSON pointers (RFC 6901); anything else is a
+//! dot-notation path like the ones used by `patch`, where numeric segments
+//! index into arrays (`readings.0.value`).
+
+use crate::{Client, Error};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Look up the value at `path`
+pub fn get_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    if path.starts_with('/') {
+        return value.pointer(path);
+    }
+
+    path.split('.').try_fold(value, |current, segment| match current {
+        Value::Object(map) => map.get(segment),
+        Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+impl Client {
+    /// Retrieve the value at `path` within the stored document
+    ///
+    /// Returns `Ok(None)` if the path does not exist. The full document is
+    /// fetched and the sub-value extracted client-side.
+    pub async fn retrieve_path<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>, Error> {
+        let resp = self.retrieve().await?;
+        get_path(&resp.data, path)
+            .map(|value| T::deserialize(value).map_err(Error::from))
+            .transpose()
+    }
+}
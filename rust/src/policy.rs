@@ -0,0 +1,580 @@
+//! Retries, rate limits, circuit breaking, caching, compression and failover as configuration
+//!
+//! A [`ClientPolicy`] collects the behavior operations teams tune, so it can
+//! live in a JSON or TOML file (the latter with the `toml` feature) instead
+//! of code:
+//!
+//! ```toml
+//! timeout = "10s"
+//! failover = ["https://kv-backup.example.com"]
+//!
+//! [retry]
+//! max_attempts = 4
+//! initial_backoff = "250ms"
+//!
+//! [rate_limit]
+//! requests_per_second = 5
+//! burst = 10
+//!
+//! [circuit_breaker]
+//! failure_threshold = 5
+//! open_for = "1m"
+//!
+//! [cache]
+//! max_age = "10m"
+//! ```
+//!
+//! Durations are numbers of seconds or strings such as `"250ms"`, `"30s"` or
+//! `"1h30m"`. Unknown keys are rejected so typos do not go unnoticed;
+//! `compression` needs the `compression` feature.
+//!
+//! Retries cover the errors [`Error::is_retryable`] accepts, waiting out
+//! `Retry-After` when the server sends one. Every request is retried,
+//! including writes, so a store that timed out after reaching the server
+//! may be recorded twice in history. On connection failures, timeouts and
+//! `5xx` responses the request moves on to the next failover base URL right
+//! away, and later requests stay on the URL that last answered until
+//! `reprobe_primary` (default one minute) has passed and one of them tries
+//! the primary again.
+//! `circuit_breaker` fails requests fast once outages persist, as described
+//! in [`circuit`](crate::circuit). `cache` enables
+//! [`Client::retrieve_available`] and friends with that
+//! [`AvailabilityPolicy`]. Policies layer with [`ClientPolicy::or`]:
+//!
+//! ```no_run
+//! use keyvalue_client::policy::ClientPolicy;
+//! use keyvalue_client::Client;
+//!
+//! # fn main() -> Result<(), keyvalue_client::BuildError> {
+//! let policy = ClientPolicy::load("policy.json")?.or(ClientPolicy::from_json(r#"{"timeout": 30}"#)?);
+//! let client = Client::builder().token("word-word-word-word-word").policy(policy).build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Client::retrieve_available`]: crate::Client::retrieve_available
+
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+use crate::{AvailabilityPolicy, BuildError, CircuitBreakerPolicy, Client, ClientBuilder, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Client behavior loaded from configuration; unset fields keep the defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientPolicy {
+    #[serde(with = "duration::option", skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<CircuitBreakerPolicy>,
+    /// Serve cached reads and queue writes during outages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<AvailabilityPolicy>,
+    #[cfg(feature = "compression")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Base URLs to try, in order, when the primary is unreachable
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failover: Vec<String>,
+    /// How long to stay on a failover base URL before trying the primary again
+    #[serde(with = "duration::option", skip_serializing_if = "Option::is_none")]
+    pub reprobe_primary: Option<Duration>,
+}
+
+/// How often and how patiently to retry failed requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Attempts per request, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles with each further retry
+    #[serde(with = "duration")]
+    pub initial_backoff: Duration,
+    /// Longest delay between attempts, including `Retry-After`
+    #[serde(with = "duration")]
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before attempt `attempt + 1`, with full jitter
+    pub(crate) fn backoff(&self, attempt: u32, error: &Error) -> Duration {
+        if let Error::RateLimited {
+            retry_after: Some(retry_after),
+            ..
+        } = error
+        {
+            return (*retry_after).min(self.max_backoff);
+        }
+        self.delay(attempt)
+    }
+
+    /// Delay before attempt `attempt + 1` when the server gave no `Retry-After`
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(self.max_backoff);
+        let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        ceiling.mul_f64(jitter)
+    }
+}
+
+/// Client-side token bucket shared by a client and its clones
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitPolicy {
+    /// Sustained request rate
+    pub requests_per_second: f64,
+    /// Requests that may be sent at once after a quiet period
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+impl ClientPolicy {
+    /// Policy from JSON text
+    pub fn from_json(text: &str) -> Result<Self, BuildError> {
+        serde_json::from_str(text).map_err(|e| BuildError::Config(format!("Invalid policy: {}", e)))
+    }
+
+    /// Policy from TOML text
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, BuildError> {
+        toml::from_str(text).map_err(|e| BuildError::Config(format!("Invalid policy: {}", e)))
+    }
+
+    /// Policy from a `.json` or `.toml` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BuildError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| BuildError::Config(format!("{}: {}", path.display(), e)))?;
+        let in_file = |e: BuildError| match e {
+            BuildError::Config(message) => BuildError::Config(format!("{}: {}", path.display(), message)),
+            e => e,
+        };
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&text).map_err(in_file),
+            #[cfg(not(feature = "toml"))]
+            Some("toml") => Err(BuildError::Config(format!(
+                "{}: TOML policies need the `toml` feature",
+                path.display()
+            ))),
+            _ => Self::from_json(&text).map_err(in_file),
+        }
+    }
+
+    /// Fill fields unset here from `fallback`
+    pub fn or(self, fallback: ClientPolicy) -> Self {
+        Self {
+            timeout: self.timeout.or(fallback.timeout),
+            retry: self.retry.or(fallback.retry),
+            rate_limit: self.rate_limit.or(fallback.rate_limit),
+            circuit_breaker: self.circuit_breaker.or(fallback.circuit_breaker),
+            cache: self.cache.or(fallback.cache),
+            #[cfg(feature = "compression")]
+            compression: self.compression.or(fallback.compression),
+            failover: if self.failover.is_empty() {
+                fallback.failover
+            } else {
+                self.failover
+            },
+            reprobe_primary: self.reprobe_primary.or(fallback.reprobe_primary),
+        }
+    }
+
+    /// Apply the fields that are set to `builder`
+    pub fn apply(self, builder: ClientBuilder) -> ClientBuilder {
+        builder.policy(self)
+    }
+
+    /// Turn the policy into the client's runtime state
+    pub(crate) fn into_client(self, mut client: Client) -> Client {
+        client.retry = self.retry.filter(|retry| retry.max_attempts > 1);
+        client.rate_limiter = self.rate_limit.map(|limit| Arc::new(RateLimiter::new(limit)));
+        client.failover = Failover::new(self.failover, self.reprobe_primary.unwrap_or(DEFAULT_REPROBE));
+        if let Some(circuit_breaker) = self.circuit_breaker {
+            client = client.with_circuit_breaker(circuit_breaker);
+        }
+        #[cfg(feature = "compression")]
+        if let Some(compression) = self.compression {
+            client = client.with_compression(compression);
+        }
+        match self.cache {
+            Some(cache) => client.with_availability_policy(cache),
+            None => client,
+        }
+    }
+}
+
+/// Token bucket state behind a [`RateLimitPolicy`]
+pub(crate) struct RateLimiter {
+    policy: RateLimitPolicy,
+    /// Tokens available and when they were counted; negative while callers wait
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new(policy: RateLimitPolicy) -> Self {
+        Self {
+            policy,
+            bucket: Mutex::new((f64::from(policy.burst.max(1)), Instant::now())),
+        }
+    }
+
+    /// Wait until a request may be sent
+    pub(crate) async fn acquire(&self) {
+        let rate = self.policy.requests_per_second;
+        if rate <= 0.0 || !rate.is_finite() {
+            return;
+        }
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let (tokens, counted) = &mut *bucket;
+            let now = Instant::now();
+            let burst = f64::from(self.policy.burst.max(1));
+            *tokens = (*tokens + now.duration_since(*counted).as_secs_f64() * rate).min(burst);
+            *counted = now;
+            *tokens -= 1.0;
+            (*tokens < 0.0).then(|| Duration::from_secs_f64(-*tokens / rate))
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Time on a failover base URL after which the primary is tried again by default
+pub const DEFAULT_REPROBE: Duration = Duration::from_secs(60);
+
+/// Failover base URLs and the one currently in use; 0 is the client's own
+pub(crate) struct Failover {
+    bases: Vec<String>,
+    active: AtomicUsize,
+    reprobe: Duration,
+    /// When the client moved off the primary or last tried it again
+    left_primary: Mutex<Instant>,
+}
+
+impl Failover {
+    fn new(bases: impl IntoIterator<Item = impl Into<String>>, reprobe: Duration) -> Option<Arc<Self>> {
+        let bases: Vec<String> = bases
+            .into_iter()
+            .map(|url| url.into().trim_end_matches('/').to_string())
+            .collect();
+        (!bases.is_empty()).then(|| {
+            Arc::new(Self {
+                bases,
+                active: AtomicUsize::new(0),
+                reprobe,
+                left_primary: Mutex::new(Instant::now()),
+            })
+        })
+    }
+}
+
+impl Client {
+    /// Fall back to `urls`, in order, when the base URL is unreachable
+    ///
+    /// Requests stay on the URL that last answered; after
+    /// [`DEFAULT_REPROBE`] on a fallback, one request tries the primary
+    /// again. Clones share the URL in use.
+    pub fn with_failover(mut self, urls: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.failover = Failover::new(urls, DEFAULT_REPROBE);
+        self
+    }
+
+    /// Base URL index to send the next request to
+    pub(crate) fn active_base(&self) -> usize {
+        let Some(failover) = &self.failover else {
+            return 0;
+        };
+        let active = failover.active.load(Ordering::Relaxed);
+        if active != 0 {
+            let mut left_primary = failover.left_primary.lock().unwrap_or_else(|e| e.into_inner());
+            if left_primary.elapsed() >= failover.reprobe {
+                // Only this request probes; the rest stay on the fallback until it answers
+                *left_primary = Instant::now();
+                return 0;
+            }
+        }
+        active
+    }
+
+    /// Point `request` at base URL `index`
+    pub(crate) fn route(&self, request: &mut reqwest::Request, index: usize) -> Result<(), Error> {
+        let Some(failover) = &self.failover else {
+            return Ok(());
+        };
+        let current = request.url().as_str();
+        let path = [&*self.base_url]
+            .into_iter()
+            .chain(failover.bases.iter().map(String::as_str))
+            .find_map(|base| current.strip_prefix(base))
+            .unwrap_or_default();
+        let base = match index {
+            0 => &*self.base_url,
+            i => &failover.bases[i - 1],
+        };
+        *request.url_mut() = format!("{}{}", base, path)
+            .parse()
+            .map_err(|e| Error::Validation(format!("Invalid failover URL {}: {}", base, e)))?;
+        Ok(())
+    }
+
+    /// Remember `index` as the base URL that answered
+    pub(crate) fn settle_base(&self, index: usize) {
+        if let Some(failover) = &self.failover {
+            if failover.active.swap(index, Ordering::Relaxed) == 0 && index != 0 {
+                *failover.left_primary.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+            }
+        }
+    }
+
+    /// Base URL to fail over to after `tried` bases failed, starting from `start`
+    pub(crate) fn next_base(&self, start: usize, tried: usize) -> Option<usize> {
+        let failover = self.failover.as_ref()?;
+        let count = failover.bases.len() + 1;
+        (tried < count).then_some((start + tried) % count)
+    }
+
+    /// Delay before retrying after `error` on attempt `attempt`, if another attempt is allowed
+    pub(crate) fn retry_delay(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        let retry = self.retry.as_ref()?;
+        (attempt < retry.max_attempts && error.is_retryable()).then(|| retry.backoff(attempt, error))
+    }
+
+    /// Delay before a loop pacing its own writes retries after `error`, if it should
+    ///
+    /// Only rate limits are retried, per `fallback`, and only on clients
+    /// without a retry policy, which has already retried the request.
+    pub(crate) fn rate_limit_delay(&self, fallback: &RetryPolicy, attempt: u32, error: &Error) -> Option<Duration> {
+        let limited = matches!(error, Error::RateLimited { .. });
+        (self.retry.is_none() && limited && attempt < fallback.max_attempts)
+            .then(|| fallback.backoff(attempt, error))
+    }
+}
+
+/// Durations as seconds or strings like `"250ms"` or `"1h30m"`
+pub(crate) mod duration {
+    use crate::Ttl;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        match duration.subsec_millis() {
+            0 if duration.as_secs() > 0 => serializer.serialize_str(&format!("{}s", duration.as_secs())),
+            _ => serializer.serialize_str(&format!("{}ms", duration.as_millis())),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Seconds(f64),
+            Text(String),
+        }
+        let invalid = |what: &dyn std::fmt::Display| serde::de::Error::custom(format!("Invalid duration {}", what));
+        match Raw::deserialize(deserializer)? {
+            Raw::Seconds(seconds) => Duration::try_from_secs_f64(seconds).map_err(|_| invalid(&seconds)),
+            Raw::Text(text) => match text.trim().strip_suffix("ms") {
+                Some(millis) => millis
+                    .trim()
+                    .parse()
+                    .map(Duration::from_millis)
+                    .map_err(|_| invalid(&format_args!("{:?}", text))),
+                None => Ttl::parse(&text)
+                    .map(|ttl| Duration::from_secs(i32::from(ttl) as u64))
+                    .map_err(serde::de::Error::custom),
+            },
+        }
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use std::time::Duration;
+
+        pub fn serialize<S: Serializer>(
+            duration: &Option<Duration>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match duration {
+                Some(duration) => super::serialize(duration, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Duration>, D::Error> {
+            #[derive(Deserialize)]
+            struct Wrapped(#[serde(with = "super")] Duration);
+            Ok(Option::<Wrapped>::deserialize(deserializer)?.map(|Wrapped(duration)| duration))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limited(retry_after: Option<Duration>) -> Error {
+        Error::RateLimited {
+            retry_after,
+            message: "Slow down".to_string(),
+        }
+    }
+
+    fn failover_client(reprobe: Duration) -> Client {
+        let mut client = Client::builder()
+            .base_url("https://primary.test")
+            .build()
+            .unwrap();
+        client.failover = Failover::new(["https://second.test/", "https://third.test"], reprobe);
+        client
+    }
+
+    #[test]
+    fn delays_double_up_to_the_cap_with_jitter() {
+        let retry = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+        };
+        for (attempt, ceiling) in [(1, 100), (2, 200), (3, 350), (40, 350)] {
+            for _ in 0..20 {
+                assert!(retry.delay(attempt) <= Duration::from_millis(ceiling), "attempt {}", attempt);
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_is_honored_up_to_the_cap() {
+        let retry = RetryPolicy::default();
+        let error = rate_limited(Some(Duration::from_secs(2)));
+        assert_eq!(retry.backoff(1, &error), Duration::from_secs(2));
+        let error = rate_limited(Some(Duration::from_secs(600)));
+        assert_eq!(retry.backoff(1, &error), retry.max_backoff);
+        assert!(retry.backoff(1, &rate_limited(None)) <= retry.initial_backoff);
+    }
+
+    #[test]
+    fn retries_stop_at_max_attempts_and_on_permanent_errors() {
+        let client = Client::builder()
+            .policy(ClientPolicy {
+                retry: Some(RetryPolicy::default()),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let error = rate_limited(None);
+        assert!(client.retry_delay(2, &error).is_some());
+        assert!(client.retry_delay(3, &error).is_none());
+        assert!(client.retry_delay(1, &Error::Validation("bad".to_string())).is_none());
+        // Clients with a retry policy already retried rate limits themselves
+        assert!(client.rate_limit_delay(&RetryPolicy::default(), 1, &error).is_none());
+        assert!(Client::new("word-word-word-word-word")
+            .rate_limit_delay(&RetryPolicy::default(), 1, &error)
+            .is_some());
+    }
+
+    #[test]
+    fn policies_parse_durations_and_reject_unknown_fields() {
+        let policy = ClientPolicy::from_json(
+            r#"{"timeout": 1.5, "retry": {"initial_backoff": "250ms", "max_backoff": "1m"}, "reprobe_primary": "2h"}"#,
+        )
+        .unwrap();
+        assert_eq!(policy.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(
+            policy.retry,
+            Some(RetryPolicy {
+                initial_backoff: Duration::from_millis(250),
+                max_backoff: Duration::from_secs(60),
+                ..Default::default()
+            })
+        );
+        assert_eq!(policy.reprobe_primary, Some(Duration::from_secs(7200)));
+        assert_eq!(ClientPolicy::from_json(&serde_json::to_string(&policy).unwrap()).unwrap(), policy);
+
+        for text in [r#"{"timeouts": 1}"#, r#"{"timeout": "soon"}"#, r#"{"timeout": -1}"#] {
+            assert!(matches!(ClientPolicy::from_json(text), Err(BuildError::Config(_))), "{}", text);
+        }
+    }
+
+    #[test]
+    fn or_fills_only_unset_fields() {
+        let own = ClientPolicy {
+            timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let fallback = ClientPolicy {
+            timeout: Some(Duration::from_secs(9)),
+            failover: vec!["https://backup.test".to_string()],
+            ..Default::default()
+        };
+        let merged = own.or(fallback);
+        assert_eq!(merged.timeout, Some(Duration::from_secs(1)));
+        assert_eq!(merged.failover, ["https://backup.test"]);
+    }
+
+    #[test]
+    fn next_base_tries_every_url_once_from_the_start() {
+        let client = failover_client(DEFAULT_REPROBE);
+        let order: Vec<_> = (0..4).map(|tried| client.next_base(2, tried)).collect();
+        assert_eq!(order, [Some(2), Some(0), Some(1), None]);
+        assert_eq!(Client::new("word-word-word-word-word").next_base(0, 1), None);
+    }
+
+    #[test]
+    fn route_moves_the_path_between_base_urls() {
+        let client = failover_client(DEFAULT_REPROBE);
+        let mut request = reqwest::Request::new(
+            reqwest::Method::GET,
+            "https://primary.test/api/retrieve?x=1".parse().unwrap(),
+        );
+        client.route(&mut request, 1).unwrap();
+        assert_eq!(request.url().as_str(), "https://second.test/api/retrieve?x=1");
+        client.route(&mut request, 2).unwrap();
+        assert_eq!(request.url().as_str(), "https://third.test/api/retrieve?x=1");
+        client.route(&mut request, 0).unwrap();
+        assert_eq!(request.url().as_str(), "https://primary.test/api/retrieve?x=1");
+    }
+
+    #[test]
+    fn clients_stay_on_the_fallback_until_one_request_reprobes() {
+        let client = failover_client(Duration::from_millis(20));
+        assert_eq!(client.active_base(), 0);
+        client.clone().settle_base(2);
+        assert_eq!(client.active_base(), 2);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(client.active_base(), 0);
+        assert_eq!(client.active_base(), 2);
+        client.settle_base(0);
+        assert_eq!(client.active_base(), 0);
+    }
+}
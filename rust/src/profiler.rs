@@ -0,0 +1,216 @@
+//! Sampling profiler for the code paths that issue requests
+//!
+//! Rate-limit pressure usually comes from one chatty loop, but [`TrafficStats`]
+//! only say which endpoint is busy, not who calls it. A [`Profiler`] attached
+//! with [`Client::with_profiler`] captures a backtrace for one request in
+//! every `n`, attributes it to the first frame outside this SDK and its
+//! runtime, and adds up requests and bytes per call site.
+//!
+//! Backtraces are slow to capture, so keep `n` high in production. Call
+//! sites show file and line only when debug info is available; otherwise
+//! they show the function name.
+//!
+//! ```no_run
+//! use keyvalue_client::profiler::Profiler;
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let profiler = Profiler::sampling(10);
+//! let client = Client::new("word-word-word-word-word").with_profiler(profiler.clone());
+//! for _ in 0..100 {
+//!     client.retrieve().await?;
+//! }
+//! print!("{}", profiler.report());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`TrafficStats`]: crate::TrafficStats
+
+use crate::{Client, Endpoint};
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Symbol prefixes of frames that belong to the SDK, its dependencies or the runtime
+const SKIPPED_FRAMES: &[&str] = &[
+    "keyvalue_client::",
+    "std::",
+    "core::",
+    "alloc::",
+    "tokio::",
+    "futures_util::",
+    "futures_core::",
+    "reqwest::",
+    "hyper::",
+    "tower::",
+    "rust_begin_unwind",
+    "__rust",
+];
+
+/// Shared request sampler; clones record into the same report
+#[derive(Clone)]
+pub struct Profiler {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    every: u64,
+    seen: AtomicU64,
+    sites: Mutex<HashMap<(String, Endpoint), SiteStats>>,
+}
+
+/// Sampled traffic from one call site to one endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiteStats {
+    /// First caller frame outside the SDK, e.g. `app::sync::push (src/sync.rs:42:9)`
+    pub site: String,
+    pub endpoint: Endpoint,
+    /// Requests sampled from this site
+    pub sampled: u64,
+    /// Sampled requests scaled by the sampling rate
+    pub estimated_requests: u64,
+    /// Request body bytes of the sampled requests
+    pub bytes_sent: u64,
+    /// Response body bytes of the sampled requests
+    pub bytes_received: u64,
+}
+
+/// Call sites ordered by estimated requests, busiest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileReport {
+    /// Requests seen by the profiler, sampled or not
+    pub requests: u64,
+    pub sites: Vec<SiteStats>,
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} requests, {} call sites sampled", self.requests, self.sites.len())?;
+        for site in &self.sites {
+            writeln!(
+                f,
+                "{:>8} req  {:>10} B out  {:>10} B in  {:?}  {}",
+                site.estimated_requests, site.bytes_sent, site.bytes_received, site.endpoint, site.site
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Call site of a sampled request, waiting for its response size
+pub(crate) struct Sample {
+    site: String,
+    endpoint: Endpoint,
+    bytes_sent: u64,
+}
+
+impl Profiler {
+    /// Sample one request in every `every`
+    pub fn sampling(every: u64) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                every: every.max(1),
+                seen: AtomicU64::new(0),
+                sites: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Sampled call sites so far
+    pub fn report(&self) -> ProfileReport {
+        let sites = self.inner.sites.lock().unwrap_or_else(|e| e.into_inner());
+        let mut sites: Vec<SiteStats> = sites.values().cloned().collect();
+        sites.sort_by(|a, b| {
+            b.estimated_requests
+                .cmp(&a.estimated_requests)
+                .then(b.bytes_sent.cmp(&a.bytes_sent))
+                .then_with(|| a.site.cmp(&b.site))
+        });
+        ProfileReport {
+            requests: self.inner.seen.load(Ordering::Relaxed),
+            sites,
+        }
+    }
+
+    /// Forget everything sampled so far
+    pub fn reset(&self) {
+        self.inner.seen.store(0, Ordering::Relaxed);
+        self.inner.sites.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Capture the call site if this request is picked for sampling
+    pub(crate) fn sample(&self, endpoint: Endpoint, bytes_sent: usize) -> Option<Sample> {
+        let seen = self.inner.seen.fetch_add(1, Ordering::Relaxed);
+        if !seen.is_multiple_of(self.inner.every) {
+            return None;
+        }
+        Some(Sample {
+            site: call_site(&Backtrace::force_capture().to_string()),
+            endpoint,
+            bytes_sent: bytes_sent as u64,
+        })
+    }
+
+    pub(crate) fn record(&self, sample: Sample, bytes_received: usize) {
+        let mut sites = self.inner.sites.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = sites
+            .entry((sample.site.clone(), sample.endpoint))
+            .or_insert_with(|| SiteStats {
+                site: sample.site,
+                endpoint: sample.endpoint,
+                sampled: 0,
+                estimated_requests: 0,
+                bytes_sent: 0,
+                bytes_received: 0,
+            });
+        stats.sampled += 1;
+        stats.estimated_requests += self.inner.every;
+        stats.bytes_sent += sample.bytes_sent;
+        stats.bytes_received += bytes_received as u64;
+    }
+}
+
+impl fmt::Debug for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profiler")
+            .field("every", &self.inner.every)
+            .field("requests", &self.inner.seen.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// Sample requests into `profiler`
+    pub fn with_profiler(mut self, profiler: Profiler) -> Self {
+        self.profiler = Some(profiler);
+        self
+    }
+}
+
+/// First frame of a rendered backtrace that is not the SDK or the runtime
+fn call_site(backtrace: &str) -> String {
+    let mut lines = backtrace.lines().map(str::trim).peekable();
+    while let Some(line) = lines.next() {
+        // Frames look like `12: app::main::{{closure}}`, then `at src/main.rs:8:5`
+        let Some((index, symbol)) = line.split_once(": ") else {
+            continue;
+        };
+        if index.parse::<usize>().is_err() {
+            continue;
+        }
+        let symbol = symbol.trim();
+        let location = lines.peek().and_then(|next| next.strip_prefix("at ")).map(str::to_string);
+        let bare = symbol.trim_start_matches('<');
+        if SKIPPED_FRAMES.iter().any(|prefix| bare.starts_with(prefix)) {
+            continue;
+        }
+        return match location {
+            Some(location) => format!("{} ({})", symbol, location),
+            None => symbol.to_string(),
+        };
+    }
+    "<unknown>".to_string()
+}
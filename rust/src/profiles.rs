@@ -0,0 +1,295 @@
+//! Named client profiles kept in the config file
+//!
+//! Teams that switch between staging and production tokens keep them as
+//! named [`Profile`]s in the file at [`config::default_path`], by default
+//! `~/.config/keyvalue/config`. It is the INI-style file read by
+//! [`Client::from_config_file`], and the `kv` CLI reads it for `--profile`
+//! and edits it with `kv profile`, so all of them see the same settings:
+//!
+//! ```text
+//! [profile staging]
+//! token = "other-other-other-other-other"
+//! base_url = "https://staging.key-value.co"
+//!
+//! [profile production]
+//! token = "word-word-word-word-word"
+//! timeout = 10s
+//! ```
+//!
+//! Creating a profile appends its section and deleting one removes it,
+//! leaving the rest of the file, comments included, as it was. Changes are
+//! written to the file right away, readable only by its owner on Unix since
+//! it holds tokens.
+//!
+//! ```no_run
+//! use keyvalue_client::config::Profile;
+//! use keyvalue_client::profiles::Profiles;
+//!
+//! # async fn run() -> Result<(), keyvalue_client::Error> {
+//! let mut profiles = Profiles::load()?;
+//! if profiles.get("staging").is_none() {
+//!     let staging = Profile::from_url("kv://other-other-other-other-other@staging.key-value.co")?;
+//!     profiles.create("staging", staging)?;
+//! }
+//!
+//! let client = profiles.client("production")?;
+//! client.retrieve().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::config::{self, Profile};
+use crate::{BuildError, Client};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Named profiles from one config file
+#[derive(Clone)]
+pub struct Profiles {
+    path: PathBuf,
+    text: String,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl fmt::Debug for Profiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profiles")
+            .field("path", &self.path)
+            .field("names", &self.profiles.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Profiles {
+    /// Profiles from the [default path](config::default_path), none if the file does not exist
+    pub fn load() -> Result<Self, BuildError> {
+        let path = config::default_path().ok_or_else(|| {
+            BuildError::Config("No config file: set KV_CONFIG or HOME".to_string())
+        })?;
+        Self::open(path)
+    }
+
+    /// Profiles from the file at `path`, none if it does not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BuildError> {
+        let path = path.as_ref().to_path_buf();
+        let in_file = |message: &dyn fmt::Display| BuildError::Config(format!("{}: {}", path.display(), message));
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(in_file(&e)),
+        };
+        let mut profiles = BTreeMap::new();
+        for (name, _) in config::sections(&text).filter(|(_, line)| config::is_entry(line.trim())) {
+            if profiles.contains_key(name) {
+                continue;
+            }
+            if let Some(profile) = Profile::parse(&text, name).map_err(|e| in_file(&e))? {
+                profiles.insert(name.to_string(), profile);
+            }
+        }
+        Ok(Self { path, text, profiles })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Profile names, in sorted order
+    pub fn list(&self) -> impl Iterator<Item = &str> + '_ {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Add profile `name` and save the file
+    ///
+    /// Fails if a profile of that name exists; [`delete`](Self::delete) it first to replace it.
+    pub fn create(&mut self, name: &str, profile: Profile) -> Result<(), BuildError> {
+        if name.trim().is_empty() || name.trim() != name || name.contains(['[', ']', '\n', '\r']) {
+            return Err(BuildError::Config(format!("Invalid profile name {:?}", name)));
+        }
+        if self.profiles.contains_key(name) {
+            return Err(BuildError::Config(format!("Profile {:?} already exists", name)));
+        }
+
+        let mut section = format!("[profile {}]\n", name);
+        for (key, value) in [("token", &profile.token), ("base_url", &profile.base_url)] {
+            if let Some(value) = value {
+                if value.contains(['"', '\n', '\r']) {
+                    return Err(BuildError::Config(format!("Profile {} has an invalid {}", name, key)));
+                }
+                section.push_str(&format!("{} = \"{}\"\n", key, value));
+            }
+        }
+        if let Some(timeout) = profile.timeout {
+            if timeout.is_zero() || timeout.subsec_nanos() != 0 {
+                return Err(BuildError::Config(format!(
+                    "Profile {} timeout must be a whole number of seconds",
+                    name
+                )));
+            }
+            section.push_str(&format!("timeout = {}s\n", timeout.as_secs()));
+        }
+
+        if !self.text.is_empty() {
+            if !self.text.ends_with('\n') {
+                self.text.push('\n');
+            }
+            self.text.push('\n');
+        }
+        self.text.push_str(&section);
+        self.profiles.insert(name.to_string(), profile);
+        self.save()
+    }
+
+    /// Remove profile `name` and save the file; returns whether it existed
+    ///
+    /// Every line of the profile's sections goes, including comments within them.
+    pub fn delete(&mut self, name: &str) -> Result<bool, BuildError> {
+        if self.profiles.remove(name).is_none() {
+            return Ok(false);
+        }
+        let kept: Vec<&str> = config::sections(&self.text)
+            .filter(|(section, _)| *section != name)
+            .map(|(_, line)| line)
+            .collect();
+        self.text = kept.join("\n").trim_start_matches('\n').to_string();
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        self.save().map(|()| true)
+    }
+
+    /// Client with the settings of profile `name`
+    pub fn client(&self, name: &str) -> Result<Client, BuildError> {
+        let profile = self.get(name).ok_or_else(|| {
+            BuildError::Config(format!("{}: no profile named {:?}", self.path.display(), name))
+        })?;
+        profile.clone().apply(Client::builder()).build()
+    }
+
+    /// Write the file atomically, readable only by its owner on Unix
+    fn save(&self) -> Result<(), BuildError> {
+        let in_file = |message: &dyn fmt::Display| {
+            BuildError::Config(format!("{}: {}", self.path.display(), message))
+        };
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| in_file(&e))?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let write = || -> std::io::Result<()> {
+            let mut tmp = options.open(&tmp_path)?;
+            tmp.write_all(self.text.as_bytes())?;
+            tmp.sync_all()?;
+            std::fs::rename(&tmp_path, &self.path)
+        };
+        write().map_err(|e| in_file(&e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Path in the temp directory, removed when dropped
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("kv-profiles-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn staging() -> Profile {
+        Profile {
+            token: Some("other-other-other-other-other".to_string()),
+            base_url: Some("https://staging.test".to_string()),
+            timeout: Some(Duration::from_secs(10)),
+        }
+    }
+
+    #[test]
+    fn missing_files_have_no_profiles() {
+        let path = TempPath::new("missing");
+        let profiles = Profiles::open(&path.0).unwrap();
+        assert_eq!(profiles.list().count(), 0);
+        assert!(profiles.client("staging").is_err());
+    }
+
+    #[test]
+    fn created_profiles_are_saved_and_read_back() {
+        let path = TempPath::new("create");
+        let mut profiles = Profiles::open(&path.0).unwrap();
+        profiles.create("staging", staging()).unwrap();
+
+        let reopened = Profiles::open(&path.0).unwrap();
+        assert_eq!(reopened.get("staging"), Some(&staging()));
+        assert_eq!(reopened.list().collect::<Vec<_>>(), ["staging"]);
+        assert_eq!(Profile::load(&path.0, "staging").unwrap(), staging());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path.0).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn delete_keeps_the_rest_of_the_file() {
+        let path = TempPath::new("delete");
+        let text = "# shared settings\ntoken = \"word-word-word-word-word\"\n\n[profile staging]\n# staging only\ntoken = \"x\"\n\n[profile production]\ntimeout = 5s\n";
+        std::fs::write(&path.0, text).unwrap();
+
+        let mut profiles = Profiles::open(&path.0).unwrap();
+        assert_eq!(profiles.list().collect::<Vec<_>>(), ["default", "production", "staging"]);
+        assert!(profiles.delete("staging").unwrap());
+        assert!(!profiles.delete("staging").unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path.0).unwrap(),
+            "# shared settings\ntoken = \"word-word-word-word-word\"\n\n[profile production]\ntimeout = 5s\n"
+        );
+    }
+
+    #[test]
+    fn invalid_names_values_and_duplicates_are_rejected() {
+        let path = TempPath::new("invalid");
+        let mut profiles = Profiles::open(&path.0).unwrap();
+        profiles.create("staging", staging()).unwrap();
+        let quoted = Profile {
+            token: Some("a\"b".to_string()),
+            ..Default::default()
+        };
+        let fractional = Profile {
+            timeout: Some(Duration::from_millis(1500)),
+            ..Default::default()
+        };
+        for (name, profile) in [
+            ("", Profile::default()),
+            (" padded", Profile::default()),
+            ("a]b", Profile::default()),
+            ("staging", Profile::default()),
+            ("quoted", quoted),
+            ("fractional", fractional),
+        ] {
+            assert!(matches!(profiles.create(name, profile), Err(BuildError::Config(_))), "{:?}", name);
+        }
+        assert_eq!(Profiles::open(&path.0).unwrap().list().collect::<Vec<_>>(), ["staging"]);
+    }
+}
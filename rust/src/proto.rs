@@ -0,0 +1,125 @@
+//! Protocol Buffers payloads
+//!
+//! [`Client::store_proto`] stores an encoded message as a compact envelope
+//! naming its type, with the bytes in standard base64:
+//!
+//! ```json
+//! {"@type": "type.googleapis.com/sensors.v1.Reading", "@proto": "CgNrMTIQ..."}
+//! ```
+//!
+//! [`Client::retrieve_proto`] checks the type before decoding. Messages plug
+//! in through [`ProtoMessage`]; for `prost` types that is a forward to
+//! `Name::full_name`, `Message::encode_to_vec` and `Message::decode`.
+//!
+//! ```
+//! use keyvalue_client::proto::ProtoMessage;
+//!
+//! /// Hand-encoded `message Counter { uint32 value = 1; }` for the example
+//! struct Counter(u8);
+//!
+//! impl ProtoMessage for Counter {
+//!     fn full_name() -> String {
+//!         "demo.Counter".to_string()
+//!     }
+//!     fn encode(&self) -> Vec<u8> {
+//!         vec![0x08, self.0]
+//!     }
+//!     fn decode(bytes: &[u8]) -> Result<Self, String> {
+//!         match bytes {
+//!             [0x08, value] if *value < 0x80 => Ok(Counter(*value)),
+//!             _ => Err("unsupported encoding".to_string()),
+//!         }
+//!     }
+//! }
+//!
+//! let envelope = keyvalue_client::proto::to_envelope(&Counter(7));
+//! assert_eq!(envelope["@proto"], "CAc=");
+//! let counter: Counter = keyvalue_client::proto::from_envelope(&envelope).unwrap();
+//! assert_eq!(counter.0, 7);
+//! ```
+
+use crate::{Client, Error, ResponseMeta, StoreResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+const TYPE_FIELD: &str = "@type";
+const PROTO_FIELD: &str = "@proto";
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// Message that can be stored in a protobuf envelope
+pub trait ProtoMessage: Sized {
+    /// Fully qualified message name, e.g. `sensors.v1.Reading`
+    fn full_name() -> String;
+
+    /// Wire-format encoding of the message
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode a message from its wire format
+    fn decode(bytes: &[u8]) -> Result<Self, String>;
+}
+
+/// Retrieved data decoded into a protobuf message
+#[derive(Debug)]
+pub struct ProtoRetrieveResponse<M> {
+    pub data: M,
+    pub version: i32,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub meta: ResponseMeta,
+}
+
+/// Envelope holding the encoded `message`
+pub fn to_envelope<M: ProtoMessage>(message: &M) -> Value {
+    serde_json::json!({
+        TYPE_FIELD: format!("{}{}", TYPE_URL_PREFIX, M::full_name()),
+        PROTO_FIELD: STANDARD.encode(message.encode()),
+    })
+}
+
+/// Decode the message in an envelope, checking it holds an `M`
+pub fn from_envelope<M: ProtoMessage>(data: &Value) -> Result<M, Error> {
+    let field = |name: &str| {
+        data.get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::Proto(format!("Stored data has no `{}` field", name)))
+    };
+    let type_url = field(TYPE_FIELD)?;
+    let found = type_url.rsplit('/').next().unwrap_or(type_url);
+    let expected = M::full_name();
+    if found != expected {
+        return Err(Error::Proto(format!("Expected {}, found {}", expected, found)));
+    }
+
+    let bytes = STANDARD
+        .decode(field(PROTO_FIELD)?)
+        .map_err(|e| Error::Proto(format!("Invalid base64: {}", e)))?;
+    M::decode(&bytes).map_err(|e| Error::Proto(format!("Failed to decode {}: {}", expected, e)))
+}
+
+impl Client {
+    /// Store a protobuf message in an envelope
+    pub async fn store_proto<M: ProtoMessage>(
+        &self,
+        message: &M,
+        ttl: Option<i32>,
+    ) -> Result<StoreResponse, Error> {
+        self.store(&to_envelope(message), ttl).await
+    }
+
+    /// Retrieve data and decode it as an `M`
+    pub async fn retrieve_proto<M: ProtoMessage>(
+        &self,
+    ) -> Result<ProtoRetrieveResponse<M>, Error> {
+        let resp = self.retrieve().await?;
+
+        Ok(ProtoRetrieveResponse {
+            data: from_envelope(&resp.data)?,
+            version: resp.version,
+            updated_at: resp.updated_at,
+            expires_at: resp.expires_at,
+            meta: resp.meta,
+        })
+    }
+}
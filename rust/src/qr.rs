@@ -0,0 +1,90 @@
+//! Tokens as QR codes and phonetic spellings, for provisioning devices
+//!
+//! When a person carries a token from one screen to a device, scanning is
+//! quicker and safer than typing. [`qr_svg`] and [`qr_png`] render a token
+//! as a QR code for a web page or a printed label, and [`qr_text`] draws one
+//! in block characters for a terminal. Where the token has to be read out
+//! instead, [`phonetic`] spells it in the NATO alphabet.
+//!
+//! ```
+//! use keyvalue_client::qr;
+//!
+//! # fn main() -> Result<(), keyvalue_client::Error> {
+//! let token = "otter-amber-lunar-pilot-tulip";
+//! let svg = qr::qr_svg(token)?;
+//! assert!(svg.contains("<svg"));
+//! assert!(qr::phonetic(token).starts_with("Oscar Tango Tango Echo Romeo Dash Alfa"));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Error;
+use image::{ImageFormat, Luma};
+use qrcode::render::{svg, unicode};
+use qrcode::{EcLevel, QrCode};
+use std::io::Cursor;
+
+/// Smallest rendered image side, in pixels, so codes scan from a phone at arm's length
+const MIN_IMAGE_SIZE: u32 = 256;
+
+/// NATO spelling of `a` to `z`
+const LETTERS: [&str; 26] = [
+    "Alfa", "Bravo", "Charlie", "Delta", "Echo", "Foxtrot", "Golf", "Hotel", "India", "Juliett", "Kilo", "Lima",
+    "Mike", "November", "Oscar", "Papa", "Quebec", "Romeo", "Sierra", "Tango", "Uniform", "Victor", "Whiskey",
+    "X-ray", "Yankee", "Zulu",
+];
+
+/// Spelling of `0` to `9` in radiotelephony
+const DIGITS: [&str; 10] = ["Zero", "One", "Two", "Three", "Four", "Five", "Six", "Seven", "Eight", "Niner"];
+
+/// QR code of `token` as an SVG document
+pub fn qr_svg(token: &str) -> Result<String, Error> {
+    Ok(encode(token)?
+        .render::<svg::Color<'_>>()
+        .min_dimensions(MIN_IMAGE_SIZE, MIN_IMAGE_SIZE)
+        .build())
+}
+
+/// QR code of `token` as a grayscale PNG image
+pub fn qr_png(token: &str) -> Result<Vec<u8>, Error> {
+    let image = encode(token)?
+        .render::<Luma<u8>>()
+        .min_dimensions(MIN_IMAGE_SIZE, MIN_IMAGE_SIZE)
+        .build();
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| Error::Validation(format!("Cannot render QR code as PNG: {}", e)))?;
+    Ok(png)
+}
+
+/// QR code of `token` drawn with Unicode half blocks, two modules per character
+///
+/// Dark modules are filled blocks; on a dark terminal background the code
+/// shows inverted, which most phone scanners also read.
+pub fn qr_text(token: &str) -> Result<String, Error> {
+    Ok(encode(token)?.render::<unicode::Dense1x2>().build())
+}
+
+/// `token` spelled in the NATO phonetic alphabet, e.g. `Oscar Tango ... Dash Alfa ...`
+///
+/// Letters are spelled regardless of case, digits as `Zero` to `Niner` and
+/// `-` as `Dash`; any other character is kept as is.
+pub fn phonetic(token: &str) -> String {
+    let spelled: Vec<String> = token
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' => LETTERS[usize::from(c.to_ascii_lowercase() as u8 - b'a')].to_string(),
+            '0'..='9' => DIGITS[usize::from(c as u8 - b'0')].to_string(),
+            '-' => "Dash".to_string(),
+            other => other.to_string(),
+        })
+        .collect();
+    spelled.join(" ")
+}
+
+/// Medium error correction survives smudged labels while keeping tokens at a small version
+fn encode(token: &str) -> Result<QrCode, Error> {
+    QrCode::with_error_correction_level(token, EcLevel::M)
+        .map_err(|e| Error::Validation(format!("Cannot encode token as QR code: {}", e)))
+}
@@ -0,0 +1,299 @@
+//! FIFO job queue inside a document
+//!
+//! A [`JobQueue`] keeps its jobs at a dot-notation path in the stored value
+//! as `{"next_id": 8, "jobs": [{"id": 7, "enqueued_at": "...", "attempts": 0,
+//! "value": ...}]}`, oldest first. Producers [`push`](JobQueue::push) jobs;
+//! consumers [`claim`](JobQueue::claim) the oldest visible job, which hides
+//! it from other consumers for a visibility timeout, and
+//! [`ack`](JobQueue::ack) it once done. A job whose consumer crashes becomes
+//! visible again when the timeout passes and is handed to the next claim,
+//! with its attempt count raised. Every change is a versioned patch retried
+//! on version conflicts, so concurrent consumers never claim the same job
+//! at once; conflicting writers back off and retry. A missing document is
+//! created empty first and only patched once read back unchanged, but the
+//! API has no create-if-absent, so two producers creating the same queue at
+//! once can still lose a job; store the document up front where that
+//! matters.
+//!
+//! Delivery is at least once: a consumer slower than its timeout can see its
+//! job claimed by another, and its late [`ack`](JobQueue::ack) then returns
+//! `false`. Jobs that keep failing stay in the queue; check
+//! [`Claimed::attempts`] to give up on them. Each operation rewrites the whole
+//! queue, which suits tens to hundreds of pending jobs, not a firehose.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let queue = client.queue::<String>("jobs");
+//! queue.push(&"resize photo-17.jpg".to_string()).await?;
+//!
+//! while let Some(job) = queue.claim(Duration::from_secs(60)).await? {
+//!     println!("working on {} (attempt {})", job.value, job.attempts);
+//!     queue.ack(&job).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::idempotency::generate_key;
+use crate::path::get_path;
+use crate::{Client, Error, PatchOperations, RetryPolicy};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Retries of a queue change after version conflicts, spread out so busy consumers take turns
+const CONFLICT_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 10,
+    initial_backoff: Duration::from_millis(20),
+    max_backoff: Duration::from_secs(1),
+};
+
+/// Job as stored in the queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredJob {
+    id: u64,
+    enqueued_at: DateTime<Utc>,
+    attempts: u32,
+    /// Receipt of the current claim, if the job was ever claimed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    receipt: Option<String>,
+    /// Until when the job is hidden from other claims
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    visible_at: Option<DateTime<Utc>>,
+    value: Value,
+}
+
+impl StoredJob {
+    fn is_visible(&self, now: DateTime<Utc>) -> bool {
+        self.visible_at.is_none_or(|at| at <= now)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Stored {
+    next_id: u64,
+    jobs: Vec<StoredJob>,
+}
+
+/// Job handed to a consumer by [`JobQueue::claim`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claimed<T> {
+    pub id: u64,
+    pub value: T,
+    /// Claims of this job so far, including this one
+    pub attempts: u32,
+    pub enqueued_at: DateTime<Utc>,
+    /// When the job becomes visible to other consumers unless acked first
+    pub visible_at: DateTime<Utc>,
+    receipt: String,
+}
+
+/// Work queue stored at a path in the document
+pub struct JobQueue<'a, T = Value> {
+    client: &'a Client,
+    path: String,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> JobQueue<'_, T> {
+    /// Path of the queue within the document
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Add a job at the back of the queue, returning its id
+    pub async fn push(&self, value: &T) -> Result<u64, Error> {
+        let value = serde_json::to_value(value)?;
+        self.update(|queue| {
+            let id = queue.next_id;
+            queue.next_id += 1;
+            queue.jobs.push(StoredJob {
+                id,
+                enqueued_at: Utc::now(),
+                attempts: 0,
+                receipt: None,
+                visible_at: None,
+                value: value.clone(),
+            });
+            Some(id)
+        })
+        .await
+        .map(|id| id.unwrap_or_default())
+    }
+
+    /// Claim the oldest visible job, hiding it from other consumers for `visibility`
+    ///
+    /// Returns `None` if every job is claimed or the queue is empty.
+    pub async fn claim(&self, visibility: Duration) -> Result<Option<Claimed<T>>, Error> {
+        let visibility = chrono::Duration::from_std(visibility)
+            .map_err(|_| Error::Validation("Visibility timeout out of range".to_string()))?;
+        let claimed = self
+            .update(|queue| {
+                let now = Utc::now();
+                let job = queue.jobs.iter_mut().find(|job| job.is_visible(now))?;
+                job.attempts += 1;
+                job.receipt = Some(generate_key());
+                job.visible_at = Some(now + visibility);
+                Some(job.clone())
+            })
+            .await?;
+        let Some(job) = claimed else {
+            return Ok(None);
+        };
+        Ok(Some(Claimed {
+            id: job.id,
+            value: serde_json::from_value(job.value)?,
+            attempts: job.attempts,
+            enqueued_at: job.enqueued_at,
+            visible_at: job.visible_at.unwrap_or_default(),
+            receipt: job.receipt.unwrap_or_default(),
+        }))
+    }
+
+    /// Remove a finished job; returns `false` if its claim expired and it was claimed again
+    pub async fn ack(&self, job: &Claimed<T>) -> Result<bool, Error> {
+        let acked = self
+            .update(|queue| {
+                let i = queue.jobs.iter().position(|stored| holds(stored, job))?;
+                queue.jobs.remove(i);
+                Some(())
+            })
+            .await?;
+        Ok(acked.is_some())
+    }
+
+    /// Give a claimed job back for the next claim right away; `false` if it was claimed again
+    pub async fn release(&self, job: &Claimed<T>) -> Result<bool, Error> {
+        let released = self
+            .update(|queue| {
+                let stored = queue.jobs.iter_mut().find(|stored| holds(stored, job))?;
+                stored.visible_at = None;
+                Some(())
+            })
+            .await?;
+        Ok(released.is_some())
+    }
+
+    /// Keep a claimed job hidden for `visibility` from now; `false` if its claim already expired
+    pub async fn extend(&self, job: &mut Claimed<T>, visibility: Duration) -> Result<bool, Error> {
+        let visibility = chrono::Duration::from_std(visibility)
+            .map_err(|_| Error::Validation("Visibility timeout out of range".to_string()))?;
+        let extended = self
+            .update(|queue| {
+                let now = Utc::now();
+                let stored = queue.jobs.iter_mut().find(|stored| holds(stored, job) && !stored.is_visible(now))?;
+                stored.visible_at = Some(now + visibility);
+                stored.visible_at
+            })
+            .await?;
+        if let Some(visible_at) = extended {
+            job.visible_at = visible_at;
+        }
+        Ok(extended.is_some())
+    }
+
+    /// Jobs in the queue, claimed or not
+    pub async fn len(&self) -> Result<usize, Error> {
+        Ok(self.load().await?.jobs.len())
+    }
+
+    pub async fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Jobs not currently claimed
+    pub async fn visible(&self) -> Result<usize, Error> {
+        let now = Utc::now();
+        Ok(self.load().await?.jobs.iter().filter(|job| job.is_visible(now)).count())
+    }
+
+    async fn load(&self) -> Result<Stored, Error> {
+        match self.client.retrieve_existing().await? {
+            Some(current) => self.read(&current.data),
+            None => Ok(Stored::default()),
+        }
+    }
+
+    /// Apply `change` to the queue and write it back, retrying on version conflicts
+    ///
+    /// Nothing is written when `change` returns `None`.
+    async fn update<R>(&self, mut change: impl FnMut(&mut Stored) -> Option<R>) -> Result<Option<R>, Error> {
+        let mut attempt = 0;
+        let mut created = None;
+        loop {
+            attempt += 1;
+            let Some(current) = self.client.retrieve_existing().await? else {
+                if attempt >= CONFLICT_RETRY.max_attempts {
+                    return Err(Error::Conflict {
+                        current_version: None,
+                        message: "Queue document deleted while it was being created".to_string(),
+                    });
+                }
+                // Create the document, then read it back and patch it like any other
+                created = Some(self.client.store(&Value::Object(Map::new()), None).await?.version);
+                continue;
+            };
+            if created.take().is_some_and(|version| version != current.version) {
+                // Another writer got in between creating the document and reading it back
+                if attempt >= CONFLICT_RETRY.max_attempts {
+                    return Err(Error::Conflict {
+                        current_version: Some(current.version),
+                        message: "Queue document changed while it was being created".to_string(),
+                    });
+                }
+                tokio::time::sleep(CONFLICT_RETRY.delay(attempt)).await;
+                continue;
+            }
+            let version = current.version;
+            let mut queue = self.read(&current.data)?;
+            let Some(result) = change(&mut queue) else {
+                return Ok(None);
+            };
+
+            let patch = PatchOperations {
+                set: Some(HashMap::from([(self.path.clone(), serde_json::to_value(&queue)?)])),
+                remove: None,
+            };
+            match self.client.patch(version, &patch, None).await {
+                Ok(_) => return Ok(Some(result)),
+                Err(Error::Conflict { .. }) if attempt < CONFLICT_RETRY.max_attempts => {
+                    tokio::time::sleep(CONFLICT_RETRY.delay(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read(&self, data: &Value) -> Result<Stored, Error> {
+        match get_path(data, &self.path) {
+            None | Some(Value::Null) => Ok(Stored::default()),
+            Some(queue) => Stored::deserialize(queue).map_err(|e| {
+                Error::Validation(format!("Value at {:?} is not a job queue: {}", self.path, e))
+            }),
+        }
+    }
+}
+
+/// Whether `stored` is still claimed by `job`'s claim
+fn holds<T>(stored: &StoredJob, job: &Claimed<T>) -> bool {
+    stored.id == job.id && stored.receipt.as_deref() == Some(job.receipt.as_str())
+}
+
+impl Client {
+    /// Job queue stored at `path` within the document
+    pub fn queue<T: Serialize + DeserializeOwned>(&self, path: impl Into<String>) -> JobQueue<'_, T> {
+        JobQueue {
+            client: self,
+            path: path.into(),
+            _type: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Converging stored state on a declared desired state
+//!
+//! A [`Reconciler`] repeatedly reads the desired value from a local source,
+//! such as a file checked into git, compares it with what is stored, and
+//! when they differ applies the smallest merge patch that makes them equal.
+//! Manual edits and stray writers are undone on the next pass, and
+//! [`ReconcileStats`] tracks how often that happened, GitOps style.
+//!
+//! Patches use JSON Merge Patch semantics (see [`merge_patch_from_diff`]), so
+//! a desired member set to `null` is removed rather than stored as `null`.
+//! Values other than objects are replaced whole.
+//!
+//! ```no_run
+//! use keyvalue_client::reconcile::Reconciler;
+//! use keyvalue_client::{Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let reconciler = Reconciler::new(client, || {
+//!     let text = std::fs::read_to_string("config/desired.json")?;
+//!     Ok(serde_json::from_str(&text)?)
+//! })
+//! .interval(Duration::from_secs(30))
+//! .on_drift(|drift| println!("Correcting {} field(s): {}", drift.fields, drift.patch))
+//! .spawn();
+//!
+//! tokio::signal::ctrl_c().await?;
+//! println!("{:?}", reconciler.stats());
+//! reconciler.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::merge_patch::merge_patch_from_diff;
+use crate::{Client, Error, PatchOperations, RetryPolicy};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+type DesiredSource = Box<dyn FnMut() -> Result<Value, Error> + Send>;
+type DriftHook = Box<dyn FnMut(&Drift) + Send>;
+type ErrorHook = Box<dyn FnMut(&Error) + Send>;
+
+/// Retries of a pass after another writer changed the value in between
+const CONFLICT_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    initial_backoff: Duration::from_millis(50),
+    max_backoff: Duration::from_secs(2),
+};
+
+/// Difference found between the stored and the desired value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drift {
+    /// Stored version the patch applies to; `None` if nothing was stored
+    pub version: Option<i32>,
+    /// Merge patch turning the stored value into the desired one
+    pub patch: Value,
+    /// Leaf members the patch sets or removes
+    pub fields: usize,
+}
+
+/// Result of one reconciliation pass
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReconcileOutcome {
+    /// Stored value already matched
+    InSync { version: i32 },
+    /// Drift was found and corrected
+    Corrected { version: i32, drift: Drift },
+}
+
+/// Counters for a [`Reconciler`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReconcileStats {
+    /// Passes attempted
+    pub passes: u64,
+    /// Passes that found the stored value in sync
+    pub in_sync: u64,
+    /// Passes that found and corrected drift
+    pub corrected: u64,
+    /// Passes that failed
+    pub errors: u64,
+    pub last_drift_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Loop keeping a token's value equal to a desired state
+pub struct Reconciler {
+    client: Client,
+    desired: DesiredSource,
+    interval: Duration,
+    ttl: Option<i32>,
+    on_drift: Option<DriftHook>,
+    on_error: Option<ErrorHook>,
+    stats: Arc<Mutex<ReconcileStats>>,
+}
+
+impl Reconciler {
+    /// Reconcile `client`'s value with what `desired` returns on each pass
+    pub fn new(
+        client: Client,
+        desired: impl FnMut() -> Result<Value, Error> + Send + 'static,
+    ) -> Self {
+        Self {
+            client,
+            desired: Box::new(desired),
+            interval: Duration::from_secs(60),
+            ttl: None,
+            on_drift: None,
+            on_error: None,
+            stats: Arc::default(),
+        }
+    }
+
+    /// Time between passes when run with [`Reconciler::spawn`] (default 60s)
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// TTL applied with every correction
+    pub fn ttl(mut self, ttl: Option<i32>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Call `hook` with each drift before it is corrected
+    pub fn on_drift(mut self, hook: impl FnMut(&Drift) + Send + 'static) -> Self {
+        self.on_drift = Some(Box::new(hook));
+        self
+    }
+
+    /// Call `hook` when a pass fails, instead of logging a warning
+    pub fn on_error(mut self, hook: impl FnMut(&Error) + Send + 'static) -> Self {
+        self.on_error = Some(Box::new(hook));
+        self
+    }
+
+    pub fn stats(&self) -> ReconcileStats {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Run one pass: read the desired value, compare, and patch if needed
+    pub async fn reconcile_once(&mut self) -> Result<ReconcileOutcome, Error> {
+        let result = self.pass().await;
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats.passes += 1;
+        match &result {
+            Ok(ReconcileOutcome::InSync { .. }) => stats.in_sync += 1,
+            Ok(ReconcileOutcome::Corrected { .. }) => {
+                stats.corrected += 1;
+                stats.last_drift_at = Some(Utc::now());
+            }
+            Err(e) => {
+                stats.errors += 1;
+                stats.last_error = Some(e.to_string());
+            }
+        }
+        result
+    }
+
+    /// Run passes every interval on a background task
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn spawn(mut self) -> ReconcilerHandle {
+        let (stop, mut stopped) = watch::channel(false);
+        let stats = self.stats.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = stopped.changed() => return,
+                    _ = ticker.tick() => {}
+                }
+                if let Err(e) = self.reconcile_once().await {
+                    match &mut self.on_error {
+                        Some(hook) => hook(&e),
+                        None => tracing::warn!(error = %e, "reconciliation failed"),
+                    }
+                }
+            }
+        });
+        ReconcilerHandle { stop, task, stats }
+    }
+
+    async fn pass(&mut self) -> Result<ReconcileOutcome, Error> {
+        // A null member means "absent", as in the patches sent
+        let desired = without_nulls((self.desired)()?);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let current = self.client.retrieve_existing().await?;
+            let mergeable = desired.is_object()
+                && current.as_ref().is_some_and(|current| current.data.is_object());
+            let mut operations = None;
+            let drift = match current {
+                Some(current) if current.data == desired => {
+                    return Ok(ReconcileOutcome::InSync {
+                        version: current.version,
+                    });
+                }
+                Some(current) => {
+                    let patch = merge_patch_from_diff(&current.data, &desired);
+                    if mergeable {
+                        operations = Some(PatchOperations::from_merge_patch_onto(&patch, &current.data)?);
+                    }
+                    Drift {
+                        version: Some(current.version),
+                        fields: count_fields(&patch),
+                        patch,
+                    }
+                }
+                None => Drift {
+                    version: None,
+                    fields: count_fields(&desired),
+                    patch: desired.clone(),
+                },
+            };
+            if let Some(hook) = &mut self.on_drift {
+                hook(&drift);
+            }
+
+            let written = match (drift.version, operations) {
+                (Some(version), Some(operations)) => {
+                    self.client.patch(version, &operations, self.ttl).await.map(|resp| resp.version)
+                }
+                (Some(version), _) => self
+                    .client
+                    .store_if_version(&desired, version, self.ttl)
+                    .await
+                    .map(|resp| resp.version),
+                (None, _) => self.client.store(&desired, self.ttl).await.map(|resp| resp.version),
+            };
+            match written {
+                Ok(version) => return Ok(ReconcileOutcome::Corrected { version, drift }),
+                // Someone wrote in between; diff against their write
+                Err(Error::Conflict { .. }) if attempt < CONFLICT_RETRY.max_attempts => {
+                    tokio::time::sleep(CONFLICT_RETRY.delay(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Running [`Reconciler`] task
+///
+/// Dropping the handle stops the task like [`ReconcilerHandle::shutdown`],
+/// without waiting for it.
+pub struct ReconcilerHandle {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+    stats: Arc<Mutex<ReconcileStats>>,
+}
+
+impl ReconcilerHandle {
+    pub fn stats(&self) -> ReconcileStats {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Stop after the pass in progress, if any
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        let _ = self.task.await;
+    }
+}
+
+/// Leaf members set or removed by a merge patch
+fn count_fields(patch: &Value) -> usize {
+    match patch {
+        Value::Object(members) if !members.is_empty() => members.values().map(count_fields).sum(),
+        _ => 1,
+    }
+}
+
+/// `value` with null object members dropped, at every depth
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(members) => Value::Object(
+            members
+                .into_iter()
+                .filter(|(_, member)| !member.is_null())
+                .map(|(name, member)| (name, without_nulls(member)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
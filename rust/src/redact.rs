@@ -0,0 +1,313 @@
+//! Redaction of payload content in logs and errors
+//!
+//! The client emits `tracing` debug events for request and response bodies and
+//! includes server error messages in API errors like [`Error::Api`](crate::Error::Api). Both pass
+//! through the client's [`Redactor`] first, so secrets stored in values don't
+//! leak when debug logging is enabled. The redactor can also be applied to
+//! fixtures or any other JSON before it leaves the process.
+//!
+//! Tokens are masked regardless of the redactor: `token` fields in logged
+//! bodies, the client's own token anywhere in logs and error messages, and
+//! the `Debug` output of the client and token-carrying types all show only
+//! the first word, as in [`mask_token`].
+//!
+//! ```
+//! use keyvalue_client::Redactor;
+//!
+//! let redactor = Redactor::new()
+//!     .path("credentials.*")
+//!     .pattern(r"sk_live_[A-Za-z0-9]+")
+//!     .unwrap();
+//!
+//! let value = serde_json::json!({"credentials": {"password": "hunter2"}, "note": "key sk_live_abc"});
+//! assert_eq!(
+//!     redactor.redact_value(&value),
+//!     serde_json::json!({"credentials": {"password": "[REDACTED]"}, "note": "key [REDACTED]"})
+//! );
+//! ```
+
+use crate::Client;
+use regex::Regex;
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Replacement for redacted content
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Field name holding tokens in request and response bodies
+const TOKEN_FIELD: &str = "token";
+
+/// Token with everything after the first word masked
+///
+/// ```
+/// assert_eq!(keyvalue_client::redact::mask_token("apple-brave-cider-delta-eagle"), "apple-****");
+/// ```
+pub fn mask_token(token: &str) -> String {
+    match token.split_once('-') {
+        Some((first, _)) => format!("{}-****", first),
+        None => "****".to_string(),
+    }
+}
+
+/// First `max_len` characters of `text`, followed by `...` if it was longer
+///
+/// ```
+/// assert_eq!(keyvalue_client::redact::truncate("temperature", 4), "temp...");
+/// ```
+pub fn truncate(text: &str, max_len: usize) -> String {
+    match text.char_indices().nth(max_len) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Rule selecting content to redact
+#[derive(Debug, Clone)]
+pub enum RedactionRule {
+    /// Dot-notation path (`user.email`), where `*` matches any key or array index
+    Path(Vec<String>),
+    /// Regex applied to every string value and to free text such as error messages
+    Pattern(Regex),
+}
+
+/// Ordered set of redaction rules
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    rules: Vec<RedactionRule>,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redact the value at a dot-notation path (a leading `$.` is accepted)
+    pub fn path(mut self, path: &str) -> Self {
+        let path = path.strip_prefix("$.").unwrap_or(path);
+        self.rules.push(RedactionRule::Path(
+            path.split('.').map(str::to_string).collect(),
+        ));
+        self
+    }
+
+    /// Redact every match of a regex
+    pub fn pattern(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.rules.push(RedactionRule::Pattern(Regex::new(pattern)?));
+        Ok(self)
+    }
+
+    /// Add a prebuilt rule
+    pub fn rule(mut self, rule: RedactionRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Whether no rules are registered
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Return a copy of `value` with all rules applied
+    pub fn redact_value(&self, value: &Value) -> Value {
+        let mut value = value.clone();
+        for rule in &self.rules {
+            match rule {
+                RedactionRule::Path(path) => redact_path(&mut value, path),
+                RedactionRule::Pattern(regex) => redact_strings(&mut value, regex),
+            }
+        }
+        value
+    }
+
+    /// Apply pattern rules to free text
+    ///
+    /// Path rules also apply when the text is a JSON document.
+    pub fn redact_str(&self, text: &str) -> String {
+        if self.is_empty() {
+            return text.to_string();
+        }
+        if let Ok(value @ (Value::Object(_) | Value::Array(_))) = serde_json::from_str(text) {
+            return self.redact_value(&value).to_string();
+        }
+
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            if let RedactionRule::Pattern(regex) = rule {
+                text = regex.replace_all(&text, REDACTED).into_owned();
+            }
+        }
+        text
+    }
+}
+
+fn redact_path(value: &mut Value, path: &[String]) {
+    let Some((head, rest)) = path.split_first() else {
+        *value = Value::String(REDACTED.to_string());
+        return;
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if head == "*" || head == key {
+                    redact_path(child, rest);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter_mut().enumerate() {
+                if head == "*" || head.parse() == Ok(index) {
+                    redact_path(child, rest);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redact_strings(value: &mut Value, regex: &Regex) {
+    match value {
+        Value::String(s) if regex.is_match(s) => {
+            *s = regex.replace_all(s, REDACTED).into_owned();
+        }
+        Value::Object(map) => map.values_mut().for_each(|child| redact_strings(child, regex)),
+        Value::Array(items) => items.iter_mut().for_each(|child| redact_strings(child, regex)),
+        _ => {}
+    }
+}
+
+fn mask_token_fields(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                match child {
+                    Value::String(token) if key == TOKEN_FIELD => *token = mask_token(token),
+                    _ => mask_token_fields(child),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(mask_token_fields),
+        _ => {}
+    }
+}
+
+impl Client {
+    /// Redact payload content in logs and API error messages
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Arc::new(redactor);
+        self
+    }
+
+    pub(crate) fn log_request_body(&self, endpoint: &str, body: &Value) {
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            let mut body = self.redactor.redact_value(body);
+            mask_token_fields(&mut body);
+            tracing::debug!(endpoint, body = %self.mask_own_token(&body.to_string()), "sending request");
+        }
+    }
+
+    pub(crate) fn log_response_body(&self, status: StatusCode, body: &str) {
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            let mut body = self.redactor.redact_str(body);
+            if let Ok(mut value @ (Value::Object(_) | Value::Array(_))) = serde_json::from_str(&body) {
+                mask_token_fields(&mut value);
+                body = value.to_string();
+            }
+            tracing::debug!(%status, body = %self.mask_own_token(&body), "received response");
+        }
+    }
+
+    /// Replace the client's own token in free text with its masked form
+    pub(crate) fn mask_own_token(&self, text: &str) -> String {
+        match self.token.as_deref() {
+            Some(token) if !token.is_empty() && text.contains(token) => {
+                text.replace(token, &mask_token(token))
+            }
+            _ => text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn paths_match_keys_wildcards_and_indices() {
+        let redactor = Redactor::new().path("$.users.*.email").path("keys.1");
+        let value = json!({
+            "users": [{"email": "a@test", "name": "A"}, {"name": "B"}],
+            "keys": ["public", "secret"],
+            "email": "top@test",
+        });
+        assert_eq!(
+            redactor.redact_value(&value),
+            json!({
+                "users": [{"email": REDACTED, "name": "A"}, {"name": "B"}],
+                "keys": ["public", REDACTED],
+                "email": "top@test",
+            })
+        );
+        // Whole subtrees are replaced, and paths missing from the value are ignored
+        assert_eq!(
+            Redactor::new().path("users").path("a.b.c").redact_value(&value)["users"],
+            json!(REDACTED)
+        );
+    }
+
+    #[test]
+    fn patterns_apply_to_nested_strings_only() {
+        let redactor = Redactor::new().pattern(r"\d{4}-\d{4}").unwrap();
+        let value = json!({"card": "1234-5678 exp", "list": ["x 0000-1111"], "n": 12345678});
+        assert_eq!(
+            redactor.redact_value(&value),
+            json!({"card": "[REDACTED] exp", "list": ["x [REDACTED]"], "n": 12345678})
+        );
+        assert!(Redactor::new().pattern("(").is_err());
+    }
+
+    #[test]
+    fn free_text_is_redacted_as_json_when_it_parses() {
+        let redactor = Redactor::new().path("password").pattern("hunter2").unwrap();
+        assert_eq!(redactor.redact_str("login with hunter2 failed"), "login with [REDACTED] failed");
+        assert_eq!(
+            redactor.redact_str(r#"{"password": "x", "note": "hunter2"}"#),
+            json!({"password": REDACTED, "note": REDACTED}).to_string()
+        );
+        assert_eq!(Redactor::new().redact_str("hunter2"), "hunter2");
+    }
+
+    #[test]
+    fn token_fields_are_masked_at_any_depth() {
+        let mut value = json!({
+            "token": "apple-brave-cider-delta-eagle",
+            "operations": [{"token": "fluff-grape-honey-ivory-joker"}],
+            "count": {"token": 3},
+        });
+        mask_token_fields(&mut value);
+        assert_eq!(
+            value,
+            json!({"token": "apple-****", "operations": [{"token": "fluff-****"}], "count": {"token": 3}})
+        );
+        assert_eq!(mask_token("nodashes"), "****");
+    }
+
+    #[test]
+    fn own_token_is_masked_in_messages() {
+        let client = Client::new("apple-brave-cider-delta-eagle");
+        assert_eq!(
+            client.mask_own_token("bad token apple-brave-cider-delta-eagle"),
+            "bad token apple-****"
+        );
+        assert_eq!(client.mask_own_token("nothing here"), "nothing here");
+    }
+
+    #[test]
+    fn truncate_counts_characters() {
+        assert_eq!(truncate("héllo wörld", 5), "héllo...");
+        assert_eq!(truncate("short", 5), "short");
+        assert_eq!(truncate("", 0), "");
+    }
+}
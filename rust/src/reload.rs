@@ -0,0 +1,186 @@
+//! Typed application config with hot reload
+//!
+//! A service keeps its configuration as one document and holds a
+//! [`ConfigWatcher`]: it deserializes the document into the service's config
+//! type, checks it with an optional validator, and polls for new versions
+//! with [`Client::watch`]. Each valid version is published on a
+//! [`tokio::sync::watch`] channel as an `Arc<T>`, so request handlers read
+//! the current config without locking and background tasks can wait for
+//! changes. Versions that fail to deserialize or validate are logged with
+//! `tracing` and skipped, keeping the last good config. Documents stored
+//! with [`Client::store_as`] are unwrapped and their schema fingerprint
+//! checked.
+//!
+//! ```no_run
+//! use keyvalue_client::reload::ConfigWatcher;
+//! use keyvalue_client::{Client, Error};
+//! use serde::Deserialize;
+//! use std::time::Duration;
+//!
+//! #[derive(Debug, Deserialize)]
+//! struct AppConfig {
+//!     max_connections: u32,
+//!     banner: String,
+//! }
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let config = ConfigWatcher::<AppConfig>::builder(client)
+//!     .interval(Duration::from_secs(10))
+//!     .validate(|config| match config.max_connections {
+//!         0 => Err("max_connections must be positive".to_string()),
+//!         _ => Ok(()),
+//!     })
+//!     .start()
+//!     .await?;
+//!
+//! println!("banner: {}", config.current().banner);
+//! let mut changes = config.subscribe();
+//! while changes.changed().await.is_ok() {
+//!     println!("reloaded: {:?}", changes.borrow_and_update());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::typed::unwrap_envelope;
+use crate::{Client, Error, RetrieveResponse};
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Polling interval when none is set
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+type Validator<T> = Arc<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+
+/// Settings for a [`ConfigWatcher`], created by [`ConfigWatcher::builder`]
+pub struct ConfigWatcherBuilder<T> {
+    client: Client,
+    interval: Duration,
+    validator: Option<Validator<T>>,
+    _config: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for ConfigWatcherBuilder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigWatcherBuilder")
+            .field("interval", &self.interval)
+            .field("validator", &self.validator.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> ConfigWatcherBuilder<T> {
+    /// Poll for new versions every `interval`
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Reject configs for which `validate` returns an error message
+    pub fn validate(mut self, validate: impl Fn(&T) -> Result<(), String> + Send + Sync + 'static) -> Self {
+        self.validator = Some(Arc::new(validate));
+        self
+    }
+
+    /// Load the current config and start watching it on a background task
+    ///
+    /// Fails if the document is missing, does not deserialize into `T` or is
+    /// rejected by the validator. Must be called within a Tokio runtime.
+    pub async fn start(self) -> Result<ConfigWatcher<T>, Error> {
+        let resp = self.client.retrieve().await?;
+        let version = resp.version;
+        let config = parse(resp, self.validator.as_ref())?;
+        let (sender, receiver) = watch::channel(Arc::new(config));
+        tokio::spawn(reload(self.client, self.interval, version, self.validator, sender));
+        Ok(ConfigWatcher { receiver })
+    }
+}
+
+/// Current config of type `T`, reloaded when the stored document changes
+///
+/// Polling stops once the watcher and every receiver from
+/// [`subscribe`](Self::subscribe) are dropped.
+pub struct ConfigWatcher<T> {
+    receiver: watch::Receiver<Arc<T>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for ConfigWatcher<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigWatcher")
+            .field("current", &*self.receiver.borrow())
+            .finish()
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> ConfigWatcher<T> {
+    /// Watch the document of `client`'s token as config of type `T`
+    pub fn builder(client: Client) -> ConfigWatcherBuilder<T> {
+        ConfigWatcherBuilder {
+            client,
+            interval: DEFAULT_RELOAD_INTERVAL,
+            validator: None,
+            _config: PhantomData,
+        }
+    }
+}
+
+impl<T> ConfigWatcher<T> {
+    /// Latest valid config
+    pub fn current(&self) -> Arc<T> {
+        self.receiver.borrow().clone()
+    }
+
+    /// Receiver notified each time a new valid config is loaded
+    pub fn subscribe(&self) -> watch::Receiver<Arc<T>> {
+        self.receiver.clone()
+    }
+}
+
+async fn reload<T: DeserializeOwned>(
+    client: Client,
+    interval: Duration,
+    mut version: i32,
+    validator: Option<Validator<T>>,
+    sender: watch::Sender<Arc<T>>,
+) {
+    let mut changes = pin!(client.watch(interval));
+    loop {
+        let change = tokio::select! {
+            _ = sender.closed() => return,
+            change = changes.next() => change,
+        };
+        let resp = match change {
+            Some(Ok(resp)) if resp.version == version => continue,
+            Some(Ok(resp)) => resp,
+            Some(Err(e)) => {
+                tracing::warn!(error = %e, "failed to poll config");
+                continue;
+            }
+            None => return,
+        };
+        let new_version = resp.version;
+        version = new_version;
+        match parse(resp, validator.as_ref()) {
+            Ok(config) => {
+                tracing::debug!(version = new_version, "reloaded config");
+                sender.send_replace(Arc::new(config));
+            }
+            Err(e) => tracing::warn!(error = %e, version = new_version, "ignoring invalid config"),
+        }
+    }
+}
+
+fn parse<T: DeserializeOwned>(resp: RetrieveResponse, validator: Option<&Validator<T>>) -> Result<T, Error> {
+    let (config, _) = unwrap_envelope::<T>(resp.data)?;
+    if let Some(validate) = validator {
+        validate(&config).map_err(|message| Error::Validation(format!("Invalid config: {}", message)))?;
+    }
+    Ok(config)
+}
@@ -0,0 +1,134 @@
+//! Mirroring writes to replica tokens
+//!
+//! A [`ReplicatedClient`] stores and deletes under a primary token and every
+//! replica token at once, and reads from the first of them that answers, so
+//! a token lost to an outage, an expired plan or a self-hosted server going
+//! down does not take the data with it. Replicas can live on other servers:
+//! each one is a full [`Client`] with its own base URL and settings.
+//!
+//! With [`Replication::BestEffort`] a write succeeds once the primary has
+//! it; replica failures are logged and returned in
+//! [`ReplicatedWrite::replicas`]. With [`Replication::Strict`] it fails
+//! unless every copy was written, though the copies that succeeded stay
+//! written. Versions are counted per token and differ between copies.
+//!
+//! ```no_run
+//! use keyvalue_client::replicated::{ReplicatedClient, Replication};
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let mirror = Client::new("word-word-word-word-two").with_base_url("https://kv.internal.example");
+//! let client = ReplicatedClient::new(Client::new("word-word-word-word-one"), vec![mirror])
+//!     .with_replication(Replication::Strict);
+//!
+//! client.store(&serde_json::json!({"firmware": "2.4.1"}), None).await?;
+//! let current = client.retrieve().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::degraded::is_outage;
+use crate::multi::{run_all, FanOut};
+use crate::{Client, DeleteResponse, Error, RetrieveResponse, StoreResponse};
+use serde_json::Value;
+use std::future::Future;
+
+/// How many copies a write needs to succeed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Replication {
+    /// The primary; replica failures are only reported
+    #[default]
+    BestEffort,
+    /// The primary and every replica
+    Strict,
+}
+
+/// Primary token mirrored to replica tokens
+#[derive(Debug, Clone)]
+pub struct ReplicatedClient {
+    primary: Client,
+    /// In read order
+    replicas: Vec<Client>,
+    replication: Replication,
+}
+
+/// Result of a write to the primary and its replicas
+#[derive(Debug)]
+pub struct ReplicatedWrite<T> {
+    pub primary: T,
+    /// Per-replica results, in the order the replicas were given
+    pub replicas: FanOut<T>,
+}
+
+impl ReplicatedClient {
+    pub fn new(primary: Client, replicas: Vec<Client>) -> Self {
+        Self {
+            primary,
+            replicas,
+            replication: Replication::default(),
+        }
+    }
+
+    pub fn with_replication(mut self, replication: Replication) -> Self {
+        self.replication = replication;
+        self
+    }
+
+    pub fn primary(&self) -> &Client {
+        &self.primary
+    }
+
+    pub fn replicas(&self) -> &[Client] {
+        &self.replicas
+    }
+
+    /// Store `data` under the primary and every replica
+    pub async fn store(&self, data: &Value, ttl: Option<i32>) -> Result<ReplicatedWrite<StoreResponse>, Error> {
+        self.write(|client| client.store(data, ttl)).await
+    }
+
+    /// Delete the value under the primary and every replica
+    pub async fn delete(&self) -> Result<ReplicatedWrite<DeleteResponse>, Error> {
+        self.write(Client::delete).await
+    }
+
+    /// Value from the primary, or from the first replica that answers
+    ///
+    /// Only outages (connection failures, timeouts and `5xx` responses) move
+    /// on to the next copy; any other error, including [`Error::NotFound`],
+    /// is returned as is.
+    pub async fn retrieve(&self) -> Result<RetrieveResponse, Error> {
+        let mut result = self.primary.retrieve().await;
+        for replica in &self.replicas {
+            match &result {
+                Err(e) if is_outage(e) => tracing::warn!(error = %e, "copy unavailable, reading the next one"),
+                _ => break,
+            }
+            result = replica.retrieve().await;
+        }
+        result
+    }
+
+    /// Run `op` on every copy at once and apply the replication mode
+    async fn write<'a, T, F, Fut>(&'a self, op: F) -> Result<ReplicatedWrite<T>, Error>
+    where
+        F: Fn(&'a Client) -> Fut,
+        Fut: Future<Output = Result<T, Error>> + 'a,
+    {
+        let (primary, replicas) = futures_util::join!(
+            op(&self.primary),
+            run_all(&self.replicas, self.replicas.len(), &op)
+        );
+        for (_, e) in replicas.failures() {
+            tracing::warn!(error = %e, "replica write failed");
+        }
+        let primary = primary?;
+        let replicas = match self.replication {
+            Replication::BestEffort => replicas,
+            Replication::Strict => FanOut {
+                results: replicas.into_result()?.into_iter().map(Ok).collect(),
+            },
+        };
+        Ok(ReplicatedWrite { primary, replicas })
+    }
+}
@@ -0,0 +1,117 @@
+//! History sampling and decimation
+//!
+//! The API has no sampling parameter, so sampling is applied client-side by
+//! [`Client::history_sampled`] while it walks every page of the requested
+//! range. `EveryNth` is applied page by page as events arrive; `MaxPoints`
+//! needs the whole range before it can pick evenly spaced events.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error, HistoryOptions};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let options = HistoryOptions {
+//!     since: Some("2025-01-01T00:00:00Z".to_string()),
+//!     ..Default::default()
+//! }
+//! .max_points(500);
+//! let events = client.history_sampled(&options).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error, HistoryEvent, HistoryOptions};
+use futures_util::TryStreamExt;
+use std::pin::pin;
+
+/// Client-side sampling strategy for history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    /// Keep every nth event, starting with the first
+    EveryNth(usize),
+    /// Keep at most this many evenly spaced events, including the first and last
+    MaxPoints(usize),
+}
+
+impl Sampling {
+    /// Apply the sampling strategy to an ordered list of events
+    pub fn apply(&self, events: Vec<HistoryEvent>) -> Vec<HistoryEvent> {
+        match *self {
+            Sampling::EveryNth(n) => events.into_iter().step_by(n.max(1)).collect(),
+            Sampling::MaxPoints(max) => decimate(events, max),
+        }
+    }
+}
+
+impl HistoryOptions {
+    /// Keep every nth event when read via [`Client::history_sampled`]
+    pub fn every_nth(mut self, n: usize) -> Self {
+        self.sampling = Some(Sampling::EveryNth(n));
+        self
+    }
+
+    /// Keep at most `max` events when read via [`Client::history_sampled`]
+    pub fn max_points(mut self, max: usize) -> Self {
+        self.sampling = Some(Sampling::MaxPoints(max));
+        self
+    }
+}
+
+impl Client {
+    /// Read every page of history matching `options` and apply its sampling
+    pub async fn history_sampled(
+        &self,
+        options: &HistoryOptions,
+    ) -> Result<Vec<HistoryEvent>, Error> {
+        let mut pages = pin!(self.history_pages(options));
+        let mut events = vec![];
+        let mut seen = 0usize;
+
+        while let Some(page) = pages.try_next().await? {
+            match options.sampling {
+                Some(Sampling::EveryNth(n)) => {
+                    let n = n.max(1);
+                    for event in page.events {
+                        if seen.is_multiple_of(n) {
+                            events.push(event);
+                        }
+                        seen += 1;
+                    }
+                }
+                _ => events.extend(page.events),
+            }
+        }
+
+        Ok(match options.sampling {
+            Some(Sampling::MaxPoints(max)) => decimate(events, max),
+            _ => events,
+        })
+    }
+}
+
+fn decimate(events: Vec<HistoryEvent>, max: usize) -> Vec<HistoryEvent> {
+    let len = events.len();
+    if len <= max {
+        return events;
+    }
+    if max <= 1 {
+        return events.into_iter().take(max).collect();
+    }
+
+    // Evenly spaced indices from first to last, inclusive
+    let mut next = 0;
+    let mut picked = 0;
+    events
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, event)| {
+            if i == next {
+                picked += 1;
+                next = picked * (len - 1) / (max - 1);
+                Some(event)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,191 @@
+//! Background batching of operations from anywhere in an application
+//!
+//! A [`Scheduler`] collects operations submitted over time and sends them as
+//! batches every interval, or sooner once 100 are waiting. Each submission
+//! returns a future resolving to its own [`BatchResult`]. Rate limit
+//! responses are retried after the server's `Retry-After` delay, unless the
+//! client's [retry policy](crate::policy) already retried them, and when the
+//! server reports no requests left the scheduler waits for the window reset.
+//!
+//! ```no_run
+//! use keyvalue_client::{BatchOperation, Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new_without_token();
+//! let scheduler = client.scheduler(Duration::from_millis(500));
+//!
+//! let pending: Vec<_> = ["word-word-word-word-one", "word-word-word-word-two"]
+//!     .into_iter()
+//!     .map(|token| {
+//!         scheduler.submit(BatchOperation {
+//!             action: "retrieve".to_string(),
+//!             token: token.to_string(),
+//!             data: None,
+//!             ttl: None,
+//!             patch: None,
+//!             version: None,
+//!         })
+//!     })
+//!     .collect();
+//! for result in futures_util::future::join_all(pending).await {
+//!     println!("{:?}", result?.data);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BatchOperation, BatchResult, Client, Error, RateLimit, RetryPolicy};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+
+/// Retries of a rate limited batch, on clients whose own policy does not retry
+const RATE_LIMIT_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    initial_backoff: Duration::from_millis(500),
+    max_backoff: Duration::from_secs(60),
+};
+
+/// Operations accepted by the batch endpoint per request
+pub(crate) const MAX_BATCH: usize = 100;
+
+/// `X-RateLimit-Reset` values above this are unix timestamps, below are seconds
+const RESET_TIMESTAMP_THRESHOLD: u64 = 1_000_000_000;
+
+struct Pending {
+    operation: BatchOperation,
+    reply: oneshot::Sender<Result<BatchResult, Error>>,
+}
+
+/// Handle for submitting operations to a background batching task
+///
+/// Clones share the same task, which stops after the last handle is dropped
+/// and the remaining operations are sent.
+#[derive(Clone)]
+pub struct Scheduler {
+    queue: mpsc::UnboundedSender<Pending>,
+}
+
+impl Scheduler {
+    /// Queue an operation for the next batch
+    pub fn submit(&self, operation: BatchOperation) -> Scheduled {
+        let (reply, result) = oneshot::channel();
+        // If the task is gone the reply is dropped and `Scheduled` reports it
+        let _ = self.queue.send(Pending { operation, reply });
+        Scheduled { result }
+    }
+}
+
+/// Result of a submitted operation, available once its batch completes
+pub struct Scheduled {
+    result: oneshot::Receiver<Result<BatchResult, Error>>,
+}
+
+impl Future for Scheduled {
+    type Output = Result<BatchResult, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.result).poll(cx).map(|result| {
+            result.unwrap_or_else(|_| {
+                Err(Error::BatchFailed {
+                    status: None,
+                    message: "Scheduler stopped".to_string(),
+                })
+            })
+        })
+    }
+}
+
+impl Client {
+    /// Start a [`Scheduler`] that sends queued operations every `interval`
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn scheduler(&self, interval: Duration) -> Scheduler {
+        let (queue, incoming) = mpsc::unbounded_channel();
+        tokio::spawn(run(self.clone(), interval, incoming));
+        Scheduler { queue }
+    }
+}
+
+async fn run(client: Client, interval: Duration, mut incoming: mpsc::UnboundedReceiver<Pending>) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut pending = Vec::new();
+    loop {
+        tokio::select! {
+            next = incoming.recv() => match next {
+                Some(op) => {
+                    pending.push(op);
+                    if pending.len() >= MAX_BATCH {
+                        flush(&client, &mut pending).await;
+                    }
+                }
+                None => {
+                    flush(&client, &mut pending).await;
+                    return;
+                }
+            },
+            _ = ticker.tick() => flush(&client, &mut pending).await,
+        }
+    }
+}
+
+/// Send everything pending, in batches of at most [`MAX_BATCH`]
+async fn flush(client: &Client, pending: &mut Vec<Pending>) {
+    while !pending.is_empty() {
+        let chunk: Vec<Pending> = pending.drain(..pending.len().min(MAX_BATCH)).collect();
+        let (operations, replies): (Vec<_>, Vec<_>) =
+            chunk.into_iter().map(|p| (p.operation, p.reply)).unzip();
+
+        let mut attempt = 0;
+        let outcome = loop {
+            attempt += 1;
+            let outcome = client.batch(operations.clone()).await;
+            match outcome.as_ref().err().and_then(|e| client.rate_limit_delay(&RATE_LIMIT_RETRY, attempt, e)) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => break outcome,
+            }
+        };
+
+        match outcome {
+            Ok(resp) => {
+                let mut results = resp.results.into_iter();
+                for reply in replies {
+                    let result = results.next().ok_or_else(|| Error::BatchFailed {
+                        status: None,
+                        message: "Missing result for operation".to_string(),
+                    });
+                    let _ = reply.send(result);
+                }
+                if let Some(wait) = resp.meta.rate_limit.and_then(exhausted_for) {
+                    tokio::time::sleep(wait).await;
+                }
+            }
+            Err(e) => {
+                let message = e.to_string();
+                for reply in replies {
+                    let _ = reply.send(Err(Error::BatchFailed {
+                        status: e.status(),
+                        message: message.clone(),
+                    }));
+                }
+            }
+        }
+    }
+}
+
+/// Time until the rate limit window resets, if no requests are left in it
+pub(crate) fn exhausted_for(rate_limit: RateLimit) -> Option<Duration> {
+    if rate_limit.remaining != Some(0) {
+        return None;
+    }
+    let reset = rate_limit.reset?;
+    if reset < RESET_TIMESTAMP_THRESHOLD {
+        return Some(Duration::from_secs(reset));
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(Duration::from_secs(reset.saturating_sub(now)))
+}
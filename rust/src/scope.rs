@@ -0,0 +1,160 @@
+//! Structured concurrency for SDK operations
+//!
+//! [`Client::scope`] runs a body with a [`Scope`] that owns everything spawned
+//! through it: tasks, [buffered writers](Scope::buffered_writer) and
+//! [sync tasks](Scope::sync). When the body finishes, spawned tasks are
+//! cancelled, writers are flushed and sync tasks shut down before `scope`
+//! returns. If the `scope` future itself is dropped, for example because the
+//! request handler running it was cancelled, tasks are cancelled at once and
+//! the flushes finish on a detached task.
+//!
+//! ```no_run
+//! use keyvalue_client::{BufferedWriterOptions, Client, Error};
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! client
+//!     .scope(|scope| async move {
+//!         let writer = scope.buffered_writer(BufferedWriterOptions::default());
+//!         let sync = scope.client().sync().watch(Duration::from_secs(5), |resp| {
+//!             println!("Version {}", resp.version)
+//!         });
+//!         scope.sync(sync);
+//!
+//!         for i in 0..100 {
+//!             writer.set("progress", i.into()).await?;
+//!         }
+//!         Ok(())
+//!     })
+//!     .await?;
+//! // The watcher is stopped and every update has been sent
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BufferedWriter, BufferedWriterOptions, Client, Error, SyncBuilder, SyncHandle};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinSet;
+
+/// Handle for spawning work tied to a [`Client::scope`]
+///
+/// Cheap to clone; all clones belong to the same scope. Once the scope has
+/// exited, further spawns are dropped without running.
+#[derive(Clone)]
+pub struct Scope {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Owned {
+    tasks: JoinSet<()>,
+    writers: Vec<BufferedWriter>,
+    syncs: Vec<SyncHandle>,
+}
+
+struct Inner {
+    client: Client,
+    /// `None` once the scope has exited
+    owned: Mutex<Option<Owned>>,
+}
+
+impl Scope {
+    /// Client the scope was opened on
+    pub fn client(&self) -> &Client {
+        &self.inner.client
+    }
+
+    /// Run `task` in the background until it finishes or the scope exits
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.with_owned(|owned| {
+            owned.tasks.spawn(task);
+        });
+    }
+
+    /// Start a [`BufferedWriter`] that is flushed when the scope exits
+    pub fn buffered_writer(&self, options: BufferedWriterOptions) -> BufferedWriter {
+        let writer = self.inner.client.buffered_writer(options);
+        self.with_owned(|owned| owned.writers.push(writer.clone()));
+        writer
+    }
+
+    /// Spawn `sync`, shutting it down when the scope exits
+    pub fn sync(&self, sync: SyncBuilder) {
+        self.with_owned(|owned| owned.syncs.push(sync.spawn()));
+    }
+
+    fn with_owned(&self, register: impl FnOnce(&mut Owned)) {
+        let mut owned = self.inner.owned.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(owned) = owned.as_mut() {
+            register(owned);
+        }
+    }
+
+    /// Take what the scope owns, leaving it closed
+    fn exit(&self) -> Option<Owned> {
+        self.inner.owned.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+impl Owned {
+    /// Cancel tasks, then flush writers and shut down sync tasks
+    async fn close(mut self) -> Result<(), Error> {
+        self.tasks.shutdown().await;
+        let mut result = Ok(());
+        for writer in &self.writers {
+            result = result.and(writer.flush().await);
+        }
+        for sync in self.syncs {
+            result = result.and(sync.shutdown().await);
+        }
+        result
+    }
+}
+
+/// Closes the scope on a detached task if the body is dropped before finishing
+struct ExitGuard(Scope);
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        let Some(mut owned) = self.0.exit() else {
+            return;
+        };
+        owned.tasks.abort_all();
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(e) = owned.close().await {
+                    tracing::warn!(error = %e, "failed to close dropped scope");
+                }
+            });
+        }
+    }
+}
+
+impl Client {
+    /// Run `body` with a [`Scope`], cleaning up everything spawned in it on exit
+    ///
+    /// Returns the body's error if it failed, otherwise the first error from
+    /// flushing writers or shutting down sync tasks.
+    pub async fn scope<T, F, Fut>(&self, body: F) -> Result<T, Error>
+    where
+        F: FnOnce(Scope) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let scope = Scope {
+            inner: Arc::new(Inner {
+                client: self.clone(),
+                owned: Mutex::new(Some(Owned::default())),
+            }),
+        };
+        let guard = ExitGuard(scope.clone());
+
+        let result = body(scope).await;
+        let closed = match guard.0.exit() {
+            Some(owned) => owned.close().await,
+            None => Ok(()),
+        };
+        result.and_then(|value| closed.map(|()| value))
+    }
+}
@@ -0,0 +1,88 @@
+//! In-memory storage for the client token
+//!
+//! With the `secrecy` feature the token is held in a [`SecretString`], which
+//! is zeroized once the last client sharing it is dropped. Pass a
+//! `SecretString` to [`Client::from_secret`] or
+//! [`ClientBuilder::secret_token`](crate::ClientBuilder::secret_token) so the
+//! token never sits in an ordinary `String` on the way in.
+
+#[cfg(feature = "secrecy")]
+use crate::Client;
+#[cfg(feature = "secrecy")]
+use secrecy::{ExposeSecret, SecretString};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Token shared by a client and its clones
+#[derive(Clone)]
+pub(crate) struct Token {
+    #[cfg(feature = "secrecy")]
+    inner: Arc<SecretString>,
+    #[cfg(not(feature = "secrecy"))]
+    inner: Arc<str>,
+}
+
+impl Deref for Token {
+    type Target = str;
+
+    #[cfg(feature = "secrecy")]
+    fn deref(&self) -> &str {
+        self.inner.expose_secret()
+    }
+
+    #[cfg(not(feature = "secrecy"))]
+    fn deref(&self) -> &str {
+        &self.inner
+    }
+}
+
+impl From<String> for Token {
+    #[cfg(feature = "secrecy")]
+    fn from(token: String) -> Self {
+        SecretString::from(token).into()
+    }
+
+    #[cfg(not(feature = "secrecy"))]
+    fn from(token: String) -> Self {
+        Self {
+            inner: Arc::from(token),
+        }
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl From<SecretString> for Token {
+    fn from(token: SecretString) -> Self {
+        Self {
+            inner: Arc::new(token),
+        }
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl Client {
+    /// Create a new client with a token kept in a [`SecretString`]
+    ///
+    /// Panics like [`Client::new`].
+    ///
+    /// ```
+    /// use keyvalue_client::secrecy::SecretString;
+    /// use keyvalue_client::Client;
+    ///
+    /// let token = SecretString::from("word-word-word-word-word");
+    /// let client = Client::from_secret(token);
+    /// assert!(!format!("{:?}", client).contains("word-word-word"));
+    /// ```
+    pub fn from_secret(token: SecretString) -> Self {
+        crate::ClientBuilder::new()
+            .secret_token(token)
+            .build()
+            .expect("Failed to build HTTP client")
+    }
+
+    /// Set the default token from a [`SecretString`]
+    pub fn set_secret_token(&mut self, token: SecretString) {
+        self.token = Some(token.into());
+        self.last_store = Arc::default();
+    }
+}
@@ -0,0 +1,253 @@
+//! `tower::Service` adapters (feature `tower`)
+//!
+//! Each service owns a clone of the [`Client`] and performs one operation per
+//! call, so standard tower layers (timeouts, concurrency and rate limits, load
+//! shedding, retries, tracing) can wrap Key-Value calls. Request types are
+//! `Clone` for use with retry layers; [`Error::is_retryable`] is a suitable
+//! retry predicate.
+//!
+//! ```no_run
+//! use keyvalue_client::service::StoreRequest;
+//! use keyvalue_client::Client;
+//! use std::time::Duration;
+//! use tower::{Service, ServiceBuilder, ServiceExt};
+//!
+//! # async fn run() -> Result<(), tower::BoxError> {
+//! let client = Client::new("word-word-word-word-word");
+//! let mut store = ServiceBuilder::new()
+//!     .concurrency_limit(4)
+//!     .timeout(Duration::from_secs(5))
+//!     .service(client.store_service());
+//!
+//! let request = StoreRequest::new(serde_json::json!({"temperature": 23.5}));
+//! let resp = store.ready().await?.call(request).await?;
+//! println!("Stored version {}", resp.version);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    BatchOperation, BatchResponse, Client, DeleteResponse, Error, HistoryOptions,
+    HistoryResponse, PatchOperations, PatchResponse, RetrieveResponse, StoreResponse,
+};
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// Future returned by the services in this module
+pub type ServiceFuture<T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send>>;
+
+/// Arguments to [`Client::store`]
+#[derive(Debug, Clone)]
+pub struct StoreRequest {
+    pub data: Value,
+    pub ttl: Option<i32>,
+}
+
+impl StoreRequest {
+    pub fn new(data: Value) -> Self {
+        Self { data, ttl: None }
+    }
+
+    /// Expire the stored data after `ttl` seconds
+    pub fn ttl(mut self, ttl: i32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+/// Arguments to [`Client::patch`]
+#[derive(Debug, Clone)]
+pub struct PatchRequest {
+    pub version: i32,
+    pub patch: PatchOperations,
+    pub ttl: Option<i32>,
+}
+
+impl PatchRequest {
+    pub fn new(version: i32, patch: PatchOperations) -> Self {
+        Self {
+            version,
+            patch,
+            ttl: None,
+        }
+    }
+
+    /// Expire the patched data after `ttl` seconds
+    pub fn ttl(mut self, ttl: i32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+/// Service calling [`Client::store`]
+#[derive(Clone)]
+pub struct StoreService {
+    client: Client,
+}
+
+impl Service<StoreRequest> for StoreService {
+    type Response = StoreResponse;
+    type Error = Error;
+    type Future = ServiceFuture<StoreResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: StoreRequest) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move { client.store(&request.data, request.ttl).await })
+    }
+}
+
+/// Service calling [`Client::retrieve`]
+#[derive(Clone)]
+pub struct RetrieveService {
+    client: Client,
+}
+
+impl Service<()> for RetrieveService {
+    type Response = RetrieveResponse;
+    type Error = Error;
+    type Future = ServiceFuture<RetrieveResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: ()) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move { client.retrieve().await })
+    }
+}
+
+/// Service calling [`Client::patch`]
+#[derive(Clone)]
+pub struct PatchService {
+    client: Client,
+}
+
+impl Service<PatchRequest> for PatchService {
+    type Response = PatchResponse;
+    type Error = Error;
+    type Future = ServiceFuture<PatchResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: PatchRequest) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move { client.patch(request.version, &request.patch, request.ttl).await })
+    }
+}
+
+/// Service calling [`Client::delete`]
+#[derive(Clone)]
+pub struct DeleteService {
+    client: Client,
+}
+
+impl Service<()> for DeleteService {
+    type Response = DeleteResponse;
+    type Error = Error;
+    type Future = ServiceFuture<DeleteResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: ()) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move { client.delete().await })
+    }
+}
+
+/// Service calling [`Client::history`]
+#[derive(Clone)]
+pub struct HistoryService {
+    client: Client,
+}
+
+impl Service<HistoryOptions> for HistoryService {
+    type Response = HistoryResponse;
+    type Error = Error;
+    type Future = ServiceFuture<HistoryResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, options: HistoryOptions) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move { client.history(&options).await })
+    }
+}
+
+/// Service calling [`Client::batch`]
+#[derive(Clone)]
+pub struct BatchService {
+    client: Client,
+}
+
+impl Service<Vec<BatchOperation>> for BatchService {
+    type Response = BatchResponse;
+    type Error = Error;
+    type Future = ServiceFuture<BatchResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, operations: Vec<BatchOperation>) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move { client.batch(operations).await })
+    }
+}
+
+impl Client {
+    /// [`StoreService`] backed by a clone of this client
+    pub fn store_service(&self) -> StoreService {
+        StoreService {
+            client: self.clone(),
+        }
+    }
+
+    /// [`RetrieveService`] backed by a clone of this client
+    pub fn retrieve_service(&self) -> RetrieveService {
+        RetrieveService {
+            client: self.clone(),
+        }
+    }
+
+    /// [`PatchService`] backed by a clone of this client
+    pub fn patch_service(&self) -> PatchService {
+        PatchService {
+            client: self.clone(),
+        }
+    }
+
+    /// [`DeleteService`] backed by a clone of this client
+    pub fn delete_service(&self) -> DeleteService {
+        DeleteService {
+            client: self.clone(),
+        }
+    }
+
+    /// [`HistoryService`] backed by a clone of this client
+    pub fn history_service(&self) -> HistoryService {
+        HistoryService {
+            client: self.clone(),
+        }
+    }
+
+    /// [`BatchService`] backed by a clone of this client
+    pub fn batch_service(&self) -> BatchService {
+        BatchService {
+            client: self.clone(),
+        }
+    }
+}
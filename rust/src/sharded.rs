@@ -0,0 +1,327 @@
+//! Documents larger than one token's limit, split across several tokens
+//!
+//! A [`ShardedStore`] serializes a value, cuts the JSON text into chunks that
+//! each fit comfortably under the tier limit, and stores chunk `i` under the
+//! `i`-th shard token. A manifest under its own token lists every chunk
+//! with its SHA-256 hash and the shard version it was written at; retrieving
+//! reads the manifest, fetches the chunks in parallel, checks each hash and
+//! the hash of the whole, and parses the result.
+//!
+//! Shards are written before the manifest, so a reader never follows a
+//! manifest to chunks that do not exist yet. A reader racing a writer can
+//! still see new chunks under an old manifest; that fails the integrity
+//! check instead of returning a mix, and a retry reads the new document.
+//!
+//! ```no_run
+//! use keyvalue_client::sharded::ShardedStore;
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let manifest = Client::new("word-word-word-word-one");
+//! let shards = ["word-word-word-word-two", "word-word-word-word-three"]
+//!     .into_iter()
+//!     .map(Client::new)
+//!     .collect();
+//! let store = ShardedStore::new(manifest, shards);
+//!
+//! let readings: Vec<f64> = (0..20_000).map(|i| i as f64 * 0.25).collect();
+//! let written = store.store(&serde_json::json!({"readings": readings}), None).await?;
+//! println!("{} bytes across {} shards", written.bytes, written.shards);
+//!
+//! let back = store.retrieve().await?;
+//! assert_eq!(back.data["readings"][1], 0.25);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::size::Tier;
+use crate::{hex, Client, Error};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Field holding the manifest in the manifest token's value
+pub const MANIFEST_FIELD: &str = "_sharded";
+
+const FORMAT_VERSION: u32 = 1;
+/// Shard requests in flight at once
+const CONCURRENCY: usize = 4;
+
+/// Value spread over a manifest token and several shard tokens
+#[derive(Debug, Clone)]
+pub struct ShardedStore {
+    manifest: Client,
+    shards: Vec<Client>,
+    chunk_bytes: usize,
+}
+
+/// Result of [`ShardedStore::store`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardedStoreResponse {
+    /// Version of the manifest written
+    pub version: i32,
+    /// Shards holding the document
+    pub shards: usize,
+    /// Length of the serialized document
+    pub bytes: u64,
+}
+
+/// Result of [`ShardedStore::retrieve`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardedValue {
+    pub data: Value,
+    /// Version of the manifest read
+    pub version: i32,
+    pub shards: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    bytes: u64,
+    sha256: String,
+    chunks: Vec<ChunkRef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChunkRef {
+    sha256: String,
+    /// Shard version the chunk was written at
+    version: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Chunk {
+    index: usize,
+    data: String,
+}
+
+impl ShardedStore {
+    /// Keep the manifest under `manifest` and chunks under `shards`, in order
+    ///
+    /// Chunks default to 90% of the free tier's limit.
+    pub fn new(manifest: Client, shards: Vec<Client>) -> Self {
+        Self {
+            manifest,
+            shards,
+            chunk_bytes: (Tier::Free.max_payload_bytes() / 10 * 9) as usize,
+        }
+    }
+
+    /// Largest chunk to store per shard, in bytes of JSON
+    pub fn with_chunk_bytes(mut self, chunk_bytes: usize) -> Self {
+        self.chunk_bytes = chunk_bytes.max(64);
+        self
+    }
+
+    /// Largest document the shards can hold, in bytes of JSON
+    pub fn capacity(&self) -> u64 {
+        (self.chunk_bytes * self.shards.len()) as u64
+    }
+
+    /// Split `data` over the shards, then write the manifest
+    ///
+    /// Fails with [`Error::PayloadTooLarge`] before writing anything if the
+    /// document needs more shards than configured.
+    pub async fn store(&self, data: &Value, ttl: Option<i32>) -> Result<ShardedStoreResponse, Error> {
+        let text = data.to_string();
+        let chunks = split(&text, self.chunk_bytes);
+        if chunks.len() > self.shards.len() {
+            return Err(Error::PayloadTooLarge {
+                size: Some(text.len() as u64),
+                limit: Some(self.capacity()),
+                message: format!(
+                    "Document of {} bytes needs {} shards, only {} configured",
+                    text.len(),
+                    chunks.len(),
+                    self.shards.len()
+                ),
+            });
+        }
+
+        let chunks: Vec<ChunkRef> = stream::iter(chunks.into_iter().enumerate())
+            .map(|(index, chunk)| async move {
+                let value = serde_json::to_value(Chunk {
+                    index,
+                    data: chunk.to_string(),
+                })?;
+                let resp = self.shards[index].store(&value, ttl).await?;
+                Ok::<_, Error>(ChunkRef {
+                    sha256: sha256_hex(chunk.as_bytes()),
+                    version: resp.version,
+                })
+            })
+            .buffered(CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let manifest = Manifest {
+            format: FORMAT_VERSION,
+            bytes: text.len() as u64,
+            sha256: sha256_hex(text.as_bytes()),
+            chunks,
+        };
+        let shards = manifest.chunks.len();
+        let value = serde_json::json!({ MANIFEST_FIELD: manifest });
+        let resp = self.manifest.store(&value, ttl).await?;
+        Ok(ShardedStoreResponse {
+            version: resp.version,
+            shards,
+            bytes: text.len() as u64,
+        })
+    }
+
+    /// Read the manifest and reassemble the document, checking every hash
+    pub async fn retrieve(&self) -> Result<ShardedValue, Error> {
+        let resp = self.manifest.retrieve().await?;
+        let manifest: Manifest = resp
+            .data
+            .get(MANIFEST_FIELD)
+            .cloned()
+            .ok_or_else(|| integrity("Manifest token does not hold a sharded manifest".to_string()))
+            .and_then(|manifest| Ok(serde_json::from_value(manifest)?))?;
+        if manifest.format != FORMAT_VERSION {
+            return Err(integrity(format!("Unsupported manifest format {}", manifest.format)));
+        }
+        if manifest.chunks.len() > self.shards.len() {
+            return Err(integrity(format!(
+                "Manifest lists {} shards, only {} configured",
+                manifest.chunks.len(),
+                self.shards.len()
+            )));
+        }
+
+        let parts: Vec<String> = stream::iter(manifest.chunks.iter().enumerate())
+            .map(|(index, expected)| async move {
+                let resp = self.shards[index].retrieve().await?;
+                let chunk: Chunk = serde_json::from_value(resp.data)?;
+                if chunk.index != index || sha256_hex(chunk.data.as_bytes()) != expected.sha256 {
+                    return Err(integrity(format!(
+                        "Shard {} does not match the manifest (manifest wrote version {}, shard is at {})",
+                        index, expected.version, resp.version
+                    )));
+                }
+                Ok(chunk.data)
+            })
+            .buffered(CONCURRENCY)
+            .try_collect()
+            .await?;
+
+        let text = parts.concat();
+        if sha256_hex(text.as_bytes()) != manifest.sha256 {
+            return Err(integrity("Reassembled document does not match the manifest hash".to_string()));
+        }
+        Ok(ShardedValue {
+            data: serde_json::from_str(&text)?,
+            version: resp.version,
+            shards: manifest.chunks.len(),
+            bytes: manifest.bytes,
+        })
+    }
+}
+
+/// Cut `text` at char boundaries into pieces whose JSON string encoding fits `max` bytes
+fn split(text: &str, max: usize) -> Vec<&str> {
+    // Leave room for the surrounding quotes and the chunk wrapper
+    let budget = max.saturating_sub(32).max(8);
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut encoded = 0;
+    for (i, c) in text.char_indices() {
+        let len = match c {
+            '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+            c if (c as u32) < 0x20 => 6,
+            c => c.len_utf8(),
+        };
+        if encoded + len > budget && i > start {
+            chunks.push(&text[start..i]);
+            start = i;
+            encoded = 0;
+        }
+        encoded += len;
+    }
+    if start < text.len() || chunks.is_empty() {
+        chunks.push(&text[start..]);
+    }
+    chunks
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn integrity(message: String) -> Error {
+    Error::Validation(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+    use serde_json::json;
+
+    fn encoded_len(chunk: &str, index: usize) -> usize {
+        serde_json::to_string(&Chunk { index, data: chunk.to_string() }).unwrap().len()
+    }
+
+    #[test]
+    fn split_fits_every_chunk_and_keeps_the_text() {
+        let text = json!({"note": "quote \" backslash \\ tab \t é 🙂 \u{1}", "n": [1, 2, 3]}).to_string().repeat(20);
+        for max in [64, 100, 257] {
+            let chunks = split(&text, max);
+            assert_eq!(chunks.concat(), text);
+            for (index, chunk) in chunks.iter().enumerate() {
+                assert!(encoded_len(chunk, index) <= max, "{} bytes over {}", encoded_len(chunk, index), max);
+            }
+        }
+    }
+
+    #[test]
+    fn split_always_returns_a_chunk() {
+        assert_eq!(split("", 64), [""]);
+        assert_eq!(split("\"x\"", 64), ["\"x\""]);
+        // Tiny limits still leave room for whole characters
+        assert_eq!(split("🙂🙂🙂", 0), ["🙂🙂", "🙂"]);
+    }
+
+    fn store(server: &MockServer, shards: usize) -> ShardedStore {
+        let shards = (0..shards).map(|i| server.client(&format!("shard-{}", i))).collect();
+        ShardedStore::new(server.client("manifest"), shards).with_chunk_bytes(64)
+    }
+
+    #[tokio::test]
+    async fn documents_round_trip_across_shards() {
+        let server = MockServer::start().await;
+        let data = json!({"readings": (0..20).collect::<Vec<_>>(), "unit": "°C"});
+        let resp = store(&server, 4).store(&data, None).await.unwrap();
+        assert!(resp.shards > 1);
+        assert_eq!(resp.bytes, data.to_string().len() as u64);
+
+        let value = store(&server, 4).retrieve().await.unwrap();
+        assert_eq!((value.data, value.shards, value.version), (data, resp.shards, resp.version));
+    }
+
+    #[tokio::test]
+    async fn oversized_documents_write_nothing() {
+        let server = MockServer::start().await;
+        let data = json!({"readings": (0..100).collect::<Vec<_>>()});
+        let error = store(&server, 2).store(&data, None).await.unwrap_err();
+        assert!(matches!(error, Error::PayloadTooLarge { limit: Some(128), .. }));
+        assert_eq!(server.requests("/api/store"), 0);
+    }
+
+    #[tokio::test]
+    async fn changed_shards_fail_the_hash_check() {
+        let server = MockServer::start().await;
+        let data = json!({"readings": (0..20).collect::<Vec<_>>()});
+        store(&server, 4).store(&data, None).await.unwrap();
+
+        let mut chunk = server.stored("shard-1").unwrap();
+        chunk["data"] = json!("tampered");
+        server.put("shard-1", chunk);
+        let error = store(&server, 4).retrieve().await.unwrap_err();
+        assert!(matches!(error, Error::Validation(message) if message.starts_with("Shard 1 does not match")));
+    }
+}
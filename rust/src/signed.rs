@@ -0,0 +1,184 @@
+//! Ed25519-signed payloads
+//!
+//! A [`SignedClient`] signs what it stores with an ed25519 key and verifies
+//! what it retrieves, so a reader notices values altered in storage or by a
+//! misbehaving proxy. The stored document is an envelope:
+//!
+//! ```json
+//! {"_signature": {"alg": "ed25519", "key": "<public key>", "sig": "<signature>"}, "value": {...}}
+//! ```
+//!
+//! Key and signature are base64. The signature covers the token and the
+//! canonical JSON of the value (keys sorted), joined by `\n`, so a signed
+//! value copied to another token fails to verify. A signer trusts its own key;
+//! readers that do not write are built with [`SignedClient::verifier`] and
+//! the public keys they trust.
+//!
+//! ```no_run
+//! use keyvalue_client::ed25519_dalek::SigningKey;
+//! use keyvalue_client::signed::SignedClient;
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run(seed: [u8; 32]) -> Result<(), Error> {
+//! let key = SigningKey::from_bytes(&seed);
+//! let public = key.verifying_key();
+//!
+//! let writer = SignedClient::new(Client::new("word-word-word-word-word"), key);
+//! writer.store(&serde_json::json!({"firmware": "2.1.0"}), None).await?;
+//!
+//! let reader = SignedClient::verifier(Client::new("word-word-word-word-word"), [public]);
+//! let resp = reader.retrieve().await?;
+//! println!("verified: {}", resp.data);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dedup::canonical_json;
+use crate::{Client, Error, RetrieveResponse, StoreResponse};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde_json::Value;
+use std::fmt;
+
+const SIGNATURE_FIELD: &str = "_signature";
+const VALUE_FIELD: &str = "value";
+const ALGORITHM: &str = "ed25519";
+
+/// Client that signs stored values and verifies retrieved ones
+#[derive(Clone)]
+pub struct SignedClient {
+    client: Client,
+    key: Option<SigningKey>,
+    trusted: Vec<VerifyingKey>,
+}
+
+impl fmt::Debug for SignedClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignedClient")
+            .field("client", &self.client)
+            .field("signs", &self.key.is_some())
+            .field("trusted", &self.trusted.len())
+            .finish()
+    }
+}
+
+impl SignedClient {
+    /// Sign with `key` and trust its public key
+    pub fn new(client: Client, key: SigningKey) -> Self {
+        Self {
+            client,
+            trusted: vec![key.verifying_key()],
+            key: Some(key),
+        }
+    }
+
+    /// Sign with the 32-byte seed in `seed`, base64 encoded
+    #[cfg(feature = "secrecy")]
+    pub fn from_secret(client: Client, seed: &secrecy::SecretString) -> Result<Self, Error> {
+        use secrecy::ExposeSecret;
+        use zeroize::Zeroizing;
+
+        let bytes = Zeroizing::new(
+            BASE64
+                .decode(seed.expose_secret().trim())
+                .map_err(|e| Error::Validation(format!("Invalid signing key: {}", e)))?,
+        );
+        let seed: Zeroizing<[u8; 32]> = Zeroizing::new(
+            bytes
+                .as_slice()
+                .try_into()
+                .map_err(|_| Error::Validation("Signing key must be 32 bytes".to_string()))?,
+        );
+        Ok(Self::new(client, SigningKey::from_bytes(&seed)))
+    }
+
+    /// Only verify, trusting `keys`; storing fails
+    pub fn verifier(client: Client, keys: impl IntoIterator<Item = VerifyingKey>) -> Self {
+        Self {
+            client,
+            key: None,
+            trusted: keys.into_iter().collect(),
+        }
+    }
+
+    /// Also accept values signed by `key`
+    pub fn with_trusted_key(mut self, key: VerifyingKey) -> Self {
+        self.trusted.push(key);
+        self
+    }
+
+    /// The wrapped client
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Sign and store a value
+    pub async fn store(&self, data: &Value, ttl: Option<i32>) -> Result<StoreResponse, Error> {
+        let key = self
+            .key
+            .as_ref()
+            .ok_or_else(|| Error::Validation("Verifier has no signing key".to_string()))?;
+        let signature = key.sign(&self.message(data)?);
+        let envelope = serde_json::json!({
+            SIGNATURE_FIELD: {
+                "alg": ALGORITHM,
+                "key": BASE64.encode(key.verifying_key().as_bytes()),
+                "sig": BASE64.encode(signature.to_bytes()),
+            },
+            VALUE_FIELD: data,
+        });
+        self.client.store(&envelope, ttl).await
+    }
+
+    /// Retrieve a value, failing unless it carries a valid signature from a trusted key
+    pub async fn retrieve(&self) -> Result<RetrieveResponse, Error> {
+        let mut resp = self.client.retrieve().await?;
+        resp.data = self.verify(resp.data)?;
+        Ok(resp)
+    }
+
+    /// Verify a signed envelope, such as a history payload, and return the value inside
+    pub fn verify(&self, data: Value) -> Result<Value, Error> {
+        let Value::Object(mut envelope) = data else {
+            return Err(unsigned());
+        };
+        if envelope.len() != 2 {
+            return Err(unsigned());
+        }
+        let (Some(signature), Some(value)) = (envelope.remove(SIGNATURE_FIELD), envelope.remove(VALUE_FIELD)) else {
+            return Err(unsigned());
+        };
+        if signature["alg"] != ALGORITHM {
+            return Err(Error::InvalidSignature(format!("Unsupported algorithm {}", signature["alg"])));
+        }
+        let key = decode::<32>(&signature["key"], "key")?;
+        let key = self
+            .trusted
+            .iter()
+            .find(|trusted| trusted.as_bytes() == &key)
+            .ok_or_else(|| Error::InvalidSignature("Signed by an untrusted key".to_string()))?;
+        let signature = Signature::from_bytes(&decode::<64>(&signature["sig"], "signature")?);
+        key.verify(&self.message(&value)?, &signature)
+            .map_err(|_| Error::InvalidSignature("Signature does not match the value".to_string()))?;
+        Ok(value)
+    }
+
+    /// Bytes signed for `value` under this client's token
+    fn message(&self, value: &Value) -> Result<Vec<u8>, Error> {
+        let token = self.client.token.as_deref().ok_or(Error::MissingToken)?;
+        Ok(format!("{}\n{}", token, canonical_json(value)).into_bytes())
+    }
+}
+
+fn decode<const N: usize>(field: &Value, name: &str) -> Result<[u8; N], Error> {
+    field
+        .as_str()
+        .and_then(|encoded| BASE64.decode(encoded).ok())
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::InvalidSignature(format!("Malformed {}", name)))
+}
+
+fn unsigned() -> Error {
+    Error::InvalidSignature("Value is not signed".to_string())
+}
@@ -0,0 +1,231 @@
+//! Stream pipelines ending in Key-Value
+//!
+//! [`StoreSink`] and [`BatchSink`] implement [`futures_util::Sink`], so a
+//! stream of readings or operations can be forwarded straight into the API.
+//! Items are buffered and sent as batches of up to
+//! [`SinkOptions::max_batch`], at most one request per
+//! [`SinkOptions::min_interval`]. Rate limit responses are retried after the
+//! server's `Retry-After` delay, unless the client's
+//! [retry policy](crate::policy) already retried them, and when the server
+//! reports no requests left the sink waits for the window reset before
+//! sending more.
+//!
+//! Flushing the sink sends what is buffered; `forward` and `send_all` flush
+//! whenever the stream has nothing ready. A failed request, or a failed
+//! operation within one, is returned from the next call on the sink as
+//! [`Error::BatchFailed`].
+//!
+//! Writes go through the batch endpoint. Values for the client's own token
+//! are encrypted, checksummed and compressed as [`Client::store`] would, but
+//! other store features, such as contracts and the journal, are skipped.
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use keyvalue_client::sink::SinkOptions;
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run(readings: impl futures_util::Stream<Item = f64>) -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let sink = client.store_sink(SinkOptions::default())?;
+//! readings
+//!     .map(|celsius| Ok(serde_json::json!({ "celsius": celsius })))
+//!     .forward(sink)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::scheduler::{exhausted_for, MAX_BATCH};
+use crate::{BatchOperation, Client, Error, RetryPolicy};
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, Sink};
+use serde_json::Value;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Retries of a rate limited batch, on clients whose own policy does not retry
+const RATE_LIMIT_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    initial_backoff: Duration::from_millis(500),
+    max_backoff: Duration::from_secs(60),
+};
+
+/// Batching and pacing for [`StoreSink`] and [`BatchSink`]
+#[derive(Debug, Clone)]
+pub struct SinkOptions {
+    /// Items per batch request, at most 100
+    pub max_batch: usize,
+    /// Shortest time between batch requests
+    pub min_interval: Duration,
+    /// TTL of values written by a [`StoreSink`]
+    pub ttl: Option<i32>,
+}
+
+impl Default for SinkOptions {
+    fn default() -> Self {
+        Self {
+            max_batch: MAX_BATCH,
+            min_interval: Duration::from_millis(100),
+            ttl: None,
+        }
+    }
+}
+
+/// Sink sending [`BatchOperation`]s in batches
+pub struct BatchSink {
+    client: Client,
+    options: SinkOptions,
+    buffer: Vec<BatchOperation>,
+    in_flight: Option<BoxFuture<'static, Result<(), Error>>>,
+}
+
+impl BatchSink {
+    /// Wait for the batch being sent, if any
+    fn poll_in_flight(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let Some(in_flight) = self.in_flight.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(in_flight.poll_unpin(cx));
+        self.in_flight = None;
+        Poll::Ready(result)
+    }
+
+    /// Start sending the buffered operations
+    fn start_batch(&mut self) {
+        let operations = std::mem::take(&mut self.buffer);
+        self.in_flight = Some(send(self.client.clone(), operations, self.options.min_interval).boxed());
+    }
+
+    fn max_batch(&self) -> usize {
+        self.options.max_batch.clamp(1, MAX_BATCH)
+    }
+}
+
+impl Sink<BatchOperation> for BatchSink {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_in_flight(cx))?;
+        if this.buffer.len() < this.max_batch() {
+            return Poll::Ready(Ok(()));
+        }
+        this.start_batch();
+        this.poll_in_flight(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, operation: BatchOperation) -> Result<(), Error> {
+        self.get_mut().buffer.push(operation);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_in_flight(cx))?;
+        if this.buffer.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        this.start_batch();
+        this.poll_in_flight(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Sink storing each JSON value to the client's token, in batches
+///
+/// The document holds the latest value; each value is recorded in history.
+pub struct StoreSink {
+    inner: BatchSink,
+    token: String,
+}
+
+impl Sink<Value> for StoreSink {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, data: Value) -> Result<(), Error> {
+        let operation = BatchOperation {
+            action: "store".to_string(),
+            token: self.token.clone(),
+            data: Some(data),
+            ttl: self.inner.options.ttl,
+            patch: None,
+            version: None,
+        };
+        Pin::new(&mut self.inner).start_send(operation)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+impl Client {
+    /// Sink sending operations to the batch endpoint
+    pub fn batch_sink(&self, options: SinkOptions) -> BatchSink {
+        BatchSink {
+            client: self.clone(),
+            options,
+            buffer: vec![],
+            in_flight: None,
+        }
+    }
+
+    /// Sink storing values to this client's token
+    pub fn store_sink(&self, options: SinkOptions) -> Result<StoreSink, Error> {
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?.to_string();
+        Ok(StoreSink {
+            inner: self.batch_sink(options),
+            token,
+        })
+    }
+}
+
+/// Send one batch, then hold the sink until the next batch may go out
+async fn send(client: Client, operations: Vec<BatchOperation>, min_interval: Duration) -> Result<(), Error> {
+    let next = Instant::now() + min_interval;
+    let mut attempt = 0;
+    let resp = loop {
+        attempt += 1;
+        let outcome = client.batch(operations.clone()).await;
+        match outcome.as_ref().err().and_then(|e| client.rate_limit_delay(&RATE_LIMIT_RETRY, attempt, e)) {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => {
+                break outcome.map_err(|e| Error::BatchFailed {
+                    status: e.status(),
+                    message: e.to_string(),
+                })?
+            }
+        }
+    };
+
+    let failed: Vec<_> = resp.results.iter().filter(|result| !result.success).collect();
+    if let Some(first) = failed.first() {
+        return Err(Error::BatchFailed {
+            status: None,
+            message: format!(
+                "{} of {} operations failed: {}",
+                failed.len(),
+                operations.len(),
+                first.error.as_deref().unwrap_or("unknown error")
+            ),
+        });
+    }
+    match resp.meta.rate_limit.and_then(exhausted_for) {
+        Some(wait) => tokio::time::sleep(wait).await,
+        None => tokio::time::sleep_until(next).await,
+    }
+    Ok(())
+}
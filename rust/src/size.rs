@@ -0,0 +1,318 @@
+//! Payload size estimation and tier fit
+//!
+//! Payload limits apply to the compressed JSON. [`estimate`] measures the
+//! compact JSON encoding of a value, estimates its compressed size, and finds
+//! the smallest [`Tier`] it fits, warning when it is close to that tier's
+//! limit. The compressed figure models deflate without running it and
+//! usually lands within 15% of gzip, so leave headroom near a limit; check
+//! [`Client::usage`](crate::Client::usage) for the limit the server enforces.
+//!
+//! Once a client knows its tier, from [`Client::with_tier`] or from the first
+//! store response, it runs the same estimate before sending a large payload
+//! and fails with [`Error::PayloadTooLarge`] locally instead of uploading a
+//! blob the server will reject.
+//!
+//! ```
+//! use keyvalue_client::size::{estimate, Tier};
+//!
+//! let readings: Vec<f64> = (0..1000).map(|i| i as f64 / 10.0).collect();
+//! let report = estimate(&serde_json::json!({"readings": readings}));
+//! assert!(report.compressed_bytes < report.bytes);
+//! assert_eq!(report.tier, Some(Tier::Free));
+//! ```
+
+use crate::{Client, Error};
+use serde_json::Value;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Share of a limit above which [`estimate`] warns
+pub const DEFAULT_WARN_RATIO: f64 = 0.9;
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Earlier positions tried per match search
+const MAX_PROBES: usize = 128;
+/// Gzip header and trailer
+const FRAMING_BYTES: u64 = 18;
+/// Approximate cost of describing one used symbol in a dynamic Huffman table
+const TABLE_BITS_PER_SYMBOL: f64 = 4.0;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Hosted service plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+    Free,
+    Developer,
+    Pro,
+    Enterprise,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 4] = [Tier::Free, Tier::Developer, Tier::Pro, Tier::Enterprise];
+
+    /// Published maximum payload size, in bytes; Enterprise limits can be raised further
+    pub fn max_payload_bytes(self) -> u64 {
+        match self {
+            Tier::Free => 100_000,
+            Tier::Developer => 1_000_000,
+            Tier::Pro => 10_000_000,
+            Tier::Enterprise => 100_000_000,
+        }
+    }
+}
+
+impl FromStr for Tier {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        Tier::ALL
+            .into_iter()
+            .find(|tier| tier.to_string().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| Error::Validation(format!("Unknown tier '{}'", name)))
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Tier::Free => "free",
+            Tier::Developer => "developer",
+            Tier::Pro => "pro",
+            Tier::Enterprise => "enterprise",
+        })
+    }
+}
+
+/// Size of a value as it would be stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeReport {
+    /// Compact JSON encoding, in bytes
+    pub bytes: u64,
+    /// Estimated compressed size, in bytes
+    pub compressed_bytes: u64,
+    /// Smallest tier whose limit fits the compressed size, if any
+    pub tier: Option<Tier>,
+    /// Share of that tier's limit used, from 0 to 1
+    pub limit_used: f64,
+    /// Human-readable notes about tight or exceeded limits
+    pub warnings: Vec<String>,
+}
+
+/// Estimate the stored size of `value`, warning above [`DEFAULT_WARN_RATIO`] of a limit
+pub fn estimate(value: &Value) -> SizeReport {
+    estimate_with(value, DEFAULT_WARN_RATIO)
+}
+
+/// Like [`estimate`], warning once `warn_ratio` of the fitting tier's limit is used
+pub fn estimate_with(value: &Value, warn_ratio: f64) -> SizeReport {
+    let json = value.to_string();
+    let bytes = json.len() as u64;
+    let compressed_bytes = compressed_size(json.as_bytes());
+
+    let tier = Tier::ALL
+        .into_iter()
+        .find(|tier| compressed_bytes <= tier.max_payload_bytes());
+    let mut warnings = vec![];
+    let limit_used = match tier {
+        Some(tier) => {
+            let used = compressed_bytes as f64 / tier.max_payload_bytes() as f64;
+            if used >= warn_ratio {
+                warnings.push(format!(
+                    "Uses {:.0}% of the {} tier's {} byte limit",
+                    used * 100.0,
+                    tier,
+                    tier.max_payload_bytes()
+                ));
+            }
+            used
+        }
+        None => {
+            warnings.push(format!(
+                "Exceeds every published tier limit ({} bytes compressed)",
+                compressed_bytes
+            ));
+            1.0
+        }
+    };
+    if let Some(tier) = tier.filter(|tier| *tier > Tier::Free) {
+        warnings.push(format!("Too large for the free tier; needs the {} tier", tier));
+    }
+
+    SizeReport {
+        bytes,
+        compressed_bytes,
+        tier,
+        limit_used,
+        warnings,
+    }
+}
+
+impl Client {
+    /// Check payloads against `tier`'s limit before sending them
+    ///
+    /// Without this the tier is learned from the first store or patch response.
+    pub fn with_tier(mut self, tier: Tier) -> Self {
+        self.tier = Arc::new(Mutex::new(Some(tier)));
+        self
+    }
+
+    /// Turn the local payload size check on or off; on by default
+    pub fn with_size_check(mut self, enabled: bool) -> Self {
+        self.size_check = enabled;
+        self
+    }
+
+    /// Tier whose limit local size checks use, if known
+    pub fn tier(&self) -> Option<Tier> {
+        *self.tier.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Remember the tier named in a response
+    pub(crate) fn learn_tier(&self, name: &str) {
+        if let Ok(tier) = name.parse() {
+            *self.tier.lock().unwrap_or_else(|e| e.into_inner()) = Some(tier);
+        }
+    }
+
+    /// Fail with [`Error::PayloadTooLarge`] if `data` will not fit the known tier
+    pub(crate) fn check_payload_size(&self, data: &Value) -> Result<(), Error> {
+        let Some(tier) = self.tier().filter(|_| self.size_check) else {
+            return Ok(());
+        };
+        let limit = tier.max_payload_bytes();
+        // Anything that fits uncompressed fits; only estimate larger payloads
+        let mut counter = ByteCounter(0);
+        serde_json::to_writer(&mut counter, data)?;
+        if counter.0 <= limit {
+            return Ok(());
+        }
+
+        let report = estimate(data);
+        if report.compressed_bytes <= limit {
+            return Ok(());
+        }
+        let upgrade = match report.tier {
+            Some(fits) if fits > tier => format!(", or move to the {} tier", fits),
+            _ => String::new(),
+        };
+        Err(Error::PayloadTooLarge {
+            size: Some(report.compressed_bytes),
+            limit: Some(limit),
+            message: format!(
+                "Payload is about {} bytes compressed, over the {} tier's {} byte limit; \
+                 trim it, compress large fields or split it with ShardedStore{}",
+                report.compressed_bytes, tier, limit, upgrade
+            ),
+        })
+    }
+}
+
+/// Writer that only counts bytes
+struct ByteCounter(u64);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Estimated deflate output size: greedy LZ77 matching, then the order-0
+/// entropy of the literal/length and distance alphabets plus their extra bits
+fn compressed_size(data: &[u8]) -> u64 {
+    let mut heads = vec![usize::MAX; 1 << 15];
+    let mut chain = vec![usize::MAX; data.len()];
+    let hash = |i: usize| {
+        let h = (data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize;
+        h & ((1 << 15) - 1)
+    };
+
+    let mut literal_lengths = [0u64; 256 + LENGTH_BASES.len()];
+    let mut distances = [0u64; DISTANCE_BASES.len()];
+    let mut extra_bits = 0u64;
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(i);
+            let mut candidate = heads[h];
+            let mut probes = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && probes < MAX_PROBES {
+                let max = MAX_MATCH.min(data.len() - i);
+                let len = (0..max).take_while(|&k| data[candidate + k] == data[i + k]).count();
+                if len > best.0 {
+                    best = (len, i - candidate);
+                }
+                candidate = chain[candidate];
+                probes += 1;
+            }
+            chain[i] = heads[h];
+            heads[h] = i;
+        }
+
+        let (len, distance) = best;
+        if len >= MIN_MATCH {
+            let (code, extra) = deflate_code(len, &LENGTH_BASES, &LENGTH_EXTRA);
+            literal_lengths[256 + code] += 1;
+            let (code, distance_extra) = deflate_code(distance, &DISTANCE_BASES, &DISTANCE_EXTRA);
+            distances[code] += 1;
+            extra_bits += u64::from(extra + distance_extra);
+
+            // Index the skipped positions so later matches can find them
+            let end = (i + len).min(data.len().saturating_sub(MIN_MATCH - 1));
+            (i + 1..end).for_each(|j| {
+                let h = hash(j);
+                chain[j] = heads[h];
+                heads[h] = j;
+            });
+            i += len;
+        } else {
+            literal_lengths[data[i] as usize] += 1;
+            i += 1;
+        }
+    }
+
+    let bits = entropy_bits(&literal_lengths) + entropy_bits(&distances) + extra_bits as f64;
+    (bits / 8.0).ceil() as u64 + FRAMING_BYTES
+}
+
+/// Deflate code index and extra bits for a match length or distance
+fn deflate_code(value: usize, bases: &[u16], extra: &[u8]) -> (usize, u8) {
+    let code = bases.iter().rposition(|&base| usize::from(base) <= value).unwrap_or(0);
+    (code, extra[code])
+}
+
+/// Bits to code the symbols with an ideal prefix code, plus a rough table cost
+fn entropy_bits(counts: &[u64]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    let used = counts.iter().filter(|&&count| count > 0).count();
+    let symbols: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| -(count as f64) * (count as f64 / total as f64).log2())
+        .sum();
+    symbols + used as f64 * TABLE_BITS_PER_SYMBOL
+}
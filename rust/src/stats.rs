@@ -0,0 +1,97 @@
+//! Per-endpoint traffic accounting
+//!
+//! Every request made through a [`Client`] (and its clones) is counted with
+//! its request and response body sizes, so metered deployments can see how
+//! bandwidth splits between storage, history and batch traffic. Header bytes
+//! and TLS overhead are not included.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Endpoint, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! client.retrieve().await?;
+//!
+//! let stats = client.stats();
+//! let retrieve = stats.endpoint(Endpoint::Retrieve);
+//! println!("{} requests, {} bytes in", retrieve.requests, retrieve.bytes_received);
+//! println!("{} bytes total", stats.total().bytes_received);
+//! client.reset_stats();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Endpoint};
+use std::collections::BTreeMap;
+
+/// Counters for one endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EndpointStats {
+    /// Requests attempted, including ones that failed
+    pub requests: u64,
+    /// Request body bytes
+    pub bytes_sent: u64,
+    /// Response body bytes
+    pub bytes_received: u64,
+}
+
+impl EndpointStats {
+    fn add(&mut self, other: &EndpointStats) {
+        self.requests += other.requests;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+    }
+}
+
+/// Snapshot of a client's traffic since creation or the last reset
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    endpoints: BTreeMap<Endpoint, EndpointStats>,
+}
+
+impl TrafficStats {
+    /// Counters for one endpoint, zero if it was never called
+    pub fn endpoint(&self, endpoint: Endpoint) -> EndpointStats {
+        self.endpoints.get(&endpoint).copied().unwrap_or_default()
+    }
+
+    /// Endpoints that were called, with their counters
+    pub fn endpoints(&self) -> impl Iterator<Item = (Endpoint, EndpointStats)> + '_ {
+        self.endpoints.iter().map(|(endpoint, stats)| (*endpoint, *stats))
+    }
+
+    /// Counters summed over all endpoints
+    pub fn total(&self) -> EndpointStats {
+        let mut total = EndpointStats::default();
+        for stats in self.endpoints.values() {
+            total.add(stats);
+        }
+        total
+    }
+
+    pub(crate) fn record_request(&mut self, endpoint: Endpoint, bytes_sent: usize) {
+        let stats = self.endpoints.entry(endpoint).or_default();
+        stats.requests += 1;
+        stats.bytes_sent += bytes_sent as u64;
+    }
+
+    pub(crate) fn record_response(&mut self, endpoint: Endpoint, bytes_received: usize) {
+        self.endpoints.entry(endpoint).or_default().bytes_received += bytes_received as u64;
+    }
+}
+
+impl Client {
+    /// Traffic counted so far by this client and its clones
+    pub fn stats(&self) -> TrafficStats {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Zero all traffic counters
+    pub fn reset_stats(&self) {
+        self.record_stats(|stats| *stats = TrafficStats::default());
+    }
+
+    pub(crate) fn record_stats(&self, update: impl FnOnce(&mut TrafficStats)) {
+        update(&mut self.stats.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+}
@@ -0,0 +1,126 @@
+//! Detecting response fields the SDK does not know about
+//!
+//! Responses are decoded leniently by default: fields the server adds are
+//! skipped silently, which keeps old SDK versions working but also hides
+//! contract drift. With [`Strictness::Report`] every skipped field is logged
+//! the first time it is seen and counted in [`Client::unknown_fields`];
+//! [`Strictness::Deny`] additionally fails the call with [`Error::Decode`],
+//! like `#[serde(deny_unknown_fields)]`. Run staging or CI in one of these
+//! modes to hear about new fields before production does.
+//!
+//! Only fields of the SDK's response types are checked; the stored value
+//! itself is free-form.
+//!
+//! ```no_run
+//! use keyvalue_client::strict::Strictness;
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word").with_strictness(Strictness::Report);
+//! client.retrieve().await?;
+//! for field in client.unknown_fields().fields() {
+//!     println!("{:?} returned unknown field {} ({} times)", field.endpoint, field.path, field.count);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Error::Decode`]: crate::Error::Decode
+
+use crate::{Client, Endpoint};
+use std::collections::BTreeMap;
+
+/// How to treat response fields the SDK does not know about
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Skip them silently
+    #[default]
+    Lenient,
+    /// Skip them, but log and count them
+    Report,
+    /// Count them and fail the call
+    Deny,
+}
+
+/// One unknown field seen in responses from one endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownField {
+    pub endpoint: Endpoint,
+    /// Dotted path of the field in the response, e.g. `meta.region`
+    pub path: String,
+    /// Responses the field appeared in
+    pub count: u64,
+}
+
+/// Unknown fields seen since the client was created or the report was cleared
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnknownFields {
+    fields: BTreeMap<(Endpoint, String), u64>,
+}
+
+impl UnknownFields {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Fields ordered by endpoint, then path
+    pub fn fields(&self) -> impl Iterator<Item = UnknownField> + '_ {
+        self.fields.iter().map(|((endpoint, path), count)| UnknownField {
+            endpoint: *endpoint,
+            path: path.clone(),
+            count: *count,
+        })
+    }
+
+    /// Count `path` for `endpoint`; returns whether it is new
+    fn record(&mut self, endpoint: Endpoint, path: String) -> bool {
+        let count = self.fields.entry((endpoint, path)).or_default();
+        *count += 1;
+        *count == 1
+    }
+}
+
+impl Client {
+    /// Check responses for fields the SDK does not know about
+    pub fn with_strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+
+    /// Unknown fields seen so far by this client and its clones
+    pub fn unknown_fields(&self) -> UnknownFields {
+        self.unknown_fields.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Forget the unknown fields seen so far
+    pub fn clear_unknown_fields(&self) {
+        *self.unknown_fields.lock().unwrap_or_else(|e| e.into_inner()) = UnknownFields::default();
+    }
+
+    /// Count the fields skipped while decoding a response from `endpoint`
+    pub(crate) fn record_unknown_fields(&self, endpoint: Endpoint, paths: Vec<String>) {
+        let mut fields = self.unknown_fields.lock().unwrap_or_else(|e| e.into_inner());
+        for path in paths {
+            if fields.record(endpoint, path.clone()) {
+                tracing::warn!(?endpoint, field = %path, "response has a field the SDK does not know");
+            }
+        }
+    }
+}
+
+/// Render a skipped field's path like `events[3].extra`, or `events[].extra` without indices
+pub(crate) fn field_path(path: &serde_ignored::Path<'_>, indices: bool) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } if indices => format!("{}[{}]", field_path(parent, indices), index),
+        Path::Seq { parent, .. } => format!("{}[]", field_path(parent, indices)),
+        Path::Map { parent, key } => match field_path(parent, indices) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        Path::Some { parent } | Path::NewtypeStruct { parent } | Path::NewtypeVariant { parent } => {
+            field_path(parent, indices)
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Following new history events, like `tail -f`
+//!
+//! [`Client::history_tail`] polls the history endpoint and yields each event
+//! newer than the last one seen, oldest first, paging back when more events
+//! arrived between polls than fit in one page. Polls that find nothing back
+//! off up to [`TailOptions::max_interval`]; new events reset the interval.
+//! Errors are yielded without ending the stream, and no event is skipped
+//! because of them.
+//!
+//! Without [`TailOptions::after`] the stream starts with events written after
+//! the first poll. To resume after a restart, store the `seq` of the last
+//! event handled and pass it as `after`.
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use keyvalue_client::tail::TailOptions;
+//! use keyvalue_client::{Client, Error};
+//! use std::pin::pin;
+//!
+//! # async fn run(last_handled: Option<i32>) -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let options = TailOptions {
+//!     after: last_handled,
+//!     ..TailOptions::default()
+//! };
+//! let mut events = pin!(client.history_tail(options));
+//! while let Some(event) = events.next().await {
+//!     let event = event?;
+//!     println!("#{} {}", event.seq, event.payload);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{ClassifiedType, Client, Error, HistoryEvent, HistoryOptions};
+use futures_util::stream::{self, Stream};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Where and how fast [`Client::history_tail`] polls
+#[derive(Debug, Clone)]
+pub struct TailOptions {
+    /// Start after the event with this `seq`; `None` for events after the first poll
+    pub after: Option<i32>,
+    /// Delay between polls while events keep arriving
+    pub interval: Duration,
+    /// Longest delay between polls when nothing arrives or polls fail
+    pub max_interval: Duration,
+    /// Events fetched per history request
+    pub page_size: i32,
+    /// Only follow events of this type, as [`HistoryOptions::type_filter`]
+    pub type_filter: Option<ClassifiedType>,
+}
+
+impl Default for TailOptions {
+    fn default() -> Self {
+        Self {
+            after: None,
+            interval: Duration::from_secs(2),
+            max_interval: Duration::from_secs(30),
+            page_size: 100,
+            type_filter: None,
+        }
+    }
+}
+
+struct Tail {
+    after: Option<i32>,
+    ready: VecDeque<HistoryEvent>,
+    delay: Duration,
+    first: bool,
+}
+
+impl Client {
+    /// Stream history events as they are written, oldest first
+    pub fn history_tail(&self, options: TailOptions) -> impl Stream<Item = Result<HistoryEvent, Error>> + '_ {
+        let tail = Tail {
+            after: options.after,
+            ready: VecDeque::new(),
+            delay: options.interval,
+            first: true,
+        };
+        stream::unfold((tail, options), move |(mut tail, options)| async move {
+            loop {
+                if let Some(event) = tail.ready.pop_front() {
+                    return Some((Ok(event), (tail, options)));
+                }
+                if !tail.first {
+                    tokio::time::sleep(tail.delay).await;
+                }
+                tail.first = false;
+
+                let polled = match tail.after {
+                    Some(after) => self.events_after(after, &options).await,
+                    None => self.latest_seq(&options).await.map(|latest| {
+                        tail.after = Some(latest);
+                        vec![]
+                    }),
+                };
+                match polled {
+                    Ok(events) if events.is_empty() => {
+                        tail.delay = (tail.delay * 2).min(options.max_interval);
+                    }
+                    Ok(events) => {
+                        tail.delay = options.interval;
+                        tail.after = events.last().map(|event| event.seq);
+                        tail.ready.extend(events);
+                    }
+                    Err(e) => {
+                        tail.delay = (tail.delay * 2).min(options.max_interval);
+                        return Some((Err(e), (tail, options)));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Events newer than `after`, oldest first
+    async fn events_after(&self, after: i32, options: &TailOptions) -> Result<Vec<HistoryEvent>, Error> {
+        let mut query = HistoryOptions {
+            limit: Some(options.page_size.max(1)),
+            type_filter: options.type_filter.clone(),
+            ..HistoryOptions::default()
+        };
+        let mut events = vec![];
+        loop {
+            let page = self.history(&query).await?;
+            let mut reached = !page.pagination.has_more || page.events.is_empty();
+            for event in page.events {
+                if event.seq <= after {
+                    reached = true;
+                    break;
+                }
+                query.before = Some(event.seq);
+                events.push(event);
+            }
+            if reached {
+                break;
+            }
+        }
+        events.reverse();
+        Ok(events)
+    }
+
+    /// `seq` of the newest event, or 0 if there is none
+    async fn latest_seq(&self, options: &TailOptions) -> Result<i32, Error> {
+        let query = HistoryOptions {
+            limit: Some(1),
+            type_filter: options.type_filter.clone(),
+            ..HistoryOptions::default()
+        };
+        let page = self.history(&query).await?;
+        Ok(page.events.first().map_or(0, |event| event.seq))
+    }
+}
@@ -0,0 +1,239 @@
+//! Threshold-crossing detection for noisy numeric series
+//!
+//! Each [`Threshold`] has a dead band of width `hysteresis` centred on its
+//! level: the series is above once it exceeds the top of the band and below
+//! once it falls under the bottom, so jitter around the level does not flap.
+//! With a minimum duration, a new state must also hold that long before it is
+//! reported. The first sample only establishes the initial state.
+//!
+//! ```
+//! use chrono::{Duration, TimeZone, Utc};
+//! use keyvalue_client::threshold::{Direction, Threshold, ThresholdDetector};
+//! use keyvalue_client::timeseries::Sample;
+//!
+//! let start = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+//! let values = [20.0, 24.9, 25.1, 24.8, 26.0, 26.5, 23.0];
+//! let samples: Vec<_> = values
+//!     .iter()
+//!     .enumerate()
+//!     .map(|(i, &value)| Sample { at: start + Duration::minutes(i as i64), value })
+//!     .collect();
+//!
+//! let mut detector = ThresholdDetector::new(vec![Threshold::new(25.0).hysteresis(1.0)]);
+//! let events = detector.scan(&samples);
+//! assert_eq!(events.len(), 2);
+//! assert_eq!(events[0].direction, Direction::Above);
+//! assert_eq!(events[0].value, 26.0);
+//! assert_eq!(events[1].direction, Direction::Below);
+//! ```
+
+use crate::timeseries::Sample;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream, StreamExt};
+use std::time::Duration;
+
+/// Side of a threshold the series is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Above,
+    Below,
+}
+
+/// Level to watch, with hysteresis and a minimum duration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    pub level: f64,
+    /// Width of the dead band centred on `level`
+    pub hysteresis: f64,
+    /// How long a new state must hold before it is reported
+    pub min_duration: Duration,
+}
+
+impl Threshold {
+    pub fn new(level: f64) -> Self {
+        Self {
+            level,
+            hysteresis: 0.0,
+            min_duration: Duration::ZERO,
+        }
+    }
+
+    pub fn hysteresis(mut self, hysteresis: f64) -> Self {
+        self.hysteresis = hysteresis.abs();
+        self
+    }
+
+    pub fn min_duration(mut self, min_duration: Duration) -> Self {
+        self.min_duration = min_duration;
+        self
+    }
+
+    fn classify(&self, value: f64) -> Option<Direction> {
+        let half = self.hysteresis / 2.0;
+        if value > self.level + half {
+            Some(Direction::Above)
+        } else if value < self.level - half {
+            Some(Direction::Below)
+        } else {
+            None
+        }
+    }
+}
+
+/// Confirmed crossing of a threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdEvent {
+    pub threshold: Threshold,
+    /// Side of the threshold the series moved to
+    pub direction: Direction,
+    /// Time of the first sample past the dead band
+    pub at: DateTime<Utc>,
+    /// Time the crossing was confirmed, `at` plus at least `min_duration`
+    pub confirmed_at: DateTime<Utc>,
+    /// Value of the confirming sample
+    pub value: f64,
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    threshold: Threshold,
+    current: Option<Direction>,
+    pending: Option<(Direction, DateTime<Utc>)>,
+}
+
+/// Stateful detector over a time-ordered series
+#[derive(Debug, Clone)]
+pub struct ThresholdDetector {
+    states: Vec<State>,
+}
+
+impl ThresholdDetector {
+    pub fn new(thresholds: impl IntoIterator<Item = Threshold>) -> Self {
+        Self {
+            states: thresholds
+                .into_iter()
+                .map(|threshold| State {
+                    threshold,
+                    current: None,
+                    pending: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Feed the next sample, returning any crossings it confirms
+    pub fn observe(&mut self, sample: Sample) -> Vec<ThresholdEvent> {
+        let mut events = vec![];
+        for state in &mut self.states {
+            let Some(side) = state.threshold.classify(sample.value) else {
+                // Inside the dead band: neither confirms nor cancels a pending change
+                continue;
+            };
+            let Some(current) = state.current else {
+                state.current = Some(side);
+                continue;
+            };
+            if side == current {
+                state.pending = None;
+                continue;
+            }
+
+            let since = match state.pending {
+                Some((direction, since)) if direction == side => since,
+                _ => sample.at,
+            };
+            let held = (sample.at - since).to_std().unwrap_or_default();
+            if held >= state.threshold.min_duration {
+                state.current = Some(side);
+                state.pending = None;
+                events.push(ThresholdEvent {
+                    threshold: state.threshold,
+                    direction: side,
+                    at: since,
+                    confirmed_at: sample.at,
+                    value: sample.value,
+                });
+            } else {
+                state.pending = Some((side, since));
+            }
+        }
+        events
+    }
+
+    /// Feed a slice of samples in order, returning all confirmed crossings
+    pub fn scan(&mut self, samples: &[Sample]) -> Vec<ThresholdEvent> {
+        samples.iter().flat_map(|sample| self.observe(*sample)).collect()
+    }
+}
+
+/// Adapt a stream of samples into a stream of confirmed crossings
+pub fn crossings<S>(
+    samples: S,
+    mut detector: ThresholdDetector,
+) -> impl Stream<Item = ThresholdEvent>
+where
+    S: Stream<Item = Sample>,
+{
+    samples.flat_map(move |sample| stream::iter(detector.observe(sample)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn minutes(values: &[f64]) -> Vec<Sample> {
+        let start = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| Sample { at: start + chrono::Duration::minutes(i as i64), value })
+            .collect()
+    }
+
+    fn directions(events: &[ThresholdEvent]) -> Vec<Direction> {
+        events.iter().map(|event| event.direction).collect()
+    }
+
+    #[test]
+    fn jitter_inside_the_dead_band_does_not_flap() {
+        let mut detector = ThresholdDetector::new([Threshold::new(10.0).hysteresis(-2.0)]);
+        let events = detector.scan(&minutes(&[8.0, 10.5, 9.5, 10.9, 11.5, 10.2, 9.1, 8.5]));
+        assert_eq!(directions(&events), [Direction::Above, Direction::Below]);
+        assert_eq!((events[0].value, events[1].value), (11.5, 8.5));
+    }
+
+    #[test]
+    fn first_sample_only_sets_the_initial_state() {
+        let mut detector = ThresholdDetector::new([Threshold::new(0.0)]);
+        assert!(detector.scan(&minutes(&[5.0, 6.0])).is_empty());
+        assert_eq!(directions(&detector.scan(&minutes(&[-1.0]))), [Direction::Below]);
+    }
+
+    #[test]
+    fn min_duration_confirms_only_states_that_hold() {
+        let threshold = Threshold::new(0.0).min_duration(Duration::from_secs(120));
+        let mut detector = ThresholdDetector::new([threshold]);
+        // A one-minute excursion is dropped; the second one holds for two minutes
+        let samples = minutes(&[-1.0, 1.0, -1.0, 1.0, 1.0, 1.0]);
+        let events = detector.scan(&samples);
+        assert_eq!(directions(&events), [Direction::Above]);
+        assert_eq!((events[0].at, events[0].confirmed_at), (samples[3].at, samples[5].at));
+    }
+
+    #[test]
+    fn thresholds_are_tracked_independently() {
+        let mut detector = ThresholdDetector::new([Threshold::new(10.0), Threshold::new(20.0)]);
+        let events = detector.scan(&minutes(&[0.0, 15.0, 25.0]));
+        let levels: Vec<f64> = events.iter().map(|event| event.threshold.level).collect();
+        assert_eq!(levels, [10.0, 20.0]);
+    }
+
+    #[tokio::test]
+    async fn crossings_adapts_a_stream() {
+        let detector = ThresholdDetector::new([Threshold::new(1.0)]);
+        let samples = stream::iter(minutes(&[0.0, 2.0, 0.0]));
+        let events: Vec<ThresholdEvent> = crossings(samples, detector).collect().await;
+        assert_eq!(directions(&events), [Direction::Above, Direction::Below]);
+    }
+}
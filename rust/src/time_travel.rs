@@ -0,0 +1,114 @@
+//! Reading the value as it was at a past moment
+//!
+//! [`Client::retrieve_at`] walks history from the newest event back to the
+//! last one written at or before the requested time and returns its payload
+//! with the event's sequence number and timestamp, answering questions like
+//! "what did the config say when the incident started?". Only moments still
+//! covered by retained history can be answered. [`Client::rollback`] goes
+//! one step further and stores the payload of a past event as the current
+//! value.
+//!
+//! ```no_run
+//! use chrono::{TimeZone, Utc};
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let incident = Utc.with_ymd_and_hms(2024, 3, 1, 14, 5, 0).unwrap();
+//! let then = client.retrieve_at(incident).await?;
+//! println!("Event {} from {}: {}", then.seq, then.created_at, then.data);
+//!
+//! let restored = client.rollback(then.seq).await?;
+//! println!("Rolled back to event {} as version {}", then.seq, restored.version);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error, HistoryEvent, HistoryOptions, StoreResponse};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use serde_json::Value;
+
+/// History events fetched per page while searching
+const PAGE_SIZE: i32 = 100;
+
+/// Value as of a past moment, with the history event it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueAt {
+    pub data: Value,
+    /// Sequence number of the history event
+    pub seq: i32,
+    /// When the value was written
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<HistoryEvent> for ValueAt {
+    fn from(event: HistoryEvent) -> Self {
+        Self {
+            data: event.payload,
+            seq: event.seq,
+            created_at: event.created_at,
+            expires_at: event.expires_at,
+        }
+    }
+}
+
+impl Client {
+    /// Value as of `at`, from the latest history event written at or before it
+    ///
+    /// Fails with [`Error::NotFound`] if no retained event is that old, or if
+    /// the value had already expired by `at`.
+    pub async fn retrieve_at(&self, at: DateTime<Utc>) -> Result<ValueAt, Error> {
+        let options = HistoryOptions {
+            limit: Some(PAGE_SIZE),
+            ..Default::default()
+        };
+        let mut pages = std::pin::pin!(self.history_pages(&options));
+        while let Some(page) = pages.try_next().await? {
+            // Pages run newest first, so the first old enough event is the answer
+            let Some(event) = page.events.into_iter().find(|event| event.created_at <= at) else {
+                continue;
+            };
+            if event.expires_at.is_some_and(|expires_at| expires_at <= at) {
+                return Err(Error::NotFound {
+                    message: format!("Value written at {} had expired by {}", event.created_at, at),
+                });
+            }
+            return Ok(event.into());
+        }
+        Err(Error::NotFound {
+            message: format!("No history event at or before {}", at),
+        })
+    }
+
+    /// Store the payload of history event `seq` as the current value
+    ///
+    /// The value is stored without a TTL, as a new version; history keeps
+    /// every version written since. Fails with [`Error::NotFound`] if event
+    /// `seq` is not retained.
+    pub async fn rollback(&self, seq: i32) -> Result<StoreResponse, Error> {
+        let event = self.history_event(seq).await?;
+        self.store(&event.data, None).await
+    }
+
+    /// History event `seq`, if still retained
+    pub(crate) async fn history_event(&self, seq: i32) -> Result<ValueAt, Error> {
+        let not_found = || Error::NotFound {
+            message: format!("No history event with seq {}", seq),
+        };
+        let options = HistoryOptions {
+            limit: Some(1),
+            before: Some(seq.checked_add(1).ok_or_else(not_found)?),
+            ..Default::default()
+        };
+        let event = self
+            .history(&options)
+            .await?
+            .events
+            .into_iter()
+            .find(|event| event.seq == seq)
+            .ok_or_else(not_found)?;
+        Ok(event.into())
+    }
+}
@@ -0,0 +1,150 @@
+//! Time-weighted statistics over irregularly sampled numeric history
+//!
+//! A plain mean over history over-weights bursts of closely spaced samples.
+//! These helpers weight each sample by the time it covers instead.
+//!
+//! ```
+//! use chrono::{TimeZone, Utc};
+//! use keyvalue_client::timeseries::{integrate, Interpolation, Sample};
+//!
+//! // Power in watts: 1000 W for one hour, then 0 W
+//! let samples = vec![
+//!     Sample { at: Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(), value: 1000.0 },
+//!     Sample { at: Utc.with_ymd_and_hms(2025, 1, 1, 1, 0, 0).unwrap(), value: 0.0 },
+//! ];
+//! let watt_seconds = integrate(&samples, Interpolation::Previous);
+//! assert_eq!(watt_seconds / 3_600_000.0, 1.0); // 1 kWh
+//! ```
+
+use crate::HistoryEvent;
+use chrono::{DateTime, Utc};
+
+/// Numeric value observed at a point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub at: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// How values are assumed to evolve between samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Straight line between consecutive samples (trapezoidal rule)
+    #[default]
+    Linear,
+    /// Each value holds until the next sample (step function)
+    Previous,
+}
+
+/// Numeric samples from history events with a `numeric_value`, oldest first
+pub fn samples_from_events(events: &[HistoryEvent]) -> Vec<Sample> {
+    let mut samples: Vec<_> = events
+        .iter()
+        .filter_map(|event| {
+            event.numeric_value.map(|value| Sample {
+                at: event.created_at,
+                value,
+            })
+        })
+        .collect();
+    samples.sort_by_key(|sample| sample.at);
+    samples
+}
+
+/// Integral of the samples over time, in value × seconds
+///
+/// Samples need not be sorted. Returns 0 for fewer than two samples.
+pub fn integrate(samples: &[Sample], interpolation: Interpolation) -> f64 {
+    segments(samples)
+        .map(|(a, b, dt)| match interpolation {
+            Interpolation::Linear => (a.value + b.value) / 2.0 * dt,
+            Interpolation::Previous => a.value * dt,
+        })
+        .sum()
+}
+
+/// Time-weighted average of the samples between the first and last sample
+///
+/// Returns `None` for no samples, and the value itself when all samples share
+/// one timestamp.
+pub fn time_weighted_average(samples: &[Sample], interpolation: Interpolation) -> Option<f64> {
+    let first = samples.iter().min_by_key(|s| s.at)?;
+    let last = samples.iter().max_by_key(|s| s.at)?;
+    let span = seconds_between(first.at, last.at);
+    if span == 0.0 {
+        return Some(samples.iter().map(|s| s.value).sum::<f64>() / samples.len() as f64);
+    }
+    Some(integrate(samples, interpolation) / span)
+}
+
+/// Consecutive sample pairs in time order with the seconds between them
+fn segments(samples: &[Sample]) -> impl Iterator<Item = (Sample, Sample, f64)> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by_key(|sample| sample.at);
+    let pairs: Vec<_> = sorted
+        .windows(2)
+        .map(|w| (w[0], w[1], seconds_between(w[0].at, w[1].at)))
+        .collect();
+    pairs.into_iter()
+}
+
+pub(crate) fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    fn samples(points: &[(i64, f64)]) -> Vec<Sample> {
+        points.iter().map(|&(seconds, value)| Sample { at: at(seconds), value }).collect()
+    }
+
+    #[test]
+    fn integrate_by_interpolation_in_any_order() {
+        let ramp = samples(&[(10, 4.0), (0, 0.0), (20, 4.0)]);
+        assert_eq!(integrate(&ramp, Interpolation::Linear), 20.0 + 40.0);
+        assert_eq!(integrate(&ramp, Interpolation::Previous), 0.0 + 40.0);
+        assert_eq!(integrate(&ramp[..1], Interpolation::Linear), 0.0);
+        assert_eq!(integrate(&[], Interpolation::Previous), 0.0);
+    }
+
+    #[test]
+    fn average_weights_samples_by_the_time_they_cover() {
+        // A burst of high readings within one second barely moves the average
+        let burst = samples(&[(0, 0.0), (99, 0.0), (99, 100.0), (100, 0.0)]);
+        let average = time_weighted_average(&burst, Interpolation::Previous).unwrap();
+        assert!((average - 1.0).abs() < 1e-9);
+
+        assert_eq!(time_weighted_average(&samples(&[(5, 2.0), (5, 4.0)]), Interpolation::Linear), Some(3.0));
+        assert_eq!(time_weighted_average(&[], Interpolation::Linear), None);
+    }
+
+    #[test]
+    fn samples_come_from_numeric_events_oldest_first() {
+        let event = |seq: i32, seconds: i64, value: Option<f64>| HistoryEvent {
+            seq,
+            created_at: at(seconds),
+            expires_at: None,
+            classified_type: None,
+            numeric_value: value,
+            text_value: None,
+            confidence: None,
+            payload: serde_json::Value::Null,
+        };
+        let events = [event(3, 20, Some(2.0)), event(2, 10, None), event(1, 0, Some(1.0))];
+        assert_eq!(samples_from_events(&events), samples(&[(0, 1.0), (20, 2.0)]));
+    }
+
+    #[test]
+    fn seconds_between_keeps_milliseconds() {
+        let later = at(0) + chrono::Duration::milliseconds(1500);
+        assert_eq!(seconds_between(at(0), later), 1.5);
+        assert_eq!(seconds_between(later, at(0)), -1.5);
+    }
+}
@@ -0,0 +1,111 @@
+//! Tokens kept in the operating system's credential store
+//!
+//! Desktop apps that remember a user's Key-Value token should not write it
+//! to a plaintext config file. [`TokenStore::system`] saves it instead in
+//! the macOS Keychain, the Windows Credential Manager or, on Linux and the
+//! BSDs, the Secret Service (GNOME Keyring, KWallet). Each token is an entry
+//! under a service name, `keyvalue` unless set with
+//! [`TokenStore::with_service`], and an account name chosen by the app.
+//!
+//! ```no_run
+//! use keyvalue_client::token_store::TokenStore;
+//! use keyvalue_client::Error;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let store = TokenStore::system().with_service("com.example.notes");
+//! if store.load("default")?.is_none() {
+//!     store.save("default", "word-word-word-word-word")?;
+//! }
+//!
+//! let client = store.client("default")?;
+//! client.retrieve().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{mask_token, Client, ClientBuilder, Error};
+use keyring::Entry;
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// Service name entries are saved under by default
+pub const DEFAULT_SERVICE: &str = "keyvalue";
+
+/// Tokens saved in the OS credential store, one per account name
+#[derive(Clone)]
+pub struct TokenStore {
+    service: String,
+    builder: ClientBuilder,
+}
+
+impl fmt::Debug for TokenStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenStore")
+            .field("service", &self.service)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TokenStore {
+    /// Store backed by the platform's credential store
+    pub fn system() -> Self {
+        Self {
+            service: DEFAULT_SERVICE.to_string(),
+            builder: Client::builder(),
+        }
+    }
+
+    /// Save entries under `service`, e.g. the app's bundle id, instead of `keyvalue`
+    pub fn with_service(mut self, service: impl Into<String>) -> Self {
+        self.service = service.into();
+        self
+    }
+
+    /// Build clients from `builder`, e.g. for a self-hosted base URL
+    pub fn with_client_builder(mut self, builder: ClientBuilder) -> Self {
+        self.builder = builder;
+        self
+    }
+
+    /// Save `token` for `account`, replacing any previous one
+    pub fn save(&self, account: &str, token: &str) -> Result<(), Error> {
+        self.entry(account)?
+            .set_password(token)
+            .map_err(|e| store_error(account, e))
+    }
+
+    /// Token saved for `account`, if any
+    pub fn load(&self, account: &str) -> Result<Option<Zeroizing<String>>, Error> {
+        match self.entry(account)?.get_password() {
+            Ok(token) => Ok(Some(Zeroizing::new(token))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(store_error(account, e)),
+        }
+    }
+
+    /// Delete the token saved for `account`; returns whether there was one
+    pub fn delete(&self, account: &str) -> Result<bool, Error> {
+        match self.entry(account)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(store_error(account, e)),
+        }
+    }
+
+    /// Client for the token saved for `account`
+    pub fn client(&self, account: &str) -> Result<Client, Error> {
+        let token = self
+            .load(account)?
+            .ok_or_else(|| Error::TokenStore(format!("No token saved for account {:?}", account)))?;
+        tracing::debug!(account, token = %mask_token(&token), "building client from credential store");
+        Ok(self.builder.clone().token(token.as_str()).build()?)
+    }
+
+    fn entry(&self, account: &str) -> Result<Entry, Error> {
+        Entry::new(&self.service, account).map_err(|e| store_error(account, e))
+    }
+}
+
+fn store_error(account: &str, e: keyring::Error) -> Error {
+    Error::TokenStore(format!("Account {:?}: {}", account, e))
+}
@@ -0,0 +1,207 @@
+//! Human-friendly TTLs
+//!
+//! [`Ttl`] parses strings like `"90s"`, `"2h"`, `"7d"` or `"1h30m"` into the
+//! whole seconds the API expects. A bare number is taken as seconds. It
+//! deserializes from either form, so config files can use the same syntax.
+//!
+//! ```
+//! use keyvalue_client::Ttl;
+//!
+//! # fn main() -> Result<(), keyvalue_client::Error> {
+//! assert_eq!(Ttl::parse("1h30m")?.seconds(), 5400);
+//! assert_eq!(Ttl::parse("7d")?.to_string(), "7d");
+//! assert!(Ttl::parse("0s").is_err());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+const UNITS: &[(char, i64)] = &[('w', 604_800), ('d', 86_400), ('h', 3_600), ('m', 60), ('s', 1)];
+
+/// Positive time-to-live in whole seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ttl(i32);
+
+impl Ttl {
+    /// Parse a TTL such as `"90s"`, `"15m"`, `"2h"`, `"7d"`, `"1w"`, `"1h30m"` or `"3600"`
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let invalid =
+            |reason: &str| Error::Validation(format!("Invalid TTL {:?}: {}", text, reason));
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            return Err(invalid("empty"));
+        }
+        if let Ok(seconds) = trimmed.parse::<i64>() {
+            return Self::checked(seconds).ok_or_else(|| invalid("must be positive and fit in i32"))
+        }
+
+        let mut total: i64 = 0;
+        let mut number = String::new();
+        let mut last_unit = None;
+        for c in trimmed.chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            let position = UNITS
+                .iter()
+                .position(|(unit, _)| *unit == c.to_ascii_lowercase())
+                .ok_or_else(|| invalid(&format!("unknown unit {:?}", c)))?;
+            if number.is_empty() {
+                return Err(invalid("unit without a number"));
+            }
+            if last_unit.is_some_and(|last| position <= last) {
+                return Err(invalid("units must go from largest to smallest"));
+            }
+            let value: i64 = number.parse().map_err(|_| invalid("out of range"))?;
+            total = value
+                .checked_mul(UNITS[position].1)
+                .and_then(|seconds| total.checked_add(seconds))
+                .ok_or_else(|| invalid("out of range"))?;
+            number.clear();
+            last_unit = Some(position);
+        }
+        if !number.is_empty() {
+            return Err(invalid("number without a unit"));
+        }
+        Self::checked(total).ok_or_else(|| invalid("must be positive and fit in i32"))
+    }
+
+    /// TTL of `seconds`, which must be positive
+    pub fn from_secs(seconds: i32) -> Result<Self, Error> {
+        Self::checked(seconds.into())
+            .ok_or_else(|| Error::Validation(format!("TTL must be positive, got {}", seconds)))
+    }
+
+    /// Seconds, as passed to `store`, `patch` and batch operations
+    pub fn seconds(self) -> i32 {
+        self.0
+    }
+
+    pub fn as_duration(self) -> Duration {
+        Duration::from_secs(self.0 as u64)
+    }
+
+    /// Time left until `at`, at least one second
+    pub(crate) fn until(at: DateTime<Utc>) -> Self {
+        Self::checked((at - Utc::now()).num_seconds().max(1)).unwrap_or(Ttl(i32::MAX))
+    }
+
+    fn checked(seconds: i64) -> Option<Self> {
+        i32::try_from(seconds).ok().filter(|s| *s > 0).map(Ttl)
+    }
+}
+
+impl FromStr for Ttl {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        Self::parse(text)
+    }
+}
+
+impl From<Ttl> for i32 {
+    fn from(ttl: Ttl) -> Self {
+        ttl.0
+    }
+}
+
+/// Formats in days, hours, minutes and seconds, e.g. `1h30m`
+impl fmt::Display for Ttl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = i64::from(self.0);
+        for (unit, seconds) in &UNITS[1..] {
+            if rest >= *seconds {
+                write!(f, "{}{}", rest / seconds, unit)?;
+                rest %= seconds;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Ttl {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Ttl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Seconds(i64),
+            Text(String),
+        }
+        let ttl = match Raw::deserialize(deserializer)? {
+            Raw::Seconds(seconds) => Ttl::checked(seconds)
+                .ok_or_else(|| Error::Validation(format!("Invalid TTL: {}", seconds))),
+            Raw::Text(text) => Ttl::parse(&text),
+        };
+        ttl.map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(text: &str) -> i32 {
+        Ttl::parse(text).unwrap().seconds()
+    }
+
+    #[test]
+    fn parses_numbers_and_units() {
+        assert_eq!(seconds("3600"), 3600);
+        assert_eq!(seconds(" 90s "), 90);
+        assert_eq!(seconds("15m"), 900);
+        assert_eq!(seconds("2H"), 7200);
+        assert_eq!(seconds("7d"), 604_800);
+        assert_eq!(seconds("1w2d"), 777_600);
+        assert_eq!(seconds("1h30m"), 5400);
+        assert_eq!(seconds("1h0m5s"), 3605);
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        for text in ["", "  ", "h", "5x", "1h30", "30m1h", "1h1h", "1h 30m", "1.5h", "-5m"] {
+            assert!(Ttl::parse(text).is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn rejects_zero_negative_and_overflowing_ttls() {
+        for text in ["0", "0s", "0h0m", "-1", "2147483648", "99999999999999999999", "100000w"] {
+            assert!(matches!(Ttl::parse(text), Err(Error::Validation(_))), "{:?} parsed", text);
+        }
+        assert_eq!(seconds("2147483647"), i32::MAX);
+        assert!(Ttl::from_secs(0).is_err());
+    }
+
+    #[test]
+    fn displays_in_the_largest_units() {
+        assert_eq!(Ttl::parse("90").unwrap().to_string(), "1m30s");
+        assert_eq!(Ttl::parse("1w").unwrap().to_string(), "7d");
+        assert_eq!(Ttl::parse("1d1s").unwrap().to_string(), "1d1s");
+        for text in ["45s", "1h30m", "3d4h5m6s"] {
+            let ttl = Ttl::parse(text).unwrap();
+            assert_eq!(Ttl::parse(&ttl.to_string()).unwrap(), ttl);
+        }
+    }
+
+    #[test]
+    fn deserializes_from_numbers_or_text() {
+        assert_eq!(serde_json::from_str::<Ttl>("60").unwrap().seconds(), 60);
+        assert_eq!(serde_json::from_str::<Ttl>("\"2h\"").unwrap().seconds(), 7200);
+        assert!(serde_json::from_str::<Ttl>("0").is_err());
+        assert!(serde_json::from_str::<Ttl>("\"soon\"").is_err());
+        assert_eq!(serde_json::to_string(&Ttl::parse("5400").unwrap()).unwrap(), "\"1h30m\"");
+    }
+}
@@ -0,0 +1,291 @@
+//! Typed store/retrieve with optional schema fingerprints
+//!
+//! When fingerprints are enabled the stored document is wrapped in an envelope:
+//!
+//! ```json
+//! {"_schema": {"type": "SensorConfig", "hash": "9f2c..."}, "value": {...}}
+//! ```
+//!
+//! `retrieve_as` always unwraps the envelope and verifies the fingerprint
+//! against the requested type, whether or not fingerprints are enabled on the
+//! reading client.
+
+use crate::{
+    hex, ClassifiedType, Client, Error, HistoryEvent, HistoryOptions, HistoryPagination, HistoryResponse, ResponseMeta,
+    StoreResponse,
+};
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, TryStreamExt};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+const SCHEMA_FIELD: &str = "_schema";
+const VALUE_FIELD: &str = "value";
+
+/// Identifies the shape of a stored type: serde type name plus a hash of its field names
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaFingerprint {
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub hash: String,
+}
+
+impl SchemaFingerprint {
+    /// Compute the fingerprint of `T`
+    ///
+    /// Structs are identified by their serde name and declared field names, so
+    /// the fingerprint is stable across programs and field order. Other types
+    /// fall back to the Rust type name.
+    pub fn of<T: DeserializeOwned>() -> Self {
+        let mut found = None;
+        let _ = T::deserialize(Introspector { found: &mut found });
+
+        match found {
+            Some((name, fields)) => Self::from_parts(name, fields),
+            None => Self::from_parts(std::any::type_name::<T>(), &[]),
+        }
+    }
+
+    /// Fingerprint of a type name and its field names, as computed by [`of`](Self::of)
+    ///
+    /// The hash is the first 8 bytes of SHA-256 over the name followed by each
+    /// field name in sorted order, each preceded by a zero byte, in lowercase hex.
+    pub fn from_parts(type_name: &str, fields: &[&str]) -> Self {
+        let mut fields = fields.to_vec();
+        fields.sort_unstable();
+
+        let mut hasher = Sha256::new();
+        hasher.update(type_name.as_bytes());
+        for field in fields {
+            hasher.update([0]);
+            hasher.update(field.as_bytes());
+        }
+        let hash = hex(&hasher.finalize()[..8]);
+
+        Self {
+            type_name: type_name.to_string(),
+            hash,
+        }
+    }
+}
+
+impl fmt::Display for SchemaFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.type_name, self.hash)
+    }
+}
+
+/// Retrieved data deserialized into `T`
+#[derive(Debug)]
+pub struct TypedRetrieveResponse<T> {
+    pub data: T,
+    pub version: i32,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Fingerprint found in the stored envelope, if any
+    pub fingerprint: Option<SchemaFingerprint>,
+    pub meta: ResponseMeta,
+}
+
+/// History event with its payload deserialized into `T`
+#[derive(Debug)]
+pub struct TypedHistoryEvent<T> {
+    pub seq: i32,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub classified_type: Option<ClassifiedType>,
+    pub numeric_value: Option<f64>,
+    pub text_value: Option<String>,
+    pub confidence: Option<f64>,
+    pub payload: T,
+}
+
+/// History event whose payload could not be decoded
+#[derive(Debug)]
+pub struct EventDecodeFailure {
+    pub seq: i32,
+    pub payload: Value,
+    pub error: Error,
+}
+
+/// Page of typed history events
+///
+/// Events that fail to decode are collected in `failures` instead of failing the page.
+#[derive(Debug)]
+pub struct TypedHistoryResponse<T> {
+    pub events: Vec<TypedHistoryEvent<T>>,
+    pub failures: Vec<EventDecodeFailure>,
+    pub pagination: HistoryPagination,
+    pub meta: ResponseMeta,
+}
+
+impl<T: DeserializeOwned> From<HistoryResponse> for TypedHistoryResponse<T> {
+    fn from(resp: HistoryResponse) -> Self {
+        let mut events = Vec::with_capacity(resp.events.len());
+        let mut failures = vec![];
+
+        for event in resp.events {
+            let HistoryEvent {
+                seq,
+                created_at,
+                expires_at,
+                classified_type,
+                numeric_value,
+                text_value,
+                confidence,
+                payload,
+            } = event;
+
+            match unwrap_envelope::<T>(payload.clone()) {
+                Ok((payload, _)) => events.push(TypedHistoryEvent {
+                    seq,
+                    created_at,
+                    expires_at,
+                    classified_type,
+                    numeric_value,
+                    text_value,
+                    confidence,
+                    payload,
+                }),
+                Err(error) => failures.push(EventDecodeFailure {
+                    seq,
+                    payload,
+                    error,
+                }),
+            }
+        }
+
+        Self {
+            events,
+            failures,
+            pagination: resp.pagination,
+            meta: resp.meta,
+        }
+    }
+}
+
+impl Client {
+    /// Wrap values stored via `store_as` in a schema fingerprint envelope
+    pub fn with_schema_fingerprints(mut self, enabled: bool) -> Self {
+        self.schema_fingerprints = enabled;
+        self
+    }
+
+    /// Store a typed value
+    pub async fn store_as<T: Serialize + DeserializeOwned>(
+        &self,
+        value: &T,
+        ttl: Option<i32>,
+    ) -> Result<StoreResponse, Error> {
+        let mut data = serde_json::to_value(value)?;
+        if self.schema_fingerprints {
+            data = envelope(&SchemaFingerprint::of::<T>(), data);
+        }
+        self.store(&data, ttl).await
+    }
+
+    /// Retrieve data and deserialize it into `T`, verifying any schema fingerprint
+    pub async fn retrieve_as<T: DeserializeOwned>(&self) -> Result<TypedRetrieveResponse<T>, Error> {
+        let resp = self.retrieve().await?;
+        let (data, fingerprint) = unwrap_envelope::<T>(resp.data)?;
+
+        Ok(TypedRetrieveResponse {
+            data,
+            version: resp.version,
+            updated_at: resp.updated_at,
+            expires_at: resp.expires_at,
+            fingerprint,
+            meta: resp.meta,
+        })
+    }
+
+    /// Query history, deserializing each event payload into `T`
+    pub async fn history_as<T: DeserializeOwned>(
+        &self,
+        options: &HistoryOptions,
+    ) -> Result<TypedHistoryResponse<T>, Error> {
+        Ok(self.history(options).await?.into())
+    }
+
+    /// Stream typed history pages, following pagination until exhausted
+    pub fn history_stream_as<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        options: &HistoryOptions,
+    ) -> impl Stream<Item = Result<TypedHistoryResponse<T>, Error>> + 'a {
+        self.history_pages(options).map_ok(TypedHistoryResponse::from)
+    }
+}
+
+/// Wrap a value in a schema envelope
+pub(crate) fn envelope(fingerprint: &SchemaFingerprint, value: Value) -> Value {
+    serde_json::json!({
+        SCHEMA_FIELD: fingerprint,
+        VALUE_FIELD: value,
+    })
+}
+
+/// Stored value inside a schema envelope, or the data itself
+pub(crate) fn envelope_value(data: &Value) -> &Value {
+    match data.as_object() {
+        Some(obj) if obj.len() == 2 && obj.contains_key(SCHEMA_FIELD) => {
+            obj.get(VALUE_FIELD).unwrap_or(data)
+        }
+        _ => data,
+    }
+}
+
+/// Split a stored value into its payload and fingerprint, verifying the fingerprint against `T`
+pub(crate) fn unwrap_envelope<T: DeserializeOwned>(
+    data: Value,
+) -> Result<(T, Option<SchemaFingerprint>), Error> {
+    let mut data = data;
+    let envelope = data
+        .as_object()
+        .filter(|obj| obj.len() == 2 && obj.contains_key(VALUE_FIELD))
+        .and_then(|obj| obj.get(SCHEMA_FIELD))
+        .and_then(|schema| SchemaFingerprint::deserialize(schema).ok());
+
+    let Some(found) = envelope else {
+        return Ok((serde_json::from_value(data)?, None));
+    };
+
+    let expected = SchemaFingerprint::of::<T>();
+    if found != expected {
+        return Err(Error::SchemaMismatch { expected, found });
+    }
+
+    let value = data[VALUE_FIELD].take();
+    Ok((serde_json::from_value(value)?, Some(found)))
+}
+
+/// Deserializer that records the struct name and fields requested by a type, then bails out
+struct Introspector<'a> {
+    found: &'a mut Option<(&'static str, &'static [&'static str])>,
+}
+
+impl<'de> Deserializer<'de> for Introspector<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.found = Some((name, fields));
+        Err(de::Error::custom("introspection complete"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
@@ -0,0 +1,237 @@
+//! Unit-aware sensor readings (feature `units`)
+//!
+//! A [`SensorReading`] stores its unit alongside the value, as
+//! `{"value": 23.5, "unit": "°C"}`, so devices reporting in different units can
+//! write to related tokens without silently mixing them. Conversions go through
+//! [`uom`], and converting between different quantities is an error.
+//!
+//! ```
+//! use keyvalue_client::units::{SensorReading, Unit};
+//!
+//! let reading = SensorReading::new(212.0, Unit::Fahrenheit);
+//! assert!((reading.value_in(Unit::Celsius).unwrap() - 100.0).abs() < 1e-9);
+//! assert!(reading.value_in(Unit::Kilopascal).is_err());
+//! ```
+
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uom::si::f64::{ElectricPotential, Energy, Power, Pressure, ThermodynamicTemperature};
+use uom::si::{electric_potential, energy, power, pressure, thermodynamic_temperature};
+
+/// Physical quantity measured by a unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Quantity {
+    Temperature,
+    Pressure,
+    Energy,
+    Power,
+    Voltage,
+    Ratio,
+}
+
+/// Supported units, serialized by symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Unit {
+    #[serde(rename = "°C", alias = "C")]
+    Celsius,
+    #[serde(rename = "°F", alias = "F")]
+    Fahrenheit,
+    #[serde(rename = "K")]
+    Kelvin,
+    #[serde(rename = "Pa")]
+    Pascal,
+    #[serde(rename = "hPa")]
+    Hectopascal,
+    #[serde(rename = "kPa")]
+    Kilopascal,
+    #[serde(rename = "bar")]
+    Bar,
+    #[serde(rename = "psi")]
+    Psi,
+    #[serde(rename = "J")]
+    Joule,
+    #[serde(rename = "kJ")]
+    Kilojoule,
+    #[serde(rename = "Wh")]
+    WattHour,
+    #[serde(rename = "kWh")]
+    KilowattHour,
+    #[serde(rename = "W")]
+    Watt,
+    #[serde(rename = "kW")]
+    Kilowatt,
+    #[serde(rename = "V")]
+    Volt,
+    #[serde(rename = "mV")]
+    Millivolt,
+    #[serde(rename = "%")]
+    Percent,
+}
+
+impl Unit {
+    /// Quantity this unit measures
+    pub fn quantity(self) -> Quantity {
+        match self {
+            Unit::Celsius | Unit::Fahrenheit | Unit::Kelvin => Quantity::Temperature,
+            Unit::Pascal | Unit::Hectopascal | Unit::Kilopascal | Unit::Bar | Unit::Psi => {
+                Quantity::Pressure
+            }
+            Unit::Joule | Unit::Kilojoule | Unit::WattHour | Unit::KilowattHour => Quantity::Energy,
+            Unit::Watt | Unit::Kilowatt => Quantity::Power,
+            Unit::Volt | Unit::Millivolt => Quantity::Voltage,
+            Unit::Percent => Quantity::Ratio,
+        }
+    }
+
+    /// Unit symbol, as stored
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+            Unit::Kelvin => "K",
+            Unit::Pascal => "Pa",
+            Unit::Hectopascal => "hPa",
+            Unit::Kilopascal => "kPa",
+            Unit::Bar => "bar",
+            Unit::Psi => "psi",
+            Unit::Joule => "J",
+            Unit::Kilojoule => "kJ",
+            Unit::WattHour => "Wh",
+            Unit::KilowattHour => "kWh",
+            Unit::Watt => "W",
+            Unit::Kilowatt => "kW",
+            Unit::Volt => "V",
+            Unit::Millivolt => "mV",
+            Unit::Percent => "%",
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// Numeric value tagged with its unit
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SensorReading {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl SensorReading {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Self { value, unit }
+    }
+
+    /// Convert to another unit of the same quantity
+    pub fn convert(&self, unit: Unit) -> Result<Self, Error> {
+        if self.unit.quantity() != unit.quantity() {
+            return Err(Error::Validation(format!(
+                "Cannot convert {} to {}: different quantities",
+                self.unit, unit
+            )));
+        }
+        Ok(Self {
+            value: from_base(unit, to_base(self.unit, self.value)),
+            unit,
+        })
+    }
+
+    /// Value expressed in `unit`
+    pub fn value_in(&self, unit: Unit) -> Result<f64, Error> {
+        self.convert(unit).map(|reading| reading.value)
+    }
+
+    pub fn temperature(&self) -> Option<ThermodynamicTemperature> {
+        let kelvin = to_base(self.unit, self.value);
+        (self.unit.quantity() == Quantity::Temperature).then(|| {
+            ThermodynamicTemperature::new::<thermodynamic_temperature::kelvin>(kelvin)
+        })
+    }
+
+    pub fn pressure(&self) -> Option<Pressure> {
+        (self.unit.quantity() == Quantity::Pressure)
+            .then(|| Pressure::new::<pressure::pascal>(to_base(self.unit, self.value)))
+    }
+
+    pub fn energy(&self) -> Option<Energy> {
+        (self.unit.quantity() == Quantity::Energy)
+            .then(|| Energy::new::<energy::joule>(to_base(self.unit, self.value)))
+    }
+
+    pub fn power(&self) -> Option<Power> {
+        (self.unit.quantity() == Quantity::Power)
+            .then(|| Power::new::<power::watt>(to_base(self.unit, self.value)))
+    }
+
+    pub fn voltage(&self) -> Option<ElectricPotential> {
+        let volts = to_base(self.unit, self.value);
+        (self.unit.quantity() == Quantity::Voltage)
+            .then(|| ElectricPotential::new::<electric_potential::volt>(volts))
+    }
+}
+
+impl fmt::Display for SensorReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
+/// Value in the SI base unit of its quantity
+fn to_base(unit: Unit, value: f64) -> f64 {
+    use thermodynamic_temperature as t;
+    match unit {
+        Unit::Celsius => ThermodynamicTemperature::new::<t::degree_celsius>(value).get::<t::kelvin>(),
+        Unit::Fahrenheit => {
+            ThermodynamicTemperature::new::<t::degree_fahrenheit>(value).get::<t::kelvin>()
+        }
+        Unit::Kelvin => value,
+        Unit::Pascal => value,
+        Unit::Hectopascal => Pressure::new::<pressure::hectopascal>(value).get::<pressure::pascal>(),
+        Unit::Kilopascal => Pressure::new::<pressure::kilopascal>(value).get::<pressure::pascal>(),
+        Unit::Bar => Pressure::new::<pressure::bar>(value).get::<pressure::pascal>(),
+        Unit::Psi => Pressure::new::<pressure::pound_force_per_square_inch>(value)
+            .get::<pressure::pascal>(),
+        Unit::Joule => value,
+        Unit::Kilojoule => Energy::new::<energy::kilojoule>(value).get::<energy::joule>(),
+        Unit::WattHour => Energy::new::<energy::watt_hour>(value).get::<energy::joule>(),
+        Unit::KilowattHour => Energy::new::<energy::kilowatt_hour>(value).get::<energy::joule>(),
+        Unit::Watt => value,
+        Unit::Kilowatt => Power::new::<power::kilowatt>(value).get::<power::watt>(),
+        Unit::Volt => value,
+        Unit::Millivolt => ElectricPotential::new::<electric_potential::millivolt>(value)
+            .get::<electric_potential::volt>(),
+        Unit::Percent => value,
+    }
+}
+
+/// Value in `unit` from the SI base unit of its quantity
+fn from_base(unit: Unit, value: f64) -> f64 {
+    use thermodynamic_temperature as t;
+    match unit {
+        Unit::Celsius => ThermodynamicTemperature::new::<t::kelvin>(value).get::<t::degree_celsius>(),
+        Unit::Fahrenheit => {
+            ThermodynamicTemperature::new::<t::kelvin>(value).get::<t::degree_fahrenheit>()
+        }
+        Unit::Kelvin => value,
+        Unit::Pascal => value,
+        Unit::Hectopascal => Pressure::new::<pressure::pascal>(value).get::<pressure::hectopascal>(),
+        Unit::Kilopascal => Pressure::new::<pressure::pascal>(value).get::<pressure::kilopascal>(),
+        Unit::Bar => Pressure::new::<pressure::pascal>(value).get::<pressure::bar>(),
+        Unit::Psi => Pressure::new::<pressure::pascal>(value)
+            .get::<pressure::pound_force_per_square_inch>(),
+        Unit::Joule => value,
+        Unit::Kilojoule => Energy::new::<energy::joule>(value).get::<energy::kilojoule>(),
+        Unit::WattHour => Energy::new::<energy::joule>(value).get::<energy::watt_hour>(),
+        Unit::KilowattHour => Energy::new::<energy::joule>(value).get::<energy::kilowatt_hour>(),
+        Unit::Watt => value,
+        Unit::Kilowatt => Power::new::<power::watt>(value).get::<power::kilowatt>(),
+        Unit::Volt => value,
+        Unit::Millivolt => ElectricPotential::new::<electric_potential::volt>(value)
+            .get::<electric_potential::millivolt>(),
+        Unit::Percent => value,
+    }
+}
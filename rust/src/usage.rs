@@ -0,0 +1,72 @@
+//! Tier, storage and quota introspection
+//!
+//! [`Client::usage`] reads the usage endpoint for the current token. Request
+//! quota falls back to the `X-RateLimit-*` headers when the body omits it.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let usage = client.usage().await?;
+//! if usage.requests_remaining.is_some_and(|left| left < 10) {
+//!     println!("Slowing down, {:?} requests left", usage.requests_remaining);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Endpoint, Error, ResponseMeta};
+use serde::{Deserialize, Serialize};
+
+/// Limits and consumption for a token
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Usage {
+    /// Pricing tier, e.g. `free`
+    #[serde(default)]
+    pub tier: Option<String>,
+    /// Bytes currently stored
+    #[serde(default, alias = "storageUsed")]
+    pub storage_used: Option<u64>,
+    /// Storage allowed for the tier, in bytes
+    #[serde(default, alias = "storageLimit")]
+    pub storage_limit: Option<u64>,
+    /// Largest accepted payload, in bytes
+    #[serde(default, alias = "maxPayloadSize", alias = "max_size")]
+    pub max_payload_size: Option<u64>,
+    /// Requests allowed in the current window
+    #[serde(default, alias = "requestsLimit")]
+    pub requests_limit: Option<u64>,
+    /// Requests left in the current window
+    #[serde(default, alias = "requestsRemaining")]
+    pub requests_remaining: Option<u64>,
+    #[serde(skip)]
+    pub meta: ResponseMeta,
+}
+
+impl Usage {
+    /// Storage left before the tier limit, when both figures are known
+    pub fn storage_remaining(&self) -> Option<u64> {
+        Some(self.storage_limit?.saturating_sub(self.storage_used?))
+    }
+
+    /// Whether a payload of `bytes` is within the payload limit, if known
+    pub fn allows_payload(&self, bytes: u64) -> bool {
+        self.max_payload_size.is_none_or(|max| bytes <= max)
+    }
+}
+
+impl Client {
+    /// Tier, storage and remaining request quota for the current token
+    pub async fn usage(&self) -> Result<Usage, Error> {
+        let token = self.token.as_deref().ok_or(Error::MissingToken)?;
+
+        let request = self.auth.apply(self.http_client.get(self.url(Endpoint::Usage)), token);
+        let mut usage: Usage = self.execute(Endpoint::Usage, request).await?;
+        if let Some(rate_limit) = usage.meta.rate_limit {
+            usage.requests_limit = usage.requests_limit.or(rate_limit.limit);
+            usage.requests_remaining = usage.requests_remaining.or(rate_limit.remaining);
+        }
+        Ok(usage)
+    }
+}
@@ -0,0 +1,403 @@
+//! Encrypted on-disk store for many tenants' tokens
+//!
+//! Backends that hold Key-Value tokens on behalf of their own users keep them
+//! in a [`TokenVault`]: one file, each token sealed with AES-256-GCM under a
+//! key given directly or derived from a passphrase (PBKDF2-HMAC-SHA256). A
+//! token is bound to its tenant id, so swapping entries in the file makes
+//! them fail to open rather than hand one tenant another's token. Tenant ids
+//! themselves are stored in the clear. Tokens are decrypted only when looked
+//! up, and [`TokenVault::client`] turns one straight into a [`Client`].
+//!
+//! ```no_run
+//! use keyvalue_client::vault::TokenVault;
+//! use keyvalue_client::Error;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let passphrase = std::env::var("VAULT_PASSPHRASE").unwrap_or_default();
+//! let mut vault = TokenVault::open_with_passphrase("tokens.vault", &passphrase)?;
+//! vault.insert("acme", "word-word-word-word-word")?;
+//!
+//! let client = vault.client("acme")?;
+//! client.retrieve().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{mask_token, Client, ClientBuilder, Error};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
+
+const FORMAT_VERSION: u32 = 1;
+/// PBKDF2 rounds for new vaults, per the OWASP recommendation for SHA-256
+const PBKDF2_ROUNDS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const CHECK_VALUE: &str = "keyvalue-token-vault";
+/// Associated data for the check value; tenant entries use the tenant id
+const CHECK_AAD: &[u8] = b"";
+
+/// 256-bit vault key, zeroized on drop
+#[derive(Clone)]
+pub struct VaultKey([u8; 32]);
+
+impl VaultKey {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Fresh random key, e.g. to keep in a secrets manager
+    pub fn generate() -> Self {
+        let mut bytes = [0; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    /// Key derived from `passphrase` with PBKDF2-HMAC-SHA256
+    pub fn derive(passphrase: &str, salt: &[u8], rounds: u32) -> Self {
+        let mut bytes = [0; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, rounds, &mut bytes);
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Drop for VaultKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for VaultKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("VaultKey(****)")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<Kdf>,
+    /// [`CHECK_VALUE`] sealed under the key, to reject a wrong key on open
+    check: Sealed,
+    tenants: BTreeMap<String, Sealed>,
+}
+
+/// Passphrase derivation settings, kept so the same key can be derived again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Kdf {
+    salt: String,
+    rounds: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    token: String,
+}
+
+/// Tenant tokens encrypted at rest in one file
+pub struct TokenVault {
+    path: PathBuf,
+    cipher: Aes256Gcm,
+    file: VaultFile,
+    builder: ClientBuilder,
+}
+
+impl fmt::Debug for TokenVault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenVault")
+            .field("path", &self.path)
+            .field("tenants", &self.file.tenants.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TokenVault {
+    /// Open the vault at `path` with `key`, starting an empty one if the file does not exist
+    pub fn open(path: impl AsRef<Path>, key: &VaultKey) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        match read_file(&path)? {
+            Some(file) if file.kdf.is_some() => {
+                Err(vault_error("Vault is passphrase-protected; use open_with_passphrase"))
+            }
+            Some(file) => Self::existing(path, key, file),
+            None => Self::new(path, key, None),
+        }
+    }
+
+    /// Open the vault at `path`, deriving its key from `passphrase`
+    ///
+    /// A new vault gets a random salt.
+    pub fn open_with_passphrase(path: impl AsRef<Path>, passphrase: &str) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        match read_file(&path)? {
+            Some(file) => {
+                let Some(kdf) = &file.kdf else {
+                    return Err(vault_error("Vault uses a raw key; use open"));
+                };
+                let salt = BASE64
+                    .decode(&kdf.salt)
+                    .map_err(|_| vault_error("Vault file has an invalid salt"))?;
+                let key = VaultKey::derive(passphrase, &salt, kdf.rounds);
+                Self::existing(path, &key, file)
+            }
+            None => {
+                let mut salt = [0; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let key = VaultKey::derive(passphrase, &salt, PBKDF2_ROUNDS);
+                let kdf = Kdf {
+                    salt: BASE64.encode(salt),
+                    rounds: PBKDF2_ROUNDS,
+                };
+                Self::new(path, &key, Some(kdf))
+            }
+        }
+    }
+
+    /// Empty vault, written on the first insert
+    fn new(path: PathBuf, key: &VaultKey, kdf: Option<Kdf>) -> Result<Self, Error> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_bytes()));
+        let check = seal(&cipher, CHECK_VALUE, CHECK_AAD)?;
+        Ok(Self {
+            path,
+            cipher,
+            file: VaultFile {
+                version: FORMAT_VERSION,
+                kdf,
+                check,
+                tenants: BTreeMap::new(),
+            },
+            builder: Client::builder(),
+        })
+    }
+
+    fn existing(path: PathBuf, key: &VaultKey, file: VaultFile) -> Result<Self, Error> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_bytes()));
+        match open(&cipher, &file.check, CHECK_AAD) {
+            Some(check) if check.as_str() == CHECK_VALUE => {}
+            _ => return Err(vault_error("Wrong vault key or passphrase")),
+        }
+        Ok(Self {
+            path,
+            cipher,
+            file,
+            builder: Client::builder(),
+        })
+    }
+
+    /// Build clients from `builder`, e.g. for a self-hosted base URL
+    pub fn with_client_builder(mut self, builder: ClientBuilder) -> Self {
+        self.builder = builder;
+        self
+    }
+
+    /// Store `token` for `tenant`, replacing any previous one, and save the file
+    pub fn insert(&mut self, tenant: &str, token: &str) -> Result<(), Error> {
+        let sealed = seal(&self.cipher, token, tenant.as_bytes())?;
+        self.file.tenants.insert(tenant.to_string(), sealed);
+        self.save()
+    }
+
+    /// Forget `tenant`'s token and save the file; returns whether it was present
+    pub fn remove(&mut self, tenant: &str) -> Result<bool, Error> {
+        if self.file.tenants.remove(tenant).is_none() {
+            return Ok(false);
+        }
+        self.save().map(|()| true)
+    }
+
+    /// Decrypt `tenant`'s token
+    ///
+    /// Fails if the entry cannot be opened, e.g. because the file was edited
+    /// or the entry was moved from another tenant.
+    pub fn token(&self, tenant: &str) -> Result<Option<Zeroizing<String>>, Error> {
+        let Some(sealed) = self.file.tenants.get(tenant) else {
+            return Ok(None);
+        };
+        open(&self.cipher, sealed, tenant.as_bytes())
+            .map(Some)
+            .ok_or_else(|| vault_error(&format!("Cannot decrypt the token for tenant {:?}", tenant)))
+    }
+
+    pub fn contains(&self, tenant: &str) -> bool {
+        self.file.tenants.contains_key(tenant)
+    }
+
+    /// Tenant ids, in sorted order
+    pub fn tenants(&self) -> impl Iterator<Item = &str> + '_ {
+        self.file.tenants.keys().map(String::as_str)
+    }
+
+    /// Client for `tenant`'s token
+    pub fn client(&self, tenant: &str) -> Result<Client, Error> {
+        let token = self
+            .token(tenant)?
+            .ok_or_else(|| vault_error(&format!("No token for tenant {:?}", tenant)))?;
+        tracing::debug!(tenant, token = %mask_token(&token), "building client from vault");
+        Ok(self.builder.clone().token(token.as_str()).build()?)
+    }
+
+    /// Write the file atomically, readable only by its owner on Unix
+    fn save(&self) -> Result<(), Error> {
+        let json = serde_json::to_vec_pretty(&self.file)?;
+        let tmp_path = self.path.with_extension("tmp");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut tmp = options.open(&tmp_path)?;
+        tmp.write_all(&json)?;
+        tmp.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+fn read_file(path: &Path) -> Result<Option<VaultFile>, Error> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let file: VaultFile = serde_json::from_slice(&bytes)?;
+    if file.version != FORMAT_VERSION {
+        return Err(vault_error(&format!("Unsupported vault format version {}", file.version)));
+    }
+    Ok(Some(file))
+}
+
+fn seal(cipher: &Aes256Gcm, plain: &str, aad: &[u8]) -> Result<Sealed, Error> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: plain.as_bytes(),
+        aad,
+    };
+    let sealed = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| vault_error("Encryption failed"))?;
+    Ok(Sealed {
+        nonce: BASE64.encode(nonce),
+        token: BASE64.encode(sealed),
+    })
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &Sealed, aad: &[u8]) -> Option<Zeroizing<String>> {
+    let nonce = BASE64.decode(&sealed.nonce).ok().filter(|nonce| nonce.len() == 12)?;
+    let ciphertext = BASE64.decode(&sealed.token).ok()?;
+    let payload = Payload {
+        msg: &ciphertext,
+        aad,
+    };
+    let plain = cipher.decrypt(Nonce::from_slice(&nonce), payload).ok()?;
+    String::from_utf8(plain).ok().map(Zeroizing::new)
+}
+
+fn vault_error(message: &str) -> Error {
+    Error::Vault(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path in the temp directory, removed when dropped
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("kv-vault-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn key(byte: u8) -> VaultKey {
+        VaultKey::from_bytes([byte; 32])
+    }
+
+    #[test]
+    fn tokens_survive_reopening() {
+        let path = TempPath::new("reopen");
+        let mut vault = TokenVault::open(&path.0, &key(1)).unwrap();
+        vault.insert("acme", "word-word-word-word-word").unwrap();
+        vault.insert("globex", "other-other-other-other-other").unwrap();
+        assert!(!std::fs::read_to_string(&path.0).unwrap().contains("word-word"));
+
+        let vault = TokenVault::open(&path.0, &key(1)).unwrap();
+        assert_eq!(vault.tenants().collect::<Vec<_>>(), ["acme", "globex"]);
+        assert_eq!(vault.token("acme").unwrap().unwrap().as_str(), "word-word-word-word-word");
+        assert!(vault.token("initech").unwrap().is_none());
+    }
+
+    #[test]
+    fn wrong_key_is_rejected_on_open() {
+        let path = TempPath::new("wrong-key");
+        TokenVault::open(&path.0, &key(1)).unwrap().insert("acme", "word-word-word-word-word").unwrap();
+        assert!(matches!(TokenVault::open(&path.0, &key(2)), Err(Error::Vault(_))));
+        assert!(matches!(TokenVault::open_with_passphrase(&path.0, "secret"), Err(Error::Vault(_))));
+    }
+
+    #[test]
+    fn swapped_entries_fail_to_open() {
+        let path = TempPath::new("swapped");
+        let mut vault = TokenVault::open(&path.0, &key(1)).unwrap();
+        vault.insert("acme", "word-word-word-word-word").unwrap();
+        vault.insert("globex", "other-other-other-other-other").unwrap();
+
+        let acme = vault.file.tenants["acme"].clone();
+        vault.file.tenants.insert("globex".to_string(), acme);
+        assert!(matches!(vault.token("globex"), Err(Error::Vault(_))));
+        assert!(vault.token("acme").is_ok());
+    }
+
+    #[test]
+    fn tampered_entries_fail_to_open() {
+        let path = TempPath::new("tampered");
+        let mut vault = TokenVault::open(&path.0, &key(1)).unwrap();
+        vault.insert("acme", "word-word-word-word-word").unwrap();
+
+        let sealed = vault.file.tenants.get_mut("acme").unwrap();
+        let mut bytes = BASE64.decode(&sealed.token).unwrap();
+        bytes[0] ^= 1;
+        sealed.token = BASE64.encode(bytes);
+        assert!(matches!(vault.token("acme"), Err(Error::Vault(_))));
+    }
+
+    #[test]
+    fn remove_reports_whether_the_tenant_was_present() {
+        let path = TempPath::new("remove");
+        let mut vault = TokenVault::open(&path.0, &key(1)).unwrap();
+        vault.insert("acme", "word-word-word-word-word").unwrap();
+        assert!(vault.remove("acme").unwrap());
+        assert!(!vault.remove("acme").unwrap());
+        assert!(!TokenVault::open(&path.0, &key(1)).unwrap().contains("acme"));
+    }
+
+    #[test]
+    fn unsupported_format_versions_are_rejected() {
+        let path = TempPath::new("version");
+        TokenVault::open(&path.0, &key(1)).unwrap().insert("acme", "word-word-word-word-word").unwrap();
+        let text = std::fs::read_to_string(&path.0).unwrap().replace("\"version\": 1", "\"version\": 2");
+        std::fs::write(&path.0, text).unwrap();
+        assert!(matches!(TokenVault::open(&path.0, &key(1)), Err(Error::Vault(_))));
+    }
+}
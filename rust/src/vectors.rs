@@ -0,0 +1,476 @@
+//! Wire-format test vectors (feature `test-vectors`)
+//!
+//! [`WIRE_VECTORS`] is a language-neutral JSON description of what this SDK
+//! sends and accepts: request bodies and headers per operation, response
+//! bodies it must decode, error bodies and how they are classified, the typed
+//! schema envelope, and patch semantics. Other SDKs can replay the request
+//! vectors against their own request builders, and server implementations
+//! can check their responses and patch handling with the verifier methods.
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() {
+//! let vectors = keyvalue_client::vectors::load();
+//! let failures = vectors.verify_client().await;
+//! assert!(failures.is_empty(), "{:?}", failures);
+//! # }
+//! ```
+
+use crate::{
+    BatchOperation, BatchResponse, ClassifiedType, Client, DeleteResponse, Error, GenerateResponse,
+    HistoryOptions, HistoryResponse, Middleware, PatchOperations, PatchResponse,
+    RetrieveResponse, SchemaFingerprint, StoreResponse,
+};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// The test vectors as JSON
+pub const WIRE_VECTORS: &str = include_str!("../vectors/wire.json");
+
+/// Parsed test vector suite
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestVectors {
+    pub version: u32,
+    pub requests: Vec<RequestVector>,
+    pub responses: Vec<ResponseVector>,
+    pub errors: Vec<ErrorVector>,
+    pub envelopes: Vec<EnvelopeVector>,
+    pub patches: Vec<PatchVector>,
+}
+
+/// Parse [`WIRE_VECTORS`]
+pub fn load() -> TestVectors {
+    serde_json::from_str(WIRE_VECTORS).expect("bundled test vectors are valid")
+}
+
+/// Vector that did not hold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub name: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.reason)
+    }
+}
+
+/// SDK call whose HTTP request is described by a [`RequestVector`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Operation {
+    Generate {
+        turnstile_token: Option<String>,
+    },
+    Store {
+        data: Value,
+        ttl: Option<i32>,
+    },
+    Retrieve,
+    Delete,
+    Patch {
+        version: i32,
+        patch: PatchOperations,
+        ttl: Option<i32>,
+    },
+    History {
+        limit: Option<i32>,
+        before: Option<i32>,
+        since: Option<String>,
+        #[serde(rename = "type")]
+        type_filter: Option<ClassifiedType>,
+    },
+    Batch {
+        operations: Vec<BatchOperation>,
+    },
+}
+
+/// Expected HTTP request for an operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestVector {
+    pub name: String,
+    /// Token configured on the client, if any
+    pub token: Option<String>,
+    pub operation: Operation,
+    pub expected: ExpectedRequest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpectedRequest {
+    pub method: String,
+    /// Path and query string, e.g. `/api/history?limit=10`
+    pub path: String,
+    /// Headers that must be present, with lowercase names
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Headers that must not be present, with lowercase names
+    #[serde(default)]
+    pub absent_headers: Vec<String>,
+    /// JSON body, compared structurally
+    pub body: Option<Value>,
+}
+
+impl ExpectedRequest {
+    /// Compare a request built by any SDK against the expectation
+    pub fn check<'a>(
+        &self,
+        method: &str,
+        path: &str,
+        headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+        body: Option<&[u8]>,
+    ) -> Result<(), String> {
+        if !method.eq_ignore_ascii_case(&self.method) {
+            return Err(format!("method {} != {}", method, self.method));
+        }
+        if path != self.path {
+            return Err(format!("path {} != {}", path, self.path));
+        }
+
+        let headers: BTreeMap<String, &str> = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .collect();
+        for (name, expected) in &self.headers {
+            match headers.get(name) {
+                Some(value) if value.starts_with(expected.as_str()) => {}
+                Some(value) => return Err(format!("header {}: {} != {}", name, value, expected)),
+                None => return Err(format!("missing header {}", name)),
+            }
+        }
+        if let Some(name) = self.absent_headers.iter().find(|name| headers.contains_key(*name)) {
+            return Err(format!("unexpected header {}", name));
+        }
+
+        let body: Option<Value> = body
+            .filter(|body| !body.is_empty())
+            .map(serde_json::from_slice)
+            .transpose()
+            .map_err(|e| format!("body is not JSON: {}", e))?;
+        if body != self.body {
+            return Err(format!(
+                "body {} != {}",
+                body.unwrap_or_default(),
+                self.body.clone().unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Response type a [`ResponseVector`] body decodes into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseKind {
+    Generate,
+    Store,
+    Retrieve,
+    Delete,
+    Patch,
+    History,
+    Batch,
+}
+
+/// Successful response body the SDK must decode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseVector {
+    pub name: String,
+    pub response: ResponseKind,
+    pub body: Value,
+}
+
+impl ResponseVector {
+    /// Check that a server response body decodes as this vector's response type
+    pub fn check(&self, body: &str) -> Result<(), String> {
+        fn decode<T: serde::de::DeserializeOwned>(body: &str) -> Result<(), String> {
+            let de = &mut serde_json::Deserializer::from_str(body);
+            serde_path_to_error::deserialize::<_, T>(de)
+                .map(drop)
+                .map_err(|e| format!("at `{}`: {}", e.path(), e.inner()))
+        }
+        match self.response {
+            ResponseKind::Generate => decode::<GenerateResponse>(body),
+            ResponseKind::Store => decode::<StoreResponse>(body),
+            ResponseKind::Retrieve => decode::<RetrieveResponse>(body),
+            ResponseKind::Delete => decode::<DeleteResponse>(body),
+            ResponseKind::Patch => decode::<PatchResponse>(body),
+            ResponseKind::History => decode::<HistoryResponse>(body),
+            ResponseKind::Batch => decode::<BatchResponse>(body),
+        }
+    }
+}
+
+/// Error response and how the SDK classifies it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorVector {
+    pub name: String,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: Value,
+    pub expected: ExpectedError,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpectedError {
+    /// One of `not_found`, `unauthorized`, `conflict`, `payload_too_large`,
+    /// `rate_limited`, `server` or `api`
+    pub kind: String,
+    pub message: String,
+    #[serde(default)]
+    pub current_version: Option<i32>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+    #[serde(default)]
+    pub retry_after_secs: Option<u64>,
+    pub retryable: bool,
+}
+
+impl ErrorVector {
+    /// How this crate classifies the error response
+    pub fn classify(&self) -> Result<ExpectedError, String> {
+        let status = StatusCode::from_u16(self.status).map_err(|e| e.to_string())?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| e.to_string())?;
+            headers.insert(name, value.parse().map_err(|e| format!("{}", e))?);
+        }
+        let error = Error::from_response(status, &headers, &self.body.to_string());
+        let retryable = error.is_retryable();
+        let mut classified = ExpectedError {
+            kind: String::new(),
+            message: String::new(),
+            current_version: None,
+            limit: None,
+            retry_after_secs: None,
+            retryable,
+        };
+        let (kind, message) = match error {
+            Error::NotFound { message } => ("not_found", message),
+            Error::Unauthorized { message, .. } => ("unauthorized", message),
+            Error::Conflict { current_version, message } => {
+                classified.current_version = current_version;
+                ("conflict", message)
+            }
+            Error::PayloadTooLarge { limit, message, .. } => {
+                classified.limit = limit;
+                ("payload_too_large", message)
+            }
+            Error::RateLimited { retry_after, message } => {
+                classified.retry_after_secs = retry_after.map(|d| d.as_secs());
+                ("rate_limited", message)
+            }
+            Error::Server { message, .. } => ("server", message),
+            Error::Api { message, .. } => ("api", message),
+            other => return Err(format!("unexpected error {:?}", other)),
+        };
+        classified.kind = kind.to_string();
+        classified.message = message;
+        Ok(classified)
+    }
+}
+
+/// Typed value stored with a schema fingerprint envelope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvelopeVector {
+    pub name: String,
+    pub type_name: String,
+    pub fields: Vec<String>,
+    pub value: Value,
+    /// Document as stored, including the envelope
+    pub stored: Value,
+}
+
+/// Patch applied to a document and the resulting document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchVector {
+    pub name: String,
+    pub document: Value,
+    pub patch: PatchOperations,
+    pub result: Value,
+}
+
+impl PatchVector {
+    /// Compare a server's patched document against the expected result
+    pub fn check(&self, actual: &Value) -> Result<(), String> {
+        if actual == &self.result {
+            Ok(())
+        } else {
+            Err(format!("{} != {}", actual, self.result))
+        }
+    }
+}
+
+/// Apply patch operations to a document as the API does
+///
+/// `set` paths are applied first, creating intermediate objects as needed,
+/// then `remove` paths. Paths use dot notation; removing a missing path is
+/// not an error.
+pub fn apply_patch(document: &Value, patch: &PatchOperations) -> Value {
+    let mut document = document.clone();
+    let mut sets: Vec<_> = patch.set.iter().flatten().collect();
+    sets.sort_by_key(|(path, _)| path.as_str());
+    for (path, value) in sets {
+        let mut current = &mut document;
+        let mut segments = path.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            if !current.is_object() {
+                *current = Value::Object(Map::new());
+            }
+            let object = current.as_object_mut().expect("just made an object");
+            if segments.peek().is_none() {
+                object.insert(segment.to_string(), value.clone());
+                break;
+            }
+            current = object.entry(segment).or_insert_with(|| Value::Object(Map::new()));
+        }
+    }
+    for path in patch.remove.iter().flatten() {
+        let mut segments: Vec<&str> = path.split('.').collect();
+        let last = segments.pop().expect("split yields at least one segment");
+        let parent = segments
+            .into_iter()
+            .try_fold(&mut document, |current, segment| current.get_mut(segment));
+        if let Some(Value::Object(object)) = parent {
+            object.remove(last);
+        }
+    }
+    document
+}
+
+#[derive(Debug, Clone)]
+struct Captured {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+}
+
+/// Middleware that records a request and stops it from being sent
+struct Capture(Arc<Mutex<Option<Captured>>>);
+
+impl Middleware for Capture {
+    fn on_request(&self, request: &mut reqwest::Request) -> Result<(), Error> {
+        let url = request.url();
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let headers = request
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned())
+            })
+            .collect();
+        let body = request.body().and_then(|body| body.as_bytes()).map(<[u8]>::to_vec);
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(Captured {
+            method: request.method().to_string(),
+            path,
+            headers,
+            body,
+        });
+        Err(Error::Validation("request captured".to_string()))
+    }
+}
+
+impl TestVectors {
+    /// Check every vector against this crate's own implementation
+    pub async fn verify_client(&self) -> Vec<Failure> {
+        let mut failures = vec![];
+        let mut fail = |name: &str, reason: String| {
+            failures.push(Failure {
+                name: name.to_string(),
+                reason,
+            })
+        };
+
+        for vector in &self.requests {
+            if let Err(reason) = capture_request(vector).await {
+                fail(&vector.name, reason);
+            }
+        }
+        for vector in &self.responses {
+            if let Err(reason) = vector.check(&vector.body.to_string()) {
+                fail(&vector.name, reason);
+            }
+        }
+        for vector in &self.errors {
+            match vector.classify() {
+                Ok(classified) if classified == vector.expected => {}
+                Ok(classified) => fail(&vector.name, format!("classified as {:?}", classified)),
+                Err(reason) => fail(&vector.name, reason),
+            }
+        }
+        for vector in &self.envelopes {
+            let fields: Vec<&str> = vector.fields.iter().map(String::as_str).collect();
+            let fingerprint = SchemaFingerprint::from_parts(&vector.type_name, &fields);
+            let stored = crate::typed::envelope(&fingerprint, vector.value.clone());
+            if stored != vector.stored {
+                fail(&vector.name, format!("{} != {}", stored, vector.stored));
+            }
+        }
+        for vector in &self.patches {
+            if let Err(reason) = vector.check(&apply_patch(&vector.document, &vector.patch)) {
+                fail(&vector.name, reason);
+            }
+        }
+        failures
+    }
+}
+
+async fn capture_request(vector: &RequestVector) -> Result<(), String> {
+    let captured = Arc::new(Mutex::new(None));
+    let mut client = Client::new_without_token()
+        .with_base_url("http://vectors.invalid")
+        .with_middleware(Capture(captured.clone()));
+    if let Some(token) = &vector.token {
+        client.set_token(token.clone());
+    }
+
+    let _ = match vector.operation.clone() {
+        Operation::Generate { turnstile_token } => {
+            client.generate(turnstile_token.as_deref()).await.map(drop)
+        }
+        Operation::Store { data, ttl } => client.store(&data, ttl).await.map(drop),
+        Operation::Retrieve => client.retrieve().await.map(drop),
+        Operation::Delete => client.delete().await.map(drop),
+        Operation::Patch { version, patch, ttl } => {
+            client.patch(version, &patch, ttl).await.map(drop)
+        }
+        Operation::History {
+            limit,
+            before,
+            since,
+            type_filter,
+        } => {
+            let options = HistoryOptions {
+                limit,
+                before,
+                since,
+                type_filter,
+                ..Default::default()
+            };
+            client.history(&options).await.map(drop)
+        }
+        Operation::Batch { operations } => client.batch(operations).await.map(drop),
+    };
+
+    let captured = captured
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .ok_or("no request was sent")?;
+    vector.expected.check(
+        &captured.method,
+        &captured.path,
+        captured.headers.iter().map(|(name, value)| (name.as_str(), value.as_str())),
+        captured.body.as_deref(),
+    )
+}
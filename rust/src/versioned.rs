@@ -0,0 +1,137 @@
+//! Values that remember the version they were read at
+//!
+//! [`Client::retrieve_versioned`] returns a [`VersionedValue`]: the typed
+//! data with its version and timestamps, tied to the client it came from.
+//! [`VersionedValue::save`] and [`VersionedValue::patch`] write back at that
+//! version, so a write made by someone else in between fails with
+//! [`Error::Conflict`] instead of being overwritten, and a successful write
+//! moves the value to the new version. [`VersionedValue::update`] wraps the
+//! read-modify-write loop, re-reading and retrying on conflicts.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Thermostat {
+//!     target: f64,
+//!     mode: String,
+//! }
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let mut thermostat = client.retrieve_versioned::<Thermostat>().await?;
+//!
+//! thermostat.data.mode = "eco".to_string();
+//! thermostat.save(None).await?;
+//!
+//! thermostat.update(None, |t| t.target -= 0.5).await?;
+//! println!("Now at version {}", thermostat.version);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::typed::{envelope, unwrap_envelope, SchemaFingerprint};
+use crate::{Client, Error, PatchOperations, PatchResponse, RetryPolicy};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Retries of [`VersionedValue::update`] after version conflicts
+const CONFLICT_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 5,
+    initial_backoff: Duration::from_millis(20),
+    max_backoff: Duration::from_secs(1),
+};
+
+/// Typed value with the version it was read or last written at
+#[derive(Debug, Clone)]
+pub struct VersionedValue<T> {
+    client: Client,
+    pub data: T,
+    pub version: i32,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Client {
+    /// Retrieve data as a `T` that can be saved back at its version
+    pub async fn retrieve_versioned<T: DeserializeOwned>(&self) -> Result<VersionedValue<T>, Error> {
+        let resp = self.retrieve_as::<T>().await?;
+        Ok(VersionedValue {
+            client: self.clone(),
+            data: resp.data,
+            version: resp.version,
+            updated_at: resp.updated_at,
+            expires_at: resp.expires_at,
+        })
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> VersionedValue<T> {
+    /// Client the value is read from and written with
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+
+    /// Re-read the stored value, discarding local changes
+    pub async fn refresh(&mut self) -> Result<(), Error> {
+        *self = self.client.retrieve_versioned().await?;
+        Ok(())
+    }
+
+    /// Replace the stored value with `data` if it is still at `version`
+    ///
+    /// The value must serialize to a JSON object (see
+    /// [`Client::store_if_version`]). On [`Error::Conflict`] nothing is
+    /// written and the value keeps its old version; call
+    /// [`refresh`](Self::refresh) to pick up the other write.
+    pub async fn save(&mut self, ttl: Option<i32>) -> Result<(), Error> {
+        let mut data = serde_json::to_value(&self.data)?;
+        if self.client.schema_fingerprints {
+            data = envelope(&SchemaFingerprint::of::<T>(), data);
+        }
+        let resp = self.client.store_if_version(&data, self.version, ttl).await?;
+        self.advance(&resp);
+        Ok(())
+    }
+
+    /// Apply `patch` to the stored value if it is still at `version`
+    ///
+    /// Paths address the document as stored. On success `data` becomes the
+    /// patched value.
+    pub async fn patch(&mut self, patch: &PatchOperations, ttl: Option<i32>) -> Result<(), Error> {
+        let resp = self.client.patch(self.version, patch, ttl).await?;
+        let (data, _) = unwrap_envelope::<T>(resp.data.clone())?;
+        self.data = data;
+        self.advance(&resp);
+        Ok(())
+    }
+
+    /// Apply `edit` and save, re-reading and editing again on conflicts
+    pub async fn update(&mut self, ttl: Option<i32>, mut edit: impl FnMut(&mut T)) -> Result<(), Error> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            edit(&mut self.data);
+            match self.save(ttl).await {
+                Err(Error::Conflict { .. }) if attempt < CONFLICT_RETRY.max_attempts => {
+                    tokio::time::sleep(CONFLICT_RETRY.delay(attempt)).await;
+                    self.refresh().await?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn advance(&mut self, resp: &PatchResponse) {
+        self.version = resp.version;
+        self.updated_at = resp.updated_at;
+        self.expires_at = resp.expires_at;
+    }
+}
@@ -0,0 +1,142 @@
+//! Last seen document version per token
+//!
+//! Every retrieve, store, patch and batch response tells the client the
+//! current version of a document; [`Client::last_version`] returns the latest
+//! one seen for the current token, and a delete or `404` forgets it. Clones
+//! share what they have seen.
+//!
+//! With [`Client::with_auto_version`], `store` of a JSON object becomes a
+//! [`Client::store_if_version`] at the last seen version, so a write made by
+//! someone else in between fails with [`Error::Conflict`] instead of being
+//! overwritten. The conflict does not update the last seen version: retrieve
+//! the document and merge before storing again. [`Client::patch_latest`] does the same for patches.
+//!
+//! ```no_run
+//! use keyvalue_client::{Client, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word").with_auto_version(true);
+//!
+//! let mut config = client.retrieve().await?.data;
+//! config["mode"] = "eco".into();
+//! match client.store(&config, None).await {
+//!     Ok(resp) => println!("Stored version {}", resp.version),
+//!     Err(Error::Conflict { .. }) => println!("Changed since our last read"),
+//!     Err(e) => return Err(e),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::conditional::{patchable, replacement_patch};
+use crate::{Client, Error, PatchOperations, PatchResponse, StoreResponse};
+use serde_json::Value;
+
+impl Client {
+    /// Check versions on `store` against the last seen version
+    ///
+    /// Only applies to JSON object payloads once a version has been seen;
+    /// other stores are sent unconditionally.
+    pub fn with_auto_version(mut self, enabled: bool) -> Self {
+        self.auto_version = enabled;
+        self
+    }
+
+    /// Latest version seen for the current token, if any
+    pub fn last_version(&self) -> Option<i32> {
+        let token = self.token.as_deref()?;
+        let versions = self.versions.lock().unwrap_or_else(|e| e.into_inner());
+        versions.get(token).copied()
+    }
+
+    /// Patch at the last seen version, retrieving the document first if none is known
+    pub async fn patch_latest(
+        &self,
+        patch: &PatchOperations,
+        ttl: Option<i32>,
+    ) -> Result<PatchResponse, Error> {
+        let version = match self.last_version() {
+            Some(version) => version,
+            None => self.retrieve_raw().await?.version,
+        };
+        self.patch(version, patch, ttl).await
+    }
+
+    /// Version an automatically checked store of `data` must match
+    pub(crate) fn auto_version_for(&self, data: &Value) -> Option<i32> {
+        if !self.auto_version || !data.is_object() {
+            return None;
+        }
+        self.last_version()
+    }
+
+    /// Store `data` only if the document is still at `version`
+    ///
+    /// Sent as a patch of the differences where possible. Checksum and
+    /// compression envelopes, and documents with top-level fields containing
+    /// `.`, cannot be patched, so those are stored whole once the version is checked. After
+    /// a conflict the last seen version stays at `version`, so a retry fails
+    /// too until the document is retrieved again.
+    pub(crate) async fn store_checked(
+        &self,
+        data: &Value,
+        version: i32,
+        ttl: Option<i32>,
+    ) -> Result<StoreResponse, Error> {
+        let result = self.send_checked(data, version, ttl).await;
+        if let Err(Error::Conflict { .. }) = &result {
+            self.set_version(self.token.as_deref(), Some(version));
+        }
+        result
+    }
+
+    async fn send_checked(
+        &self,
+        data: &Value,
+        version: i32,
+        ttl: Option<i32>,
+    ) -> Result<StoreResponse, Error> {
+        self.check_payload_size(data)?;
+        let current = self.retrieve_expecting(version).await?;
+        if self.wraps_payload() || !patchable(data) || !patchable(&current.data) {
+            let sealed = self.encrypt_fields(data)?;
+            return self.store_raw(&sealed, ttl).await;
+        }
+        let patch = replacement_patch(&current.data, data)?;
+        let sealed = self.encrypt_patch(&patch)?;
+        let resp = self.patch_raw(version, &sealed, ttl).await?;
+        Ok(StoreResponse {
+            success: resp.success,
+            message: format!("Stored version {}", resp.version),
+            size: resp.size,
+            tier: resp.tier,
+            version: resp.version,
+            updated_at: resp.updated_at,
+            expires_at: resp.expires_at,
+            meta: resp.meta,
+        })
+    }
+
+    /// Record the version in a response for the current token
+    pub(crate) fn track_version<T>(
+        &self,
+        result: &Result<T, Error>,
+        version: impl FnOnce(&T) -> i32,
+    ) {
+        match result {
+            Ok(resp) => self.set_version(self.token.as_deref(), Some(version(resp))),
+            Err(Error::NotFound { .. }) => self.set_version(self.token.as_deref(), None),
+            Err(_) => {}
+        }
+    }
+
+    /// Record or forget the version for `token`
+    pub(crate) fn set_version(&self, token: Option<&str>, version: Option<i32>) {
+        let Some(token) = token else { return };
+        let mut versions = self.versions.lock().unwrap_or_else(|e| e.into_inner());
+        match version {
+            Some(version) => versions.insert(token.to_string(), version),
+            None => versions.remove(token),
+        };
+    }
+}
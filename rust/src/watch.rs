@@ -0,0 +1,58 @@
+//! Change notifications by polling
+//!
+//! The API has no push channel, so [`Client::watch`] polls `retrieve` and
+//! yields the document whenever its version changes, starting with the
+//! current version. Polls after the first are conditional
+//! ([`Client::retrieve_if_newer`]), so unchanged data costs no body. A missing
+//! document is treated as "not yet written" and polling continues. Errors are
+//! yielded without ending the stream.
+//!
+//! ```no_run
+//! use futures_util::StreamExt;
+//! use keyvalue_client::{Client, Error};
+//! use std::pin::pin;
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let client = Client::new("word-word-word-word-word");
+//! let mut changes = pin!(client.watch(Duration::from_secs(5)));
+//! while let Some(change) = changes.next().await {
+//!     println!("version {}", change?.version);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Client, Error, RetrieveResponse};
+use futures_util::stream::{self, Stream};
+use std::time::Duration;
+
+impl Client {
+    /// Stream the document each time its version changes, polling every `interval`
+    pub fn watch(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<RetrieveResponse, Error>> + '_ {
+        stream::unfold((None, true), move |(mut last_version, mut first)| async move {
+            loop {
+                if !first {
+                    tokio::time::sleep(interval).await;
+                }
+                first = false;
+
+                let change = match last_version {
+                    Some(version) => self.retrieve_if_newer(version).await,
+                    None => self.retrieve().await.map(Some),
+                };
+                match change {
+                    Ok(Some(resp)) => {
+                        last_version = Some(resp.version);
+                        return Some((Ok(resp), (last_version, false)));
+                    }
+                    Ok(None) | Err(Error::NotFound { .. }) => {}
+                    Err(e) => return Some((Err(e), (last_version, false))),
+                }
+            }
+        })
+    }
+}
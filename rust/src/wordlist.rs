@@ -0,0 +1,195 @@
+//! Token wordlist for offline checks (feature `wordlist`)
+//!
+//! Tokens are five words drawn from the [EFF large wordlist], less its four
+//! hyphenated entries, joined with `-`. With the list embedded, provisioning
+//! tools can catch a mistyped token before it reaches the server, point at
+//! the word that is wrong, and say how hard a token is to guess.
+//!
+//! ```
+//! use keyvalue_client::wordlist;
+//!
+//! assert!(wordlist::validate("capable-germinate-disbelief-survival-quantum").is_ok());
+//!
+//! let typos = wordlist::typos("capable-germinate-disbelief-ocaen-quantum");
+//! assert_eq!(typos[0].to_string(), "did you mean 'ocean' not 'ocaen'?");
+//! assert_eq!(
+//!     wordlist::correct("capable-germinate-disbelief-ocaen-quantum").as_deref(),
+//!     Some("capable-germinate-disbelief-ocean-quantum")
+//! );
+//! assert!(wordlist::entropy_bits("capable-germinate-disbelief-survival-quantum") > 64.0);
+//! ```
+//!
+//! The wordlist is by the Electronic Frontier Foundation, under the Creative
+//! Commons Attribution 3.0 license.
+//!
+//! [EFF large wordlist]: https://www.eff.org/dice
+
+use crate::Error;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Words in a generated token
+pub const TOKEN_WORDS: usize = 5;
+
+/// Largest edit distance, counting swapped letters as one edit, for a suggestion
+const MAX_DISTANCE: usize = 2;
+
+const WORDLIST: &str = include_str!("../wordlist/eff_large_wordlist.txt");
+
+/// The token words, in sorted order
+pub fn words() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| WORDLIST.lines().collect())
+}
+
+/// Whether `word` is a token word
+pub fn contains(word: &str) -> bool {
+    words().binary_search(&word).is_ok()
+}
+
+/// Closest token word to `word`, if one is within two edits
+///
+/// Ties go to the word sharing the first letter, then to the first in the list.
+pub fn suggest(word: &str) -> Option<&'static str> {
+    let word = word.to_ascii_lowercase();
+    if let Ok(i) = words().binary_search(&word.as_str()) {
+        return Some(words()[i]);
+    }
+    words()
+        .iter()
+        .map(|candidate| (distance(&word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= MAX_DISTANCE)
+        .min_by_key(|(distance, candidate)| (*distance, candidate.as_bytes().first() != word.as_bytes().first()))
+        .map(|(_, candidate)| *candidate)
+}
+
+/// A word of a token that is not in the wordlist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Typo {
+    /// Position of the word in the token, from 0
+    pub index: usize,
+    pub word: String,
+    pub suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for Typo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.suggestion {
+            Some(suggestion) => write!(f, "did you mean '{}' not '{}'?", suggestion, self.word),
+            None => write!(f, "'{}' is not a token word", self.word),
+        }
+    }
+}
+
+/// Words of `token` that are not in the wordlist, with the closest words to them
+pub fn typos(token: &str) -> Vec<Typo> {
+    token
+        .trim()
+        .split('-')
+        .enumerate()
+        .filter(|(_, word)| !contains(word))
+        .map(|(index, word)| Typo {
+            index,
+            word: word.to_string(),
+            suggestion: suggest(word),
+        })
+        .collect()
+}
+
+/// Check that `token` is five words from the wordlist
+///
+/// The error names each unknown word and, where there is one, the word it
+/// was probably meant to be.
+pub fn validate(token: &str) -> Result<(), Error> {
+    let count = token.trim().split('-').count();
+    if count != TOKEN_WORDS {
+        return Err(Error::Validation(format!(
+            "Token has {} words, expected {}",
+            count, TOKEN_WORDS
+        )));
+    }
+    let typos = typos(token);
+    if typos.is_empty() {
+        return Ok(());
+    }
+    let problems: Vec<String> = typos
+        .iter()
+        .map(|typo| format!("word {}: {}", typo.index + 1, typo))
+        .collect();
+    Err(Error::Validation(format!("Invalid token: {}", problems.join("; "))))
+}
+
+/// `token` with each unknown word replaced by its suggestion, if every one has one
+pub fn correct(token: &str) -> Option<String> {
+    let corrected: Option<Vec<&str>> = token
+        .trim()
+        .split('-')
+        .map(|word| if contains(word) { Some(word) } else { suggest(word) })
+        .collect();
+    corrected.map(|words| words.join("-"))
+}
+
+/// Estimated bits of entropy in `token`
+///
+/// Words from the list count as a uniform pick from it, about 12.9 bits
+/// each, so a generated token has about 64.6. Other words are scored as
+/// random lowercase letters, which overestimates anything a person made up.
+pub fn entropy_bits(token: &str) -> f64 {
+    let per_word = (words().len() as f64).log2();
+    let per_letter = 26f64.log2();
+    token
+        .trim()
+        .split('-')
+        .filter(|word| !word.is_empty())
+        .map(|word| match contains(word) {
+            true => per_word,
+            false => word.chars().count() as f64 * per_letter,
+        })
+        .sum()
+}
+
+/// Rough guessing resistance of a token, from [`entropy_bits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strength {
+    /// Under 48 bits, e.g. three words
+    Weak,
+    /// Under 64 bits, e.g. four words
+    Fair,
+    /// 64 bits or more, as generated tokens have
+    Strong,
+}
+
+impl Strength {
+    pub fn of(token: &str) -> Self {
+        match entropy_bits(token) {
+            bits if bits < 48.0 => Strength::Weak,
+            bits if bits < 64.0 => Strength::Fair,
+            _ => Strength::Strong,
+        }
+    }
+}
+
+/// Edit distance counting insertions, deletions, substitutions and swaps of adjacent letters
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len().abs_diff(b.len()) > MAX_DISTANCE {
+        return usize::MAX;
+    }
+    // Rows i-2, i-1 and i of the distance matrix
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,58 @@
+//! Runs the `kv` binary against a one-shot local server
+#![cfg(feature = "cli")]
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::process::Command;
+use std::thread;
+
+/// Answer one request with a retrieve response, returning the request head
+fn serve_retrieve(listener: TcpListener) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).unwrap();
+            assert!(read > 0, "connection closed before the request head");
+            request.extend_from_slice(&buf[..read]);
+        }
+        let body = r#"{"success":true,"data":{"hello":"world"},"version":1,"updated_at":"2026-01-01T00:00:00Z","expires_at":null}"#;
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+        String::from_utf8_lossy(&request).to_lowercase()
+    })
+}
+
+#[test]
+fn flags_suffice_without_a_config_file() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = serve_retrieve(listener);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_kv"))
+        .env_clear()
+        .args(["--token", "a-b-c-d-e", "--url", &url, "get"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "kv failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("world"));
+    assert!(server.join().unwrap().contains("x-kv-token: a-b-c-d-e"));
+}
+
+#[test]
+fn named_profile_requires_a_config_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_kv"))
+        .env_clear()
+        .args(["--profile", "staging", "--token", "a-b-c-d-e", "--url", "http://127.0.0.1:9", "get"])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No config file"));
+}